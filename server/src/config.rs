@@ -0,0 +1,114 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::execution::CACHE_DIR_ENV;
+
+/// Environment variable holding the path of the server config file
+pub const SERVER_CONFIG_ENV: &str = "STARTHUB_SERVER_CONFIG";
+/// Name of the server config file in the starthub config directory
+pub const SERVER_CONFIG_FILENAME: &str = "server.json";
+/// Environment variables of the settings, in the order of the fields of `ServerConfig`
+pub const BIND_ENV: &str = "STARTHUB_BIND";
+pub const DB_PATH_ENV: &str = "STARTHUB_DB_PATH";
+pub const UI_DIR_ENV: &str = "STARTHUB_UI_DIR";
+pub const WORKERS_ENV: &str = "STARTHUB_WORKERS";
+
+/// Settings of the server that may come from its config file or the environment rather than its
+/// command line. Unset settings fall back to the next source: flag, then environment, then file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Host and port the server listens on
+    pub bind: Option<String>,
+    /// Directory where downloaded artifacts are cached
+    pub cache_dir: Option<PathBuf>,
+    /// Path of the SQLite database
+    pub db_path: Option<PathBuf>,
+    /// Directory of the built console UI
+    pub ui_dir: Option<PathBuf>,
+    /// Number of runs executed at the same time
+    pub workers: Option<usize>,
+    /// Token manifest requests are authenticated with. Only read from the file, so that it shows
+    /// up neither on the command line nor in the environment of the process.
+    pub auth_token: Option<String>,
+}
+
+impl ServerConfig {
+    /// Where the config file is read from: `STARTHUB_SERVER_CONFIG`, or else `server.json` in the
+    /// starthub config directory, next to the database and the saved token
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os(SERVER_CONFIG_ENV)
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("starthub").join(SERVER_CONFIG_FILENAME)))
+    }
+
+    /// Reads a config file. A missing file is an empty config, an invalid one an error.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => anyhow::bail!("Failed to read the server config {}: {}", path.display(), e),
+        };
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid server config {}: {}", path.display(), e))
+    }
+
+    /// Reads the settings given as environment variables
+    pub fn from_env(env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let workers = match env(WORKERS_ENV) {
+            Some(workers) => Some(workers.trim().parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("{} must be a number of runs, got '{}'", WORKERS_ENV, workers))?),
+            None => None,
+        };
+        Ok(Self {
+            bind: env(BIND_ENV),
+            cache_dir: env(CACHE_DIR_ENV).map(PathBuf::from),
+            db_path: env(DB_PATH_ENV).map(PathBuf::from),
+            ui_dir: env(UI_DIR_ENV).map(PathBuf::from),
+            workers,
+            auth_token: None,
+        })
+    }
+
+    /// This config with the settings of `other` taking precedence
+    pub fn overridden_by(self, other: ServerConfig) -> ServerConfig {
+        ServerConfig {
+            bind: other.bind.or(self.bind),
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            db_path: other.db_path.or(self.db_path),
+            ui_dir: other.ui_dir.or(self.ui_dir),
+            workers: other.workers.or(self.workers),
+            auth_token: other.auth_token.or(self.auth_token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_override_each_other() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join(SERVER_CONFIG_FILENAME);
+        assert_eq!(ServerConfig::from_file(&path).unwrap(), ServerConfig::default());
+
+        std::fs::write(&path, r#"{"bind": "0.0.0.0:4000", "workers": 8, "db_path": "/var/lib/starthub/server.db"}"#).unwrap();
+        let file = ServerConfig::from_file(&path).unwrap();
+        assert_eq!(file.bind.as_deref(), Some("0.0.0.0:4000"));
+
+        let env = ServerConfig::from_env(|name| (name == WORKERS_ENV).then(|| "2".to_string())).unwrap();
+        let config = file.overridden_by(env);
+        assert_eq!(config.bind.as_deref(), Some("0.0.0.0:4000"));
+        assert_eq!(config.workers, Some(2));
+        assert_eq!(config.db_path, Some(PathBuf::from("/var/lib/starthub/server.db")));
+
+        assert!(ServerConfig::from_env(|name| (name == WORKERS_ENV).then(|| "many".to_string())).is_err());
+        std::fs::write(&path, r#"{"auth_token": "from-file"}"#).unwrap();
+        assert_eq!(ServerConfig::from_file(&path).unwrap().auth_token.as_deref(), Some("from-file"));
+
+        std::fs::write(&path, r#"{"port": 4000}"#).unwrap();
+        assert!(ServerConfig::from_file(&path).unwrap_err().to_string().contains("unknown field `port`"));
+    }
+}
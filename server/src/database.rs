@@ -1,5 +1,7 @@
 use anyhow::Result;
 use rusqlite::{Connection, params};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Mutex;
 use dirs;
 use serde_json::Value;
@@ -19,8 +21,13 @@ impl Database {
         std::fs::create_dir_all(&config_dir)?;
         
         let db_path = config_dir.join("server.db");
+        Self::open(&db_path)
+    }
+
+    /// Open (or create) the database at an explicit path
+    pub fn open(db_path: &Path) -> Result<Self> {
         println!("🗄️  SQLite database path: {:?}", db_path);
-        let conn = Connection::open(&db_path)?;
+        let conn = Connection::open(db_path)?;
         let conn = Mutex::new(conn);
         
         let db = Self { conn };
@@ -70,9 +77,11 @@ impl Database {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS executions (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                run_id TEXT,
                 action_ref TEXT NOT NULL,
                 action_version_id TEXT,
                 inputs TEXT NOT NULL,
+                inputs_redacted INTEGER NOT NULL DEFAULT 0,
                 outputs TEXT,
                 status TEXT NOT NULL,
                 error_message TEXT,
@@ -97,6 +106,28 @@ impl Database {
             [],
         )?;
 
+        // Create run_steps table to checkpoint the outputs of each completed step of a run
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS run_steps (
+                run_id TEXT NOT NULL,
+                step_path TEXT NOT NULL,
+                outputs TEXT NOT NULL,
+                completed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (run_id, step_path)
+            )",
+            [],
+        )?;
+
+        // Create aliases table mapping short names to action references
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS aliases (
+                alias TEXT PRIMARY KEY,
+                target TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
         // Create indexes for better query performance
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_actions_slug ON actions(slug)",
@@ -165,14 +196,45 @@ impl Database {
             }
         }
 
+        // Migration: Add run_id column to executions if it doesn't exist
+        let table_info: Result<String, rusqlite::Error> = conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type='table' AND name='executions'",
+            [],
+            |row| row.get(0),
+        );
+
+        if let Ok(sql) = table_info {
+            if !sql.contains("run_id") {
+                conn.execute(
+                    "ALTER TABLE executions ADD COLUMN run_id TEXT",
+                    [],
+                )?;
+            }
+            // Runs recorded before are taken as storing their inputs as given
+            if !sql.contains("inputs_redacted") {
+                conn.execute(
+                    "ALTER TABLE executions ADD COLUMN inputs_redacted INTEGER NOT NULL DEFAULT 0",
+                    [],
+                )?;
+            }
+        }
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_executions_run_id ON executions(run_id)",
+            [],
+        )?;
+
         Ok(())
     }
 
-    /// Store a new execution
+    /// Store a new execution. `inputs_redacted` tells whether some of the inputs were redacted
+    /// before being stored, so that the run can't be resumed or re-run with them.
     pub fn create_execution(
         &self,
+        run_id: &str,
         action_ref: &str,
         inputs: &Value,
+        inputs_redacted: bool,
         status: &str,
         action_version_id: Option<&str>,
     ) -> Result<i64> {
@@ -181,9 +243,9 @@ impl Database {
         
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO executions (action_ref, action_version_id, inputs, status, started_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![action_ref, action_version_id, inputs_json, status, started_at],
+            "INSERT INTO executions (run_id, action_ref, action_version_id, inputs, inputs_redacted, status, started_at) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![run_id, action_ref, action_version_id, inputs_json, inputs_redacted, status, started_at],
         )?;
 
         Ok(conn.last_insert_rowid())
@@ -211,6 +273,18 @@ impl Database {
         Ok(())
     }
 
+    /// Replace the recorded inputs of an execution with their secrets redacted, marking them as such
+    pub fn redact_execution_inputs(&self, execution_id: i64, inputs: &Value) -> Result<()> {
+        let inputs_json = serde_json::to_string(inputs)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE executions SET inputs = ?1, inputs_redacted = 1 WHERE id = ?2",
+            params![inputs_json, execution_id],
+        )?;
+
+        Ok(())
+    }
+
     /// Add a log entry for an execution
     pub fn add_log(
         &self,
@@ -228,73 +302,211 @@ impl Database {
         Ok(())
     }
 
-    /// Get execution history
-    pub fn get_executions(
+    /// Get a page of the execution history, most recent first, optionally for a single action
+    pub fn list_executions(
         &self,
-        limit: Option<i32>,
+        limit: usize,
+        offset: usize,
         action_ref: Option<&str>,
     ) -> Result<Vec<ExecutionRecord>> {
-        let limit = limit.unwrap_or(100);
         let conn = self.conn.lock().unwrap();
-        
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, action_ref, inputs, outputs, status, error_message, started_at, completed_at, created_at, inputs_redacted 
+             FROM executions 
+             WHERE ?1 IS NULL OR action_ref = ?1 
+             ORDER BY started_at DESC, id DESC 
+             LIMIT ?2 OFFSET ?3"
+        )?;
+        let rows = stmt.query_map(params![action_ref, limit as i64, offset as i64], Self::map_execution_record)?;
+
         let mut executions = Vec::new();
-        
-        if let Some(ref_filter) = action_ref {
-            let mut stmt = conn.prepare(
-                "SELECT id, action_ref, inputs, outputs, status, error_message, started_at, completed_at, created_at 
-                 FROM executions 
-                 WHERE action_ref = ?1 
-                 ORDER BY started_at DESC 
-                 LIMIT ?2"
-            )?;
-            let rows = stmt.query_map(params![ref_filter, limit], |row| {
-                Ok(ExecutionRecord {
-                    id: row.get(0)?,
-                    action_ref: row.get(1)?,
-                    inputs: row.get::<_, String>(2)?.parse().unwrap_or(Value::Null),
-                    outputs: row.get::<_, Option<String>>(3)?
-                        .map(|s| s.parse().unwrap_or(Value::Null))
-                        .unwrap_or(Value::Null),
-                    status: row.get(4)?,
-                    error_message: row.get(5)?,
-                    started_at: row.get(6)?,
-                    completed_at: row.get(7)?,
-                    created_at: row.get(8)?,
-                })
-            })?;
-            
-            for row in rows {
-                executions.push(row?);
-            }
-        } else {
-            let mut stmt = conn.prepare(
-                "SELECT id, action_ref, inputs, outputs, status, error_message, started_at, completed_at, created_at 
-                 FROM executions 
-                 ORDER BY started_at DESC 
-                 LIMIT ?1"
+        for row in rows {
+            executions.push(row?);
+        }
+        Ok(executions)
+    }
+
+    /// Count the executions in the history, optionally for a single action
+    pub fn count_executions(&self, action_ref: Option<&str>) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM executions WHERE ?1 IS NULL OR action_ref = ?1",
+            params![action_ref],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Get an execution by its id
+    pub fn get_execution(&self, id: i64) -> Result<Option<ExecutionRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, action_ref, inputs, outputs, status, error_message, started_at, completed_at, created_at, inputs_redacted 
+             FROM executions 
+             WHERE id = ?1"
+        )?;
+
+        let mut rows = stmt.query_map(params![id], Self::map_execution_record)?;
+        match rows.next() {
+            Some(row) => Ok(Some(row?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the execution recorded for a run id
+    pub fn get_execution_by_run_id(&self, run_id: &str) -> Result<Option<ExecutionRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, action_ref, inputs, outputs, status, error_message, started_at, completed_at, created_at, inputs_redacted 
+             FROM executions 
+             WHERE run_id = ?1 
+             ORDER BY started_at DESC 
+             LIMIT 1"
+        )?;
+
+        let mut rows = stmt.query_map(params![run_id], Self::map_execution_record)?;
+        match rows.next() {
+            Some(row) => Ok(Some(row?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Helper function to map a row to ExecutionRecord
+    fn map_execution_record(row: &rusqlite::Row) -> rusqlite::Result<ExecutionRecord> {
+        Ok(ExecutionRecord {
+            id: row.get(0)?,
+            run_id: row.get(1)?,
+            action_ref: row.get(2)?,
+            inputs: row.get::<_, String>(3)?.parse().unwrap_or(Value::Null),
+            outputs: row.get::<_, Option<String>>(4)?
+                .map(|s| s.parse().unwrap_or(Value::Null))
+                .unwrap_or(Value::Null),
+            status: row.get(5)?,
+            error_message: row.get(6)?,
+            started_at: row.get(7)?,
+            completed_at: row.get(8)?,
+            created_at: row.get(9)?,
+            inputs_redacted: row.get(10)?,
+        })
+    }
+
+    /// Remove the finished runs started before `cutoff`, along with their logs and step checkpoints.
+    /// With `dry_run`, only count them. Returns the number of runs.
+    pub fn prune_executions(&self, cutoff: chrono::DateTime<chrono::Utc>, dry_run: bool) -> Result<usize> {
+        let cutoff = cutoff.to_rfc3339();
+        let mut conn = self.conn.lock().unwrap();
+        let filter = "started_at < ?1 AND status != 'running'";
+
+        if dry_run {
+            let count: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM executions WHERE {}", filter),
+                params![cutoff],
+                |row| row.get(0),
             )?;
-            let rows = stmt.query_map(params![limit], |row| {
-                Ok(ExecutionRecord {
-                    id: row.get(0)?,
-                    action_ref: row.get(1)?,
-                    inputs: row.get::<_, String>(2)?.parse().unwrap_or(Value::Null),
-                    outputs: row.get::<_, Option<String>>(3)?
-                        .map(|s| s.parse().unwrap_or(Value::Null))
-                        .unwrap_or(Value::Null),
-                    status: row.get(4)?,
-                    error_message: row.get(5)?,
-                    started_at: row.get(6)?,
-                    completed_at: row.get(7)?,
-                    created_at: row.get(8)?,
-                })
-            })?;
-            
-            for row in rows {
-                executions.push(row?);
-            }
+            return Ok(count as usize);
         }
 
-        Ok(executions)
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!("DELETE FROM run_steps WHERE run_id IN (SELECT run_id FROM executions WHERE {})", filter),
+            params![cutoff],
+        )?;
+        tx.execute(
+            &format!("DELETE FROM execution_logs WHERE execution_id IN (SELECT id FROM executions WHERE {})", filter),
+            params![cutoff],
+        )?;
+        let removed = tx.execute(
+            &format!("DELETE FROM executions WHERE {}", filter),
+            params![cutoff],
+        )?;
+        tx.commit()?;
+
+        Ok(removed)
+    }
+
+    /// Checkpoint the outputs of a completed step of a run
+    pub fn save_run_step(
+        &self,
+        run_id: &str,
+        step_path: &str,
+        outputs: &Vec<Value>,
+    ) -> Result<()> {
+        let outputs_json = serde_json::to_string(outputs)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO run_steps (run_id, step_path, outputs, completed_at)
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+             ON CONFLICT(run_id, step_path) DO UPDATE SET
+                outputs = excluded.outputs,
+                completed_at = excluded.completed_at",
+            params![run_id, step_path, outputs_json],
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the checkpointed step outputs of a run, keyed by step path
+    pub fn get_run_steps(&self, run_id: &str) -> Result<HashMap<String, Vec<Value>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT step_path, outputs
+             FROM run_steps
+             WHERE run_id = ?1"
+        )?;
+
+        let rows = stmt.query_map(params![run_id], |row| {
+            let step_path: String = row.get(0)?;
+            let outputs: String = row.get(1)?;
+            Ok((step_path, outputs))
+        })?;
+
+        let mut steps = HashMap::new();
+        for row in rows {
+            let (step_path, outputs) = row?;
+            let outputs: Vec<Value> = serde_json::from_str(&outputs).unwrap_or_default();
+            steps.insert(step_path, outputs);
+        }
+
+        Ok(steps)
+    }
+
+    /// Point an alias at an action reference, replacing its previous target
+    pub fn set_alias(&self, alias: &str, target: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO aliases (alias, target, created_at)
+             VALUES (?1, ?2, CURRENT_TIMESTAMP)
+             ON CONFLICT(alias) DO UPDATE SET
+                target = excluded.target,
+                created_at = excluded.created_at",
+            params![alias, target],
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the action reference an alias points at
+    pub fn get_alias(&self, alias: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT target FROM aliases WHERE alias = ?1")?;
+        let mut rows = stmt.query_map(params![alias], |row| row.get::<_, String>(0))?;
+        match rows.next() {
+            Some(row) => Ok(Some(row?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get every alias with its target, sorted by alias
+    pub fn get_aliases(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT alias, target FROM aliases ORDER BY alias")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
     }
 
     /// Get logs for a specific execution
@@ -658,6 +870,7 @@ impl Database {
 #[derive(Debug, Clone)]
 pub struct ExecutionRecord {
     pub id: i64,
+    pub run_id: Option<String>,
     pub action_ref: String,
     pub inputs: Value,
     pub outputs: Value,
@@ -666,6 +879,8 @@ pub struct ExecutionRecord {
     pub started_at: String,
     pub completed_at: Option<String>,
     pub created_at: String,
+    /// Whether some of the inputs were redacted before being stored
+    pub inputs_redacted: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -708,3 +923,95 @@ pub struct ActionWithVersion {
     pub latest_version: Option<ActionVersionRecord>,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record_run(db: &Database, run_id: &str, age: chrono::Duration, status: &str) {
+        let id = db.create_execution(run_id, "test/action:0.0.1", &json!([]), false, status, None).unwrap();
+        db.save_run_step(run_id, "step1", &vec![json!("hello")]).unwrap();
+        let started_at = (chrono::Utc::now() - age).to_rfc3339();
+        db.conn.lock().unwrap().execute(
+            "UPDATE executions SET started_at = ?1 WHERE id = ?2",
+            params![started_at, id],
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_aliases() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&temp_dir.path().join("server.db")).unwrap();
+        assert_eq!(db.get_alias("weather").unwrap(), None);
+
+        db.set_alias("weather", "starthubhq/get-weather-by-location-name:0.0.1").unwrap();
+        db.set_alias("coords", "starthubhq/get-coordinates:0.0.1").unwrap();
+        assert_eq!(db.get_alias("weather").unwrap().as_deref(), Some("starthubhq/get-weather-by-location-name:0.0.1"));
+
+        // Setting an alias again repoints it
+        db.set_alias("weather", "starthubhq/get-weather-by-location-name:0.0.2").unwrap();
+        assert_eq!(db.get_aliases().unwrap(), vec![
+            ("coords".to_string(), "starthubhq/get-coordinates:0.0.1".to_string()),
+            ("weather".to_string(), "starthubhq/get-weather-by-location-name:0.0.2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_list_executions_pages_through_the_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&temp_dir.path().join("server.db")).unwrap();
+        for (run_id, age) in [("run-a", 3), ("run-b", 2), ("run-c", 1)] {
+            record_run(&db, run_id, chrono::Duration::minutes(age), "success");
+        }
+        db.create_execution("other", "test/other:0.0.1", &json!([]), false, "running", None).unwrap();
+
+        let run_ids = |records: Vec<ExecutionRecord>| records.into_iter().map(|r| r.run_id.unwrap()).collect::<Vec<_>>();
+        assert_eq!(run_ids(db.list_executions(2, 0, None).unwrap()), vec!["other", "run-c"]);
+        assert_eq!(run_ids(db.list_executions(2, 2, None).unwrap()), vec!["run-b", "run-a"]);
+        assert!(db.list_executions(2, 4, None).unwrap().is_empty());
+        assert_eq!(run_ids(db.list_executions(10, 1, Some("test/action:0.0.1")).unwrap()), vec!["run-b", "run-a"]);
+        assert_eq!(db.count_executions(None).unwrap(), 4);
+        assert_eq!(db.count_executions(Some("test/other:0.0.1")).unwrap(), 1);
+
+        let record = db.get_execution_by_run_id("run-b").unwrap().unwrap();
+        assert_eq!(db.get_execution(record.id).unwrap().unwrap().run_id.as_deref(), Some("run-b"));
+        assert!(db.get_execution(record.id + 100).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_prune_executions_removes_only_old_finished_runs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&temp_dir.path().join("server.db")).unwrap();
+        record_run(&db, "old-run", chrono::Duration::days(10), "success");
+        record_run(&db, "old-running-run", chrono::Duration::days(10), "running");
+        record_run(&db, "recent-run", chrono::Duration::minutes(5), "error");
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(7);
+
+        // Dry runs only count
+        assert_eq!(db.prune_executions(cutoff, true).unwrap(), 1);
+        assert!(db.get_execution_by_run_id("old-run").unwrap().is_some());
+
+        assert_eq!(db.prune_executions(cutoff, false).unwrap(), 1);
+        assert!(db.get_execution_by_run_id("old-run").unwrap().is_none());
+        assert!(db.get_run_steps("old-run").unwrap().is_empty());
+        assert!(db.get_execution_by_run_id("old-running-run").unwrap().is_some());
+        assert!(db.get_execution_by_run_id("recent-run").unwrap().is_some());
+        assert_eq!(db.get_run_steps("recent-run").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_redacted_inputs_are_marked() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&temp_dir.path().join("server.db")).unwrap();
+        let plain = db.create_execution("plain", "test/action:0.0.1", &json!(["***"]), false, "running", None).unwrap();
+        let secret = db.create_execution("secret", "test/action:0.0.1", &json!(["***"]), true, "running", None).unwrap();
+        assert!(!db.get_execution(plain).unwrap().unwrap().inputs_redacted);
+        assert!(db.get_execution(secret).unwrap().unwrap().inputs_redacted);
+
+        // Redacting the inputs after the run marks them too
+        db.redact_execution_inputs(plain, &json!(["token ***"])).unwrap();
+        let record = db.get_execution_by_run_id("plain").unwrap().unwrap();
+        assert_eq!(record.inputs, json!(["token ***"]));
+        assert!(record.inputs_redacted);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::logger::forward_step_output;
 use crate::models::ShAction;
+use crate::step::StepContext;
 use anyhow::{bail, Result};
+use serde::Serialize;
 use serde_json::Value;
 use std::path::PathBuf;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -9,16 +12,60 @@ use zip::ZipArchive;
 use std::fs::File;
 use std::io::Read;
 
+/// Label set on every step container, holding the id of the step
+pub const STEP_LABEL: &str = "so.starthub.step";
+/// Label set on every step container, holding the unique name of the container
+pub const CONTAINER_LABEL: &str = "so.starthub.container";
+
+/// Error returned when the container of a Docker step exits unsuccessfully
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerStepError {
+    /// Id of the step that ran the container
+    pub step_id: String,
+    /// Image the container was started from
+    pub image: String,
+    /// Effective command that was run
+    pub command: String,
+    /// Exit code of the container, None if it was terminated by a signal
+    pub exit_code: Option<i32>,
+}
+
+impl std::fmt::Display for DockerStepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(f, "step '{}' failed: `{}` exited with code {}", self.step_id, self.command, code),
+            None => write!(f, "step '{}' failed: `{}` was terminated by a signal", self.step_id, self.command),
+        }
+    }
+}
+
+impl std::error::Error for DockerStepError {}
+
+/// Checks that the docker CLI can be found in `search_path` (PATH when None) and that its daemon is reachable,
+/// returning the reason when it isn't
+pub async fn check_docker_available(search_path: Option<&std::ffi::OsStr>) -> Result<()> {
+    let search_path = search_path.map(|path| path.to_os_string()).or_else(|| std::env::var_os("PATH"));
+    let Ok(docker) = which::which_in("docker", search_path, std::env::current_dir()?) else {
+        bail!("the docker CLI isn't installed or isn't in PATH");
+    };
+    let output = tokio::process::Command::new(docker)
+        .kill_on_drop(true)
+        .args(["info", "--format", "{{.ServerVersion}}"])
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().find(|line| !line.trim().is_empty()).unwrap_or("docker info failed");
+        bail!("the docker daemon isn't reachable: {}", reason.trim());
+    }
+    Ok(())
+}
+
 /// Executes a Docker step by running the referenced container image
 /// The container is expected to read JSON from stdin and print a JSON array on stdout
-pub async fn run_docker_step(
-    action: &ShAction,
-    inputs: &Value,
-    _cache_dir: &PathBuf,
-    log_info: &(dyn Fn(&str, Option<&str>) + Send + Sync),
-    log_success: &(dyn Fn(&str, Option<&str>) + Send + Sync),
-    log_error: &(dyn Fn(&str, Option<&str>) + Send + Sync),
-) -> Result<String> {
+pub async fn run_docker_step(action: &ShAction, inputs: &Value, context: &StepContext<'_>) -> Result<String> {
+    let StepContext { cache_dir, client, api_base, log_info, log_success, log_error, log_output } = *context;
+
     // Ensure docker is available
     if which::which("docker").is_err() {
         log_error("docker not found in PATH", Some(&action.id));
@@ -26,7 +73,7 @@ pub async fn run_docker_step(
     }
 
     // Download the Docker image artifact from registry/mirrors
-    let image_path = download_docker(&action.uses, &action.mirrors, _cache_dir).await?;
+    let image_path = download_docker(client, api_base, &action.uses, &action.mirrors, cache_dir).await?;
     log_success(&format!("Docker image downloaded: {:?}", image_path), Some(&action.id));
     
     // Verify the Docker image exists and is readable
@@ -68,9 +115,36 @@ pub async fn run_docker_step(
     
     log_info(&format!("Loaded Docker image: {}", image_name), Some(&action.id));
     
-    // Construct docker run command: docker run -i --rm <image>
+    run_docker_container(&action.id, image_name, &input_json, log_success, log_error, log_output).await
+}
+
+/// Runs a loaded Docker image, feeding it the input JSON on stdin and returning its stdout.
+/// Every line the container writes is passed to `log_output` with its stream as soon as it's written.
+/// The container is removed when it fails, or when the step is abandoned because it timed out or the
+/// run was cancelled: the engine bounds the step by dropping this future.
+pub async fn run_docker_container(
+    step_id: &str,
+    image_name: &str,
+    input_json: &str,
+    log_success: &(dyn Fn(&str, Option<&str>) + Send + Sync),
+    log_error: &(dyn Fn(&str, Option<&str>) + Send + Sync),
+    log_output: &(dyn Fn(&str, &str, Option<&str>) + Send + Sync),
+) -> Result<String> {
+    // Name and label the container so that it can be found and removed if the step doesn't complete
+    let container_name = format!("starthub-{}", uuid::Uuid::new_v4());
+    let step_label = format!("{}={}", STEP_LABEL, step_id);
+    let container_label = format!("{}={}", CONTAINER_LABEL, container_name);
+    let args = [
+        "run", "-i", "--rm",
+        "--name", container_name.as_str(),
+        "--label", step_label.as_str(),
+        "--label", container_label.as_str(),
+        image_name,
+    ];
     let mut cmd = TokioCommand::new("docker");
-    cmd.arg("run").arg("-i").arg("--rm").arg(image_name);
+    // A dropped docker client is killed, and reaped by the runtime
+    cmd.args(args).kill_on_drop(true);
+    let mut guard = ContainerGuard { label: Some(container_label.clone()) };
 
     // Spawn with piped stdio
     let mut child = cmd
@@ -78,7 +152,7 @@ pub async fn run_docker_step(
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .spawn()
-        .map_err(|e| anyhow::anyhow!("Failed to spawn docker for step {}: {}", action.id, e))?;
+        .map_err(|e| anyhow::anyhow!("Failed to spawn docker for step {}: {}", step_id, e))?;
 
     // feed stdin JSON
     if let Some(stdin) = child.stdin.as_mut() {
@@ -93,6 +167,8 @@ pub async fn run_docker_step(
     let mut err_reader = BufReader::new(stderr);
 
     let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let (lines_tx, mut lines_rx) = mpsc::unbounded_channel::<(&'static str, String)>();
+    let err_lines_tx = lines_tx.clone();
 
     let pump_out = tokio::spawn(async move {
         let mut output = String::new();
@@ -102,6 +178,7 @@ pub async fn run_docker_step(
             if !trimmed.is_empty() {
                 // Print Docker stdout to server stderr (which goes to log file)
                 eprintln!("[Docker stdout] {}", trimmed);
+                let _ = lines_tx.send(("stdout", trimmed.to_string()));
             }
             output.push_str(&line);
             line.clear();
@@ -117,21 +194,30 @@ pub async fn run_docker_step(
             if !trimmed.is_empty() {
                 // Print Docker stderr to server stderr (which goes to log file)
                 eprintln!("[Docker stderr] {}", trimmed);
+                let _ = err_lines_tx.send(("stderr", trimmed.to_string()));
             }
             line.clear();
         }
     });
 
-    let status = child.wait().await?;
+    let wait = forward_step_output(child.wait(), &mut lines_rx, step_id, log_output);
+    let status = wait.await?;
     let _ = pump_out.await;
     let _ = pump_err.await;
 
+    guard.label = None;
     if !status.success() {
-        log_error(&format!("Docker execution failed with status: {}", status), Some(&action.id));
-        bail!("step '{}' failed with {}", action.id, status);
+        remove_containers(&container_label).await;
+        log_error(&format!("Docker execution failed with status: {}", status), Some(step_id));
+        return Err(DockerStepError {
+            step_id: step_id.to_string(),
+            image: image_name.to_string(),
+            command: format!("docker {}", args.join(" ")),
+            exit_code: status.code(),
+        }.into());
     }
 
-    log_success("Docker execution completed successfully", Some(&action.id));
+    log_success("Docker execution completed successfully", Some(step_id));
 
     // Collect the result from the container
     let mut results = Vec::new();
@@ -139,8 +225,6 @@ pub async fn run_docker_step(
         results.push(v);
     }
 
-    println!("results: {:#?}", results);
-    
     // Return the first result as a string
     if results.is_empty() {
         Ok(String::new())
@@ -154,8 +238,51 @@ pub async fn run_docker_step(
     }
 }
 
+/// Removes the containers carrying the given `key=value` label, running or not
+async fn remove_containers(label: &str) {
+    let label = label.to_string();
+    let _ = tokio::task::spawn_blocking(move || remove_containers_blocking(&label)).await;
+}
+
+fn remove_containers_blocking(label: &str) {
+    let Ok(output) = std::process::Command::new("docker")
+        .args(["ps", "-aq", "--filter", &format!("label={}", label)])
+        .output() else {
+        return;
+    };
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(|id| id.to_string())
+        .collect();
+    if !ids.is_empty() {
+        let _ = std::process::Command::new("docker")
+            .args(["rm", "-f"])
+            .args(&ids)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+/// Removes the container of a step if the step is abandoned before the container exits,
+/// e.g. when its future is dropped or an I/O error is returned early
+struct ContainerGuard {
+    label: Option<String>,
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        if let Some(label) = self.label.take() {
+            // Drop can't wait on docker, so remove the container from its own thread
+            std::thread::spawn(move || remove_containers_blocking(&label));
+        }
+    }
+}
+
 /// Downloads a Docker image from the registry or mirrors
 pub async fn download_docker(
+    client: &reqwest::Client,
+    api_base: &str,
     action_ref: &str, 
     mirrors: &[String], 
     cache_dir: &PathBuf
@@ -173,25 +300,11 @@ pub async fn download_docker(
         return Ok(docker_path);
     }
     
-    let client = reqwest::Client::new();
-    
     // First try the default registry
-    let parts: Vec<&str> = action_ref.split(':').collect();
-    if parts.len() != 2 {
-        return Err(anyhow::anyhow!("Invalid action reference format: {}", action_ref));
-    }
-    
-    let (namespace_slug, version) = (parts[0], parts[1]);
-    let namespace_parts: Vec<&str> = namespace_slug.split('/').collect();
-    if namespace_parts.len() != 2 {
-        return Err(anyhow::anyhow!("Invalid namespace format: {}", namespace_slug));
-    }
-    
-    let (namespace, slug) = (namespace_parts[0], namespace_parts[1]);
-    let default_url = format!("https://api.starthub.so/storage/v1/object/public/artifacts/{}/{}/{}/artifact.zip", namespace, slug, version);
+    let default_url = crate::http::registry_artifact_url(api_base, action_ref)?;
     println!("Trying to download from default registry: {}", default_url);
     
-    match try_download_from_url(&client, &default_url, &docker_dir, &docker_path).await {
+    match try_download_from_url(client, &default_url, &docker_dir, &docker_path).await {
         Ok(path) => {
             println!("Successfully downloaded from default registry");
             return Ok(path);
@@ -207,7 +320,7 @@ pub async fn download_docker(
         let url = mirror.clone();
         println!("Trying to download from mirror: {}", url);
         
-        match try_download_from_url(&client, &url, &docker_dir, &docker_path).await {
+        match try_download_from_url(client, &url, &docker_dir, &docker_path).await {
             Ok(path) => {
                 println!("Successfully downloaded from mirror: {}", url);
                 return Ok(path);
@@ -1,29 +1,691 @@
 use anyhow::Result;
 use jsonschema::JSONSchema;
+use serde::Serialize;
 use serde_json::{Value, json};
 use std::collections::HashMap;
+use std::sync::Arc;
 use dirs;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
 
-use crate::models::{ShManifest, ShKind, ShIO, ShAction, ShRole};
+use crate::models::{ShManifest, ShKind, ShIO, ShAction, ShRetry, ShRole};
 use crate::{docker, wasm};
-use crate::logger::{Logger};
+use crate::logger::{Logger, RunLogs};
+use crate::database::Database;
+use crate::http;
+use crate::secrets;
+use crate::lockfile::Lockfile;
+use crate::step::StepContext;
 
 // Constants
-const STARTHUB_API_BASE_URL: &str = "https://api.starthub.so";
-const STARTHUB_STORAGE_PATH: &str = "/storage/v1/object/public/artifacts";
+/// API base URL of the Starthub registry, which manifests and artifacts are downloaded from
+pub const STARTHUB_API_BASE_URL: &str = "https://api.starthub.so";
+/// Environment variable overriding the API base URL, e.g. for a staging or self-hosted registry
+pub const API_BASE_ENV: &str = "STARTHUB_API_BASE";
 const STARTHUB_MANIFEST_FILENAME: &str = "starthub-lock.json";
+/// Path of a manifest on the registry, relative to the API base URL
+pub const DEFAULT_MANIFEST_PATH_TEMPLATE: &str = "/storage/v1/object/public/artifacts/{namespace}/{slug}/{version}/starthub-lock.json";
+/// Environment variable overriding the manifest path template
+pub const MANIFEST_PATH_TEMPLATE_ENV: &str = "STARTHUB_MANIFEST_PATH_TEMPLATE";
+// File of a recording directory holding the manifests fetched during a run, keyed by action reference
+const MANIFEST_RECORDING_FILENAME: &str = "manifests.json";
+/// Environment variable overriding the directory where artifacts are cached
+pub const CACHE_DIR_ENV: &str = "STARTHUB_CACHE_DIR";
+/// Environment variable holding the comma-separated namespaces actions may be run from
+pub const ALLOWED_NAMESPACES_ENV: &str = "STARTHUB_ALLOWED_NAMESPACES";
+/// Environment variable listing the directories, separated by commas, that local manifests may be read from
+pub const ALLOWED_ROOTS_ENV: &str = "STARTHUB_ALLOWED_ROOTS";
+/// Environment variable listing the URLs, separated by commas, that step outputs may be posted to.
+/// A URL also allows the URLs under its path.
+pub const ALLOWED_WEBHOOKS_ENV: &str = "STARTHUB_ALLOWED_WEBHOOKS";
+/// Kind of the steps left unexpanded by a depth-limited build: their manifest wasn't fetched
+pub const UNEXPANDED_KIND: &str = "unexpanded";
+/// Environment variable holding the timeout, in seconds, of the steps that don't declare one
+pub const STEP_TIMEOUT_ENV: &str = "STARTHUB_STEP_TIMEOUT";
+/// Levels of nested actions a tree may have by default
+pub const DEFAULT_MAX_TREE_DEPTH: usize = 64;
+/// Key of the inputs given as `{"$file": "/path"}`, which the CLI replaces by the content of the file
+pub const FILE_INPUT_KEY: &str = "$file";
+/// Types of inputs and outputs that don't need to be declared in the `types` of a manifest
+const PRIMITIVE_TYPES: [&str; 9] = ["string", "bool", "boolean", "number", "integer", "object", "array", "id", "secret"];
+/// Escapes producing literal `{{` and `}}` in interpolated strings
+const ESCAPED_OPEN_BRACES: &str = r"\{\{";
+const ESCAPED_CLOSE_BRACES: &str = r"\}\}";
+/// Token standing for the current element in the input templates of a `foreach` step
+const ITEM_TOKEN: &str = "{{item}}";
+// Stand-ins for escaped braces while a template is interpolated, from the private use area
+const OPEN_BRACES_PLACEHOLDER: &str = "\u{F8F0}";
+const CLOSE_BRACES_PLACEHOLDER: &str = "\u{F8F1}";
+// Stand in for the `{{secret.NAME}}` templates of a string while its other templates are interpolated
+const SECRET_PLACEHOLDER_START: &str = "\u{F8F2}";
+const SECRET_PLACEHOLDER_END: &str = "\u{F8F3}";
+// Stands in for `{{item}}` while the templates of a `foreach` instance are interpolated
+const ITEM_PLACEHOLDER: &str = "\u{F8F4}";
+// Stands in for the `{{inputs}}` of a string while its other templates are interpolated
+const INPUTS_PLACEHOLDER: &str = "\u{F8F5}";
+
+/// What a run does when one of its steps fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Abort the run at the first failure
+    #[default]
+    FailFast,
+    /// Keep running the independent steps, and report every failure at the end
+    KeepGoing,
+}
+
+impl std::str::FromStr for FailurePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim() {
+            "fail-fast" => Ok(Self::FailFast),
+            "keep-going" => Ok(Self::KeepGoing),
+            other => Err(anyhow::anyhow!("Unknown failure policy '{}', expected 'fail-fast' or 'keep-going'", other)),
+        }
+    }
+}
+
+/// How the output values of a run are returned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputShape {
+    /// An array of the values, in declaration order
+    #[default]
+    Array,
+    /// An object of the values, keyed by output name
+    Object,
+}
+
+impl std::str::FromStr for OutputShape {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim() {
+            "array" => Ok(Self::Array),
+            "object" => Ok(Self::Object),
+            other => Err(anyhow::anyhow!("Unknown output shape '{}', expected 'array' or 'object'", other)),
+        }
+    }
+}
+
+/// Options applied to the next execution of the engine
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    /// Id of the run, used to checkpoint the outputs of completed steps
+    pub run_id: Option<String>,
+    /// Outputs of the steps completed by a previous attempt of the run, keyed by step path.
+    /// Steps found here are not executed again.
+    pub checkpoints: HashMap<String, Vec<Value>>,
+    /// Directory where the state of the action tree is dumped after every executed step
+    pub trace_dir: Option<std::path::PathBuf>,
+    /// What to do when a step fails
+    pub on_failure: FailurePolicy,
+    /// Fail the run when a provided root input is not referenced by any step or output
+    pub strict_inputs: bool,
+    /// Report how every root input was cast to its declared type
+    pub explain_inputs: bool,
+    /// Directory where every fetched manifest is recorded
+    pub record_dir: Option<std::path::PathBuf>,
+    /// Directory of a previous recording that manifests are served from instead of being fetched
+    pub replay_dir: Option<std::path::PathBuf>,
+    /// Output values substituted for the execution of steps, keyed by step path.
+    /// They are still cast to the declared output types.
+    pub mocks: HashMap<String, Vec<Value>>,
+    /// Local manifests of actions under development, keyed by `namespace/slug:version` or
+    /// `namespace/slug`. They are used instead of the registry.
+    pub workspace: HashMap<String, std::path::PathBuf>,
+    /// Serve registry manifests from the cache only, without network access
+    pub offline: bool,
+    /// Whether the output values are returned as an array or an object
+    pub output_shape: OutputShape,
+    /// URL every completed step's outputs are posted to, as `{run_id, step, output}`
+    pub step_output_webhook: Option<String>,
+    /// Versions and digests every fetched manifest must match
+    pub lockfile: Option<Lockfile>,
+    /// Stops the run before its next step, or before the next attempt of a step, once cancelled
+    pub cancellation: CancellationToken,
+}
+
+/// Manifests pre-fetched by a cache warm-up, by action reference
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheWarmReport {
+    /// Manifests downloaded from the registry
+    pub fetched: Vec<String>,
+    /// Manifests that were already cached
+    pub cached: Vec<String>,
+}
+
+/// Reads a workspace mapping file: a JSON object from action references to the directory of their
+/// project, or to their manifest. Relative paths are relative to the mapping file.
+pub fn read_workspace(path: &std::path::Path) -> Result<HashMap<String, std::path::PathBuf>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read workspace {:?}: {}", path, e))?;
+    let mapping: HashMap<String, String> = serde_json::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("Invalid workspace {:?}, expected an object of action references to paths: {}", path, e))?;
+    let base_dir = path.parent().unwrap_or(std::path::Path::new("."));
+    Ok(mapping.into_iter()
+        .map(|(action_ref, local_path)| {
+            let local_path = base_dir.join(local_path);
+            let manifest_path = if local_path.is_dir() {
+                local_path.join(STARTHUB_MANIFEST_FILENAME)
+            } else {
+                local_path
+            };
+            (action_ref, manifest_path)
+        })
+        .collect())
+}
+
+/// A step that failed during a run
+#[derive(Debug, Clone, Serialize)]
+pub struct StepFailure {
+    pub step_path: String,
+    pub message: String,
+}
+
+/// Error returned by a keep-going run in which one or more steps failed
+#[derive(Debug, Clone, Serialize)]
+pub struct StepFailures {
+    pub failures: Vec<StepFailure>,
+}
+
+impl std::fmt::Display for StepFailures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} step(s) failed:", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n  - {}: {}", failure.step_path, failure.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StepFailures {}
+
+/// Error returned when a wasm or docker step runs past its timeout
+#[derive(Debug, Clone, Serialize)]
+pub struct StepTimeout {
+    /// Path of the step in the root action tree
+    pub step_path: String,
+    /// Id of the action run by the step
+    pub step_id: String,
+    /// Reference of the action run by the step
+    pub uses: String,
+    pub timeout_secs: f64,
+    /// How long the step ran before it was abandoned
+    pub elapsed_secs: f64,
+}
+
+impl std::fmt::Display for StepTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let step = if self.step_path.is_empty() { &self.uses } else { &self.step_path };
+        write!(f, "step '{}' timed out after {:.1}s (timeout {}s)", step, self.elapsed_secs, self.timeout_secs)
+    }
+}
+
+impl std::error::Error for StepTimeout {}
+
+/// Error returned when a run is cancelled: it stops before its next step and reports the steps
+/// that settled until then, with their outputs
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionCancelled {
+    /// Path of the step the run stopped before, empty when it stopped elsewhere
+    pub step_path: String,
+    pub steps: Vec<StepResult>,
+}
+
+impl std::fmt::Display for ExecutionCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.step_path.is_empty() {
+            write!(f, "execution cancelled after {} step(s)", self.steps.len())
+        } else {
+            write!(f, "execution cancelled before step '{}', after {} step(s)", self.step_path, self.steps.len())
+        }
+    }
+}
+
+impl std::error::Error for ExecutionCancelled {}
+
+/// An output of a run, with its declared name and type
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub value: Value,
+}
+
+/// How a root input was cast to its declared type
+#[derive(Debug, Clone, Serialize)]
+pub struct InputCoercion {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    /// Value as provided
+    pub raw: Value,
+    /// Value after casting, as seen by the steps
+    pub cast: Value,
+}
+
+/// Time spent in each phase of a run, in milliseconds
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PhaseTimings {
+    /// Fetching manifests and building the action tree
+    pub build_ms: f64,
+    /// Executing the steps of the action tree
+    pub execute_ms: f64,
+}
+
+/// How a step of a run settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The step ran and succeeded
+    Completed,
+    /// The outputs of the step came from the checkpoint of a previous attempt of the run
+    Restored,
+    /// The outputs of the step were mocked
+    Mocked,
+    /// The step didn't run: its condition didn't hold, it was optional and waited on a step that
+    /// failed or was skipped, or its composition's outputs didn't need it
+    Skipped,
+    /// The step failed, and the run kept going with the other steps
+    Failed,
+}
+
+/// A step of a run, as it settled
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    /// Path of the step within the action tree (e.g. "deploy.create_vm")
+    pub step_path: String,
+    /// Id of the action of the step
+    pub step_id: String,
+    pub uses: String,
+    pub status: StepStatus,
+    /// Output values of the step, in declaration order. Null when it was skipped or failed.
+    pub outputs: Vec<Value>,
+}
+
+/// Result of a run of an action
+#[derive(Debug, Clone, Serialize)]
+pub struct RunOutcome {
+    /// Reference of the action that was run
+    pub action: String,
+    /// Id of the root action of the tree that was run
+    pub root_id: String,
+    /// Steps of the whole tree, nested ones included, in the order they settled
+    pub steps: Vec<StepResult>,
+    /// Outputs of the root action, in declaration order
+    pub outputs: Vec<OutputEntry>,
+    /// Casting of the root inputs, when the run was asked to explain them
+    pub input_report: Vec<InputCoercion>,
+    /// Split between fetching and executing
+    pub timings: PhaseTimings,
+}
+
+impl RunOutcome {
+    /// The bare output values, in declaration order
+    pub fn output_values(&self) -> Vec<Value> {
+        self.outputs.iter().map(|output| output.value.clone()).collect()
+    }
+
+    /// The output values in the given shape. An object can't be built when two outputs share a name.
+    pub fn result(&self, shape: OutputShape) -> Result<Value> {
+        match shape {
+            OutputShape::Array => Ok(Value::Array(self.output_values())),
+            OutputShape::Object => {
+                let mut result = serde_json::Map::new();
+                for output in &self.outputs {
+                    if result.insert(output.name.clone(), output.value.clone()).is_some() {
+                        anyhow::bail!(
+                            "Outputs of {} can't be returned as an object: the name '{}' is declared more than once",
+                            self.action, output.name
+                        );
+                    }
+                }
+                Ok(Value::Object(result))
+            }
+        }
+    }
+}
+
+/// Result of running a single wasm or docker action in isolation
+#[derive(Debug, Clone, Serialize)]
+pub struct LeafRun {
+    /// Reference of the action that was run
+    pub action: String,
+    /// Output of the artifact, as printed on stdout
+    pub raw_output: String,
+    /// The raw output parsed as JSON
+    pub parsed: Value,
+    /// Outputs cast to their declared types, in declaration order
+    pub outputs: Vec<OutputEntry>,
+}
+
+/// A wasm or docker step whose artifact can't be fetched from the registry nor from any mirror
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingArtifact {
+    pub step_path: String,
+    pub uses: String,
+    pub kind: String,
+    /// Each URL that was tried, with the reason it failed
+    pub tried: Vec<String>,
+}
+
+/// An action of a planned tree. The root action has an empty path.
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub path: String,
+    pub name: String,
+    pub kind: String,
+    /// Reference as written in the parent manifest
+    pub uses: String,
+    /// Concrete reference it resolved to
+    pub resolved_uses: String,
+}
+
+/// A data dependency between two sibling steps: `to` consumes an output of `from`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Flattened view of an action tree, in execution priority order
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ActionGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// A step of a dry run, listed in the order it would be executed in
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedStep {
+    pub step_path: String,
+    pub kind: String,
+    pub uses: String,
+    pub priority: i32,
+    /// Whether the step can start as soon as its parent does
+    pub ready: bool,
+    /// Why a step that isn't ready can't start yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_by: Option<String>,
+    pub inputs: Vec<PlannedInput>,
+}
+
+/// An input of a planned step: its value when the step is ready, its template otherwise
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedInput {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub resolved: bool,
+    pub value: Value,
+}
+
+/// A root input that failed validation
+#[derive(Debug, Clone, Serialize)]
+pub struct InputValidationError {
+    /// Position of the input in the action's inputs
+    pub index: usize,
+    /// Declared name of the input
+    pub name: String,
+    pub message: String,
+}
+
 pub struct ExecutionEngine {
     cache_dir: std::path::PathBuf,
     logger: Logger,
+    database: Option<Arc<Mutex<Database>>>,
+    options: ExecutionOptions,
+    trace_iteration: usize,
+    user_agent: String,
+    proxy: Option<String>,
+    http_client: reqwest::Client,
+    mirror_strategy: http::MirrorStrategy,
+    wasm_opt_level: wasm::WasmOptLevel,
+    // Extra headers sent with manifest requests, e.g. to authenticate against a private registry
+    manifest_headers: reqwest::header::HeaderMap,
+    // Manifests downloaded by this engine, keyed by URL
+    manifest_cache: std::sync::Mutex<HashMap<String, ShManifest>>,
+    // Compiled schemas of the custom types, keyed by their JSON schema
+    schema_cache: std::sync::Mutex<HashMap<String, Arc<JSONSchema>>>,
+    // Number of schemas compiled so far, i.e. misses of the schema cache
+    schema_compilations: std::sync::atomic::AtomicUsize,
+    // Namespaces actions may be fetched from, any when unset
+    allowed_namespaces: Option<Vec<String>>,
+    // Directories local manifests may be read from, any when unset
+    allowed_roots: Option<Vec<std::path::PathBuf>>,
+    // Path of a manifest on the registry, with {namespace}, {slug} and {version} placeholders
+    manifest_path_template: String,
+    // Registry the manifests and artifacts are downloaded from
+    registry_base_url: String,
+    // Result of the docker check of the current run, made before its first docker step
+    docker_available: Option<Result<(), String>>,
+    // Where {{secret.NAME}} templates are resolved from
+    secrets: Box<dyn secrets::SecretsProvider>,
+    // Timeout of the wasm and docker steps that don't declare their own
+    default_step_timeout: Option<std::time::Duration>,
+    // Levels of nested actions below the root beyond which a build fails
+    max_tree_depth: usize,
+    // Steps settled so far by the current run
+    step_results: Vec<StepResult>,
+    // Acquired before every step the engine executes, so that tests can hold a step while it runs
+    #[cfg(test)]
+    step_gate: Option<Arc<tokio::sync::Semaphore>>,
+}
+
+/// Whether a reference may be an alias: a short name without namespace nor version, such as `weather`
+pub fn is_alias(action_ref: &str) -> bool {
+    !action_ref.contains('/') && !action_ref.contains(':') && !action_ref.contains('@')
+}
+
+/// Checks that an alias is a plain name and that its target is a full action reference
+pub fn check_alias(alias: &str, target: &str) -> Result<()> {
+    if alias.is_empty() || !is_alias(alias) || alias.chars().any(char::is_whitespace) {
+        anyhow::bail!("Invalid alias '{}': it must be a plain name, without namespace, version or spaces", alias);
+    }
+    if !target.contains('/') {
+        anyhow::bail!("Invalid target '{}' for alias '{}': expected a namespace/slug[:version] reference or a file:// manifest", target, alias);
+    }
+    Ok(())
+}
+
+/// Path of an input given as a file reference, such as `{"$file": "/data/payload.bin"}`
+pub fn file_input_path(value: &Value) -> Option<&str> {
+    match value.as_object() {
+        Some(object) if object.len() == 1 => object.get(FILE_INPUT_KEY)?.as_str(),
+        _ => None,
+    }
+}
+
+/// Rejects an input given as a file reference. The CLI reads those files and sends their content,
+/// so the server never reads a path taken from a request.
+pub fn reject_file_input(value: &Value) -> Result<()> {
+    match file_input_path(value) {
+        Some(path) => Err(anyhow::anyhow!("`{}` references such as {} are only expanded by the CLI; send the content of the file instead", FILE_INPUT_KEY, path)),
+        None => Ok(()),
+    }
+}
+
+/// Rejects the inputs of a run given as file references, see `reject_file_input`
+pub fn reject_file_inputs(values: &[Value]) -> Result<()> {
+    values.iter()
+        .enumerate()
+        .try_for_each(|(index, value)| reject_file_input(value).map_err(|e| anyhow::anyhow!("Input #{}: {}", index, e)))
+}
+
+/// JSON Schema of a primitive input type, or None for a custom type
+fn primitive_json_schema(r#type: &str) -> Option<Value> {
+    let json_type = match r#type {
+        "string" | "id" => "string",
+        // Written by the caller, never shown back
+        "secret" => return Some(json!({ "type": "string", "writeOnly": true })),
+        "bool" | "boolean" => "boolean",
+        "number" => "number",
+        "integer" => "integer",
+        "object" => "object",
+        "array" => "array",
+        _ => return None,
+    };
+    Some(json!({ "type": json_type }))
+}
+
+/// The integer a float holds, when it has no fractional part and fits an i64 or a u64
+fn integral_number(n: f64) -> Option<serde_json::Number> {
+    if !n.is_finite() || n.fract() != 0.0 {
+        None
+    } else if n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Some(serde_json::Number::from(n as i64))
+    } else if n >= 0.0 && n < u64::MAX as f64 {
+        Some(serde_json::Number::from(n as u64))
+    } else {
+        None
+    }
+}
+
+/// Casts a value to a `number` or an `integer`. Whole numbers stay integers, so that `"8080"`
+/// becomes `8080` rather than `8080.0`; an `integer` rejects the others.
+fn cast_number(value: &Value, target_type: &str) -> Result<Value> {
+    let number = match value {
+        Value::String(s) => s.parse::<i64>().map(serde_json::Number::from)
+            .or_else(|_| s.parse::<u64>().map(serde_json::Number::from))
+            .ok()
+            .or_else(|| s.parse::<f64>().ok().and_then(|n| integral_number(n).or_else(|| serde_json::Number::from_f64(n))))
+            .ok_or_else(|| anyhow::anyhow!("Cannot convert string '{}' to {}", s, target_type))?,
+        Value::Number(n) => n.as_f64()
+            .filter(|_| n.is_f64())
+            .and_then(integral_number)
+            .unwrap_or_else(|| n.clone()),
+        _ => return Err(anyhow::anyhow!("Cannot convert {:?} to {}", value, target_type)),
+    };
+    if target_type == "integer" && number.is_f64() {
+        let reason = match number.as_f64() {
+            Some(n) if n.fract() == 0.0 => "it is out of the range of 64-bit integers",
+            _ => "it has a fractional part",
+        };
+        return Err(anyhow::anyhow!("Cannot convert {} to integer: {}", value, reason));
+    }
+    Ok(Value::Number(number))
+}
+
+/// Parses a step timeout given in whole seconds, where 0 means no timeout
+pub fn parse_step_timeout(value: &str) -> Result<Option<std::time::Duration>> {
+    let secs = value.trim().parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("expected a whole number of seconds, got '{}'", value))?;
+    Ok(Some(std::time::Duration::from_secs(secs)).filter(|timeout| !timeout.is_zero()))
+}
+
+/// Timeout of the steps that don't declare one, taken from the environment. None when unset; an
+/// invalid value is an error rather than a run without any timeout.
+pub fn default_step_timeout() -> Result<Option<std::time::Duration>> {
+    match std::env::var(STEP_TIMEOUT_ENV) {
+        Ok(value) => parse_step_timeout(&value).map_err(|e| anyhow::anyhow!("Invalid {}: {}", STEP_TIMEOUT_ENV, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Namespaces allowed by the environment, if it restricts them
+pub fn allowed_namespaces() -> Option<Vec<String>> {
+    let namespaces: Vec<String> = std::env::var(ALLOWED_NAMESPACES_ENV).ok()?
+        .split(',')
+        .map(|namespace| namespace.trim().to_string())
+        .filter(|namespace| !namespace.is_empty())
+        .collect();
+    Some(namespaces)
+}
+
+/// Directories allowed by the environment for local manifests, if it restricts them
+pub fn allowed_roots() -> Option<Vec<std::path::PathBuf>> {
+    let roots: Vec<std::path::PathBuf> = std::env::var(ALLOWED_ROOTS_ENV).ok()?
+        .split(',')
+        .map(|root| root.trim())
+        .filter(|root| !root.is_empty())
+        .map(std::path::PathBuf::from)
+        .collect();
+    Some(roots)
+}
+
+/// URLs allowed by the environment for step output webhooks. Without it, no webhook is allowed.
+pub fn allowed_webhooks() -> Vec<String> {
+    std::env::var(ALLOWED_WEBHOOKS_ENV).unwrap_or_default()
+        .split(',')
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+/// Whether a webhook URL has the scheme, host and port of one of the allowed URLs, and a path under
+/// its path. The URLs are compared parsed, so that e.g. `https://hooks.example.com.evil.net` isn't
+/// allowed by `https://hooks.example.com`.
+pub fn webhook_allowed(url: &str, allowed: &[String]) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    allowed.iter()
+        .filter_map(|allowed| reqwest::Url::parse(allowed).ok())
+        .any(|allowed| {
+            let prefix = allowed.path().trim_end_matches('/');
+            url.scheme() == allowed.scheme()
+                && url.host_str() == allowed.host_str()
+                && url.port_or_known_default() == allowed.port_or_known_default()
+                && url.username().is_empty()
+                && url.path().strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+}
+
+/// Resolves a local path, failing unless it is absolute and within one of the allowed roots. Symbolic
+/// links are resolved and `..` is rejected, so neither leads out of a root; a path that doesn't exist
+/// yet is checked through its closest existing ancestor. `None` allows every path.
+pub fn confine_path(path: &std::path::Path, roots: Option<&[std::path::PathBuf]>) -> Result<std::path::PathBuf> {
+    let Some(roots) = roots else {
+        return Ok(path.to_path_buf());
+    };
+    if !path.is_absolute() || path.components().any(|component| component == std::path::Component::ParentDir) {
+        anyhow::bail!("Local path {} must be absolute, without '..'", path.display());
+    }
+    let existing = path.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(path);
+    let mut resolved = existing.canonicalize()
+        .map_err(|e| anyhow::anyhow!("Failed to resolve {}: {}", path.display(), e))?;
+    if let Some(missing) = path.strip_prefix(existing).ok().filter(|missing| !missing.as_os_str().is_empty()) {
+        resolved.push(missing);
+    }
+    let allowed = roots.iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if !allowed {
+        anyhow::bail!(
+            "Local path {} is outside of the directories the server may access ({}); allow it with --allowed-root or {}",
+            path.display(),
+            roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(", "),
+            ALLOWED_ROOTS_ENV
+        );
+    }
+    Ok(resolved)
+}
+
+/// Checks that an API base URL is an http(s) URL, and drops its trailing slash
+pub fn parse_api_base(api_base: &str) -> Result<String> {
+    let api_base = api_base.trim().trim_end_matches('/');
+    let url = url::Url::parse(api_base)
+        .map_err(|e| anyhow::anyhow!("Invalid API base URL '{}': {}", api_base, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        anyhow::bail!("Invalid API base URL '{}': it must be an http or https URL", api_base);
+    }
+    Ok(api_base.to_string())
 }
 
 impl ExecutionEngine {
     pub fn new() -> Self {
-        let cache_dir = dirs::cache_dir()
-            .unwrap_or(std::env::temp_dir())
-            .join("starthub/oci");
-        
+        let cache_dir = std::env::var_os(CACHE_DIR_ENV)
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| dirs::cache_dir()
+                .unwrap_or(std::env::temp_dir())
+                .join("starthub/oci"));
+        Self::with_cache_dir(cache_dir)
+    }
+
+    /// Creates an engine caching downloaded artifacts in the given directory
+    pub fn with_cache_dir(cache_dir: std::path::PathBuf) -> Self {
         // Ensure the cache directory exists
         if let Err(e) = std::fs::create_dir_all(&cache_dir) {
             eprintln!("Warning: Failed to create cache directory {:?}: {}", cache_dir, e);
@@ -32,25 +694,302 @@ impl ExecutionEngine {
         // Create WebSocket sender internally
         let (ws_sender, _) = broadcast::channel(100);
         
+        let user_agent = http::user_agent();
+        let proxy = http::proxy();
+        let http_client = http::build_client(&user_agent, proxy.as_deref())
+            .or_else(|e| {
+                eprintln!("Warning: {}, connecting directly", e);
+                http::build_client(&user_agent, None)
+            })
+            .unwrap_or_default();
+        
         Self {
             cache_dir,
             logger: Logger::new_with_ws_sender(Some(ws_sender)),
+            database: None,
+            options: ExecutionOptions::default(),
+            trace_iteration: 0,
+            user_agent,
+            proxy,
+            http_client,
+            mirror_strategy: http::mirror_strategy(),
+            // The server refuses to start with an invalid level
+            wasm_opt_level: wasm::opt_level().unwrap_or_default(),
+            manifest_headers: reqwest::header::HeaderMap::new(),
+            manifest_cache: std::sync::Mutex::new(HashMap::new()),
+            schema_cache: std::sync::Mutex::new(HashMap::new()),
+            schema_compilations: std::sync::atomic::AtomicUsize::new(0),
+            allowed_namespaces: allowed_namespaces(),
+            allowed_roots: allowed_roots(),
+            manifest_path_template: std::env::var(MANIFEST_PATH_TEMPLATE_ENV)
+                .unwrap_or_else(|_| DEFAULT_MANIFEST_PATH_TEMPLATE.to_string()),
+            registry_base_url: STARTHUB_API_BASE_URL.to_string(),
+            docker_available: None,
+            secrets: Box::new(secrets::EnvSecretsProvider),
+            // The server refuses to start with an invalid value
+            default_step_timeout: default_step_timeout().unwrap_or_default(),
+            max_tree_depth: DEFAULT_MAX_TREE_DEPTH,
+            step_results: Vec::new(),
+            #[cfg(test)]
+            step_gate: None,
         }
     }
 
+    /// Directory where downloaded artifacts are cached
+    pub fn cache_dir(&self) -> &std::path::Path {
+        &self.cache_dir
+    }
+
     /// Get the WebSocket sender for external use
     pub fn get_ws_sender(&self) -> Option<broadcast::Sender<String>> {
         self.logger.get_ws_sender()
     }
 
+    /// Set the channel the execution logs are broadcast on, to share it between engines
+    pub fn set_ws_sender(&mut self, sender: broadcast::Sender<String>) {
+        self.logger.set_ws_sender(sender);
+    }
+
+    /// Also capture the execution logs to the in-memory buffer of the given run
+    pub fn set_run_logs(&mut self, run_logs: RunLogs, run_id: &str) {
+        self.logger.set_run_logs(run_logs, run_id);
+    }
+
+    /// Set the timeout of the wasm and docker steps that don't declare their own, None to let them run forever
+    pub fn set_default_step_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.default_step_timeout = timeout;
+    }
+
+    /// Set how many levels of nested actions a tree may have below its root
+    pub fn set_max_tree_depth(&mut self, depth: usize) {
+        self.max_tree_depth = depth;
+    }
+
+    /// Set where `{{secret.NAME}}` templates are resolved from
+    pub fn set_secrets_provider(&mut self, provider: Box<dyn secrets::SecretsProvider>) {
+        self.secrets = provider;
+    }
+
+    /// Set the database used to checkpoint the outputs of completed steps
+    pub fn set_database(&mut self, database: Arc<Mutex<Database>>) {
+        self.database = Some(database);
+    }
+
+    /// Set the User-Agent of the requests made to fetch manifests and artifacts
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.user_agent = user_agent.to_string();
+        if let Ok(client) = http::build_client(&self.user_agent, self.proxy.as_deref()) {
+            self.http_client = client;
+        }
+    }
+
+    /// Route the requests made to fetch manifests and artifacts through the given proxy
+    pub fn set_proxy(&mut self, proxy: &str) -> Result<()> {
+        self.http_client = http::build_client(&self.user_agent, Some(proxy))?;
+        self.proxy = Some(proxy.to_string());
+        Ok(())
+    }
+
+    /// Send a header with every manifest request
+    pub fn set_manifest_header(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Invalid header name '{}'", name))?;
+        let mut value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| anyhow::anyhow!("Invalid value for header '{}'", name))?;
+        // Keeps credentials out of debug output
+        value.set_sensitive(name == reqwest::header::AUTHORIZATION);
+        self.manifest_headers.insert(name, value);
+        Ok(())
+    }
+
+    /// Authenticate manifest requests with a bearer token
+    pub fn set_auth_token(&mut self, token: &str) -> Result<()> {
+        self.set_manifest_header(reqwest::header::AUTHORIZATION.as_str(), &format!("Bearer {}", token))
+    }
+
+    /// The value with the secrets of the runs of this engine replaced by `***`, for whatever is shown or
+    /// stored outside of the steps: inputs of type `secret` and resolved `{{secret.NAME}}` templates
+    pub fn redact(&self, value: &Value) -> Value {
+        self.logger.redact_value(value)
+    }
+
+    /// The inputs of a run as they may be stored before it starts: the inputs the action declares of
+    /// type `secret` are replaced by `***`. Which inputs are secrets isn't known when the manifest
+    /// can't be fetched, so then they all are.
+    pub async fn redact_secret_inputs(&self, action_ref: &str, inputs: &[Value]) -> Vec<Value> {
+        let declared = match self.fetch_manifest(action_ref).await {
+            Ok(manifest) => Self::parse_manifest_io(action_ref, "inputs", &manifest.inputs),
+            Err(e) => Err(e),
+        };
+        let Ok(declared) = declared else {
+            return inputs.iter().map(|_| json!("***")).collect();
+        };
+        inputs.iter().enumerate()
+            .map(|(index, value)| match declared.get(index) {
+                Some(io) if io.r#type == "secret" => json!("***"),
+                _ => value.clone(),
+            })
+            .collect()
+    }
+
+    /// Set the order in which the mirrors of an artifact are tried
+    pub fn set_mirror_strategy(&mut self, strategy: http::MirrorStrategy) {
+        self.mirror_strategy = strategy;
+    }
+
+    /// Set the path manifests are fetched from on the registry. `{namespace}`, `{slug}` and `{version}`
+    /// are replaced with the parts of the action reference.
+    pub fn set_manifest_path_template(&mut self, template: &str) -> Result<()> {
+        if !template.contains("{namespace}") || !template.contains("{slug}") {
+            anyhow::bail!("Invalid manifest path template '{}': it must contain {{namespace}} and {{slug}}", template);
+        }
+        self.manifest_path_template = template.to_string();
+        Ok(())
+    }
+
+    /// Set the API base URL of the registry manifests and artifacts are downloaded from
+    pub fn set_api_base(&mut self, api_base: &str) -> Result<()> {
+        self.registry_base_url = parse_api_base(api_base)?;
+        Ok(())
+    }
+
+    /// API base URL of the registry manifests and artifacts are downloaded from
+    fn api_base(&self) -> &str {
+        &self.registry_base_url
+    }
+
+    /// Restrict the namespaces actions may be fetched from. `None` allows every namespace.
+    pub fn set_allowed_namespaces(&mut self, namespaces: Option<Vec<String>>) {
+        self.allowed_namespaces = namespaces;
+    }
+
+    /// Restrict the directories local manifests, given as `file://` references or mapped by a
+    /// workspace, may be read from. `None` allows every directory.
+    pub fn set_allowed_roots(&mut self, roots: Option<Vec<std::path::PathBuf>>) {
+        self.allowed_roots = roots;
+    }
+
+    /// Set the optimization level wasm modules are compiled with
+    pub fn set_wasm_opt_level(&mut self, opt_level: wasm::WasmOptLevel) {
+        self.wasm_opt_level = opt_level;
+    }
+
+    /// Set the options applied to the next execution
+    pub fn set_options(&mut self, options: ExecutionOptions) {
+        self.options = options;
+        self.trace_iteration = 0;
+    }
+
     fn push_to_execution_buffer(&self, buffer: &mut Vec<String>, step_id: String) {
         if !buffer.contains(&step_id) {
             buffer.push(step_id);
         }
     }
 
+    /// Runs an action and returns its bare output values, as an array unless another shape was asked for
     pub async fn execute_action(&mut self, action_ref: &str, input_values: Vec<Value>) -> Result<Value> {
+        let outcome = self.execute_action_detailed(action_ref, input_values).await?;
+        outcome.result(self.options.output_shape)
+    }
+
+    /// Checks the given inputs against the declared input types of an action without running it.
+    /// Returns one error per invalid input, so an empty list means the inputs are valid.
+    pub async fn validate_inputs(&self, action_ref: &str, input_values: &[Value]) -> Result<Vec<InputValidationError>> {
+        let root_action = self.build_action_tree(action_ref, None).await?;
+
+        let mut errors = Vec::new();
+        for (index, io) in root_action.inputs.iter().enumerate() {
+            let message = match input_values.get(index) {
+                Some(value) => match reject_file_input(value).and_then(|_| self.cast(value, &io.r#type, &root_action.types)) {
+                    Ok(_) => continue,
+                    Err(e) => e.to_string(),
+                },
+                None if io.default.is_some() => continue,
+                None if io.required => "Missing required input".to_string(),
+                None => match &io.required_if {
+                    Some(expression) => match Self::evaluate_required_if(expression, &root_action.inputs, input_values) {
+                        Ok(true) => format!("Missing input, required because {}", expression),
+                        Ok(false) => continue,
+                        Err(e) => e.to_string(),
+                    },
+                    None => continue,
+                },
+            };
+            errors.push(InputValidationError {
+                index,
+                name: io.name.clone(),
+                message,
+            });
+        }
+
+        Ok(errors)
+    }
+
+    /// JSON Schema of the inputs of an action, without running it: one object with a property per
+    /// input. The custom types the inputs refer to are placed under `$defs`. Only the manifest of the
+    /// action itself is fetched, not those of its steps.
+    pub async fn input_schema(&self, action_ref: &str) -> Result<Value> {
+        let root_action = self.build_action_tree_to_depth(action_ref, None, Some(0), &[]).await?;
+        self.inputs_json_schema(action_ref, &root_action.inputs, &root_action.types)
+    }
+
+    fn inputs_json_schema(
+        &self,
+        action_ref: &str,
+        inputs: &[ShIO],
+        types: &Option<serde_json::Map<String, Value>>
+    ) -> Result<Value> {
+        let mut properties = serde_json::Map::new();
+        let mut required = Vec::new();
+        let mut defs = serde_json::Map::new();
+        for input in inputs {
+            let mut property = match primitive_json_schema(&input.r#type) {
+                Some(schema) => schema,
+                None => {
+                    let type_definition = types.as_ref()
+                        .and_then(|types| types.get(&input.r#type))
+                        .ok_or_else(|| anyhow::anyhow!(
+                            "Input '{}' of {} has type '{}', which is neither primitive nor declared in `types`",
+                            input.name, action_ref, input.r#type
+                        ))?;
+                    if !defs.contains_key(&input.r#type) {
+                        let schema = self.convert_to_json_schema(type_definition)
+                            .map_err(|e| anyhow::anyhow!("Failed to convert type '{}': {}", input.r#type, e))?;
+                        defs.insert(input.r#type.clone(), schema);
+                    }
+                    json!({ "$ref": format!("#/$defs/{}", input.r#type) })
+                }
+            };
+            if let (Some(default), Some(property)) = (&input.default, property.as_object_mut()) {
+                property.insert("default".to_string(), default.clone());
+            }
+            if input.required && input.default.is_none() {
+                required.push(json!(input.name));
+            }
+            properties.insert(input.name.clone(), property);
+        }
+
+        let mut schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": format!("Inputs of {}", action_ref),
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            // Runs take the values of the inputs as an array, in this order
+            "x-input-order": inputs.iter().map(|input| input.name.clone()).collect::<Vec<_>>(),
+        });
+        if !defs.is_empty() {
+            schema["$defs"] = Value::Object(defs);
+        }
+        Ok(schema)
+    }
+
+    /// Runs an action and returns its outputs along with their names and types
+    pub async fn execute_action_detailed(&mut self, action_ref: &str, input_values: Vec<Value>) -> Result<RunOutcome> {
         self.logger.log_info(&format!("Starting execution of action: {}", action_ref), None);
+        // Docker may have been started or stopped since the previous run
+        self.docker_available = None;
+        self.step_results.clear();
         
         // Ensure cache directory exists before starting execution.
         // It should already exist, but just in case.
@@ -62,97 +1001,422 @@ impl ExecutionEngine {
         // 1. Build the action tree
         self.logger.log_info("Building action tree...", None);
         
+        let build_started = std::time::Instant::now();
         let root_action = self.build_action_tree(
             action_ref,         // Action reference to download
             None,               // No parent action ID (root)
         ).await?;     
         
         // 1) Instantiate and assign the inputs according to the types specified
-        let typed_array_to_inject = self.cast_values_to_typed_array(
+        reject_file_inputs(&input_values)?;
+        let typed_array_to_inject = self.cast_input_values_to_typed_array(
+            action_ref,
             &root_action.inputs,
             &input_values, 
             &root_action.types)?;
         
+        let input_report = if self.options.explain_inputs {
+            Self::explain_inputs(&input_values, &typed_array_to_inject)
+        } else {
+            Vec::new()
+        };
+        for coercion in &input_report {
+            self.logger.log_info(
+                &format!("Input '{}' ({}): {} → {}", coercion.name, coercion.r#type, coercion.raw, coercion.cast),
+                Some(&root_action.id)
+            );
+        }
+        
         // Create a new action with injected inputs (avoiding deep clone)
         let new_root_action = ShAction {
             inputs: typed_array_to_inject,
             ..root_action
         };        
         
+        let build_time = build_started.elapsed();
         self.logger.log_success("Action tree built successfully", Some(&new_root_action.id));
 
+        self.check_unused_inputs(&new_root_action)?;
+
         self.logger.log_info("Executing action tree...", Some(&new_root_action.id));
-        let executed_action = self.run_action_tree(&new_root_action).await?;
+        let execute_started = std::time::Instant::now();
+        let executed_action = match self.run_action_tree(&new_root_action, "").await {
+            Ok(executed_action) => executed_action,
+            // Whatever a cancelled run failed with, the outcome is its cancellation
+            Err(e) if self.options.cancellation.is_cancelled() => {
+                let step_path = e.downcast_ref::<ExecutionCancelled>()
+                    .map(|cancelled| cancelled.step_path.clone())
+                    .unwrap_or_default();
+                let cancelled = ExecutionCancelled { step_path, steps: std::mem::take(&mut self.step_results) };
+                self.logger.log_warning(&format!("Run of {} cancelled: {}", action_ref, cancelled), Some(&new_root_action.id));
+                return Err(cancelled.into());
+            }
+            Err(e) => return Err(e),
+        };
+        let execute_time = execute_started.elapsed();
         
         self.logger.log_success("Action execution completed", Some(&new_root_action.id));
+        self.logger.log_info(
+            &format!("Built in {:.1?}, executed in {:.1?}", build_time, execute_time),
+            Some(&new_root_action.id)
+        );
 
         // Extract outputs from the executed action
-        let output_values: Vec<Value> = executed_action.outputs.iter()
-            .map(|io| io.value.clone().unwrap_or(Value::Null))
+        let outputs = executed_action.outputs.iter()
+            .map(|io| OutputEntry {
+                name: io.name.clone(),
+                r#type: io.r#type.clone(),
+                value: io.value.clone().unwrap_or(Value::Null),
+            })
             .collect();
 
-        // Return the outputs directly
-        Ok(serde_json::to_value(output_values)?)
+        Ok(RunOutcome {
+            action: action_ref.to_string(),
+            root_id: executed_action.id.clone(),
+            steps: std::mem::take(&mut self.step_results),
+            outputs,
+            input_report,
+            timings: PhaseTimings {
+                build_ms: build_time.as_secs_f64() * 1000.0,
+                execute_ms: execute_time.as_secs_f64() * 1000.0,
+            },
+        })
     }
 
-    async fn run_action_tree(&mut self, action: &ShAction) -> Result<ShAction> {
-        // Base condition.
-        
-        self.logger.log_info(&format!("Running action: {:#?}", action), None);
-        if action.kind == "wasm" || action.kind == "docker" {
-            self.logger.log_info(&format!("Executing {} wasm step: {}", action.kind, action.name), Some(&action.id));
+    /// Pairs the raw root inputs with the values they were cast to. Inputs that weren't provided are skipped.
+    fn explain_inputs(raw_values: &[Value], typed_inputs: &[ShIO]) -> Vec<InputCoercion> {
+        typed_inputs.iter()
+            .zip(raw_values)
+            .enumerate()
+            .map(|(index, (io, raw))| InputCoercion {
+                name: if io.name.is_empty() { format!("inputs[{}]", index) } else { io.name.clone() },
+                r#type: io.r#type.clone(),
+                raw: raw.clone(),
+                cast: io.value.clone().unwrap_or(Value::Null),
+            })
+            .collect()
+    }
 
-            // Extract values from inputs before serializing
-            let input_values_to_serialise: Vec<Value> = action.inputs.iter()
-                .map(|io| io.value.clone().unwrap_or(Value::Null))
-                .collect();
+    /// Runs a single wasm or docker action with the given inputs, without building or running
+    /// a composition around it. Meant for debugging leaf actions.
+    pub async fn run_leaf_action(&self, action_ref: &str, input_values: Vec<Value>) -> Result<LeafRun> {
+        let action = self.build_action_tree(action_ref, None).await?;
+        if action.kind != "wasm" && action.kind != "docker" {
+            return Err(anyhow::anyhow!("{} is a {} action; only wasm and docker actions can be tested in isolation", action_ref, action.kind));
+        }
 
-            println!("input_values_to_serialise: {:#?}", input_values_to_serialise);
-            let result_string = if action.kind == "wasm" {
-                wasm::run_wasm_step(
-                    action, 
-                    &serde_json::to_value(&input_values_to_serialise)?, 
-                    &self.cache_dir,
-                    &|msg, id| self.logger.log_info(msg, id),
-                    &|msg, id| self.logger.log_success(msg, id),
-                    &|msg, id| self.logger.log_error(msg, id),
-                ).await?
-            } else if action.kind == "docker" {
-                docker::run_docker_step(
-                    action,
-                    &serde_json::to_value(&input_values_to_serialise)?,
-                    &self.cache_dir,
-                    &|msg, id| self.logger.log_info(msg, id),
-                    &|msg, id| self.logger.log_success(msg, id),
-                    &|msg, id| self.logger.log_error(msg, id),
-                ).await?
-            } else {
-                return Err(anyhow::anyhow!("Unsupported action kind: {}", action.kind));
-            };
-            
-            println!("--------------------------------");
-            println!("result_string: {:#?}", result_string);
-            
-            // Handle empty or invalid JSON responses gracefully
-            let parsed_json = if result_string.trim().is_empty() {
-                self.logger.log_error("Action returned empty response - using empty array as fallback", Some(&action.id));
-                Value::Array(vec![])
-            } else {
-                match serde_json::from_str::<Value>(&result_string) {
-                    Ok(json) => json,
-                    Err(e) => {
-                        let error_msg = format!("Failed to parse action output as JSON: {}. Raw output: {}", e, result_string);
-                        self.logger.log_error(&error_msg, Some(&action.id));
-                        // Return an error object in the expected array format
-                        Value::Array(vec![json!({
-                            "error": format!("Invalid JSON response from action: {}", e),
-                            "raw_output": result_string
-                        })])
-                    }
+        reject_file_inputs(&input_values)?;
+        let action = ShAction {
+            inputs: self.cast_input_values_to_typed_array(action_ref, &action.inputs, &input_values, &action.types)?,
+            ..action
+        };
+        self.run_leaf(action_ref, &action).await
+    }
+
+    async fn run_leaf(&self, action_ref: &str, action: &ShAction) -> Result<LeafRun> {
+        let raw_output = self.run_leaf_step(&self.prepare_leaf_step(action).await?).await?;
+        let parsed = self.parse_leaf_output(action, &raw_output);
+
+        let values: Vec<Value> = parsed.as_array()
+            .map(|items| items.iter().cloned().map(Self::parse).collect())
+            .unwrap_or_default();
+        let outputs = self.cast_values_to_typed_array(&action.outputs, &values, &action.types)?
+            .into_iter()
+            .map(|io| OutputEntry {
+                name: io.name,
+                r#type: io.r#type,
+                value: io.value.unwrap_or(Value::Null),
+            })
+            .collect();
+
+        Ok(LeafRun {
+            action: action_ref.to_string(),
+            raw_output,
+            parsed,
+            outputs,
+        })
+    }
+
+    /// Runs the work of a step within its timeout, or the engine's default one. When it expires the work
+    /// is dropped, which also removes the container of a docker step, and a `StepTimeout` is returned.
+    async fn with_step_timeout<T>(
+        &self,
+        action: &ShAction,
+        step_path: &str,
+        work: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let Some(timeout) = action.timeout.map(std::time::Duration::from_secs).or(self.default_step_timeout) else {
+            return work.await;
+        };
+        let started = std::time::Instant::now();
+        match tokio::time::timeout(timeout, work).await {
+            Ok(result) => result,
+            Err(_) => {
+                let error = StepTimeout {
+                    step_path: step_path.to_string(),
+                    step_id: action.id.clone(),
+                    uses: action.uses.clone(),
+                    timeout_secs: timeout.as_secs_f64(),
+                    elapsed_secs: started.elapsed().as_secs_f64(),
+                };
+                self.logger.log_error(&error.to_string(), Some(&action.id));
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Runs the work of a step until it succeeds or the attempts of its retry policy are exhausted,
+    /// waiting for the policy's backoff in between. Only the error of the last attempt is returned.
+    async fn with_step_retries<T, F, Fut>(&self, action: &ShAction, step_path: &str, mut work: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let Some(retry) = action.retry.as_ref().filter(|retry| retry.max_attempts > 1) else {
+            return work().await;
+        };
+        let step = if step_path.is_empty() { action.uses.as_str() } else { step_path };
+        let mut attempt = 1;
+        loop {
+            match work().await {
+                Ok(result) => return Ok(result),
+                Err(e) if attempt < retry.max_attempts && !self.options.cancellation.is_cancelled() => {
+                    let delay = retry.delay_after(attempt);
+                    self.logger.log_info(&format!(
+                        "Step {} failed on attempt {} of {}, retrying in {} ms: {}",
+                        step, attempt, retry.max_attempts, delay.as_millis(), e
+                    ), Some(&action.id));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
                 }
+                Err(e) => {
+                    self.logger.log_error(&format!("Step {} failed after {} attempts", step, attempt), Some(&action.id));
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// The cancellation of a run that was stopped from outside, e.g. in the middle of a step, with
+    /// the steps it settled until then
+    pub fn stop_cancelled(&mut self) -> ExecutionCancelled {
+        ExecutionCancelled { step_path: String::new(), steps: std::mem::take(&mut self.step_results) }
+    }
+
+    /// Fails once the run is cancelled, before the step at the given path starts
+    fn check_cancelled(&self, step_path: &str) -> Result<()> {
+        if self.options.cancellation.is_cancelled() {
+            return Err(ExecutionCancelled { step_path: step_path.to_string(), steps: Vec::new() }.into());
+        }
+        Ok(())
+    }
+
+    /// Fails with a clear message when docker can't run the given step. Docker is only checked once per run.
+    async fn ensure_docker_available(&mut self, action: &ShAction, step_path: &str) -> Result<()> {
+        if self.docker_available.is_none() {
+            self.docker_available = Some(docker::check_docker_available(None).await.map_err(|e| e.to_string()));
+        }
+        if let Some(Err(reason)) = &self.docker_available {
+            let step = if step_path.is_empty() { action.uses.as_str() } else { step_path };
+            let message = format!("docker is required for step {} but is not available: {}", step, reason);
+            self.logger.log_error(&message, Some(&action.id));
+            anyhow::bail!(message);
+        }
+        Ok(())
+    }
+
+    /// Prepares a wasm or docker action to run: its mirrors are put in the order the artifact is downloaded
+    /// from them, and a locked artifact is checked against its digest. Done once per step, not per attempt.
+    async fn prepare_leaf_step(&self, action: &ShAction) -> Result<ShAction> {
+        let action = if self.mirror_strategy == http::MirrorStrategy::Order {
+            action.clone()
+        } else {
+            ShAction {
+                mirrors: http::order_mirrors(&self.http_client, self.mirror_strategy, &action.mirrors).await,
+                ..action.clone()
+            }
+        };
+        
+        // A locked artifact must still have the digest it was locked with
+        if let Some(lockfile) = &self.options.lockfile {
+            let artifact = self.download_artifact(&action.kind, &action.uses, &action.mirrors).await?;
+            lockfile.verify_artifact(&action.uses, &artifact)?;
+        }
+        Ok(action)
+    }
+
+    /// Runs the artifact of a wasm or docker action prepared by `prepare_leaf_step` and returns its raw output
+    async fn run_leaf_step(&self, action: &ShAction) -> Result<String> {
+        // Extract values from inputs before serializing
+        let input_values_to_serialise: Vec<Value> = action.inputs.iter()
+            .map(|io| io.value.clone().unwrap_or(Value::Null))
+            .collect();
+        
+        let context = StepContext {
+            cache_dir: &self.cache_dir,
+            client: &self.http_client,
+            api_base: self.api_base(),
+            log_info: &|msg, id| self.logger.log_info(msg, id),
+            log_success: &|msg, id| self.logger.log_success(msg, id),
+            log_error: &|msg, id| self.logger.log_error(msg, id),
+            log_output: &|stream, line, id| self.logger.log_step_output(stream, line, id),
+        };
+        let inputs = serde_json::to_value(&input_values_to_serialise)?;
+        if action.kind == "wasm" {
+            wasm::run_wasm_step(action, &inputs, self.wasm_opt_level, &context).await
+        } else if action.kind == "docker" {
+            docker::run_docker_step(action, &inputs, &context).await
+        } else {
+            Err(anyhow::anyhow!("Unsupported action kind: {}", action.kind))
+        }
+    }
+
+    /// Parses the raw output of a wasm or docker action, falling back to an array
+    /// describing the problem when it isn't valid JSON
+    fn parse_leaf_output(&self, action: &ShAction, result_string: &str) -> Value {
+        // Handle empty or invalid JSON responses gracefully
+        if result_string.trim().is_empty() {
+            self.logger.log_error("Action returned empty response - using empty array as fallback", Some(&action.id));
+            Value::Array(vec![])
+        } else {
+            match serde_json::from_str::<Value>(result_string) {
+                Ok(json) => json,
+                Err(e) => {
+                    let error_msg = format!("Failed to parse action output as JSON: {}. Raw output: {}", e, result_string);
+                    self.logger.log_error(&error_msg, Some(&action.id));
+                    // Return an error object in the expected array format
+                    Value::Array(vec![json!({
+                        "error": format!("Invalid JSON response from action: {}", e),
+                        "raw_output": result_string
+                    })])
+                }
+            }
+        }
+    }
+
+    /// Runs a step of a composition. A `foreach` step runs each of its instances in the order of
+    /// their elements, as `<step path>[<index>]`, and every output becomes an `array` of the
+    /// outputs of the instances.
+    async fn run_step(&mut self, step: &ShAction, step_path: &str) -> Result<ShAction> {
+        let Some(instances) = &step.foreach_instances else {
+            return self.run_action_tree(step, step_path).await;
+        };
+
+        let mut executed_instances = Vec::with_capacity(instances.len());
+        for (index, instance) in instances.iter().enumerate() {
+            let instance_path = format!("{}[{}]", step_path, index);
+            self.check_cancelled(&instance_path)?;
+            let executed_instance = Box::pin(self.run_action_tree(instance, &instance_path)).await
+                .map_err(|e| e.context(format!("Instance {} of step '{}' failed", index, step_path)))?;
+            self.record_step(&instance_path, &executed_instance, StepStatus::Completed);
+            executed_instances.push(executed_instance);
+        }
+        Ok(ShAction {
+            outputs: step.outputs.iter().enumerate()
+                .map(|(index, io)| ShIO {
+                    r#type: "array".to_string(),
+                    value: Some(Value::Array(executed_instances.iter()
+                        .map(|instance| instance.outputs.get(index).and_then(|output| output.value.clone()).unwrap_or(Value::Null))
+                        .collect())),
+                    ..io.clone()
+                })
+                .collect(),
+            ..step.clone()
+        })
+    }
+
+    /// Runs an action and its steps. The step path identifies the action within the
+    /// root action tree (e.g. "deploy.create_vm") and is empty for the root action.
+    /// The `pre` hooks of a composition run before its steps, and its `post` hooks after them,
+    /// even when a `pre` hook or a step failed.
+    async fn run_action_tree(&mut self, action: &ShAction, step_path: &str) -> Result<ShAction> {
+        if action.pre.is_empty() && action.post.is_empty() {
+            return self.run_action_body(action, step_path).await;
+        }
+        
+        let body = match self.run_hooks(action, step_path, "pre").await {
+            Ok(()) => self.run_action_body(action, step_path).await,
+            Err(e) => Err(e),
+        };
+        let post = self.run_hooks(action, step_path, "post").await;
+        match (body, post) {
+            (Ok(executed), Ok(())) => Ok(executed),
+            (Ok(_), Err(e)) | (Err(e), Ok(())) => Err(e),
+            (Err(e), Err(post_error)) => {
+                // The failure of the body is the one worth reporting
+                self.logger.log_error(&format!("A post hook failed too: {}", post_error), Some(&action.id));
+                Err(e)
+            }
+        }
+    }
+    
+    /// Runs the hooks of a phase in order, with their inputs resolved against the inputs of the composition.
+    /// Every `post` hook runs even when an earlier one failed; the first failure is returned.
+    async fn run_hooks(&mut self, action: &ShAction, step_path: &str, phase: &str) -> Result<()> {
+        let hooks = if phase == "pre" { &action.pre } else { &action.post };
+        let values: Vec<Value> = action.inputs.iter()
+            .map(|io| io.value.clone().unwrap_or(Value::Null))
+            .collect();
+        let inputs_object = Self::inputs_as_whole_value(&action.inputs);
+        
+        let mut first_failure = None;
+        for (index, hook) in hooks.iter().enumerate() {
+            let hook_path = Self::child_step_path(step_path, &format!("{}[{}]", phase, index));
+            self.logger.register_step(&hook.id, &format!("{}[{}]", phase, index), &hook_path);
+            self.logger.log_info(&format!("Running {} hook '{}': {}", phase, hook_path, hook.uses), Some(&hook.id));
+            let result = async {
+                let resolved: Vec<Value> = hook.inputs.iter()
+                    .map(|input| self.interpolate_into_untyped_value(&input.template, &values, &inputs_object, None))
+                    .collect::<Result<_>>()?;
+                Ok::<ShAction, anyhow::Error>(ShAction {
+                    inputs: self.cast_values_to_typed_array(&hook.inputs, &resolved, &hook.types)?,
+                    ..hook.clone()
+                })
+            }.await;
+            let result = match result {
+                Ok(resolved_hook) => Box::pin(self.run_action_tree(&resolved_hook, &hook_path)).await.map(|_| ()),
+                Err(e) => Err(e),
             };
+            if let Err(e) = result {
+                let error = anyhow::anyhow!("{} hook '{}' failed: {}", phase, hook_path, e);
+                self.logger.log_error(&error.to_string(), Some(&hook.id));
+                if phase == "pre" {
+                    return Err(error);
+                }
+                first_failure.get_or_insert(error);
+            }
+        }
+        first_failure.map_or(Ok(()), Err)
+    }
+
+    /// Runs an action and its steps, without its hooks
+    async fn run_action_body(&mut self, action: &ShAction, step_path: &str) -> Result<ShAction> {
+        // Base condition.
+        
+        // Serialize through a Value so that steps are printed in a stable (sorted) order
+        let action_json = serde_json::to_value(action)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .unwrap_or_else(|_| format!("{:#?}", action));
+        self.logger.log_info(&format!("Running action: {}", action_json), Some(&action.id));
+        if action.kind == "wasm" || action.kind == "docker" {
+            self.logger.log_info(&format!("Executing {} wasm step: {}", action.kind, action.name), Some(&action.id));
+            if action.kind == "docker" {
+                self.ensure_docker_available(action, step_path).await?;
+            }
+
+            self.check_cancelled(step_path)?;
+            let prepared = self.prepare_leaf_step(action).await?;
+            // Every attempt runs with the same resolved inputs, and each one gets the whole timeout
+            let result_string = self.with_step_retries(action, step_path, || {
+                self.with_step_timeout(action, step_path, self.run_leaf_step(&prepared))
+            }).await?;
+            
+            println!("--------------------------------");
+            println!("result_string: {:#?}", self.logger.redact(&result_string));
+            
+            let parsed_json = self.parse_leaf_output(action, &result_string);
             
-            println!("result_json: {:#?}", parsed_json);
+            println!("result_json: {:#?}", self.redact(&parsed_json));
             self.logger.log_success(&format!("{} step completed: {}", action.kind, action.name), Some(&action.id));
             
             // Log the execution result to the frontend
@@ -203,20 +1467,36 @@ impl ExecutionEngine {
             println!("✅ Step '{}' completed with {} outputs:", action.id, typed_updated_outputs.len());
             for (idx, output) in typed_updated_outputs.iter().enumerate() {
                 println!("  output[{}]: name='{}', type='{}', value={:?}", 
-                    idx, output.name, output.r#type, output.value);
+                    idx, output.name, output.r#type, output.value.as_ref().map(|value| self.redact(value)));
             }
             
             return Ok(updated_action);
         }
 
+        // When every output resolves from the inputs alone, none of the steps is needed
+        if let Some(resolved_untyped_outputs) = self.outputs_from_inputs_only(action) {
+            self.logger.log_info(
+                &format!("All outputs of {} resolve from its inputs, skipping its {} step(s)", action.name, action.steps.len()),
+                Some(&action.id)
+            );
+            for step_id in Self::sorted_step_ids(&action.steps) {
+                self.record_step(&Self::child_step_path(step_path, step_id), &action.steps[step_id], StepStatus::Skipped);
+            }
+            return Ok(ShAction {
+                outputs: self.cast_values_to_typed_array(&action.outputs, &resolved_untyped_outputs, &action.types)?,
+                ..action.clone()
+            });
+        }
+
         let mut execution_buffer: Vec<String> = Vec::new();
+        let mut failures: Vec<StepFailure> = Vec::new();
 
         // Initially, we want to inject the input values into the inputs of the steps.
         // This will help us understand what steps are ready to be executed.
         let steps_with_injected_inputs: HashMap<String, ShAction> = self.recalculate_steps(
             &action.inputs, 
             &action.steps
-        );
+        )?;
 
         let action_with_inputs_resolved_into_steps = ShAction {
             steps: steps_with_injected_inputs,
@@ -236,80 +1516,232 @@ impl ExecutionEngine {
         // Using a loop-based approach instead of recursion to avoid stack overflow
         let mut current_action = action_with_inputs_resolved_into_steps;
         let mut current_execution_buffer = execution_buffer;
-        
-        // Iterative execution loop
-        while !current_execution_buffer.is_empty() {
-            // Get the first step from the buffer
-            let current_step_id = current_execution_buffer.first().unwrap().clone();
-            // Remove the first step from the buffer
-            let remaining_buffer = current_execution_buffer.into_iter().skip(1).collect::<Vec<String>>();
+        // Steps that were executed, failed or skipped
+        let mut settled: std::collections::HashSet<String> = std::collections::HashSet::new();
+        // Steps that failed or were skipped: the optional steps waiting on them never get their outputs
+        let mut not_completed: std::collections::HashSet<String> = std::collections::HashSet::new();
+        
+        loop {
+            // Iterative execution loop
+            while !current_execution_buffer.is_empty() {
+                // Get the first step from the buffer
+                let current_step_id = current_execution_buffer.first().unwrap().clone();
+                self.check_cancelled(&Self::child_step_path(step_path, &current_step_id))?;
+                // Remove the first step from the buffer
+                let remaining_buffer = current_execution_buffer.into_iter().skip(1).collect::<Vec<String>>();
             
-            // Execute the current step
-            if let Some(step) = current_action.steps.get(&current_step_id) {
-                // Since the step is coming from the execution buffer, it means that
-                // it is ready to be executed.
-                // Execute the step
-                println!("executing step: {:#?}", step);
-                let executed_step = Box::pin(self.run_action_tree(step)).await?;
-                println!("current_step_id: {:#?}", current_step_id);
-
-                // Substitute the step in the current action with the executed step
-                let updated_steps: HashMap<String, ShAction> = current_action.steps.iter()
-                    .map(|(id, step)| {
-                        if id == &current_step_id {
-                            (id.clone(), executed_step.clone())
-                        } else {
-                            (id.clone(), step.clone())
+                // Execute the current step
+                if let Some(step) = current_action.steps.get(&current_step_id) {
+                    // Since the step is coming from the execution buffer, it means that
+                    // it is ready to be executed, unless a previous attempt of the run
+                    // already completed it.
+                    let child_step_path = Self::child_step_path(step_path, &current_step_id);
+                    self.logger.register_step(&step.id, &current_step_id, &child_step_path);
+                    let checkpoint = self.options.checkpoints.get(&child_step_path).cloned();
+                    let executed_step = if step.when_holds == Some(false) {
+                        // Its dependents carry on with null outputs
+                        self.logger.log_info(
+                            &format!("Step '{}' skipped: its condition `{}` doesn't hold", child_step_path, step.when.as_deref().unwrap_or_default()),
+                            Some(&step.id)
+                        );
+                        let skipped_step = ShAction {
+                            outputs: step.outputs.iter()
+                                .map(|io| ShIO { value: Some(Value::Null), ..io.clone() })
+                                .collect(),
+                            ..step.clone()
+                        };
+                        self.record_step(&child_step_path, &skipped_step, StepStatus::Skipped);
+                        not_completed.insert(current_step_id.clone());
+                        skipped_step
+                    } else if let Some(checkpointed_outputs) = checkpoint {
+                        self.logger.log_info(
+                            &format!("Step '{}' restored from checkpoint, skipping execution", child_step_path),
+                            Some(&step.id)
+                        );
+                        let restored_step = ShAction {
+                            outputs: self.cast_values_to_typed_array(&step.outputs, &checkpointed_outputs, &step.types)?,
+                            ..step.clone()
+                        };
+                        self.record_step(&child_step_path, &restored_step, StepStatus::Restored);
+                        restored_step
+                    } else if let Some(mocked_outputs) = self.options.mocks.get(&child_step_path).cloned() {
+                        self.logger.log_info(
+                            &format!("Step '{}' mocked, skipping execution", child_step_path),
+                            Some(&step.id)
+                        );
+                        let outputs = self.cast_values_to_typed_array(&step.outputs, &mocked_outputs, &step.types)
+                            .map_err(|e| anyhow::anyhow!("Mocked outputs of step '{}' don't match its declared outputs: {}", child_step_path, e))?;
+                        let mocked_step = ShAction {
+                            outputs,
+                            ..step.clone()
+                        };
+                        self.record_step(&child_step_path, &mocked_step, StepStatus::Mocked);
+                        mocked_step
+                    } else {
+                        // Execute the step
+                        self.logger.log_step_event("step_started", &child_step_path, &step.id);
+                        #[cfg(test)]
+                        if let Some(gate) = &self.step_gate {
+                            gate.acquire().await.expect("the step gate is never closed").forget();
                         }
-                    })
-                    .collect();
+                        let executed_step = match Box::pin(self.run_step(step, &child_step_path)).await {
+                            Ok(executed_step) => {
+                                self.logger.log_step_event("step_completed", &child_step_path, &step.id);
+                                executed_step
+                            }
+                            Err(e) if self.options.on_failure == FailurePolicy::KeepGoing => {
+                                self.logger.log_step_event("step_failed", &child_step_path, &step.id);
+                                self.record_step(&child_step_path, step, StepStatus::Failed);
+                                // Record the failure and carry on with the other steps. The steps
+                                // depending on this one never become ready.
+                                self.logger.log_error(
+                                    &format!("Step '{}' failed, continuing with the other steps: {:#}", child_step_path, e),
+                                    Some(&step.id)
+                                );
+                                match e.downcast::<StepFailures>() {
+                                    Ok(nested) => failures.extend(nested.failures),
+                                    Err(e) => failures.push(StepFailure {
+                                        step_path: child_step_path,
+                                        message: format!("{:#}", e),
+                                    }),
+                                }
+                                not_completed.insert(current_step_id.clone());
+                                settled.insert(current_step_id);
+                                current_execution_buffer = remaining_buffer;
+                                continue;
+                            }
+                            Err(e) => {
+                                // Nothing else runs: the step still in flight is dropped along with its container
+                                self.logger.log_step_event("step_failed", &child_step_path, &step.id);
+                                self.logger.log_error(
+                                    &format!("Step '{}' failed, aborting the run", child_step_path),
+                                    Some(&step.id)
+                                );
+                                return Err(e);
+                            }
+                        };
+                        self.checkpoint_step(&child_step_path, &executed_step).await;
+                        self.post_step_output(&child_step_path, &executed_step);
+                        self.record_step(&child_step_path, &executed_step, StepStatus::Completed);
+                        executed_step
+                    };
+                    println!("current_step_id: {:#?}", current_step_id);
+                    settled.insert(current_step_id.clone());
+
+                    // Substitute the step in the current action with the executed step
+                    let updated_steps: HashMap<String, ShAction> = current_action.steps.iter()
+                        .map(|(id, step)| {
+                            if id == &current_step_id {
+                                (id.clone(), executed_step.clone())
+                            } else {
+                                (id.clone(), step.clone())
+                            }
+                        })
+                        .collect();
 
                 
-                let current_action_with_updated_steps = ShAction {
-                    steps: updated_steps,
-                    ..current_action.clone()
-                };
-
-                // println!("current_action_with_updated_steps: {:#?}", current_action_with_updated_steps);
-                // By the time we get here, the current action has been updated with the outputs of the step we have just executed.
-                // However, the effects of the processing of the current step have not beem applied to the siblings yet.
-                // For each sibling, inject the outputs of the step we have just executed
-                // into the inputs of the dependent step                
-                let recalculated_steps: HashMap<String, ShAction> = self.recalculate_steps(
-                    &current_action_with_updated_steps.inputs, 
-                    &current_action_with_updated_steps.steps
-                );
+                    let current_action_with_updated_steps = ShAction {
+                        steps: updated_steps,
+                        ..current_action.clone()
+                    };
 
-                let updated_current_action = ShAction {
-                    steps: recalculated_steps,
-                    ..current_action_with_updated_steps.clone()
-                };
-                
-                // Create new buffer by combining remaining steps with new downstream steps
-                let mut new_execution_buffer = remaining_buffer;
-                if !new_execution_buffer.contains(&"outputs".to_string()) {
-                    // Find the ready steps that are directly downstream of the step we just executed
-                    let downstream_step_ids = self.find_next_step_id(
-                            &updated_current_action.steps,
-                            &current_step_id,
-                        &updated_current_action.inputs,
-                        &updated_current_action.outputs
+                    // println!("current_action_with_updated_steps: {:#?}", current_action_with_updated_steps);
+                    // By the time we get here, the current action has been updated with the outputs of the step we have just executed.
+                    // However, the effects of the processing of the current step have not beem applied to the siblings yet.
+                    // For each sibling, inject the outputs of the step we have just executed
+                    // into the inputs of the dependent step                
+                    let recalculated_steps: HashMap<String, ShAction> = self.recalculate_steps(
+                        &current_action_with_updated_steps.inputs, 
+                        &current_action_with_updated_steps.steps
                     )?;
 
-                    println!("downstream_step_ids: {:#?}", downstream_step_ids);
-                    for step_id in downstream_step_ids {
-                        self.push_to_execution_buffer(&mut new_execution_buffer, step_id);
-                    }
-                } 
+                    let updated_current_action = ShAction {
+                        steps: recalculated_steps,
+                        ..current_action_with_updated_steps.clone()
+                    };
+                
+                    // Create new buffer by combining remaining steps with new downstream steps
+                    let mut new_execution_buffer = remaining_buffer;
+                    if !new_execution_buffer.contains(&"outputs".to_string()) {
+                        // Find the ready steps that are directly downstream of the step we just executed
+                        let downstream_step_ids = self.find_next_step_id(
+                                &updated_current_action.steps,
+                                &current_step_id,
+                            &updated_current_action.inputs,
+                            &updated_current_action.outputs
+                        )?;
+
+                        println!("downstream_step_ids: {:#?}", downstream_step_ids);
+                        for step_id in downstream_step_ids {
+                            self.push_to_execution_buffer(&mut new_execution_buffer, step_id);
+                        }
+                    } 
                 
                     
-                // Update the current state for the next iteration
-                current_action = updated_current_action;
-                current_execution_buffer = new_execution_buffer;
-            } else {
-                // If step not found, continue with remaining buffer
-                current_execution_buffer = remaining_buffer;
+                    self.trace_tree(&updated_current_action);
+                    
+                    // Update the current state for the next iteration
+                    current_action = updated_current_action;
+                    current_execution_buffer = new_execution_buffer;
+                } else {
+                    // If step not found, continue with remaining buffer
+                    current_execution_buffer = remaining_buffer;
+                }
+            }
+        
+            // Once nothing can run anymore, the optional steps still waiting on a step that failed, was
+            // skipped or doesn't exist will never run: skip them with null outputs so that their dependents
+            // can carry on. Skipping a step blocks the optional steps waiting on it in turn.
+            let mut blocked_optional_steps: Vec<String> = Vec::new();
+            loop {
+                let mut blocked = Vec::new();
+                for step_id in Self::sorted_step_ids(&current_action.steps) {
+                    let step = &current_action.steps[step_id];
+                    if !step.optional || settled.contains(step_id) || blocked_optional_steps.contains(step_id) {
+                        continue;
+                    }
+                    let never_resolves = Self::step_dependencies(step)?.iter().any(|dependency| {
+                        not_completed.contains(dependency)
+                            || blocked_optional_steps.contains(dependency)
+                            || !current_action.steps.contains_key(dependency)
+                    });
+                    if never_resolves {
+                        blocked.push(step_id.clone());
+                    }
+                }
+                if blocked.is_empty() {
+                    break;
+                }
+                blocked_optional_steps.extend(blocked);
+            }
+            if blocked_optional_steps.is_empty() {
+                break;
+            }
+            for step_id in &blocked_optional_steps {
+                let step = &current_action.steps[step_id];
+                self.logger.log_warning(
+                    &format!("Optional step '{}' skipped: it waits on a step that failed or was skipped", Self::child_step_path(step_path, step_id)),
+                    Some(&step.id)
+                );
+                let skipped_step = ShAction {
+                    outputs: step.outputs.iter()
+                        .map(|io| ShIO { value: Some(Value::Null), ..io.clone() })
+                        .collect(),
+                    ..step.clone()
+                };
+                self.record_step(&Self::child_step_path(step_path, step_id), &skipped_step, StepStatus::Skipped);
+                current_action.steps.insert(step_id.clone(), skipped_step);
+                settled.insert(step_id.clone());
+                not_completed.insert(step_id.clone());
             }
+            current_action.steps = self.recalculate_steps(&current_action.inputs, &current_action.steps)?;
+            current_execution_buffer = self.find_ready_step_ids(&current_action.steps)?.into_iter()
+                .filter(|step_id| !settled.contains(step_id))
+                .collect();
+        }
+        
+        if !failures.is_empty() {
+            return Err(StepFailures { failures }.into());
         }
         
         // The outputs could be coming from the parent inputs or the sibling steps.
@@ -333,6 +1765,125 @@ impl ExecutionEngine {
         Ok(updated_action.clone())
     }
 
+    /// The outputs of a composition with steps, if they can all be resolved without running any step
+    fn outputs_from_inputs_only(&self, action: &ShAction) -> Option<Vec<Value>> {
+        if action.steps.is_empty() || action.outputs.is_empty() {
+            return None;
+        }
+        self.resolve_untyped_output_values(&action.outputs, &action.inputs, &HashMap::new())
+            .ok()
+            .filter(|values| !values.iter().any(|value| self.contains_unresolved_templates(value)))
+    }
+
+    /// Builds the path of a step from the path of its parent
+    fn child_step_path(parent_path: &str, step_id: &str) -> String {
+        if parent_path.is_empty() {
+            step_id.to_string()
+        } else {
+            format!("{}.{}", parent_path, step_id)
+        }
+    }
+
+    /// Dumps the state of the action tree to the trace directory, if one is set
+    fn trace_tree(&mut self, action: &ShAction) {
+        if let Some(trace_dir) = &self.options.trace_dir {
+            self.trace_iteration += 1;
+            let trace_path = trace_dir.join(format!("iteration-{}.json", self.trace_iteration));
+            let written = std::fs::create_dir_all(trace_dir)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| Ok(serde_json::to_string_pretty(&self.logger.redact_value(&serde_json::to_value(action)?))?))
+                .and_then(|json| Ok(std::fs::write(&trace_path, json)?));
+            if let Err(e) = written {
+                self.logger.log_error(
+                    &format!("Failed to write trace {:?}: {}", trace_path, e),
+                    Some(&action.id)
+                );
+            }
+        }
+    }
+
+    /// The compiled JSON schema of a custom type. Casting validates values over and over against
+    /// the same few types, so each schema is only compiled once per engine.
+    fn compiled_schema(&self, target_type: &str, json_schema: &Value) -> Result<Arc<JSONSchema>> {
+        let key = json_schema.to_string();
+        if let Some(compiled) = self.schema_cache.lock().ok().and_then(|cache| cache.get(&key).cloned()) {
+            return Ok(compiled);
+        }
+        let compiled = JSONSchema::compile(json_schema)
+            .map(Arc::new)
+            .map_err(|e| anyhow::anyhow!("Failed to compile schema for type '{}': {}", target_type, e))?;
+        self.schema_compilations.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Ok(mut cache) = self.schema_cache.lock() {
+            cache.insert(key, compiled.clone());
+        }
+        Ok(compiled)
+    }
+
+    /// Adds a settled step to the results of the current run
+    fn record_step(&mut self, step_path: &str, step: &ShAction, status: StepStatus) {
+        let outputs = step.outputs.iter()
+            .map(|io| match status {
+                StepStatus::Failed => Value::Null,
+                _ => io.value.clone().unwrap_or(Value::Null),
+            })
+            .collect();
+        self.step_results.push(StepResult {
+            step_path: step_path.to_string(),
+            step_id: step.id.clone(),
+            uses: step.uses.clone(),
+            status,
+            outputs,
+        });
+    }
+
+    /// Persists the outputs of a completed step so that a failed run can be resumed
+    async fn checkpoint_step(&self, step_path: &str, step: &ShAction) {
+        if let (Some(database), Some(run_id)) = (&self.database, &self.options.run_id) {
+            let output_values: Vec<Value> = step.outputs.iter()
+                .map(|io| io.value.clone().unwrap_or(Value::Null))
+                .collect();
+            // Secrets are never stored: such a step runs again when the run is resumed
+            if self.redact(&Value::Array(output_values.clone())) != Value::Array(output_values.clone()) {
+                self.logger.log_info(&format!("Step '{}' not checkpointed: its outputs hold secrets", step_path), Some(&step.id));
+                return;
+            }
+
+            let db = database.lock().await;
+            if let Err(e) = db.save_run_step(run_id, step_path, &output_values) {
+                self.logger.log_error(&format!("Failed to checkpoint step '{}': {}", step_path, e), Some(&step.id));
+            }
+        }
+    }
+
+    /// Posts the outputs of a completed step to the step output webhook, if one is set.
+    /// The request is sent in the background and never fails the run: a failed delivery is only a warning.
+    fn post_step_output(&self, step_path: &str, step: &ShAction) {
+        let Some(url) = self.options.step_output_webhook.clone() else {
+            return;
+        };
+        let output: Vec<Value> = step.outputs.iter()
+            .map(|io| io.value.clone().unwrap_or(Value::Null))
+            .collect();
+        let body = json!({
+            "run_id": self.options.run_id,
+            "step": step_path,
+            "output": self.redact(&Value::Array(output)),
+        });
+        let client = self.http_client.clone();
+        let logger = self.logger.detached();
+        let step_path = step_path.to_string();
+        tokio::spawn(async move {
+            let delivered = client.post(&url)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = delivered {
+                logger.log_warning(&format!("Failed to post the outputs of step '{}' to {}: {}", step_path, url, e), None);
+            }
+        });
+    }
+
     /// Instantiates and assigns values to IO fields in one operation
     fn cast_values_to_typed_array(
         &self,
@@ -344,41 +1895,58 @@ impl ExecutionEngine {
         // println!("io_fields: {:#?}", io_fields);
         // println!("io_values: {:#?}", io_values);
         // println!("types: {:#?}", types);
-        let mut cast_values: Vec<Value> = Vec::new();
-        // For each IO field, cast the value to the appropriate type
+        let mut io_array = Vec::with_capacity(io_fields.len());
+        // For each IO field, cast the value to the appropriate type and inject it
         for (index, io) in io_fields.iter().enumerate() {
-            // println!("casting value to type: {:#?}", io);
-            let value_to_inject = match io_values.get(index) {
-                Some(value) => {
-                    // println!("value to inject: {:#?}", value);
-                    value.clone()
-                },
-                None => {
-                    println!("No value found at index {}, skipping output", index);
-                    continue;
-                }
+            // A value left out falls back to the default of the field, if it declares one
+            let Some(value_to_inject) = io_values.get(index).or(io.default.as_ref()) else {
+                println!("No value found at index {}, skipping output", index);
+                io_array.push(io.clone());
+                continue;
             };
             
-            let converted_value = self.cast(&value_to_inject, &io.r#type, types)?;
-            cast_values.push(converted_value);
+            let converted_value = self.cast(value_to_inject, &io.r#type, types)?;
+            io_array.push(ShIO {
+                value: Some(converted_value),
+                ..io.clone()
+            });
         }
 
-        // Inject the cast values into the IO array
-        let io_array = io_fields.iter()
+        Ok(io_array)
+    }
+
+    /// Casts the input values a caller gave an action. Inputs left out take their default, or null
+    /// when they're optional; leaving out a required input without a default, or one whose
+    /// `required_if` holds, is an error. Values beyond the declared inputs are ignored.
+    fn cast_input_values_to_typed_array(
+        &self,
+        action_ref: &str,
+        io_fields: &Vec<ShIO>,
+        io_values: &Vec<Value>,
+        types: &Option<serde_json::Map<String, Value>>
+    ) -> Result<Vec<ShIO>> {
+        if io_values.len() > io_fields.len() {
+            self.logger.log_warning(&format!(
+                "{} expects {} input values, got {}: the extra ones are ignored", action_ref, io_fields.len(), io_values.len()
+            ), None);
+        }
+        self.cast_values_to_typed_array(io_fields, io_values, types)?
+            .into_iter()
             .enumerate()
-            .map(|(index, io_field)| {
-                if let Some(resolved_value) = cast_values.get(index) {
-                    ShIO {
-                        value: Some(resolved_value.clone()),
-                        ..io_field.clone()
-                    }
-                } else {
-                    io_field.clone()
-                }
+            .map(|(index, io)| match io.value {
+                Some(_) => Ok(io),
+                None if io.required => Err(anyhow::anyhow!(
+                    "{} expects {} input values, got {}: input '{}' (#{}) is required and has no default value",
+                    action_ref, io_fields.len(), io_values.len(), io.name, index
+                )),
+                None => match io.required_if.clone() {
+                    Some(expression) if Self::evaluate_required_if(&expression, io_fields, io_values)? => Err(anyhow::anyhow!(
+                        "{}: input '{}' (#{}) is missing, required because {}", action_ref, io.name, index, expression
+                    )),
+                    _ => Ok(ShIO { value: Some(Value::Null), ..io }),
+                },
             })
-            .collect();
-
-        Ok(io_array)
+            .collect()
     }
 
     /// Casts a single value to the appropriate type
@@ -394,6 +1962,8 @@ impl ExecutionEngine {
         if target_type == "string" || 
             target_type == "bool" ||
             target_type == "number" ||
+            target_type == "integer" ||
+            target_type == "secret" ||
             target_type == "object" ||
             target_type == "id" {
             
@@ -415,19 +1985,15 @@ impl ExecutionEngine {
                         _ => Value::String(serde_json::to_string(value)?),
                     }
                 },
-                "number" => {
-                    // Convert string to number if needed
-                    match value {
-                        Value::String(s) => {
-                            if let Ok(n) = s.parse::<f64>() {
-                                Value::Number(serde_json::Number::from_f64(n).unwrap_or(serde_json::Number::from(0)))
-                            } else {
-                                return Err(anyhow::anyhow!("Cannot convert string '{}' to number", s));
-                            }
-                        },
-                        Value::Number(n) => Value::Number(n.clone()),
-                        _ => return Err(anyhow::anyhow!("Cannot convert {:?} to number", value)),
-                    }
+                "number" | "integer" => cast_number(value, target_type)?,
+                "secret" => {
+                    // The steps get the value as a string, the logs never show it
+                    let secret = match value {
+                        Value::String(s) => s.clone(),
+                        _ => serde_json::to_string(value)?,
+                    };
+                    self.logger.mask(&secret);
+                    Value::String(secret)
                 },
                 "bool" => {
                     // Convert string to boolean if needed
@@ -462,6 +2028,8 @@ impl ExecutionEngine {
             let type_definition = if target_type == "string" || 
                 target_type == "bool" ||
                 target_type == "number" ||
+                target_type == "integer" ||
+                target_type == "secret" ||
                 target_type == "object" {
                 None // Primitive types don't need type definition lookup
             } else if target_type == "id" {
@@ -481,25 +2049,39 @@ impl ExecutionEngine {
                         }
                     };
 
-                    // Compile the JSON schema
-                    let compiled_schema = match JSONSchema::compile(&json_schema) {
-                        Ok(schema) => schema,
-                        Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to compile schema for type '{}': {}", target_type, e));
-                    }
+                let compiled_schema = self.compiled_schema(target_type, &json_schema)?;
+
+                let describe_errors = |instance: &Value| -> Option<String> {
+                    compiled_schema.validate(instance).err().map(|errors| {
+                        errors
+                            .map(|e| {
+                                let path = e.instance_path.to_string();
+                                if path.is_empty() { e.to_string() } else { format!("{}: {}", path, e) }
+                            })
+                            .collect::<Vec<String>>()
+                            .join("; ")
+                    })
                 };
 
-                
-                // Validate the value against the schema
-                if compiled_schema.validate(value).is_ok() {
-                    Ok(value.clone())
-                    } else {
-                    let error_list: Vec<_> = compiled_schema.validate(value).unwrap_err().collect();
-                    println!("compiled_schema: {:#?}", compiled_schema);
-                    println!("value: {:#?}", value);
-                    println!("--------------------------------");
-                    return Err(anyhow::anyhow!("Value is invalid: {:?}", error_list));
+                // An array given for an object type holds one value of the type per element
+                if let (Value::Array(elements), Some("object")) = (value, json_schema.get("type").and_then(|t| t.as_str())) {
+                    let element_errors: Vec<String> = elements.iter()
+                        .enumerate()
+                        .filter_map(|(index, element)| {
+                            describe_errors(element).map(|errors| format!("element {}: {}", index, errors))
+                        })
+                        .collect();
+                    if !element_errors.is_empty() {
+                        return Err(anyhow::anyhow!("Value is invalid for type '{}': {}", target_type, element_errors.join("; ")));
                     }
+                    return Ok(value.clone());
+                }
+
+                // Validate the value against the schema
+                match describe_errors(value) {
+                    None => Ok(value.clone()),
+                    Some(errors) => Err(anyhow::anyhow!("Value is invalid for type '{}': {}", target_type, errors)),
+                }
                 } else {
                 // No type definition provided - pass through unchanged
                 Ok(value.clone())
@@ -516,11 +2098,17 @@ impl ExecutionEngine {
         let input_values: Vec<Value> = inputs.iter()
             .map(|io| io.value.clone().unwrap_or(Value::Null))
             .collect();
+        let inputs_object = Self::inputs_as_whole_value(inputs);
 
         // For every output, we want to interpolate the template into the value
         let resolved_outputs: Result<Vec<Value>> = outputs.iter()
             .map(|output| {
-                self.interpolate_into_untyped_value(&output.template, &input_values, Some(children))
+                let value = self.interpolate_into_untyped_value(&output.template, &input_values, &inputs_object, Some(children))?;
+                match &output.transform {
+                    Some(transform) => self.apply_transform(transform, &value)
+                        .map_err(|e| anyhow::anyhow!("Failed to transform output '{}': {}", output.name, e)),
+                    None => Ok(value),
+                }
             })
             .collect();
         
@@ -531,113 +2119,215 @@ impl ExecutionEngine {
 
     fn recalculate_steps(&self,
         inputs: &Vec<ShIO>,
-        children: &HashMap<String, ShAction>) -> HashMap<String, ShAction> {
+        children: &HashMap<String, ShAction>) -> Result<HashMap<String, ShAction>> {
         
         // Extract values from the inputs vector
         let values: Vec<Value> = inputs.iter()
             .map(|io| io.value.clone().unwrap_or(Value::Null))
             .collect();
+        let inputs_object = Self::inputs_as_whole_value(inputs);
             
-        children.iter()
-            .map(|(step_id, step)| {
-                // For every input of every child, iterate through the input definitions
-                // and resolve the template to get the actual value
-                let resolved_untyped_values: Result<Vec<Value>, ()> = step.inputs.iter()
-                    .map(|definition| {
-                        // Resolve the template to get the actual value
-                        let interpolation_result = self.interpolate_into_untyped_value(&definition.template, &values, Some(children));
-                        match interpolation_result {
-                            Ok(interpolated_template) => {
-                                // Check if the resolved template still contains unresolved templates
-                                let has_unresolved = self.contains_unresolved_templates(&interpolated_template);
-                                if has_unresolved {
-                                    // Debug: Check if it's a false positive (string that looks like it has templates but is actually resolved)
-                                    if let Value::String(s) = &interpolated_template {
-                                        println!("⚠️ Step '{}' input template '{}' resolved to string that contains '{{' or '}}': length={}, first 100 chars: {}", 
-                                            step_id, 
-                                            serde_json::to_string(&definition.template).unwrap_or_default(),
-                                            s.len(),
-                                            s.chars().take(100).collect::<String>());
-                                        // Check if the string actually contains template patterns
-                                        let has_template_pattern = s.contains("{{steps.") || s.contains("{{inputs[");
-                                        if !has_template_pattern {
-                                            println!("✅ This appears to be a false positive - string doesn't contain actual template patterns, treating as resolved");
-                                            return Ok(interpolated_template);
-                                        }
-                                    }
-                                    println!("⚠️ Step '{}' input template '{}' still contains unresolved templates after interpolation: {:?}", step_id, serde_json::to_string(&definition.template).unwrap_or_default(), interpolated_template);
-                                    Err(()) // Cannot resolve this step yet
-                                } else {
-                                    Ok(interpolated_template)
-                                }
-                            },
-                            Err(e) => {
-                                println!("❌ Failed to interpolate template '{}' for step '{}': {}", serde_json::to_string(&definition.template).unwrap_or_default(), step_id, e);
-                                Err(())
-                            }
-                        }
-                    })
-                    .collect();
-
-                // Once we have resolved the inputs we want to create a new array of typed inputs to inject into the child step
-                if let Some(resolved_inputs_to_inject_into_child_step) = resolved_untyped_values.ok() {
-                    let inputs_array_to_inject = self.cast_values_to_typed_array(
-                        &step.inputs, 
-                        &resolved_inputs_to_inject_into_child_step,
-                        &step.types
-                    ).ok();
-                    
-                    if let Some(inputs_to_inject) = inputs_array_to_inject {
-                        // Create new step with injected inputs
-                        let new_step = ShAction {
-                            inputs: inputs_to_inject,
-                            ..step.clone()
-                        };
-                        (step_id.clone(), new_step)
-                    } else {
-                        // Keep original step if injection failed
-                        (step_id.clone(), step.clone())
+        Self::sorted_step_ids(children).into_iter()
+            .map(|step_id| (step_id, &children[step_id]))
+            .map(|(step_id, step)| Ok((step_id.clone(), match &step.foreach {
+                Some(foreach) => self.expand_foreach_step(step_id, step, foreach, &values, &inputs_object, children)?,
+                None => self.inject_step_inputs(step_id, step, &values, &inputs_object, children, None)?,
+            })))
+            .map(|resolved: Result<(String, ShAction)>| {
+                let (step_id, mut step) = resolved?;
+                // The condition of the step is evaluated once, as soon as the templates of its operands resolve.
+                // Each operand is interpolated on its own, so resolved values can't change the comparison.
+                if let (Some(when), None) = (&step.when, step.when_holds) {
+                    let (left, comparison) = Self::parse_when(when);
+                    let operand = |text: &str| self.when_operand(text, &values, &inputs_object, children)
+                        .filter(|value| !self.contains_unresolved_templates(value));
+                    match comparison {
+                        None => if let Some(left) = operand(left) {
+                            step.when_holds = Some(Self::evaluate_when(&left, None));
+                        },
+                        Some((equal, right)) => if let (Some(left), Some(right)) = (operand(left), operand(right)) {
+                            step.when_holds = Some(Self::evaluate_when(&left, Some((equal, &right))));
+                        },
                     }
-                } else {
-                    // Keep original step if resolution failed
-                    (step_id.clone(), step.clone())
                 }
+                Ok((step_id, step))
             })
             .collect()
     }
 
-    fn interpolate_into_untyped_value(&self, 
-        template: &Value, 
-        inputs: &Vec<Value>,
-        executed_steps: Option<&HashMap<String, ShAction>>,
-    ) -> Result<Value> {
-        // println!("Interpolating from parent inputs: {:#?}", template);
-        // println!("Variables: {:#?}", variables);
-        match template {
-            Value::String(s) => {
-                // println!("resolve_template_string: {:#?}", s);
-                let resolved = self.interpolate_string_into_untyped_value(s, inputs, executed_steps)?;
-                Ok(resolved)
-            },
-            Value::Object(obj) => {
-                // Recursively resolve object templates
-                let mut resolved_obj = serde_json::Map::new();
-                for (key, value) in obj {
-                    let resolved_value = self.interpolate_into_untyped_value(value, inputs, executed_steps)?;
-                    resolved_obj.insert(key.clone(), resolved_value);
-                }
-                
-                Ok(Value::Object(resolved_obj))
-            },
-            Value::Array(arr) => {
-                // println!("resolve_template_array: {:#?}", arr);
-                // Recursively resolve array templates
-                let mut resolved_arr = Vec::new();
-                for item in arr {
-                    let resolved_item = self.interpolate_into_untyped_value(item, inputs, executed_steps)?;
-                    resolved_arr.push(resolved_item);
+    /// Resolves the input templates of a step against the inputs of its parent and the outputs of its
+    /// siblings. The step is returned unchanged until all of them resolve. A template that can't be
+    /// interpolated, such as one referring to an unknown secret, fails the run. The element of a
+    /// `foreach` instance is only substituted once its templates are interpolated, so that it is
+    /// never read as a template itself.
+    fn inject_step_inputs(&self,
+        step_id: &str,
+        step: &ShAction,
+        values: &Vec<Value>,
+        inputs_object: &Value,
+        children: &HashMap<String, ShAction>,
+        item: Option<&Value>) -> Result<ShAction> {
+        // For every input of every child, iterate through the input definitions
+        // and resolve the template to get the actual value
+        let mut resolved_untyped_values = Vec::new();
+        for definition in &step.inputs {
+            let interpolated_template = self.interpolate_into_untyped_value(&definition.template, values, inputs_object, Some(children))
+                .map_err(|e| anyhow::anyhow!("Failed to interpolate the inputs of step '{}': {}", step_id, e))?;
+            // Check if the resolved template still contains unresolved templates
+            if self.contains_unresolved_templates(&interpolated_template) {
+                // Debug: Check if it's a false positive (string that looks like it has templates but is actually resolved)
+                let false_positive = match &interpolated_template {
+                    Value::String(s) => {
+                        println!("⚠️ Step '{}' input template '{}' resolved to string that contains '{{' or '}}': length={}, first 100 chars: {}", 
+                            step_id, 
+                            serde_json::to_string(&definition.template).unwrap_or_default(),
+                            s.len(),
+                            self.logger.redact(s).chars().take(100).collect::<String>());
+                        // Check if the string actually contains template patterns
+                        !(s.contains("{{steps.") || s.contains("{{inputs[") || s.contains("{{inputs}}"))
+                    }
+                    _ => false,
+                };
+                if !false_positive {
+                    println!("⚠️ Step '{}' input template '{}' still contains unresolved templates after interpolation: {:?}", step_id, serde_json::to_string(&definition.template).unwrap_or_default(), self.redact(&interpolated_template));
+                    // Cannot resolve this step yet: keep the original step
+                    return Ok(step.clone());
                 }
-                Ok(Value::Array(resolved_arr))
+                println!("✅ This appears to be a false positive - string doesn't contain actual template patterns, treating as resolved");
+            }
+            resolved_untyped_values.push(match item {
+                Some(item) => Self::substitute_item(&interpolated_template, item),
+                None => interpolated_template,
+            });
+        }
+
+        // Once we have resolved the inputs we want to create a new array of typed inputs to inject into the child step
+        match self.cast_values_to_typed_array(&step.inputs, &resolved_untyped_values, &step.types) {
+            // Create new step with injected inputs
+            Ok(inputs_to_inject) => Ok(ShAction {
+                inputs: inputs_to_inject,
+                ..step.clone()
+            }),
+            // Keep original step if injection failed
+            Err(_) => Ok(step.clone()),
+        }
+    }
+
+    /// Once the `foreach` template of a step resolves to an array, builds one instance of the step
+    /// per element, with `{{item}}` in its input templates standing for the element. The step fails
+    /// when the template resolves to anything else.
+    fn expand_foreach_step(&self,
+        step_id: &str,
+        step: &ShAction,
+        foreach: &str,
+        values: &Vec<Value>,
+        inputs_object: &Value,
+        children: &HashMap<String, ShAction>) -> Result<ShAction> {
+        if step.foreach_instances.is_some() {
+            return Ok(step.clone());
+        }
+        let items = match self.interpolate_into_untyped_value(&Value::String(foreach.to_string()), values, inputs_object, Some(children))? {
+            Value::Array(items) => items,
+            items if !self.contains_unresolved_templates(&items) => anyhow::bail!(
+                "`foreach` of step '{}' must resolve to an array, got {}",
+                step_id,
+                self.redact(&items)
+            ),
+            _ => return Ok(step.clone()),
+        };
+
+        let instance = ShAction {
+            inputs: step.inputs.iter()
+                .map(|io| ShIO { template: Self::mark_item(&io.template), ..io.clone() })
+                .collect(),
+            foreach: None,
+            ..step.clone()
+        };
+        let instances: Vec<ShAction> = items.iter()
+            .map(|item| self.inject_step_inputs(step_id, &instance, values, inputs_object, children, Some(item)))
+            .collect::<Result<_>>()?;
+        // The instances start together, once the inputs of every one of them resolve
+        if instances.iter().any(|instance| instance.inputs.iter().any(|input| input.value.is_none())) {
+            return Ok(step.clone());
+        }
+        Ok(ShAction {
+            foreach_instances: Some(instances),
+            ..step.clone()
+        })
+    }
+
+    /// Replaces the `{{item}}` tokens of a template with a placeholder the interpolation leaves alone
+    fn mark_item(template: &Value) -> Value {
+        match template {
+            Value::String(s) => Value::String(s.replace(ITEM_TOKEN, ITEM_PLACEHOLDER)),
+            Value::Array(items) => Value::Array(items.iter().map(Self::mark_item).collect()),
+            Value::Object(map) => Value::Object(map.iter().map(|(k, t)| (k.clone(), Self::mark_item(t))).collect()),
+            _ => template.clone(),
+        }
+    }
+
+    /// Replaces the `{{item}}` placeholder of an interpolated value with an element of a `foreach` array.
+    /// A string that is only the placeholder takes the element as is, otherwise the element is written as text.
+    fn substitute_item(template: &Value, item: &Value) -> Value {
+        match template {
+            Value::String(s) if s.trim() == ITEM_PLACEHOLDER => item.clone(),
+            Value::String(s) if s.contains(ITEM_PLACEHOLDER) => {
+                let text = match item {
+                    Value::String(text) => text.clone(),
+                    _ => item.to_string(),
+                };
+                Value::String(s.replace(ITEM_PLACEHOLDER, &text))
+            }
+            Value::Array(items) => Value::Array(items.iter().map(|t| Self::substitute_item(t, item)).collect()),
+            Value::Object(map) => Value::Object(map.iter().map(|(k, t)| (k.clone(), Self::substitute_item(t, item))).collect()),
+            _ => template.clone(),
+        }
+    }
+
+    /// Builds the value of the `{{inputs}}` token: an object mapping input names to values,
+    /// or an array of values when some of the inputs are unnamed
+    fn inputs_as_whole_value(inputs: &[ShIO]) -> Value {
+        let values = inputs.iter().map(|io| io.value.clone().unwrap_or(Value::Null));
+        if inputs.iter().any(|io| io.name.is_empty()) {
+            Value::Array(values.collect())
+        } else {
+            Value::Object(inputs.iter().map(|io| io.name.clone()).zip(values).collect())
+        }
+    }
+
+    fn interpolate_into_untyped_value(&self, 
+        template: &Value, 
+        inputs: &Vec<Value>,
+        inputs_object: &Value,
+        executed_steps: Option<&HashMap<String, ShAction>>,
+    ) -> Result<Value> {
+        // println!("Interpolating from parent inputs: {:#?}", template);
+        // println!("Variables: {:#?}", variables);
+        match template {
+            Value::String(s) => {
+                // println!("resolve_template_string: {:#?}", s);
+                let resolved = self.interpolate_string_into_untyped_value(s, inputs, inputs_object, executed_steps)?;
+                Ok(resolved)
+            },
+            Value::Object(obj) => {
+                // Recursively resolve object templates
+                let mut resolved_obj = serde_json::Map::new();
+                for (key, value) in obj {
+                    let resolved_value = self.interpolate_into_untyped_value(value, inputs, inputs_object, executed_steps)?;
+                    resolved_obj.insert(key.clone(), resolved_value);
+                }
+                
+                Ok(Value::Object(resolved_obj))
+            },
+            Value::Array(arr) => {
+                // println!("resolve_template_array: {:#?}", arr);
+                // Recursively resolve array templates
+                let mut resolved_arr = Vec::new();
+                for item in arr {
+                    let resolved_item = self.interpolate_into_untyped_value(item, inputs, inputs_object, executed_steps)?;
+                    resolved_arr.push(resolved_item);
+                }
+                Ok(Value::Array(resolved_arr))
             },
             _ => Ok(template.clone())
         }
@@ -647,8 +2337,57 @@ impl ExecutionEngine {
     fn interpolate_string_into_untyped_value(&self, 
         template: &str, 
         variables: &Vec<Value>,
+        inputs_object: &Value,
         executed_steps: Option<&HashMap<String, ShAction>>,
     ) -> Result<Value> {
+        // Hide escaped braces from the template patterns, then turn them into literal braces
+        if template.contains(ESCAPED_OPEN_BRACES) || template.contains(ESCAPED_CLOSE_BRACES) {
+            let masked = template
+                .replace(ESCAPED_OPEN_BRACES, OPEN_BRACES_PLACEHOLDER)
+                .replace(ESCAPED_CLOSE_BRACES, CLOSE_BRACES_PLACEHOLDER);
+            return match self.interpolate_string_into_untyped_value(&masked, variables, inputs_object, executed_steps)? {
+                Value::String(s) => Ok(Value::String(s
+                    .replace(OPEN_BRACES_PLACEHOLDER, "{{")
+                    .replace(CLOSE_BRACES_PLACEHOLDER, "}}"))),
+                other => Ok(other),
+            };
+        }
+
+        // Resolve the secrets of the template itself, before anything is interpolated into it, so that
+        // a value can't smuggle in a `{{secret.NAME}}` of its own. Their values are masked in the logs.
+        if template.contains("{{secret.") {
+            let mut secret_values = Vec::new();
+            let masked = secrets::template_regex().replace_all(template, |cap: &regex::Captures| {
+                secret_values.push(cap[1].to_string());
+                format!("{}{}{}", SECRET_PLACEHOLDER_START, secret_values.len() - 1, SECRET_PLACEHOLDER_END)
+            }).into_owned();
+            let secret_values = secret_values.iter()
+                .map(|name| secrets::resolve(self.secrets.as_ref(), name))
+                .collect::<Result<Vec<String>>>()?;
+            return match self.interpolate_string_into_untyped_value(&masked, variables, inputs_object, executed_steps)? {
+                Value::String(s) => Ok(Value::String(secret_values.iter().enumerate().fold(s, |s, (index, value)| {
+                    self.logger.mask(value);
+                    s.replace(&format!("{}{}{}", SECRET_PLACEHOLDER_START, index, SECRET_PLACEHOLDER_END), value)
+                }))),
+                other => Ok(other),
+            };
+        }
+
+        // Check for a reference to the whole inputs object
+        if template == "{{inputs}}" {
+            return Ok(inputs_object.clone());
+        }
+
+        // Like the secrets, `{{inputs}}` is taken from the template itself, so that an input value
+        // containing it isn't expanded into the values of every input
+        if template.contains("{{inputs}}") {
+            let masked = template.replace("{{inputs}}", INPUTS_PLACEHOLDER);
+            return match self.interpolate_string_into_untyped_value(&masked, variables, inputs_object, executed_steps)? {
+                Value::String(s) => Ok(Value::String(s.replace(INPUTS_PLACEHOLDER, &inputs_object.to_string()))),
+                other => Ok(other),
+            };
+        }
+
         // Check for simple direct input reference (no string interpolation needed)
         let simple_re = regex::Regex::new(r"^\{\{inputs\[(\d+)\]\}\}$")?;
         if let Some(cap) = simple_re.captures(template) {
@@ -712,20 +2451,20 @@ impl ExecutionEngine {
                 acc
             });
         
-        // Handle sibling step outputs: {{steps.step_name.outputs[index]}}
+        // Handle sibling step outputs: {{steps.step_name.outputs[index]}}, or the outputs of the steps
+        // nested in a sibling composition: {{steps.step_name.steps.child_name.outputs[index]}}
         if let Some(executed_steps) = executed_steps {
             // Check for simple direct step output reference (no string interpolation needed)
-            let steps_simple_re = regex::Regex::new(r"^\{\{steps\.([^.]+)\.outputs\[(\d+)\]\}\}$")?;
+            let steps_simple_re = regex::Regex::new(r"^\{\{steps\.([^.]+(?:\.steps\.[^.]+)*)\.outputs\[(\d+)\]\}\}$")?;
             if let Some(cap) = steps_simple_re.captures(template) {
                 if let (Some(step_name), Some(index_str)) = (cap.get(1), cap.get(2)) {
                     if let Ok(index) = index_str.as_str().parse::<usize>() {
                         let step_name_str = step_name.as_str();
                         println!("🔍 Looking for step '{}' in executed_steps (available keys: {:?})", step_name_str, executed_steps.keys().collect::<Vec<_>>());
-                        if let Some(step) = executed_steps.get(step_name_str) {
+                        if let Some(step) = Self::nested_step(executed_steps, step_name_str) {
                             println!("✅ Found step '{}', checking output at index {}", step_name_str, index);
                             if let Some(output) = step.outputs.get(index) {
                                 if let Some(output_value) = &output.value {
-                                    println!("✅ Step '{}' output[{}] has value: {:?}", step_name_str, index, output_value);
                                     return Ok(output_value.clone());
                                 } else {
                                     println!("⚠️ Step '{}' output[{}] exists but has no value", step_name_str, index);
@@ -741,11 +2480,11 @@ impl ExecutionEngine {
             }
             
             // Check for simple step output jsonpath reference
-            let steps_jsonpath_re = regex::Regex::new(r"^\{\{steps\.([^.]+)\.outputs\[(\d+)\]\.([^}]+)\}\}$")?;
+            let steps_jsonpath_re = regex::Regex::new(r"^\{\{steps\.([^.]+(?:\.steps\.[^.]+)*)\.outputs\[(\d+)\]\.([^}]+)\}\}$")?;
             if let Some(cap) = steps_jsonpath_re.captures(template) {
                 if let (Some(step_name), Some(index_str), Some(jsonpath)) = (cap.get(1), cap.get(2), cap.get(3)) {
                     if let Ok(index) = index_str.as_str().parse::<usize>() {
-                        if let Some(step) = executed_steps.get(step_name.as_str()) {
+                        if let Some(step) = Self::nested_step(executed_steps, step_name.as_str()) {
                             if let Some(output) = step.outputs.get(index) {
                                 if let Some(output_value) = &output.value {
                                     if let Ok(resolved_value) = self.evaluate_jsonpath(output_value, jsonpath.as_str()) {
@@ -762,6 +2501,18 @@ impl ExecutionEngine {
         Ok(Value::String(result))
     }
 
+    /// Finds a step by its path among sibling steps: `name` for a sibling, `name.steps.child` for a step
+    /// of a sibling composition, and so on. The nested steps of a composition only hold outputs once
+    /// it ran: they are empty when its outputs were mocked, restored or resolved from its inputs alone.
+    fn nested_step<'a>(steps: &'a HashMap<String, ShAction>, path: &str) -> Option<&'a ShAction> {
+        let mut names = path.split(".steps.");
+        let mut step = steps.get(names.next()?)?;
+        for name in names {
+            step = step.steps.get(name)?;
+        }
+        Some(step)
+    }
+
     /// Parses a value to a JSON object or array
     fn parse(value: Value) -> Value {
         match value {
@@ -796,6 +2547,37 @@ impl ExecutionEngine {
         }
     }
 
+    /// Builds the shape of an output transform. Strings starting with `.` are jq-like paths into
+    /// the value (`.` is the value itself, `.[0].name` a field of its first element); everything
+    /// else is copied as is.
+    fn apply_transform(&self, transform: &Value, value: &Value) -> Result<Value> {
+        match transform {
+            Value::String(path) if path.starts_with('.') => {
+                // `.[0].name` and `.items[0]` both map onto the jsonpath syntax: `0.name`, `items[0]`
+                let path = &path[1..];
+                let path = match path.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+                    Some((index, rest)) => format!("{}{}", index, rest),
+                    None => path.to_string(),
+                };
+                self.evaluate_jsonpath(value, &path)
+            },
+            Value::Object(obj) => {
+                let mut shaped = serde_json::Map::new();
+                for (key, field) in obj {
+                    shaped.insert(key.clone(), self.apply_transform(field, value)?);
+                }
+                Ok(Value::Object(shaped))
+            },
+            Value::Array(arr) => {
+                let shaped: Result<Vec<Value>> = arr.iter()
+                    .map(|item| self.apply_transform(item, value))
+                    .collect();
+                Ok(Value::Array(shaped?))
+            },
+            _ => Ok(transform.clone()),
+        }
+    }
+
     fn evaluate_jsonpath(&self, value: &Value, jsonpath: &str) -> Result<Value> {
         // Handle empty path - return the original value
         if jsonpath.is_empty() {
@@ -877,9 +2659,12 @@ impl ExecutionEngine {
         match type_definition {
             Value::Object(obj) => {
                 // Check if this is a field definition (has type, description, required)
-                // A field definition should have a "type" key and be a simple object with type/description/required
+                // A field definition should have a "type" key and be a simple object with type/description/required,
+                // optionally bounded by minimum/maximum
                 // A type definition with multiple fields should not be treated as a field definition
-                if obj.contains_key("type") && obj.len() <= 3 && (obj.contains_key("description") || obj.contains_key("required") || obj.len() == 1) {
+                let bounds = ["minimum", "maximum"].iter().filter(|key| obj.contains_key(**key)).count();
+                let field_keys = obj.len() - bounds;
+                if obj.contains_key("type") && field_keys <= 3 && (obj.contains_key("description") || obj.contains_key("required") || field_keys == 1) {
                     // This is a field definition, convert it
                     let mut property = serde_json::Map::new();
                     
@@ -893,6 +2678,16 @@ impl ExecutionEngine {
                         property.insert("description".to_string(), description.clone());
                     }
                     
+                    // Add numeric bounds
+                    for bound in ["minimum", "maximum"] {
+                        if let Some(limit) = obj.get(bound) {
+                            if !limit.is_number() {
+                                return Err(anyhow::anyhow!("`{}` must be a number, got {}", bound, limit));
+                            }
+                            property.insert(bound.to_string(), limit.clone());
+                        }
+                    }
+                    
                     // Handle nested objects recursively
                     if let Some(properties) = obj.get("properties") {
                         if let Ok(nested_schema) = self.convert_to_json_schema(properties) {
@@ -991,12 +2786,62 @@ impl ExecutionEngine {
         }
     }
 
+    /// Compiles every type definition to a JSON schema and returns the name and error of the
+    /// ones that don't compile, sorted by name
+    fn broken_type_definitions(&self, types: &HashMap<String, Value>) -> Vec<(String, String)> {
+        let mut broken: Vec<(String, String)> = types.iter()
+            .filter_map(|(name, definition)| {
+                let error = match self.convert_to_json_schema(definition) {
+                    Ok(schema) => JSONSchema::compile(&schema).err().map(|e| e.to_string())?,
+                    Err(e) => e.to_string(),
+                };
+                Some((name.clone(), error))
+            })
+            .collect();
+        broken.sort();
+        broken
+    }
+
     async fn build_action_tree(&self,
         action_ref: &str,
         // The parent id is null initially, but during recursion we pass it down to the children
         parent_action_id: Option<&str>) -> Result<ShAction> {
-        // 1. Download the manifest for the current action
-        let manifest = self.fetch_manifest(action_ref).await?;
+        self.build_action_tree_to_depth(action_ref, parent_action_id, None, &[]).await
+    }
+
+    /// Builds the tree of an action, expanding at most `depth` levels of steps below it (all when None).
+    /// Deeper steps are left as placeholders of kind `unexpanded`, without fetching their manifest.
+    /// `ancestors` are the references being built above this action, from the root down.
+    async fn build_action_tree_to_depth(&self,
+        action_ref: &str,
+        parent_action_id: Option<&str>,
+        depth: Option<usize>,
+        ancestors: &[String]) -> Result<ShAction> {
+        // 1. Download the manifest for the current action, or for the action its alias points at
+        let target_ref = self.resolve_alias(action_ref).await?;
+        
+        // An action using one of its ancestors would recurse forever: stop before fetching it again
+        let mut chain = ancestors.to_vec();
+        chain.push(target_ref.clone());
+        if let Some(start) = ancestors.iter().position(|ancestor| *ancestor == target_ref) {
+            return Err(anyhow::anyhow!("Cyclic action dependency: {}", chain[start..].join(" -> ")));
+        }
+        if ancestors.len() > self.max_tree_depth {
+            return Err(anyhow::anyhow!(
+                "Action tree exceeds the maximum depth of {}: {}", self.max_tree_depth, chain.join(" -> ")
+            ));
+        }
+        
+        let manifest = self.fetch_manifest(&target_ref).await?;
+        
+        // Broken type definitions would otherwise only fail once a value is cast
+        let broken_types = self.broken_type_definitions(&manifest.types);
+        if !broken_types.is_empty() {
+            let details: Vec<String> = broken_types.iter()
+                .map(|(name, error)| format!("'{}': {}", name, error))
+                .collect();
+            return Err(anyhow::anyhow!("Invalid type definition(s) in {}: {}", action_ref, details.join("; ")));
+        }
         
         // 2. Create action state
         // Create a unique ID for the action
@@ -1013,46 +2858,28 @@ impl ExecutionEngine {
                 Some(ShKind::Docker) => "docker".to_string(),
                 None => return Err(anyhow::anyhow!("Unknown manifest kind for action: {}", action_ref))
             },
-            uses: action_ref.to_string(),
+            // A locked action runs the artifact of the version it was locked to
+            uses: match &self.options.lockfile {
+                Some(lockfile) => lockfile.locked(&target_ref)?.resolved.clone(),
+                None => action_ref.to_string(),
+            },
+            resolved_uses: Self::concrete_ref(&target_ref, &manifest),
             // Initially empty inputs and outputs
-            inputs: manifest.inputs.as_array()
-            .map(|arr| {
-                arr.iter().filter_map(|input| {
-                    if let Some(obj) = input.as_object() {
-                        Some(ShIO {
-                            name: obj.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                            r#type: obj.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                            template: obj.get("value").cloned().unwrap_or(serde_json::Value::Null),
-                            value: None,
-                            required: obj.get("required").and_then(|v| v.as_bool()).unwrap_or(false),
-                        })
-                    } else {
-                        None
-                    }
-                }).collect()
-            })
-            .unwrap_or_default(),
-            outputs: manifest.outputs.as_array()
-                .map(|arr| {
-                    arr.iter().filter_map(|output| {
-                        if let Some(obj) = output.as_object() {
-                            Some(ShIO {
-                                name: obj.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                                r#type: obj.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                                template: obj.get("value").cloned().unwrap_or(serde_json::Value::Null),
-                                value: None,
-                                required: obj.get("required").and_then(|v| v.as_bool()).unwrap_or(false),
-                            })
-                        } else {
-                            None
-                        }
-                    }).collect()
-                })
-                .unwrap_or_default(),
+            inputs: Self::parse_manifest_io(action_ref, "inputs", &manifest.inputs)?,
+            outputs: Self::parse_manifest_io(action_ref, "outputs", &manifest.outputs)?,
             parent_action: parent_action_id.map(|s| s.to_string()),
             // TODO: find a way to determine priority at build time
             priority: 0,
+            optional: false,
+            when: None,
+            when_holds: None,
+            foreach: None,
+            foreach_instances: None,
+            timeout: None,
+            retry: None,
             steps: HashMap::new(),
+            pre: Vec::new(),
+            post: Vec::new(),
             role: manifest.role,
             // Initially empty types
             types: if manifest.types.is_empty() { None } else { Some(manifest.types.clone().into_iter().collect()) },
@@ -1063,49 +2890,108 @@ impl ExecutionEngine {
         };
         
         // 4. For each step, call the build_action_tree function recursively
+        let mut explicit_priorities = HashMap::new();
         for (_step_name, step_value) in manifest.steps {
             if let Some(uses_value) = step_value.get("uses") {
                 if let Some(uses_str) = uses_value.as_str() {
-                    let mut child_action = Box::pin(self.build_action_tree(
-                        uses_str,
-                        Some(&action_id_for_children)
-                    )).await?;
+                    let mut child_action = if depth == Some(0) {
+                        Self::unexpanded_action(uses_str, &action_id_for_children)
+                    } else {
+                        Box::pin(self.build_action_tree_to_depth(
+                            uses_str,
+                            Some(&action_id_for_children),
+                            depth.map(|depth| depth - 1),
+                            &chain
+                        )).await?
+                    };
                     
                      // Extract step inputs and inject them into the child action
                     if let Some(step_inputs) = step_value.get("inputs") {
+                        // Step inputs are positional: anything but an array would leave the child unwired
+                        if !step_inputs.is_array() {
+                            return Err(anyhow::anyhow!(
+                                "Inputs of step '{}' in {} must be an array of values, one per input of {}, got {}",
+                                _step_name, action_ref, uses_str, step_inputs
+                            ));
+                        }
                         if let Some(inputs_array) = step_inputs.as_array() {
                             for (index, input) in inputs_array.iter().enumerate() {
-                                    if let Some(child_input) = child_action.inputs.get_mut(index) {
-                                    // Handle both formats:
-                                    // 1. New format: direct values (string, object, etc.)
-                                    // 2. Old format: objects with "value" property
-                                    let template_value = if let Some(input_obj) = input.as_object() {
-                                        // Old format: object with "value" property
-                                        if let Some(value) = input_obj.get("value") {
-                                            value.clone()
-                                        } else {
-                                            input.clone()
-                                        }
-                                    } else {
-                                        // New format: direct value
-                                        input.clone()
-                                    };
-                                    
-                                    child_input.template = template_value;
+                                if let Some(child_input) = child_action.inputs.get_mut(index) {
+                                    child_input.template = Self::step_input_template(input);
                                 }
                             }
                         }
                     }
 
                     
+                    child_action.optional = step_value.get("optional")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false);
+                    
+                    if let Some(when) = step_value.get("when") {
+                        let when = when.as_str()
+                            .ok_or_else(|| anyhow::anyhow!("`when` of step '{}' must be a template string, got {}", _step_name, when))?;
+                        child_action.when = Some(when.to_string());
+                    }
+                    
+                    if let Some(foreach) = step_value.get("foreach") {
+                        let foreach = foreach.as_str()
+                            .ok_or_else(|| anyhow::anyhow!("`foreach` of step '{}' must be a template string resolving to an array, got {}", _step_name, foreach))?;
+                        child_action.foreach = Some(foreach.to_string());
+                    }
+                    
+                    if let Some(timeout) = step_value.get("timeout") {
+                        let timeout = timeout.as_u64()
+                            .filter(|secs| *secs > 0)
+                            .ok_or_else(|| anyhow::anyhow!("Timeout of step '{}' must be a positive number of seconds, got {}", _step_name, timeout))?;
+                        // Only wasm and docker steps run with a timeout: the steps of a composition set their own
+                        if child_action.kind == "composition" {
+                            return Err(anyhow::anyhow!(
+                                "Timeout of step '{}' can't apply to {}, a composition: set it on the wasm or docker steps of {} instead",
+                                _step_name, uses_str, uses_str
+                            ));
+                        }
+                        child_action.timeout = Some(timeout);
+                    }
+                    
+                    if let Some(retry) = step_value.get("retry") {
+                        let retry: ShRetry = serde_json::from_value(retry.clone())
+                            .ok()
+                            .filter(|retry: &ShRetry| retry.max_attempts > 0)
+                            .ok_or_else(|| anyhow::anyhow!(
+                                "Retry of step '{}' must be {{\"max_attempts\": <positive integer>, \"backoff_ms\": <integer>, \"exponential\": <bool>}}, got {}",
+                                _step_name, retry
+                            ))?;
+                        child_action.retry = Some(retry);
+                    }
+                    
+                    if let Some(priority) = step_value.get("priority") {
+                        let priority = priority.as_i64()
+                            .and_then(|p| i32::try_from(p).ok())
+                            .ok_or_else(|| anyhow::anyhow!("Priority of step '{}' must be an integer, got {}", _step_name, priority))?;
+                        explicit_priorities.insert(_step_name.clone(), priority);
+                    }
+                    
                     // Add child to parent's children HashMap
                     action_state.steps.insert(_step_name.clone(), child_action);
                 }
             }
         }
         
+        // 5. Build the hooks, run in order before and after the steps
+        action_state.pre = self.build_hooks("pre", &manifest.pre, action_ref, &action_id_for_children, depth, &chain).await?;
+        action_state.post = self.build_hooks("post", &manifest.post, action_ref, &action_id_for_children, depth, &chain).await?;
+        
+        // A required input left unwired would only fail once the step runs
+        let unwired = Self::unwired_required_inputs(&action_state)?;
+        if !unwired.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Composition {} leaves required step input(s) unwired: {}", action_ref, unwired.join("; ")
+            ));
+        }
+        
         // After creating the action tree, we want to calculate the priority of the action
-        let steps_with_priorities = self.produce_steps_with_priorities(&action_state.steps);
+        let steps_with_priorities = self.produce_steps_with_priorities(&action_state.steps, &explicit_priorities);
 
         action_state = ShAction {
             steps: steps_with_priorities,
@@ -1115,162 +3001,740 @@ impl ExecutionEngine {
         return Ok(action_state);
     }
 
-    fn produce_steps_with_priorities(&self, steps: &HashMap<String, ShAction>) -> HashMap<String, ShAction>{
-        // Sort step keys alphabetically to ensure deterministic ordering
-        let mut sorted_keys: Vec<_> = steps.keys().collect();
-        sorted_keys.sort();
-        
-        // Create new steps with priorities assigned based on alphabetical order
-        let mut steps_with_priorities = HashMap::new();
-        for (index, step_key) in sorted_keys.iter().enumerate() {
-            if let Some(step) = steps.get(*step_key) {
-                let mut updated_step = step.clone();
-                updated_step.priority = index as i32;
-                steps_with_priorities.insert((*step_key).clone(), updated_step);
-            }
+    /// Template of a step input as written in a manifest. Both forms are understood:
+    /// the value itself, or the older `{"value": ...}` object around it.
+    fn step_input_template(input: &Value) -> Value {
+        if crate::models::is_value_wrapper(input) {
+            input["value"].clone()
+        } else {
+            input.clone()
         }
-        
-        steps_with_priorities
     }
-    
-    pub fn find_sibling_dependencies(&self, value: &Value, steps: &HashMap<String, ShAction>) -> Result<Vec<String>> {        
-                // Look for patterns like {{steps.step_name.field}}
-                let re = regex::Regex::new(r"\{\{steps\.([^.]+)")?;
-        let mut deps = std::collections::HashSet::new();
-                
-        match value {
-            // The template could be a string, an object or an array
-            Value::String(s) => {      
-                for cap in re.captures_iter(s) {
-                    if let Some(step_name) = cap.get(1) {
-                        let step_name = step_name.as_str();
-                        // Find the step ID that corresponds to this step name
-                        let step = steps.get(step_name);
-                        if let Some(_step) = step {
-                            deps.insert(step_name.to_string());
-                        }
-                    }
-                }
-                Ok(deps.into_iter().collect())
-            },
-            // If the value is an object, we need to find the dependencies int the object
-            // recursively
-            Value::Object(obj) => {
-                for (_, v) in obj {
-                    let child_deps = self.find_sibling_dependencies(v, steps)?;
-                    deps.extend(child_deps);
-                }
-                Ok(deps.into_iter().collect())
-            },
-            // If the value is an array, we need to find the dependencies in the array
-            // recursively
-            Value::Array(arr) => {
-                for item in arr {
-                    let child_deps = self.find_sibling_dependencies(item, steps)?;
-                    deps.extend(child_deps);
-                }
-                Ok(deps.into_iter().collect())
-            },
-            _ => Ok(Vec::new())
+
+    /// Builds the `pre` or `post` hooks of a composition: steps given as `{"uses", "inputs"}`, whose
+    /// inputs may refer to the inputs of the composition
+    async fn build_hooks(&self,
+        phase: &str,
+        hooks: &[Value],
+        action_ref: &str,
+        parent_action_id: &str,
+        depth: Option<usize>,
+        chain: &[String]) -> Result<Vec<ShAction>> {
+        let mut built = Vec::new();
+        for (index, hook) in hooks.iter().enumerate() {
+            let uses = hook.get("uses").and_then(|uses| uses.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Hook {}[{}] of {} has no `uses`", phase, index, action_ref))?;
+            let inputs = match hook.get("inputs") {
+                None => &[][..],
+                Some(Value::Array(inputs)) => &inputs[..],
+                Some(_) => return Err(anyhow::anyhow!("Hook {}[{}] of {} has `inputs` that isn't an array", phase, index, action_ref)),
+            };
+            let mut hook_action = if depth == Some(0) {
+                Self::unexpanded_action(uses, parent_action_id)
+            } else {
+                Box::pin(self.build_action_tree_to_depth(uses, Some(parent_action_id), depth.map(|depth| depth - 1), chain)).await?
+            };
+            for (input, hook_input) in inputs.iter().zip(hook_action.inputs.iter_mut()) {
+                hook_input.template = Self::step_input_template(input);
+            }
+            built.push(hook_action);
         }
+        Ok(built)
     }
 
-    
-    /// Checks if a value contains unresolved template syntax
-    /// This checks for actual template patterns like {{steps.}} or {{inputs[}}, not just any {{ or }}
-    fn contains_unresolved_templates(&self, value: &Value) -> bool {
-        match value {
-            Value::String(s) => {
-                // Check for actual template patterns, not just any braces
-                // Template patterns are: {{steps.}}, {{inputs[}}, or {{outputs[}}
-                s.contains("{{steps.") || 
-                s.contains("{{inputs[") || 
-                s.contains("{{outputs[") ||
-                // Also check for incomplete patterns that might be templates
-                (s.contains("{{") && s.contains("}}") && (s.contains("steps") || s.contains("inputs") || s.contains("outputs")))
-            },
-            Value::Object(obj) => {
-                for (_, v) in obj {
-                    if self.contains_unresolved_templates(v) {
-                        return true;
-                    }
+    /// Describes the required inputs of the steps and hooks of a composition that aren't wired: their template is
+    /// null, or refers to an input the composition doesn't declare or to a step it doesn't have. Hooks only see
+    /// the inputs of the composition, so any step they refer to counts as missing.
+    fn unwired_required_inputs(action: &ShAction) -> Result<Vec<String>> {
+        let input_re = regex::Regex::new(r"\{\{inputs\[(\d+)\]")?;
+        let step_re = regex::Regex::new(r"\{\{steps\.([^.\[}]+)")?;
+        let steps = Self::sorted_step_ids(&action.steps).into_iter()
+            .map(|step_id| (format!("step '{}'", step_id), &action.steps[step_id], true));
+        let hooks = [("pre", &action.pre), ("post", &action.post)].into_iter()
+            .flat_map(|(phase, hooks)| hooks.iter().enumerate()
+                .map(move |(index, hook)| (format!("hook '{}[{}]'", phase, index), hook, false)));
+        let mut unwired = Vec::new();
+        for (label, child, sees_steps) in steps.chain(hooks) {
+            for (index, input) in child.inputs.iter().enumerate() {
+                if !input.required {
+                    continue;
                 }
-                false
-            },
-            Value::Array(arr) => {
-                for item in arr {
-                    if self.contains_unresolved_templates(item) {
-                        return true;
-                    }
+                let template = serde_json::to_string(&input.template)?;
+                let reason = if input.template.is_null() {
+                    Some("isn't wired".to_string())
+                } else if let Some(cap) = input_re.captures_iter(&template)
+                    .find(|cap| !cap[1].parse::<usize>().is_ok_and(|i| i < action.inputs.len())) {
+                    Some(format!("refers to inputs[{}], which isn't declared", &cap[1]))
+                } else if let Some(cap) = step_re.captures_iter(&template).find(|_| !sees_steps) {
+                    Some(format!("refers to step '{}', which hooks can't see", &cap[1]))
+                } else {
+                    step_re.captures_iter(&template)
+                        .find(|cap| !action.steps.contains_key(&cap[1]))
+                        .map(|cap| format!("refers to step '{}', which doesn't exist", &cap[1]))
+                };
+                if let Some(reason) = reason {
+                    unwired.push(format!("{} input '{}' (#{}) {}", label, input.name, index, reason));
                 }
-                false
-            },
-            _ => false, // Numbers, booleans, null don't contain templates
+            }
         }
+        Ok(unwired)
     }
 
-    /// Finds all ready steps - steps where all inputs are resolved and at least one output is not populated
-    fn find_ready_step_ids(
-        &self,
-        steps: &HashMap<String, ShAction>,
-    ) -> Result<Vec<String>> {
-        let mut ready_steps = Vec::new();
-        
-        for (step_id, step) in steps {
-            // Check if all inputs have been resolved (every input has a "value" field populated)
-            let all_inputs_resolved = step.inputs.iter().all(|input| {
-                input.value.is_some()
-            });
+    /// Placeholder of a step below the depth limit of a build
+    fn unexpanded_action(action_ref: &str, parent_action_id: &str) -> ShAction {
+        ShAction {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: action_ref.to_string(),
+            kind: UNEXPANDED_KIND.to_string(),
+            uses: action_ref.to_string(),
+            resolved_uses: action_ref.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            parent_action: Some(parent_action_id.to_string()),
+            priority: 0,
+            optional: false,
+            when: None,
+            when_holds: None,
+            foreach: None,
+            foreach_instances: None,
+            timeout: None,
+            retry: None,
+            steps: HashMap::new(),
+            pre: Vec::new(),
+            post: Vec::new(),
+            role: None,
+            types: None,
+            mirrors: Vec::new(),
+            permissions: None,
+        }
+    }
 
-            if all_inputs_resolved {
-                ready_steps.push(step_id.clone());
-            }
+    /// The concrete reference of a registry action: a `latest`, missing or range version
+    /// is replaced by the version of the manifest it resolved to. Other references are returned as is.
+    fn concrete_ref(action_ref: &str, manifest: &ShManifest) -> String {
+        if action_ref.contains("://") {
+            return action_ref.to_string();
+        }
+        let (name, version) = action_ref.split_once(':').unwrap_or((action_ref, ""));
+        if Self::is_pinned_version(version) || manifest.version.is_empty() {
+            action_ref.to_string()
+        } else {
+            format!("{}:{}", name, manifest.version)
         }
-        
-        Ok(ready_steps)
     }
 
-    /// Finds the first step that depends on a completed step and is now ready to execute
-    fn find_next_step_id(
-        &self,
-        steps: &HashMap<String, ShAction>,
-        completed_step_id: &str,
-        parent_inputs: &Vec<ShIO>,
-        outputs: &Vec<ShIO>,
-    ) -> Result<Vec<String>> {
-        // Find all steps that depend on the completed step and are ready
-        // Sort steps by priority (lower priority number = higher priority)
-        let mut sorted_steps: Vec<_> = steps.iter().collect();
-        sorted_steps.sort_by(|(_, a), (_, b)| {
-            a.priority.cmp(&b.priority)
-        });
+    /// Whether a version names exactly one release, rather than being missing, `latest` or a range
+    fn is_pinned_version(version: &str) -> bool {
+        !version.is_empty()
+            && version != "latest"
+            && version.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+')
+            && !version.split('.').any(|part| part == "x" || part == "X")
+    }
 
-        let mut downstream_steps = Vec::new();
+    /// Builds the tree of an action without running it, with the concrete reference of every step
+    pub async fn plan_action(&self, action_ref: &str) -> Result<(ShAction, ActionGraph)> {
+        self.plan_action_to_depth(action_ref, None).await
+    }
 
-        // if the current step is a flow control step, we want to find it among the steps, 
-        // get the next step by using the first output of the step we have just executed.
-        if let Some((_, step)) = sorted_steps.iter().find(|(id, _)| id == &completed_step_id) {
-            if step.role.as_ref().map_or(false, |r| r == &ShRole::FlowControl) {
-                
-                if let Some(output) = step.outputs.first() {
-                    if let Some(output_value) = &output.value {
-                        if let Some(output_value_str) = output_value.as_str() {
-                            let next_step_id = output_value_str;
-                            // Check if the step exists in the steps vector
-                            // if steps.contains_key(next_step_id) {
-                            downstream_steps.push(next_step_id.to_string());
-                            // }
-                        }
+    /// Like `plan_action`, expanding at most `depth` levels of steps: deeper steps are `unexpanded` placeholders
+    pub async fn plan_action_to_depth(&self, action_ref: &str, depth: Option<usize>) -> Result<(ShAction, ActionGraph)> {
+        let root = self.build_action_tree_to_depth(action_ref, None, depth, &[]).await?;
+        let mut graph = ActionGraph::default();
+        self.collect_graph(&root, "", &mut graph)?;
+        Ok((root, graph))
+    }
+
+    fn collect_graph(&self, action: &ShAction, path: &str, graph: &mut ActionGraph) -> Result<()> {
+        graph.nodes.push(GraphNode {
+            path: path.to_string(),
+            name: action.name.clone(),
+            kind: action.kind.clone(),
+            uses: action.uses.clone(),
+            resolved_uses: action.resolved_uses.clone(),
+        });
+
+        for step_id in Self::sorted_step_ids(&action.steps) {
+            let step = &action.steps[step_id];
+            let step_path = if path.is_empty() { step_id.clone() } else { format!("{}.{}", path, step_id) };
+            for input in &step.inputs {
+                let mut dependencies = self.find_sibling_dependencies(&input.template, &action.steps)?;
+                dependencies.sort();
+                for dependency in dependencies {
+                    let from = if path.is_empty() { dependency } else { format!("{}.{}", path, dependency) };
+                    let edge = GraphEdge { from, to: step_path.clone() };
+                    if !graph.edges.contains(&edge) {
+                        graph.edges.push(edge);
                     }
                 }
             }
+            self.collect_graph(step, &step_path, graph)?;
         }
-            
-        for (step_id, step) in sorted_steps {
-            // Skip if this is the step we just completed
-            if step_id == completed_step_id {
-                continue;
-            }
+        Ok(())
+    }
+
+    /// Builds an action and lists its steps in the order they would be executed in, with the inputs
+    /// they would start with, without running any of them. Steps waiting for the outputs of a sibling
+    /// are reported as blocked, and so is everything under them.
+    pub async fn dry_run(&self, action_ref: &str, input_values: Vec<Value>) -> Result<Vec<PlannedStep>> {
+        let root = self.build_action_tree(action_ref, None).await?;
+        reject_file_inputs(&input_values)?;
+        let root = ShAction {
+            inputs: self.cast_input_values_to_typed_array(action_ref, &root.inputs, &input_values, &root.types)?,
+            ..root
+        };
+
+        let mut plan = Vec::new();
+        self.plan_steps(&root, "", None, &mut plan)?;
+        Ok(plan)
+    }
+
+    fn plan_steps(&self, action: &ShAction, step_path: &str, blocked_parent: Option<&str>, plan: &mut Vec<PlannedStep>) -> Result<()> {
+        // Inputs only flow into the steps of a composition that would start
+        let steps = match blocked_parent {
+            Some(_) => action.steps.clone(),
+            None => self.recalculate_steps(&action.inputs, &action.steps)?,
+        };
+
+        let mut dependencies: HashMap<&String, Vec<String>> = HashMap::new();
+        for (step_id, step) in &steps {
+            let mut step_dependencies = Vec::new();
+            for input in &step.inputs {
+                step_dependencies.extend(self.find_sibling_dependencies(&input.template, &steps)?);
+            }
+            for template in step.when.iter().chain(&step.foreach) {
+                step_dependencies.extend(self.find_sibling_dependencies(&Value::String(template.clone()), &steps)?);
+            }
+            step_dependencies.sort();
+            step_dependencies.dedup();
+            dependencies.insert(step_id, step_dependencies);
+        }
+
+        // Like the execution loop: by priority, once the steps a step depends on have run.
+        // A dependency cycle would never run, it is listed in priority order.
+        let mut remaining = Self::sorted_step_ids(&steps);
+        while !remaining.is_empty() {
+            let next = remaining.iter()
+                .position(|step_id| dependencies[*step_id].iter().all(|dependency| {
+                    plan.iter().any(|planned| planned.step_path == Self::child_step_path(step_path, dependency))
+                }))
+                .unwrap_or(0);
+            let step_id = remaining.remove(next);
+            let step = &steps[step_id];
+            let child_step_path = Self::child_step_path(step_path, step_id);
+
+            let ready = blocked_parent.is_none() && Self::is_step_ready(step);
+            let blocked_by = match (blocked_parent, dependencies[step_id].as_slice()) {
+                _ if ready => None,
+                (Some(parent), _) => Some(format!("its parent step '{}' is blocked", parent)),
+                (None, []) => Some("its inputs can't be resolved from the inputs of its parent".to_string()),
+                (None, waiting_on) => Some(format!("it waits for the outputs of {}", waiting_on.join(", "))),
+            };
+            plan.push(PlannedStep {
+                step_path: child_step_path.clone(),
+                kind: step.kind.clone(),
+                uses: step.uses.clone(),
+                priority: step.priority,
+                ready,
+                blocked_by,
+                inputs: step.inputs.iter()
+                    .map(|input| PlannedInput {
+                        name: input.name.clone(),
+                        r#type: input.r#type.clone(),
+                        resolved: ready,
+                        value: match &input.value {
+                            Some(value) if ready => value.clone(),
+                            _ => input.template.clone(),
+                        },
+                    })
+                    .collect(),
+            });
+
+            let blocked_step = if ready { None } else { Some(blocked_parent.unwrap_or(&child_step_path)) };
+            self.plan_steps(step, &child_step_path, blocked_step, plan)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the artifact of every wasm and docker step of an action can be fetched, without
+    /// downloading it: a cached artifact passes, otherwise the registry and then the mirrors are probed
+    /// with HEAD requests. Returns the steps whose artifact isn't available anywhere.
+    pub async fn check_artifacts(&self, action_ref: &str) -> Result<Vec<MissingArtifact>> {
+        let root = self.build_action_tree(action_ref, None).await?;
+        let mut leaves = Vec::new();
+        Self::collect_leaves(&root, "", &mut leaves);
+
+        let mut missing = Vec::new();
+        for (step_path, leaf) in leaves {
+            let artifact_dir = self.cache_dir.join(leaf.uses.replace(':', "/"));
+            if artifact_dir.join("artifact.wasm").exists() || artifact_dir.join("artifact.tar").exists() {
+                continue;
+            }
+
+            let mut tried = Vec::new();
+            let mut urls = Vec::new();
+            match http::registry_artifact_url(self.api_base(), &leaf.uses) {
+                Ok(url) => urls.push(url),
+                Err(e) => tried.push(format!("registry: {}", e)),
+            }
+            urls.extend(leaf.mirrors.iter().cloned());
+
+            let mut found = false;
+            for url in urls {
+                match self.http_client.head(&url).send().await {
+                    Ok(response) if response.status().is_success() => {
+                        found = true;
+                        break;
+                    }
+                    Ok(response) => tried.push(format!("{}: HTTP {}", url, response.status().as_u16())),
+                    Err(e) => tried.push(format!("{}: {}", url, e)),
+                }
+            }
+            if !found {
+                self.logger.log_warning(&format!("Artifact of step '{}' ({}) isn't available", step_path, leaf.uses), None);
+                missing.push(MissingArtifact {
+                    step_path,
+                    uses: leaf.uses.clone(),
+                    kind: leaf.kind.clone(),
+                    tried,
+                });
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Checks the source of a local manifest before it is published, and returns the problems found: an
+    /// unknown kind, steps whose `uses` can't be fetched, inputs and outputs whose type is neither
+    /// primitive nor declared in `types`, and templates referring to steps that don't exist. Fails when
+    /// the manifest can't be parsed at all. `name` only identifies the manifest in the messages. With
+    /// `check_artifacts`, the wasm and docker artifacts the steps need are also checked, as by `check_artifacts`.
+    pub async fn validate_manifest(&self, name: &str, source: &str, check_artifacts: bool) -> Result<Vec<String>> {
+        let mut raw: Value = serde_json::from_str(source)
+            .map_err(|e| anyhow::anyhow!("{} isn't valid JSON: {}", name, e))?;
+        
+        let mut problems = Vec::new();
+        // An unknown kind is reported on its own rather than failing the whole parse
+        if let Some(kind) = raw.get("kind").filter(|kind| !kind.is_null()) {
+            if serde_json::from_value::<ShKind>(kind.clone()).is_err() {
+                problems.push(format!("unknown kind {}, expected composition, wasm or docker", kind));
+                raw["kind"] = Value::Null;
+            }
+        }
+        let manifest: ShManifest = serde_json::from_value(raw)
+            .map_err(|e| anyhow::anyhow!("{} isn't a valid manifest: {}", name, e))?;
+        
+        let mut step_names: Vec<&String> = manifest.steps.keys().collect();
+        step_names.sort();
+        let missing_steps = |template: &Value| -> Result<Vec<String>> {
+            Ok(Self::referenced_step_names(template)?.into_iter()
+                .filter(|step_name| !manifest.steps.contains_key(step_name))
+                .collect())
+        };
+        
+        for step_name in &step_names {
+            let step = &manifest.steps[*step_name];
+            match step.get("uses").and_then(|uses| uses.as_str()) {
+                Some(uses) => match self.fetch_manifest(uses).await {
+                    Err(e) => problems.push(format!("step '{}' uses {}, which can't be fetched: {}", step_name, uses, e)),
+                    Ok(_) if check_artifacts => match self.check_artifacts(uses).await {
+                        Ok(missing) => {
+                            for artifact in missing {
+                                let step_path = if artifact.step_path.is_empty() {
+                                    step_name.to_string()
+                                } else {
+                                    format!("{}.{}", step_name, artifact.step_path)
+                                };
+                                problems.push(format!(
+                                    "step '{}' needs the {} artifact of {}, which can't be fetched: {}",
+                                    step_path, artifact.kind, artifact.uses, artifact.tried.join(", ")
+                                ));
+                            }
+                        }
+                        Err(e) => problems.push(format!("step '{}' uses {}, whose artifacts can't be checked: {}", step_name, uses, e)),
+                    },
+                    Ok(_) => {}
+                },
+                None => problems.push(format!("step '{}' has no `uses`", step_name)),
+            }
+            let inputs = step.get("inputs").and_then(|inputs| inputs.as_array()).cloned().unwrap_or_default();
+            for (index, input) in inputs.iter().enumerate() {
+                for missing in missing_steps(input)? {
+                    problems.push(format!("step '{}' input #{} refers to step '{}', which doesn't exist", step_name, index, missing));
+                }
+            }
+        }
+        
+        for field in ["inputs", "outputs"] {
+            let value = if field == "inputs" { &manifest.inputs } else { &manifest.outputs };
+            let ios = match Self::parse_manifest_io(name, field, value) {
+                Ok(ios) => ios,
+                Err(e) => {
+                    problems.push(e.to_string());
+                    continue;
+                }
+            };
+            let singular = field.trim_end_matches('s');
+            for (index, io) in ios.iter().enumerate() {
+                if !PRIMITIVE_TYPES.contains(&io.r#type.as_str()) && !manifest.types.contains_key(&io.r#type) {
+                    problems.push(format!(
+                        "{} '{}' (#{}) has type '{}', which is neither a primitive type nor declared in `types`",
+                        singular, io.name, index, io.r#type
+                    ));
+                }
+                for missing in missing_steps(&io.template)? {
+                    problems.push(format!("{} '{}' (#{}) refers to step '{}', which doesn't exist", singular, io.name, index, missing));
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Lists the wasm and docker actions of a tree with their step path, in step order
+    fn collect_leaves<'a>(action: &'a ShAction, path: &str, leaves: &mut Vec<(String, &'a ShAction)>) {
+        if action.kind != "composition" {
+            leaves.push((path.to_string(), action));
+            return;
+        }
+        for step_id in Self::sorted_step_ids(&action.steps) {
+            let step_path = if path.is_empty() { step_id.clone() } else { format!("{}.{}", path, step_id) };
+            Self::collect_leaves(&action.steps[step_id], &step_path, leaves);
+        }
+    }
+
+    /// Evaluates a `required_if` expression against the provided inputs.
+    /// Supports `name == literal`, `name != literal` and a bare `name`, which holds
+    /// when that input is provided and truthy. Literals are JSON, or a single-quoted string.
+    fn evaluate_required_if(expression: &str, inputs: &[ShIO], input_values: &[Value]) -> Result<bool> {
+        let lookup = |name: &str| -> Result<Option<Value>> {
+            let index = inputs.iter().position(|io| io.name == name)
+                .ok_or_else(|| anyhow::anyhow!("Invalid required_if '{}': unknown input '{}'", expression, name))?;
+            Ok(input_values.get(index).filter(|value| !value.is_null()).cloned())
+        };
+
+        let (name, negate, literal) = if let Some((name, literal)) = expression.split_once("!=") {
+            (name.trim(), true, literal.trim())
+        } else if let Some((name, literal)) = expression.split_once("==") {
+            (name.trim(), false, literal.trim())
+        } else {
+            let truthy = match lookup(expression.trim())? {
+                None | Some(Value::Bool(false)) => false,
+                Some(Value::String(s)) => !s.is_empty(),
+                Some(_) => true,
+            };
+            return Ok(truthy);
+        };
+
+        let expected = match literal.strip_prefix('\'').and_then(|l| l.strip_suffix('\'')) {
+            Some(quoted) => Value::String(quoted.to_string()),
+            None => serde_json::from_str(literal)
+                .map_err(|_| anyhow::anyhow!("Invalid required_if '{}': cannot parse '{}'", expression, literal))?,
+        };
+        let matches = lookup(name)? == Some(expected);
+        Ok(matches != negate)
+    }
+
+    /// Splits the `when` of a step into `left == right`, `left != right` or a bare value, before any
+    /// of its templates is interpolated. Operators inside `{{...}}` are part of their template.
+    /// Returns the left operand, and whether the comparison is an equality along with its right operand.
+    fn parse_when(when: &str) -> (&str, Option<(bool, &str)>) {
+        let mut depth = 0usize;
+        let bytes = when.as_bytes();
+        let mut index = 0;
+        while index + 1 < bytes.len() {
+            match &bytes[index..index + 2] {
+                b"{{" => depth += 1,
+                b"}}" => depth = depth.saturating_sub(1),
+                b"==" | b"!=" if depth == 0 => {
+                    return (&when[..index], Some((bytes[index] == b'=', &when[index + 2..])));
+                }
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            }
+            index += 2;
+        }
+        (when, None)
+    }
+
+    /// Resolves one operand of the `when` of a step. A lone template takes the value it refers to,
+    /// text with templates is interpolated into a string, and a literal is JSON, a single-quoted
+    /// string or else the text itself. None when the operand can't be interpolated.
+    fn when_operand(&self,
+        text: &str,
+        values: &Vec<Value>,
+        inputs_object: &Value,
+        children: &HashMap<String, ShAction>) -> Option<Value> {
+        let text = text.trim();
+        if text.contains("{{") {
+            return self.interpolate_into_untyped_value(&Value::String(text.to_string()), values, inputs_object, Some(children)).ok();
+        }
+        Some(match text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+            Some(quoted) => Value::String(quoted.to_string()),
+            None => serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())),
+        })
+    }
+
+    /// Evaluates the resolved operands of the `when` of a step: a comparison when it has a right
+    /// operand, whether the left one holds otherwise. A value holds unless it's null, false, 0,
+    /// or an empty string, array or object.
+    fn evaluate_when(left: &Value, comparison: Option<(bool, &Value)>) -> bool {
+        fn truthy(value: &Value) -> bool {
+            match value {
+                Value::Null | Value::Bool(false) => false,
+                Value::Number(n) => n.as_f64() != Some(0.0),
+                Value::String(s) => !s.is_empty(),
+                Value::Array(items) => !items.is_empty(),
+                Value::Object(map) => !map.is_empty(),
+                Value::Bool(true) => true,
+            }
+        }
+
+        match comparison {
+            Some((equal, right)) => (left == right) == equal,
+            None => truthy(left),
+        }
+    }
+
+    /// Parses the inputs or outputs of a manifest, which must be an array when present
+    fn parse_manifest_io(action_ref: &str, field: &str, value: &Value) -> Result<Vec<ShIO>> {
+        let items = match value {
+            Value::Null => return Ok(Vec::new()),
+            Value::Array(items) => items,
+            Value::Object(_) => return Err(anyhow::anyhow!("Invalid manifest for {}: `{}` must be an array, got an object", action_ref, field)),
+            _ => return Err(anyhow::anyhow!("Invalid manifest for {}: `{}` must be an array, got {}", action_ref, field, value)),
+        };
+
+        Ok(items.iter().filter_map(|item| {
+            item.as_object().map(|obj| ShIO {
+                name: obj.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                r#type: obj.get("type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                template: obj.get("value").cloned().unwrap_or(serde_json::Value::Null),
+                value: None,
+                required: obj.get("required").and_then(|v| v.as_bool()).unwrap_or(false),
+                required_if: obj.get("required_if").and_then(|v| v.as_str()).map(|v| v.to_string()),
+                transform: obj.get("transform").cloned(),
+                default: obj.get("default").cloned(),
+            })
+        }).collect())
+    }
+
+    /// Assigns priorities to steps: the one given in the manifest, or else the alphabetical rank of the step
+    fn produce_steps_with_priorities(
+        &self,
+        steps: &HashMap<String, ShAction>,
+        explicit_priorities: &HashMap<String, i32>,
+    ) -> HashMap<String, ShAction>{
+        // Sort step keys alphabetically to ensure deterministic ordering
+        let mut sorted_keys: Vec<_> = steps.keys().collect();
+        sorted_keys.sort();
+        
+        // Create new steps with priorities assigned based on alphabetical order, unless explicit
+        let mut steps_with_priorities = HashMap::new();
+        for (index, step_key) in sorted_keys.iter().enumerate() {
+            if let Some(step) = steps.get(*step_key) {
+                let mut updated_step = step.clone();
+                updated_step.priority = explicit_priorities.get(*step_key).copied().unwrap_or(index as i32);
+                steps_with_priorities.insert((*step_key).clone(), updated_step);
+            }
+        }
+        
+        steps_with_priorities
+    }
+    
+    /// Warns about the provided inputs of a composition that no step or output template references,
+    /// or fails in strict mode
+    fn check_unused_inputs(&self, action: &ShAction) -> Result<()> {
+        // Wasm and docker actions hand their inputs to the artifact as a whole
+        if action.kind != "composition" {
+            return Ok(());
+        }
+
+        let mut referenced = std::collections::HashSet::new();
+        for step in action.steps.values() {
+            for input in &step.inputs {
+                referenced.extend(self.find_input_references(&input.template, action.inputs.len())?);
+            }
+        }
+        for output in &action.outputs {
+            referenced.extend(self.find_input_references(&output.template, action.inputs.len())?);
+        }
+
+        let unused: Vec<String> = action.inputs.iter()
+            .enumerate()
+            .filter(|(index, io)| !referenced.contains(index) && !matches!(io.value, None | Some(Value::Null)))
+            .map(|(index, io)| if io.name.is_empty() { format!("inputs[{}]", index) } else { io.name.clone() })
+            .collect();
+        if unused.is_empty() {
+            return Ok(());
+        }
+
+        let message = format!("Input(s) not referenced by any step or output: {}", unused.join(", "));
+        if self.options.strict_inputs {
+            return Err(anyhow::anyhow!(message));
+        }
+        self.logger.log_warning(&message, Some(&action.id));
+        Ok(())
+    }
+
+    /// Finds the indexes of the inputs referenced by a template. `{{inputs}}` references all of them.
+    fn find_input_references(&self, value: &Value, inputs_count: usize) -> Result<Vec<usize>> {
+        // Look for patterns like {{inputs[0]}} or {{inputs[0].field}}
+        let re = regex::Regex::new(r"\{\{inputs\[(\d+)\]")?;
+
+        match value {
+            Value::String(s) => {
+                if s.contains("{{inputs}}") {
+                    return Ok((0..inputs_count).collect());
+                }
+                Ok(re.captures_iter(s)
+                    .filter_map(|cap| cap.get(1).and_then(|index| index.as_str().parse().ok()))
+                    .collect())
+            },
+            Value::Object(obj) => {
+                let mut references = Vec::new();
+                for (_, v) in obj {
+                    references.extend(self.find_input_references(v, inputs_count)?);
+                }
+                Ok(references)
+            },
+            Value::Array(arr) => {
+                let mut references = Vec::new();
+                for item in arr {
+                    references.extend(self.find_input_references(item, inputs_count)?);
+                }
+                Ok(references)
+            },
+            _ => Ok(Vec::new())
+        }
+    }
+
+    pub fn find_sibling_dependencies(&self, value: &Value, steps: &HashMap<String, ShAction>) -> Result<Vec<String>> {
+        Ok(Self::referenced_step_names(value)?.into_iter()
+            .filter(|step_name| steps.contains_key(step_name))
+            .collect())
+    }
+
+    /// Names of the steps a template refers to with `{{steps.<name>...}}`, sorted and each once.
+    /// The template could be a string, or an object or array holding templates at any depth.
+    fn referenced_step_names(value: &Value) -> Result<Vec<String>> {
+        let re = regex::Regex::new(r"\{\{steps\.([^.]+)")?;
+        let mut names = std::collections::BTreeSet::new();
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::String(s) => {
+                    names.extend(re.captures_iter(s).map(|cap| cap[1].to_string()));
+                }
+                Value::Object(obj) => pending.extend(obj.values()),
+                Value::Array(arr) => pending.extend(arr.iter()),
+                _ => {}
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    
+    /// Checks if a value contains unresolved template syntax
+    /// This checks for actual template patterns like {{steps.}} or {{inputs[}}, not just any {{ or }}
+    fn contains_unresolved_templates(&self, value: &Value) -> bool {
+        match value {
+            Value::String(s) => {
+                // Escaped braces are literal text, not templates
+                let s = &s.replace(ESCAPED_OPEN_BRACES, "").replace(ESCAPED_CLOSE_BRACES, "");
+                // Check for actual template patterns, not just any braces
+                // Template patterns are: {{steps.}}, {{inputs[}}, or {{outputs[}}
+                s.contains("{{steps.") || 
+                s.contains("{{inputs[") || 
+                s.contains("{{outputs[") ||
+                // Also check for incomplete patterns that might be templates
+                (s.contains("{{") && s.contains("}}") && (s.contains("steps") || s.contains("inputs") || s.contains("outputs")))
+            },
+            Value::Object(obj) => {
+                for (_, v) in obj {
+                    if self.contains_unresolved_templates(v) {
+                        return true;
+                    }
+                }
+                false
+            },
+            Value::Array(arr) => {
+                for item in arr {
+                    if self.contains_unresolved_templates(item) {
+                        return true;
+                    }
+                }
+                false
+            },
+            _ => false, // Numbers, booleans, null don't contain templates
+        }
+    }
+
+    /// Returns the step ids ordered by priority, then by name, so that iterating over steps
+    /// doesn't depend on the HashMap order
+    fn sorted_step_ids(steps: &HashMap<String, ShAction>) -> Vec<&String> {
+        let mut step_ids: Vec<&String> = steps.keys().collect();
+        step_ids.sort_by(|a, b| steps[*a].priority.cmp(&steps[*b].priority).then_with(|| a.cmp(b)));
+        step_ids
+    }
+
+    /// Finds all ready steps - steps where all inputs are resolved and at least one output is not populated
+    fn find_ready_step_ids(
+        &self,
+        steps: &HashMap<String, ShAction>,
+    ) -> Result<Vec<String>> {
+        let mut ready_steps = Vec::new();
+        
+        for step_id in Self::sorted_step_ids(steps) {
+            let step = &steps[step_id];
+            // Check if all inputs have been resolved (every input has a "value" field populated)
+            if Self::is_step_ready(step) {
+                ready_steps.push(step_id.clone());
+            }
+        }
+        
+        Ok(ready_steps)
+    }
+
+    /// Finds the first step that depends on a completed step and is now ready to execute
+    fn find_next_step_id(
+        &self,
+        steps: &HashMap<String, ShAction>,
+        completed_step_id: &str,
+        parent_inputs: &Vec<ShIO>,
+        outputs: &Vec<ShIO>,
+    ) -> Result<Vec<String>> {
+        // Find all steps that depend on the completed step and are ready
+        // Sort steps by priority (lower priority number = higher priority)
+        let sorted_steps: Vec<_> = Self::sorted_step_ids(steps).into_iter()
+            .map(|step_id| (step_id, &steps[step_id]))
+            .collect();
+
+        let mut downstream_steps = Vec::new();
+
+        // if the current step is a flow control step, we want to find it among the steps, 
+        // get the next step by using the first output of the step we have just executed.
+        if let Some((_, step)) = sorted_steps.iter().find(|(id, _)| id == &completed_step_id) {
+            if step.role.as_ref().map_or(false, |r| r == &ShRole::FlowControl) {
+                
+                if let Some(output) = step.outputs.first() {
+                    if let Some(output_value) = &output.value {
+                        if let Some(output_value_str) = output_value.as_str() {
+                            let next_step_id = output_value_str;
+                            // Check if the step exists in the steps vector
+                            // if steps.contains_key(next_step_id) {
+                            downstream_steps.push(next_step_id.to_string());
+                            // }
+                        }
+                    }
+                }
+            }
+        }
+            
+        for (step_id, step) in sorted_steps {
+            // Skip if this is the step we just completed
+            if step_id == completed_step_id {
+                continue;
+            }
 
             let depends_on = self.step_depends_on(step, completed_step_id);
             let is_ready = self.are_all_inputs_ready(step, &step.inputs)?;
@@ -1313,12 +3777,25 @@ impl ExecutionEngine {
         step: &ShAction,
         _parent_inputs: &Vec<ShIO>,
     ) -> Result<bool> {
-        // Check if all input values are populated (not null)
-        let all_inputs_resolved = step.inputs.iter().all(|input| {
-            input.value.is_some()
-        });
-        
-        Ok(all_inputs_resolved)
+        Ok(Self::is_step_ready(step))
+    }
+
+    /// Whether all the inputs of a step have values, or those of all its instances for a `foreach`
+    /// step. A step with a `when` also waits for its condition to resolve, whether or not it will hold.
+    fn is_step_ready(step: &ShAction) -> bool {
+        let inputs_resolved = match &step.foreach {
+            Some(_) => step.foreach_instances.is_some(),
+            None => step.inputs.iter().all(|input| input.value.is_some()),
+        };
+        inputs_resolved && (step.when.is_none() || step.when_holds.is_some())
+    }
+
+    /// Names of the sibling steps the inputs, condition or `foreach` of a step refer to
+    fn step_dependencies(step: &ShAction) -> Result<Vec<String>> {
+        let templates = step.inputs.iter()
+            .map(|input| input.template.clone())
+            .chain(step.when.iter().chain(&step.foreach).map(|template| Value::String(template.clone())));
+        Self::referenced_step_names(&Value::Array(templates.collect()))
     }
 
     /// Checks if a step depends on another step (simplified dependency check)
@@ -1329,7 +3806,8 @@ impl ExecutionEngine {
                     return true;
                 }
         }
-        false
+        step.when.iter().chain(&step.foreach)
+            .any(|template| self.value_contains_dependency(&Value::String(template.clone()), dependency_step_id))
     }
 
     /// Recursively checks if a Value contains a dependency reference
@@ -1360,40 +3838,345 @@ impl ExecutionEngine {
         }
     }
 
-    // Fetches the manifest and parses into an ShManifest object
-    async fn fetch_manifest(&self, action_ref: &str) -> Result<ShManifest> {
-        // Construct storage URL for starthub-lock.json
-        let url_path = action_ref.replace(":", "/");
-        let storage_url = format!(
-            "{}{}/{}/{}",
-            STARTHUB_API_BASE_URL,
-            STARTHUB_STORAGE_PATH,
-            url_path,
-            STARTHUB_MANIFEST_FILENAME
-        );
-
-        // Download and parse starthub-lock.json
-        let client = reqwest::Client::new();
-        let response = client.get(&storage_url).send().await?;
-        
-        if response.status().is_success() {
-            // Log the response body for debugging
-            let response_text = response.text().await?;            
-            // Try to parse the JSON
-            let manifest: ShManifest = serde_json::from_str(&response_text)
-                .map_err(|e| anyhow::anyhow!("JSON parsing error: {} - Response: {}", e, response_text))?;
+    /// Fetches the manifest and parses into an ShManifest object.
+    /// Manifests are served from the replay directory, or recorded to the record directory, when set.
+    pub async fn fetch_manifest(&self, action_ref: &str) -> Result<ShManifest> {
+        // A locked reference is fetched as the version it was locked to, and must still have its digest
+        let Some(lockfile) = &self.options.lockfile else {
+            return self.fetch_unlocked_manifest(action_ref).await;
+        };
+        let locked = lockfile.locked(action_ref)?;
+        let manifest = self.fetch_unlocked_manifest(&locked.resolved).await?;
+        lockfile.verify(action_ref, &manifest)?;
         Ok(manifest)
-        } else {
-            Err(anyhow::anyhow!("Failed to download starthub-lock.json: {} from url: {}", response.status(), storage_url))
-        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    
-    use serde_json::json;
+    async fn fetch_unlocked_manifest(&self, action_ref: &str) -> Result<ShManifest> {
+        let action_ref = &self.resolve_alias(action_ref).await?;
+        self.check_namespace_allowed(action_ref)?;
+        
+        if let Some(replay_dir) = &self.options.replay_dir {
+            let recording = Self::read_manifest_recording(replay_dir)?;
+            let manifest = recording.get(action_ref)
+                .ok_or_else(|| anyhow::anyhow!("No manifest recorded for {} in {:?}", action_ref, replay_dir))?;
+            self.logger.log_info(&format!("Manifest {} replayed from {:?}", action_ref, replay_dir), None);
+            return Ok(serde_json::from_value(manifest.clone())?);
+        }
+
+        let manifest = self.load_manifest(action_ref).await?;
+        if let Some(record_dir) = &self.options.record_dir {
+            let mut recording = if record_dir.join(MANIFEST_RECORDING_FILENAME).exists() {
+                Self::read_manifest_recording(record_dir)?
+            } else {
+                serde_json::Map::new()
+            };
+            recording.insert(action_ref.to_string(), serde_json::to_value(&manifest)?);
+            std::fs::create_dir_all(record_dir)?;
+            std::fs::write(record_dir.join(MANIFEST_RECORDING_FILENAME), serde_json::to_string_pretty(&recording)?)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Expands an alias to the action reference it points at. Other references are returned as is,
+    /// as are bare slugs that aren't aliases and aliases when there is no database to look them up in.
+    async fn resolve_alias(&self, action_ref: &str) -> Result<String> {
+        let Some(database) = self.database.as_ref().filter(|_| is_alias(action_ref)) else {
+            return Ok(action_ref.to_string());
+        };
+        match database.lock().await.get_alias(action_ref)? {
+            Some(target) => {
+                self.logger.log_info(&format!("Alias {} resolved to {}", action_ref, target), None);
+                Ok(target)
+            }
+            None => Ok(action_ref.to_string()),
+        }
+    }
+
+    /// Rejects an action outside of the allowed namespaces. Local manifests (file://) aren't namespaced,
+    /// so they are rejected whenever namespaces are restricted; actions mapped by a workspace are checked
+    /// by the namespace of their reference.
+    fn check_namespace_allowed(&self, action_ref: &str) -> Result<()> {
+        let Some(allowed) = &self.allowed_namespaces else {
+            return Ok(());
+        };
+        if action_ref.starts_with("file://") {
+            anyhow::bail!(
+                "Action {} is not allowed: local manifests aren't in any of the allowed namespaces ({})",
+                action_ref,
+                allowed.join(", ")
+            );
+        }
+        let namespace = action_ref.split_once('/').map(|(namespace, _)| namespace).unwrap_or("");
+        if !allowed.iter().any(|allowed| allowed == namespace) {
+            anyhow::bail!(
+                "Action {} is not allowed: namespace '{}' is not one of the allowed namespaces ({})",
+                action_ref,
+                namespace,
+                allowed.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads the manifests recorded in a directory, keyed by action reference
+    fn read_manifest_recording(dir: &std::path::Path) -> Result<serde_json::Map<String, Value>> {
+        let path = dir.join(MANIFEST_RECORDING_FILENAME);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read manifest recording {:?}: {}", path, e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid manifest recording {:?}: {}", path, e))
+    }
+
+    /// The local manifest the workspace maps an action to: by exact reference first, then by `namespace/slug`
+    fn workspace_manifest(&self, action_ref: &str) -> Option<&std::path::PathBuf> {
+        let workspace = &self.options.workspace;
+        workspace.get(action_ref).or_else(|| {
+            let (name, _version) = action_ref.split_once(':')?;
+            workspace.get(name)
+        })
+    }
+
+    /// Reads a local manifest or downloads it from the registry
+    async fn load_manifest(&self, action_ref: &str) -> Result<ShManifest> {
+        // Actions under development in the workspace are read from their local manifest
+        if let Some(manifest_path) = self.workspace_manifest(action_ref) {
+            let manifest_path = confine_path(manifest_path, self.allowed_roots.as_deref())?;
+            self.logger.log_info(&format!("Manifest {} read from workspace {:?}", action_ref, manifest_path), None);
+            return Self::read_local_manifest(&manifest_path.to_string_lossy());
+        }
+
+        // Local manifests (file://<path>) are read straight from disk, from the allowed directories only
+        if let Some(manifest_path) = action_ref.strip_prefix("file://") {
+            let manifest_path = confine_path(std::path::Path::new(manifest_path), self.allowed_roots.as_deref())?;
+            self.logger.log_info(&format!("Manifest {} read from disk", action_ref), None);
+            return Self::read_local_manifest(&manifest_path.to_string_lossy());
+        }
+
+        // Registry manifests are kept in the cache, so that offline runs can use them
+        let cache_path = self.cached_manifest_path(action_ref);
+        if self.options.offline {
+            let Some(cache_path) = cache_path.filter(|path| path.exists()) else {
+                anyhow::bail!("Manifest of {} isn't cached, it can't be fetched offline", action_ref);
+            };
+            self.logger.log_info(&format!("Manifest {} read from cache {:?}", action_ref, cache_path), None);
+            return Self::read_local_manifest(&cache_path.to_string_lossy());
+        }
+
+        let storage_url = self.manifest_url(action_ref);
+        let manifest = self.download_manifest(action_ref, &storage_url).await?;
+        if let Some(cache_path) = cache_path {
+            let written = std::fs::create_dir_all(cache_path.parent().unwrap_or(&self.cache_dir))
+                .and_then(|_| std::fs::write(&cache_path, serde_json::to_string_pretty(&manifest).unwrap_or_default()));
+            if let Err(e) = written {
+                self.logger.log_warning(&format!("Failed to cache the manifest of {} in {:?}: {}", action_ref, cache_path, e), None);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Where the manifest of a registry action is cached, next to its artifact.
+    /// Only pinned versions are cached: a missing, `latest` or range version may resolve to another version later.
+    fn cached_manifest_path(&self, action_ref: &str) -> Option<std::path::PathBuf> {
+        let (_, version) = action_ref.split_once(':')?;
+        if !Self::is_pinned_version(version) {
+            return None;
+        }
+        Some(self.cache_dir.join(action_ref.replace(':', "/")).join(STARTHUB_MANIFEST_FILENAME))
+    }
+
+    /// Pre-fetches the manifests of the given actions and of their transitive children into the cache,
+    /// without executing anything
+    pub async fn warm_cache(&self, action_refs: &[String]) -> Result<CacheWarmReport> {
+        let mut report = CacheWarmReport::default();
+        let mut visited = std::collections::HashSet::new();
+        let mut pending: Vec<String> = action_refs.iter().rev().cloned().collect();
+
+        while let Some(action_ref) = pending.pop() {
+            if !visited.insert(action_ref.clone()) {
+                continue;
+            }
+            let manifest = match self.cached_manifest_path(&action_ref).filter(|path| path.exists()) {
+                Some(cache_path) => {
+                    self.check_namespace_allowed(&action_ref)?;
+                    report.cached.push(action_ref.clone());
+                    Self::read_local_manifest(&cache_path.to_string_lossy())?
+                }
+                None => {
+                    let manifest = self.fetch_manifest(&action_ref).await
+                        .map_err(|e| anyhow::anyhow!("Failed to warm the cache for {}: {}", action_ref, e))?;
+                    report.fetched.push(action_ref.clone());
+                    manifest
+                }
+            };
+
+            let mut children: Vec<String> = manifest.steps.values()
+                .filter_map(|step| step.get("uses").and_then(|uses| uses.as_str()))
+                .map(|uses| uses.to_string())
+                .collect();
+            children.sort();
+            pending.extend(children.into_iter().rev());
+        }
+
+        Ok(report)
+    }
+
+    /// Downloads the artifact of a wasm or docker action to the cache, or finds it there
+    async fn download_artifact(&self, kind: &str, action_ref: &str, mirrors: &[String]) -> Result<std::path::PathBuf> {
+        match kind {
+            "wasm" => wasm::download_wasm(&self.http_client, self.api_base(), action_ref, mirrors, &self.cache_dir).await,
+            "docker" => docker::download_docker(&self.http_client, self.api_base(), action_ref, mirrors, &self.cache_dir).await,
+            _ => Err(anyhow::anyhow!("Unsupported action kind: {}", kind)),
+        }
+    }
+
+    /// Resolves an action and its transitive children and hooks to concrete versions, and locks them
+    /// with the digests of their manifests and of the artifacts of wasm and docker actions
+    pub async fn lock_action(&self, action_ref: &str) -> Result<Lockfile> {
+        let mut lockfile = Lockfile::new(action_ref);
+        let mut pending = vec![action_ref.to_string()];
+
+        while let Some(action_ref) = pending.pop() {
+            // Keyed like the build looks them up, with aliases expanded
+            let action_ref = self.resolve_alias(&action_ref).await?;
+            if lockfile.actions.contains_key(&action_ref) {
+                continue;
+            }
+            let manifest = self.fetch_manifest(&action_ref).await
+                .map_err(|e| anyhow::anyhow!("Failed to lock {}: {}", action_ref, e))?;
+            let resolved = Self::concrete_ref(&action_ref, &manifest);
+            // The artifact is pinned too, since a version can be republished with another one
+            let artifact = match &manifest.kind {
+                Some(ShKind::Wasm) => Some("wasm"),
+                Some(ShKind::Docker) => Some("docker"),
+                _ => None,
+            };
+            let artifact = match artifact {
+                Some(kind) => {
+                    let path = self.download_artifact(kind, &resolved, &manifest.mirrors).await
+                        .map_err(|e| anyhow::anyhow!("Failed to lock the artifact of {}: {}", action_ref, e))?;
+                    Some(crate::lockfile::file_digest(&path)?)
+                }
+                None => None,
+            };
+            lockfile.actions.insert(action_ref.clone(), crate::lockfile::LockedAction {
+                resolved,
+                digest: crate::lockfile::manifest_digest(&manifest)?,
+                artifact,
+            });
+
+            let mut children: Vec<String> = manifest.steps.values()
+                .chain(manifest.pre.iter())
+                .chain(manifest.post.iter())
+                .filter_map(|step| step.get("uses").and_then(|uses| uses.as_str()))
+                .map(|uses| uses.to_string())
+                .collect();
+            children.sort();
+            pending.extend(children.into_iter().rev());
+        }
+
+        self.logger.log_info(&format!("Locked {} with {} action(s)", action_ref, lockfile.actions.len()), None);
+        Ok(lockfile)
+    }
+
+    /// Registry URL of the manifest of an action. A reference without a version drops the version segment.
+    fn manifest_url(&self, action_ref: &str) -> String {
+        let (name, version) = action_ref.split_once(':').unwrap_or((action_ref, ""));
+        let (namespace, slug) = name.split_once('/').unwrap_or(("", name));
+        let template = if version.is_empty() {
+            self.manifest_path_template.replace("/{version}", "")
+        } else {
+            self.manifest_path_template.clone()
+        };
+        let path = template
+            .replace("{namespace}", namespace)
+            .replace("{slug}", slug)
+            .replace("{version}", version);
+        format!("{}{}", self.api_base(), path)
+    }
+
+    fn read_local_manifest(manifest_path: &str) -> Result<ShManifest> {
+        let manifest_text = std::fs::read_to_string(manifest_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read local manifest {}: {}", manifest_path, e))?;
+        serde_json::from_str(&manifest_text)
+            .map_err(|e| anyhow::anyhow!("JSON parsing error in local manifest {}: {}", manifest_path, e))
+    }
+
+    /// Downloads and parses the starthub-lock.json at the given URL, sending the configured headers.
+    /// A manifest already downloaded by this engine is served from memory.
+    async fn download_manifest(&self, action_ref: &str, storage_url: &str) -> Result<ShManifest> {
+        let started = std::time::Instant::now();
+        let cached = self.manifest_cache.lock().ok().and_then(|cache| cache.get(storage_url).cloned());
+        if let Some(manifest) = cached {
+            self.logger.log_info(
+                &format!("Manifest {} from {}: cache hit in {:.1?}", action_ref, storage_url, started.elapsed()),
+                None
+            );
+            return Ok(manifest);
+        }
+        
+        let response = self.http_client.get(storage_url)
+            .headers(self.manifest_headers.clone())
+            .send()
+            .await?;
+        let status = response.status();
+        self.logger.log_info(
+            &format!("Manifest {} from {}: cache miss, HTTP {} in {:.1?}", action_ref, storage_url, status.as_u16(), started.elapsed()),
+            None
+        );
+        
+        if status == reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!("action not found: {}", action_ref);
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            anyhow::bail!("not authorized to access {}; run starthub login", action_ref);
+        }
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        // The whole body only goes to the server's debug traces: an HTML error page would drown the error
+        let response_text = response.text().await?;
+        tracing::debug!("Manifest {} from {}: {}", action_ref, storage_url, response_text);
+
+        let is_json = content_type.is_empty() || content_type.contains("json");
+        if !status.is_success() || !is_json {
+            anyhow::bail!(
+                "Failed to download starthub-lock.json of {}: HTTP {}{} from {}: {}",
+                action_ref,
+                status,
+                if is_json { String::new() } else { format!(" with a {} response", content_type) },
+                storage_url,
+                response_excerpt(&response_text)
+            );
+        }
+        let manifest: ShManifest = serde_json::from_str(&response_text)
+            .map_err(|e| anyhow::anyhow!(
+                "Invalid starthub-lock.json of {} from {}: {}: {}", action_ref, storage_url, e, response_excerpt(&response_text)
+            ))?;
+        if let Ok(mut cache) = self.manifest_cache.lock() {
+            cache.insert(storage_url.to_string(), manifest.clone());
+        }
+        Ok(manifest)
+    }
+}
+
+/// The first 200 bytes of a response body, cut at a character boundary, to quote in an error
+fn response_excerpt(body: &str) -> String {
+    const MAX_EXCERPT_BYTES: usize = 200;
+    let body = body.trim();
+    if body.len() <= MAX_EXCERPT_BYTES {
+        return body.to_string();
+    }
+    let end = (0..=MAX_EXCERPT_BYTES).rev().find(|index| body.is_char_boundary(*index)).unwrap_or(0);
+    format!("{}…", &body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{manifest_json, write_manifest};
+    
+    use serde_json::json;
 
     // #[test]
     // fn test_step_depends_on() {
@@ -4325,6 +7108,9 @@ mod tests {
                 template: Value::String("John".to_string()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             },
             ShIO {
                 name: "age".to_string(),
@@ -4332,6 +7118,9 @@ mod tests {
                 template: Value::Number(30.into()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values1 = vec![
@@ -4356,6 +7145,9 @@ mod tests {
                 template: Value::Bool(true),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             },
             ShIO {
                 name: "data".to_string(),
@@ -4363,6 +7155,9 @@ mod tests {
                 template: Value::Object(serde_json::Map::new()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values2 = vec![
@@ -4411,6 +7206,9 @@ mod tests {
                 template: Value::Object(serde_json::Map::new()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values3 = vec![Value::Object({
@@ -4435,6 +7233,9 @@ mod tests {
                 template: Value::String("Test".to_string()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             },
             ShIO {
                 name: "user".to_string(),
@@ -4442,6 +7243,9 @@ mod tests {
                 template: Value::Object(serde_json::Map::new()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values4 = vec![
@@ -4470,6 +7274,9 @@ mod tests {
                 template: Value::Object(serde_json::Map::new()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values5 = vec![Value::Object({
@@ -4497,6 +7304,9 @@ mod tests {
                 template: Value::String("test".to_string()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values6 = vec![Value::String("test".to_string())];
@@ -4520,6 +7330,9 @@ mod tests {
                 template: Value::String("test".to_string()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values8 = vec![Value::String("test_value".to_string())];
@@ -4556,6 +7369,9 @@ mod tests {
                 template: Value::Array(vec![]),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values9 = vec![Value::Array(vec![
@@ -4586,6 +7402,9 @@ mod tests {
                 template: Value::String("".to_string()),
                 value: None,
                 required: true,
+                required_if: None,
+                transform: None,
+                default: None,
             }
         ];
         let input_values10 = vec![Value::Null];
@@ -5282,7 +8101,7 @@ mod tests {
         assert!(outputs.is_array(), "outputs should be an array");
         let outputs_array = outputs.as_array().unwrap();
         assert_eq!(outputs_array.len(), 1);
-        assert_eq!(outputs_array[0], json!(123.0)); // Should be cast to number
+        assert_eq!(outputs_array[0], json!(123)); // Should be cast to number
     }
 
     #[tokio::test]
@@ -6622,7 +9441,8 @@ mod tests {
             Err(e) => {
                 // Expected to fail until package is published to registry
                 println!("Expected failure (package not yet published): {}", e);
-                assert!(e.to_string().contains("Failed to download starthub-lock.json") || 
+                assert!(e.to_string().contains("Failed to download starthub-lock.json") ||
+                       e.to_string().contains("action not found") ||
                        e.to_string().contains("400 Bad Request") ||
                        e.to_string().contains("404 Not Found"),
                        "Error should be related to package not found in registry");
@@ -6695,6 +9515,7 @@ mod tests {
                 // Expected to fail until package is published to registry
                 println!("Expected failure (package not yet published): {}", e);
                 assert!(e.to_string().contains("Failed to download starthub-lock.json") ||
+                       e.to_string().contains("action not found") ||
                        e.to_string().contains("400 Bad Request") ||
                        e.to_string().contains("404 Not Found"),
                        "Error should be related to package not found in registry");
@@ -6876,6 +9697,7 @@ mod tests {
                 // Expected to fail due to package not found or API issues
                 println!("Expected failure (package not found or API issues): {}", e);
                 assert!(e.to_string().contains("Failed to download starthub-lock.json") ||
+                       e.to_string().contains("action not found") ||
                        e.to_string().contains("404 Not Found") ||
                        e.to_string().contains("401 Unauthorized") ||
                        e.to_string().contains("400 Bad Request"),
@@ -7023,6 +9845,7 @@ mod tests {
                 // Expected to fail due to package not found or API issues
                 println!("Expected failure (package not found or API issues): {}", e);
                 assert!(e.to_string().contains("Failed to download starthub-lock.json") ||
+                       e.to_string().contains("action not found") ||
                        e.to_string().contains("404 Not Found") ||
                        e.to_string().contains("401 Unauthorized") ||
                        e.to_string().contains("400 Bad Request"),
@@ -7030,4 +9853,2741 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    fn test_io(name: &str, r#type: &str, template: Value) -> ShIO {
+        ShIO {
+            name: name.to_string(),
+            r#type: r#type.to_string(),
+            template,
+            value: None,
+            required: true,
+            required_if: None,
+            transform: None,
+            default: None,
+        }
+    }
+
+    fn test_action(name: &str, kind: &str, uses: &str, inputs: Vec<ShIO>, outputs: Vec<ShIO>) -> ShAction {
+        ShAction {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            kind: kind.to_string(),
+            uses: uses.to_string(),
+            resolved_uses: uses.to_string(),
+            inputs,
+            outputs,
+            parent_action: None,
+            steps: HashMap::new(),
+            pre: Vec::new(),
+            post: Vec::new(),
+            role: None,
+            priority: 0,
+            optional: false,
+            when: None,
+            when_holds: None,
+            foreach: None,
+            foreach_instances: None,
+            timeout: None,
+            retry: None,
+            types: None,
+            mirrors: vec![],
+            permissions: None,
+        }
+    }
+
+    /// A composition without steps whose only output is its only input
+    fn identity_step(name: &str, input_template: &str) -> ShAction {
+        test_action(
+            name,
+            "composition",
+            &format!("test/{}:0.0.1", name),
+            vec![test_io("value", "string", json!(input_template))],
+            vec![test_io("value", "string", json!("{{inputs[0]}}"))],
+        )
+    }
+
+    /// A leaf step whose artifact doesn't exist, so executing it always fails
+    fn failing_step(name: &str, kind: &str, input_template: &str) -> ShAction {
+        test_action(
+            name,
+            kind,
+            &format!("test/missing-{}-action:0.0.1", kind),
+            vec![test_io("value", "string", json!(input_template))],
+            vec![test_io("value", "string", Value::Null)],
+        )
+    }
+
+    fn chained_composition(step1: ShAction, step2: ShAction, step3: ShAction) -> ShAction {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![test_io("value", "string", json!("{{steps.step3.outputs[0]}}"))],
+        );
+        root.steps.insert("step1".to_string(), step1);
+        root.steps.insert("step2".to_string(), step2);
+        root.steps.insert("step3".to_string(), step3);
+        root
+    }
+
+    #[tokio::test]
+    async fn test_built_tree_equals_hand_built_action() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manifest_path = temp_dir.path().join("fetch.json");
+        let action_ref = write_manifest(&manifest_path, "fetch", json!({
+            "kind": "wasm",
+            "inputs": [{"name": "url", "type": "string", "required": true}],
+            "outputs": [{"name": "body", "type": "string"}],
+            "mirrors": ["https://mirror.example.com"],
+            "permissions": {"net": ["example.com"]}
+        }));
+
+        let tree = ExecutionEngine::new().build_action_tree(&action_ref, None).await.unwrap();
+
+        let mut body = test_io("body", "string", Value::Null);
+        body.required = false;
+        let mut expected = test_action("fetch", "wasm", &action_ref, vec![test_io("url", "string", Value::Null)], vec![body]);
+        expected.id = tree.id.clone();
+        expected.mirrors = vec!["https://mirror.example.com".to_string()];
+        expected.permissions = Some(crate::models::ShPermissions { fs: vec![], net: vec!["example.com".to_string()] });
+        assert_eq!(tree, expected);
+
+        expected.outputs[0].r#type = "number".to_string();
+        assert_ne!(tree, expected);
+    }
+
+    #[tokio::test]
+    async fn test_resume_run_skips_checkpointed_steps() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(Mutex::new(Database::open(&temp_dir.path().join("server.db")).unwrap()));
+        let run_id = "resume-test-run";
+
+        // First attempt: step1 and step2 complete, step3 fails
+        let mut engine = ExecutionEngine::new();
+        engine.set_database(database.clone());
+        engine.set_options(ExecutionOptions {
+            run_id: Some(run_id.to_string()),
+            ..Default::default()
+        });
+
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            failing_step("step3", "wasm", "{{steps.step2.outputs[0]}}"),
+        );
+        assert!(engine.run_action_tree(&root, "").await.is_err());
+
+        let checkpoints = database.lock().await.get_run_steps(run_id).unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints.get("step1"), Some(&vec![json!("hello")]));
+        assert_eq!(checkpoints.get("step2"), Some(&vec![json!("hello")]));
+        assert!(!checkpoints.contains_key("step3"));
+
+        // Second attempt: step1 and step2 would now fail if they were executed again,
+        // so the run can only reach step3 if they are restored from their checkpoints
+        engine.set_options(ExecutionOptions {
+            run_id: Some(run_id.to_string()),
+            checkpoints,
+            ..Default::default()
+        });
+
+        let root = chained_composition(
+            failing_step("step1", "docker", "{{inputs[0]}}"),
+            failing_step("step2", "docker", "{{steps.step1.outputs[0]}}"),
+            failing_step("step3", "wasm", "{{steps.step2.outputs[0]}}"),
+        );
+        let error = engine.run_action_tree(&root, "").await.unwrap_err().to_string().to_lowercase();
+        assert!(error.contains("wasm"), "resumed run should fail at step3, got: {}", error);
+        assert!(!error.contains("docker"), "resumed run should not execute step1 or step2, got: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_docker_steps_fail_clearly_without_docker() {
+        // A PATH without docker
+        let empty_path = tempfile::tempdir().unwrap();
+        let reason = docker::check_docker_available(Some(empty_path.path().as_os_str())).await.unwrap_err().to_string();
+        assert!(reason.contains("isn't installed or isn't in PATH"), "unexpected reason: {}", reason);
+
+        let mut engine = ExecutionEngine::new();
+        engine.docker_available = Some(Err(reason));
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            failing_step("step2", "docker", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let error = engine.run_action_tree(&root, "").await.unwrap_err().to_string();
+        assert!(
+            error.contains("docker is required for step step2 but is not available: the docker CLI isn't installed"),
+            "unexpected error: {}", error
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a docker daemon: run with --ignored"]
+    async fn test_docker_step_error_reports_exit_code_and_image() {
+        // alpine's default command is a shell, so the stdin payload is run as a script
+        let noop = |_: &str, _: Option<&str>| {};
+        let no_output = |_: &str, _: &str, _: Option<&str>| {};
+        let error = docker::run_docker_container("step1", "alpine:3.20", "exit 3", &noop, &noop, &no_output)
+            .await
+            .unwrap_err();
+
+        let details = error.downcast_ref::<docker::DockerStepError>().expect("expected a DockerStepError");
+        assert_eq!(details.step_id, "step1");
+        assert_eq!(details.image, "alpine:3.20");
+        assert_eq!(details.exit_code, Some(3));
+        assert!(details.command.starts_with("docker run -i --rm --name starthub-"), "command was: {}", details.command);
+        assert!(details.command.ends_with(" alpine:3.20"), "command was: {}", details.command);
+        assert!(error.to_string().contains("exited with code 3"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a docker daemon: run with --ignored"]
+    async fn test_timed_out_docker_step_leaves_no_container() {
+        let step_id = format!("timeout-{}", uuid::Uuid::new_v4());
+        let mut step = test_action(&step_id, "docker", "test/slow:0.0.1", vec![], vec![]);
+        step.timeout = Some(2);
+        let noop = |_: &str, _: Option<&str>| {};
+        let no_output = |_: &str, _: &str, _: Option<&str>| {};
+        // The step is bounded like the engine bounds every docker step: its future is dropped
+        let engine = ExecutionEngine::new();
+        let work = docker::run_docker_container(&step.id, "alpine:3.20", "sleep 30", &noop, &noop, &no_output);
+        let error = engine.with_step_timeout(&step, "slow", work).await.unwrap_err();
+        assert!(error.downcast_ref::<StepTimeout>().is_some(), "unexpected error: {}", error);
+
+        // The container is removed in the background
+        let containers = || std::process::Command::new("docker")
+            .args(["ps", "-aq", "--filter", &format!("label={}={}", docker::STEP_LABEL, step.id)])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap();
+        for _ in 0..20 {
+            if containers().is_empty() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        }
+        panic!("container left behind: {}", containers());
+    }
+
+    // wasmtime also runs modules in the text format: this one prints ["hello"] on stdout
+    const HELLO_WAT_MODULE: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 8) "[\"hello\"]")
+        (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 8))
+            (i32.store (i32.const 4) (i32.const 9))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 20)))))"#;
+
+    #[tokio::test]
+    #[ignore = "needs wasmtime on PATH: run with --ignored"]
+    async fn test_run_leaf_wasm_action_in_isolation() {
+        // Seed the artifact cache so nothing is downloaded
+        let cache_dir = tempfile::tempdir().unwrap();
+        let artifact_dir = cache_dir.path().join("test/hello/0.0.1");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        std::fs::write(artifact_dir.join("artifact.wasm"), HELLO_WAT_MODULE).unwrap();
+
+        let engine = ExecutionEngine::with_cache_dir(cache_dir.path().to_path_buf());
+        let action = test_action(
+            "hello",
+            "wasm",
+            "test/hello:0.0.1",
+            vec![],
+            vec![test_io("greeting", "string", Value::Null)],
+        );
+
+        let run = engine.run_leaf("test/hello:0.0.1", &action).await.unwrap();
+        assert_eq!(run.raw_output, r#"["hello"]"#);
+        assert_eq!(run.parsed, json!(["hello"]));
+        assert_eq!(run.outputs.len(), 1);
+        assert_eq!(run.outputs[0].name, "greeting");
+        assert_eq!(run.outputs[0].value, json!("hello"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs wasmtime on PATH: run with --ignored"]
+    async fn test_wasm_module_is_compiled_once() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let artifact_dir = cache_dir.path().join("test/hello/0.0.1");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        let module_path = artifact_dir.join("artifact.wasm");
+        std::fs::write(&module_path, HELLO_WAT_MODULE).unwrap();
+
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir.path().to_path_buf());
+        engine.set_wasm_opt_level(wasm::WasmOptLevel::SpeedAndSize);
+        let action = test_action(
+            "hello",
+            "wasm",
+            "test/hello:0.0.1",
+            vec![],
+            vec![test_io("greeting", "string", Value::Null)],
+        );
+
+        // The second run reuses the module compiled by the first
+        for _ in 0..2 {
+            let run = engine.run_leaf("test/hello:0.0.1", &action).await.unwrap();
+            assert_eq!(run.parsed, json!(["hello"]));
+        }
+        assert_eq!(wasm::compilations(&module_path, wasm::WasmOptLevel::SpeedAndSize), 1);
+        assert_eq!(wasm::compilations(&module_path, wasm::WasmOptLevel::Speed), 0);
+
+        // A module replaced in the cache is compiled again rather than running the stale compilation
+        std::fs::write(&module_path, HELLO_WAT_MODULE.replace("hello", "howdy")).unwrap();
+        let run = engine.run_leaf("test/hello:0.0.1", &action).await.unwrap();
+        assert_eq!(run.parsed, json!(["howdy"]));
+        assert_eq!(wasm::compilations(&module_path, wasm::WasmOptLevel::SpeedAndSize), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs wasmtime on PATH: run with --ignored"]
+    async fn test_timed_out_foreach_instance_is_a_step_timeout() {
+        // Spins until wasmtime is killed
+        let module = r#"(module (func (export "_start") (loop $spin (br $spin))))"#;
+        let cache_dir = tempfile::tempdir().unwrap();
+        let artifact_dir = cache_dir.path().join("test/spin/0.0.1");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        std::fs::write(artifact_dir.join("artifact.wasm"), module).unwrap();
+
+        let mut instance = test_action("spin", "wasm", "test/spin:0.0.1", vec![], vec![test_io("value", "string", Value::Null)]);
+        instance.timeout = Some(1);
+        let mut step = instance.clone();
+        step.foreach = Some("{{inputs[0]}}".to_string());
+        step.foreach_instances = Some(vec![instance]);
+
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir.path().to_path_buf());
+        let error = engine.run_step(&step, "spin").await.unwrap_err();
+        let timeout = error.downcast_ref::<StepTimeout>().expect("expected a StepTimeout");
+        assert_eq!(timeout.step_path, "spin[0]");
+        assert_eq!(error.to_string(), "Instance 0 of step 'spin' failed");
+        assert!(format!("{:#}", error).contains("step 'spin[0]' timed out after 1."), "unexpected error: {:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_interpolate_whole_inputs_object() {
+        let mut count = test_io("count", "number", Value::Null);
+        count.value = Some(json!(2));
+        let mut label = test_io("label", "string", Value::Null);
+        label.value = Some(json!("hello"));
+
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![count, label],
+            vec![test_io("value", "object", json!("{{steps.whole.outputs[0]}}"))],
+        );
+        root.steps.insert("whole".to_string(), test_action(
+            "whole",
+            "composition",
+            "test/whole:0.0.1",
+            vec![test_io("value", "object", json!("{{inputs}}"))],
+            vec![test_io("value", "object", json!("{{inputs[0]}}"))],
+        ));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!({"count": 2, "label": "hello"})));
+    }
+
+    #[test]
+    fn test_inputs_template_in_an_input_value_is_not_expanded() {
+        let engine = ExecutionEngine::new();
+        let values = vec![json!("{{inputs}}"), json!("p4ssw0rd")];
+        let inputs_object = json!({"note": "{{inputs}}", "password": "p4ssw0rd"});
+
+        // The value is data: it never expands into the other inputs, secrets included
+        let resolved = engine.interpolate_string_into_untyped_value("note: {{inputs[0]}}", &values, &inputs_object, None).unwrap();
+        assert_eq!(resolved, json!("note: {{inputs}}"));
+
+        // The template's own `{{inputs}}` still does
+        let resolved = engine.interpolate_string_into_untyped_value("{{inputs[0]}} {{inputs}}", &values, &inputs_object, None).unwrap();
+        assert_eq!(resolved, json!(format!("{{{{inputs}}}} {}", inputs_object)));
+    }
+
+    #[tokio::test]
+    async fn test_interpolate_escaped_braces() {
+        let mut name = test_io("name", "string", Value::Null);
+        name.value = Some(json!("world"));
+
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![name],
+            vec![test_io("greeting", "string", json!("{{steps.greet.outputs[0]}}"))],
+        );
+        root.steps.insert("greet".to_string(), test_action(
+            "greet",
+            "composition",
+            "test/greet:0.0.1",
+            vec![test_io("name", "string", json!("{{inputs[0]}}"))],
+            vec![test_io("greeting", "string", json!(r"hello {{inputs[0]}}, \{\{not_a_ref\}\}"))],
+        ));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!("hello world, {{not_a_ref}}")));
+    }
+
+    #[test]
+    fn test_escaped_braces_are_not_unresolved_templates() {
+        let engine = ExecutionEngine::new();
+        assert!(!engine.contains_unresolved_templates(&json!(r"\{\{steps.literal\}\}")));
+        assert!(!engine.contains_unresolved_templates(&json!("{{not_a_ref}}")));
+        assert!(engine.contains_unresolved_templates(&json!(r"\{\{ {{steps.a.outputs[0]}}")));
+
+        let interpolated = engine
+            .interpolate_string_into_untyped_value(r"\{\{inputs[0]\}\}", &vec![json!("x")], &Value::Null, None)
+            .unwrap();
+        assert_eq!(interpolated, json!("{{inputs[0]}}"));
+    }
+
+    #[test]
+    fn test_strict_inputs_rejects_unreferenced_input() {
+        let mut used = test_io("used", "string", Value::Null);
+        used.value = Some(json!("a"));
+        let mut unused = test_io("unused", "string", Value::Null);
+        unused.value = Some(json!("b"));
+
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![used, unused],
+            vec![test_io("value", "string", json!("{{steps.echo.outputs[0]}}"))],
+        );
+        root.steps.insert("echo".to_string(), identity_step("echo", "{{inputs[0]}}"));
+
+        // Without strict mode an unused input is only a warning
+        let mut engine = ExecutionEngine::new();
+        engine.check_unused_inputs(&root).unwrap();
+
+        engine.set_options(ExecutionOptions { strict_inputs: true, ..Default::default() });
+        let error = engine.check_unused_inputs(&root).unwrap_err();
+        assert!(error.to_string().ends_with(": unused"), "unexpected error: {}", error);
+
+        // Referencing the whole inputs object uses every input
+        root.outputs.push(test_io("all", "object", json!("{{inputs}}")));
+        engine.check_unused_inputs(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_output_transform_reshapes_step_output() {
+        let mut host = test_io("host", "string", Value::Null);
+        host.value = Some(json!("db.internal"));
+
+        let mut endpoint = test_io("endpoint", "object", json!("{{steps.split.outputs[0]}}"));
+        endpoint.transform = Some(json!({
+            "host": ".[0]",
+            "port": ".[1].port",
+            "tls": true
+        }));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![host],
+            vec![endpoint, test_io("raw", "object", json!("{{steps.split.outputs[0]}}"))],
+        );
+        root.steps.insert("split".to_string(), test_action(
+            "split",
+            "composition",
+            "test/split:0.0.1",
+            vec![test_io("host", "string", json!("{{inputs[0]}}"))],
+            vec![test_io("parts", "object", json!(["{{inputs[0]}}", {"port": 5432}]))],
+        ));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!({"host": "db.internal", "port": 5432, "tls": true})));
+        // Outputs without a transform are left as interpolated
+        assert_eq!(executed.outputs[1].value, Some(json!(["db.internal", {"port": 5432}])));
+    }
+
+    #[test]
+    fn test_apply_transform() {
+        let engine = ExecutionEngine::new();
+        let value = json!({"items": [{"name": "a"}, {"name": "b"}], "count": 2});
+
+        assert_eq!(engine.apply_transform(&json!("."), &value).unwrap(), value);
+        assert_eq!(engine.apply_transform(&json!(".count"), &value).unwrap(), json!(2));
+        assert_eq!(engine.apply_transform(&json!([".items[1].name", "literal"]), &value).unwrap(), json!(["b", "literal"]));
+        assert_eq!(engine.apply_transform(&json!(".[0]"), &json!(["first"])).unwrap(), json!("first"));
+        assert!(engine.apply_transform(&json!(".missing"), &value).is_err());
+    }
+
+    /// Serves HTTP on a local port, answering every request with the status and body `respond`
+    /// returns for it (request line, headers and body). Bodies starting with `<` are sent as
+    /// HTML, the others as JSON. Returns the server's base URL.
+    async fn mock_registry(respond: impl Fn(&str) -> (&'static str, String) + Send + Sync + 'static) -> String {
+        mock_slow_registry(std::time::Duration::ZERO, respond).await
+    }
+
+    /// Like [`mock_registry`], answering every request only after `delay`.
+    async fn mock_slow_registry(
+        delay: std::time::Duration,
+        respond: impl Fn(&str) -> (&'static str, String) + Send + Sync + 'static,
+    ) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0u8; 1024];
+                    loop {
+                        let text = String::from_utf8_lossy(&request).to_string();
+                        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                            let length = head.lines()
+                                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|l| l.trim().parse::<usize>().unwrap_or(0)))
+                                .unwrap_or(0);
+                            if body.len() >= length {
+                                break;
+                            }
+                        }
+                        match socket.read(&mut buffer).await {
+                            Ok(read) if read > 0 => request.extend_from_slice(&buffer[..read]),
+                            _ => break,
+                        }
+                    }
+                    tokio::time::sleep(delay).await;
+                    let (status, body) = respond(&String::from_utf8_lossy(&request));
+                    let content_type = if body.starts_with('<') { "text/html" } else { "application/json" };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status, content_type, body.len(), body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn test_manifest(version: &str) -> ShManifest {
+        serde_json::from_value(manifest_json("child", json!({
+            "version": version,
+            "kind": "wasm"
+        }))).unwrap()
+    }
+
+    #[test]
+    fn test_step_input_template_unwraps_only_value_wrappers() {
+        assert_eq!(ExecutionEngine::step_input_template(&json!({ "value": "{{inputs[0]}}" })), json!("{{inputs[0]}}"));
+        assert_eq!(ExecutionEngine::step_input_template(&json!({ "value": 1, "unit": "s" })), json!({ "value": 1, "unit": "s" }));
+        assert_eq!(ExecutionEngine::step_input_template(&json!("direct")), json!("direct"));
+    }
+
+    #[test]
+    fn test_concrete_ref() {
+        let manifest = test_manifest("1.4.2");
+        assert_eq!(ExecutionEngine::concrete_ref("starthubhq/child:latest", &manifest), "starthubhq/child:1.4.2");
+        assert_eq!(ExecutionEngine::concrete_ref("starthubhq/child", &manifest), "starthubhq/child:1.4.2");
+        assert_eq!(ExecutionEngine::concrete_ref("starthubhq/child:^1.4", &manifest), "starthubhq/child:1.4.2");
+        assert_eq!(ExecutionEngine::concrete_ref("starthubhq/child:1.x", &manifest), "starthubhq/child:1.4.2");
+        assert_eq!(ExecutionEngine::concrete_ref("starthubhq/child:1.4.0", &manifest), "starthubhq/child:1.4.0");
+        assert_eq!(ExecutionEngine::concrete_ref("file:///tmp/starthub-lock.json", &manifest), "file:///tmp/starthub-lock.json");
+    }
+
+    #[test]
+    fn test_graph_lists_resolved_child_refs() {
+        let mut latest_child = identity_step("fetch", "{{inputs[0]}}");
+        latest_child.uses = "starthubhq/child:latest".to_string();
+        latest_child.resolved_uses = ExecutionEngine::concrete_ref(&latest_child.uses, &test_manifest("1.4.2"));
+        let root = chained_composition(
+            latest_child,
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+
+        let engine = ExecutionEngine::new();
+        let mut graph = ActionGraph::default();
+        engine.collect_graph(&root, "", &mut graph).unwrap();
+
+        let paths: Vec<&str> = graph.nodes.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, vec!["", "step1", "step2", "step3"]);
+        assert_eq!(graph.nodes[1].uses, "starthubhq/child:latest");
+        assert_eq!(graph.nodes[1].resolved_uses, "starthubhq/child:1.4.2");
+        assert_eq!(graph.edges, vec![
+            GraphEdge { from: "step1".to_string(), to: "step2".to_string() },
+            GraphEdge { from: "step2".to_string(), to: "step3".to_string() },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_workspace_resolves_child_actions_locally() {
+        let workspace_dir = tempfile::tempdir().unwrap();
+        let child_dir = workspace_dir.path().join("child-action");
+        let parent_dir = workspace_dir.path().join("parent-action");
+        std::fs::create_dir_all(&child_dir).unwrap();
+        std::fs::create_dir_all(&parent_dir).unwrap();
+        write_manifest(&child_dir.join("starthub-lock.json"), "child", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let parent_path = parent_dir.join("starthub-lock.json");
+        write_manifest(&parent_path, "parent", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": { "echo": { "uses": "starthubhq/child:0.0.1", "inputs": ["{{inputs[0]}}"] } }
+        }));
+        let workspace_path = workspace_dir.path().join("starthub-workspace.json");
+        std::fs::write(&workspace_path, r#"{ "starthubhq/child": "child-action" }"#).unwrap();
+
+        // The registry is unreachable, so the child can only come from the workspace
+        let mut engine = ExecutionEngine::with_cache_dir(workspace_dir.path().join("cache"));
+        engine.set_proxy("http://127.0.0.1:9").unwrap();
+        engine.set_options(ExecutionOptions {
+            workspace: read_workspace(&workspace_path).unwrap(),
+            ..Default::default()
+        });
+        let result = engine.execute_action(&format!("file://{}", parent_path.display()), vec![json!("hello")]).await.unwrap();
+        assert_eq!(result, json!(["hello"]));
+
+        assert!(read_workspace(&workspace_dir.path().join("missing.json")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_serves_recorded_manifests() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        let root_path = temp_dir.path().join("root.json");
+        let child = format!("file://{}", child_path.display());
+        let root = format!("file://{}", root_path.display());
+        write_manifest(&child_path, "child", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": { "echo": { "uses": child, "inputs": ["{{inputs[0]}}"] } }
+        }));
+
+        let recording = temp_dir.path().join("recording");
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_options(ExecutionOptions { record_dir: Some(recording.clone()), ..Default::default() });
+        let recorded = engine.execute_action(&root, vec![json!("hello")]).await.unwrap();
+        assert_eq!(recorded, json!(["hello"]));
+
+        // Without the manifests on disk nor a reachable network, the run only succeeds from the recording
+        std::fs::remove_file(&child_path).unwrap();
+        std::fs::remove_file(&root_path).unwrap();
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_proxy("http://127.0.0.1:9").unwrap();
+        engine.set_options(ExecutionOptions { replay_dir: Some(recording.clone()), ..Default::default() });
+        let replayed = engine.execute_action(&root, vec![json!("hello")]).await.unwrap();
+        assert_eq!(replayed, recorded);
+
+        let error = engine.fetch_manifest("starthubhq/unrecorded:0.0.1").await.unwrap_err();
+        assert!(error.to_string().contains("No manifest recorded for starthubhq/unrecorded:0.0.1"), "unexpected error: {}", error);
+    }
+
+    #[test]
+    fn test_cast_enforces_numeric_bounds() {
+        let engine = ExecutionEngine::new();
+        let types: Option<serde_json::Map<String, Value>> = Some(json!({
+            "Port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+            "Server": {
+                "host": "string",
+                "port": { "type": "integer", "minimum": 1, "maximum": 65535, "required": true }
+            }
+        }).as_object().unwrap().clone());
+
+        // In range
+        assert_eq!(engine.cast(&json!(8080), "Port", &types).unwrap(), json!(8080));
+        assert!(engine.cast(&json!({ "host": "localhost", "port": 8080 }), "Server", &types).is_ok());
+
+        // Below the minimum
+        let error = engine.cast(&json!(0), "Port", &types).unwrap_err().to_string();
+        assert!(error.contains("Value is invalid for type 'Port'") && error.contains("minimum of 1"), "unexpected error: {}", error);
+
+        // Above the maximum
+        let error = engine.cast(&json!({ "host": "localhost", "port": 70000 }), "Server", &types).unwrap_err().to_string();
+        assert!(error.contains("/port") && error.contains("maximum of 65535"), "unexpected error: {}", error);
+    }
+
+    #[test]
+    fn test_custom_type_schemas_are_compiled_once() {
+        use std::sync::atomic::Ordering;
+
+        let engine = ExecutionEngine::new();
+        let types: Option<serde_json::Map<String, Value>> = Some(json!({
+            "Port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+            "Server": { "host": "string", "port": "number" }
+        }).as_object().unwrap().clone());
+
+        for port in 1..=50 {
+            assert!(engine.cast(&json!(port), "Port", &types).is_ok());
+        }
+        assert!(engine.cast(&json!(0), "Port", &types).is_err());
+        assert_eq!(engine.schema_compilations.load(Ordering::Relaxed), 1);
+
+        let fields = vec![test_io("server", "Server", Value::Null), test_io("port", "Port", Value::Null)];
+        for _ in 0..10 {
+            engine.cast_values_to_typed_array(&fields, &vec![json!({ "host": "localhost", "port": 80 }), json!(8080)], &types).unwrap();
+        }
+        assert_eq!(engine.schema_compilations.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_cast_reports_invalid_array_element_index() {
+        let engine = ExecutionEngine::new();
+        let types: Option<serde_json::Map<String, Value>> = Some(json!({
+            "Server": {
+                "host": "string",
+                "port": { "type": "integer", "minimum": 1, "maximum": 65535, "required": true }
+            }
+        }).as_object().unwrap().clone());
+
+        let servers = json!([
+            { "host": "a.example.com", "port": 80 },
+            { "host": "b.example.com", "port": 70000 },
+            { "host": "c.example.com", "port": 443 }
+        ]);
+        let error = engine.cast(&servers, "Server", &types).unwrap_err().to_string();
+        assert!(error.contains("element 1: /port") && error.contains("maximum of 65535"), "unexpected error: {}", error);
+        assert!(!error.contains("element 0") && !error.contains("element 2"), "unexpected error: {}", error);
+
+        let valid = json!([{ "host": "a.example.com", "port": 80 }, { "host": "c.example.com", "port": 443 }]);
+        assert_eq!(engine.cast(&valid, "Server", &types).unwrap(), valid);
+    }
+
+    #[test]
+    fn test_explicit_priorities_override_alphabetical_order() {
+        let engine = ExecutionEngine::new();
+        let mut steps = HashMap::new();
+        steps.insert("start".to_string(), identity_step("start", "{{inputs[0]}}"));
+        for name in ["alpha", "beta"] {
+            let mut step = identity_step(name, "{{steps.start.outputs[0]}}");
+            step.inputs[0].value = Some(json!("hello"));
+            steps.insert(name.to_string(), step);
+        }
+
+        // Alphabetical by default
+        let defaulted = engine.produce_steps_with_priorities(&steps, &HashMap::new());
+        let next = engine.find_next_step_id(&defaulted, "start", &vec![], &vec![]).unwrap();
+        assert_eq!(next, vec!["alpha", "beta"]);
+
+        // An explicit priority wins over the alphabetical rank
+        let explicit: HashMap<String, i32> = [("alpha".to_string(), 10), ("beta".to_string(), 5)].into_iter().collect();
+        let prioritized = engine.produce_steps_with_priorities(&steps, &explicit);
+        assert_eq!(prioritized["start"].priority, 2);
+        let next = engine.find_next_step_id(&prioritized, "start", &vec![], &vec![]).unwrap();
+        assert_eq!(next, vec!["beta", "alpha"]);
+    }
+
+    #[test]
+    fn test_broken_type_definitions_are_reported() {
+        let engine = ExecutionEngine::new();
+        let types: HashMap<String, Value> = [
+            ("Location".to_string(), json!({ "city": "string", "lat": { "type": "number", "required": true } })),
+            ("Broken".to_string(), json!({ "city": "string", "count": { "type": "integr", "required": true } })),
+        ].into_iter().collect();
+
+        let broken = engine.broken_type_definitions(&types);
+        assert_eq!(broken.len(), 1, "broken: {:?}", broken);
+        assert_eq!(broken[0].0, "Broken");
+    }
+
+    #[test]
+    fn test_input_schema_places_custom_types_under_defs() {
+        let engine = ExecutionEngine::new();
+        let types = serde_json::from_value(json!({
+            "Location": { "city": "string", "lat": { "type": "number", "required": true } },
+            "Unused": { "name": "string" }
+        })).unwrap();
+        let mut port = test_io("port", "integer", Value::Null);
+        port.default = Some(json!(8080));
+        let mut origin = test_io("origin", "Location", Value::Null);
+        origin.required = false;
+        let inputs = vec![test_io("host", "string", Value::Null), port, origin, test_io("destination", "Location", Value::Null)];
+
+        let schema = engine.inputs_json_schema("acme/route", &inputs, &Some(types)).unwrap();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["host"], json!({ "type": "string" }));
+        assert_eq!(schema["properties"]["port"], json!({ "type": "integer", "default": 8080 }));
+        assert_eq!(schema["properties"]["origin"], json!({ "$ref": "#/$defs/Location" }));
+        assert_eq!(schema["required"], json!(["host", "destination"]));
+        assert_eq!(schema["x-input-order"], json!(["host", "port", "origin", "destination"]));
+        assert_eq!(schema["$defs"].as_object().unwrap().len(), 1);
+        assert_eq!(schema["$defs"]["Location"]["required"], json!(["lat"]));
+
+        // The document validates payloads on its own
+        let compiled = JSONSchema::compile(&schema).unwrap();
+        assert!(compiled.is_valid(&json!({ "host": "a", "destination": { "lat": 1.5 } })));
+        assert!(!compiled.is_valid(&json!({ "host": "a", "destination": { "city": "Paris" } })));
+
+        let unknown = vec![test_io("origin", "Place", Value::Null)];
+        let error = engine.inputs_json_schema("acme/route", &unknown, &None).unwrap_err().to_string();
+        assert!(error.contains("'Place', which is neither primitive nor declared"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_input_schema_only_fetches_the_root_manifest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_path = temp_dir.path().join("root.json");
+        write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.child.outputs[0]}}"}],
+            "steps": {
+                "child": { "uses": format!("file://{}", temp_dir.path().join("missing.json").display()), "inputs": ["{{inputs[0]}}"] }
+            }
+        }));
+
+        // The step's manifest doesn't exist, which doesn't matter to the inputs of the root
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let schema = engine.input_schema(&format!("file://{}", root_path.display())).await.unwrap();
+        assert_eq!(schema["properties"]["value"], json!({ "type": "string" }));
+    }
+
+    #[test]
+    fn test_omitted_inputs_take_their_default() {
+        let engine = ExecutionEngine::new();
+        let mut port = test_io("port", "integer", Value::Null);
+        port.required = false;
+        port.default = Some(json!("8080"));
+        let mut region = test_io("region", "string", Value::Null);
+        region.required = false;
+        let fields = vec![test_io("host", "string", Value::Null), port, region];
+
+        let typed = engine.cast_input_values_to_typed_array("acme/server", &fields, &vec![json!("localhost")], &None).unwrap();
+        assert_eq!(typed[0].value, Some(json!("localhost")));
+        // Defaults are cast like given values
+        assert_eq!(typed[1].value, Some(json!(8080)));
+        assert_eq!(typed[2].value, Some(Value::Null));
+
+        let typed = engine.cast_input_values_to_typed_array("acme/server", &fields, &vec![json!("localhost"), json!(443)], &None).unwrap();
+        assert_eq!(typed[1].value, Some(json!(443)));
+
+        let error = engine.cast_input_values_to_typed_array("acme/server", &fields, &vec![], &None).unwrap_err().to_string();
+        assert_eq!(error, "acme/server expects 3 input values, got 0: input 'host' (#0) is required and has no default value");
+    }
+
+    #[test]
+    fn test_mismatched_input_lengths_are_errors_not_panics() {
+        let engine = ExecutionEngine::new();
+        let fields = vec![test_io("host", "string", Value::Null), test_io("port", "number", Value::Null)];
+
+        let result = engine.cast_input_values_to_typed_array("acme/server", &fields, &vec![json!("localhost")], &None);
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("acme/server expects 2 input values, got 1"), "unexpected error: {}", error);
+
+        // Extra values are ignored rather than indexed past the declared inputs
+        let values = vec![json!("localhost"), json!(80), json!("extra"), json!(true)];
+        let typed = engine.cast_input_values_to_typed_array("acme/server", &fields, &values, &None).unwrap();
+        assert_eq!(typed.len(), 2);
+        assert_eq!(typed[1].value, Some(json!(80)));
+
+        // Outputs don't have to be complete
+        let typed = engine.cast_values_to_typed_array(&fields, &vec![], &None).unwrap();
+        assert!(typed.iter().all(|io| io.value.is_none()));
+    }
+
+    #[test]
+    fn test_cast_keeps_whole_numbers_integers() {
+        let engine = ExecutionEngine::new();
+        let cast = |value: Value, target_type: &str| engine.cast(&value, target_type, &None);
+
+        assert_eq!(cast(json!("8080"), "number").unwrap(), json!(8080));
+        assert!(cast(json!("8080"), "number").unwrap().is_i64());
+        assert_eq!(cast(json!(8080.0), "number").unwrap(), json!(8080));
+        assert_eq!(cast(json!("-3"), "number").unwrap(), json!(-3));
+        assert_eq!(cast(json!("18446744073709551615"), "number").unwrap(), json!(u64::MAX));
+        assert_eq!(cast(json!("2.5"), "number").unwrap(), json!(2.5));
+        assert_eq!(cast(json!(0.1), "number").unwrap(), json!(0.1));
+        assert!(cast(json!("port"), "number").is_err());
+
+        assert_eq!(cast(json!("8080"), "integer").unwrap(), json!(8080));
+        assert_eq!(cast(json!(1e3), "integer").unwrap(), json!(1000));
+        let error = cast(json!("8080.5"), "integer").unwrap_err().to_string();
+        assert_eq!(error, "Cannot convert \"8080.5\" to integer: it has a fractional part");
+        let error = cast(json!(1e20), "integer").unwrap_err().to_string();
+        assert!(error.contains("out of the range of 64-bit integers"), "unexpected error: {}", error);
+        assert!(cast(json!(true), "integer").is_err());
+    }
+
+    #[test]
+    fn test_explain_inputs_reports_coercion() {
+        let engine = ExecutionEngine::new();
+        let declared = vec![test_io("port", "number", Value::Null), test_io("host", "string", Value::Null)];
+        let raw = vec![json!("8080"), json!("localhost")];
+        let typed = engine.cast_values_to_typed_array(&declared, &raw, &None).unwrap();
+
+        let report = ExecutionEngine::explain_inputs(&raw, &typed);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].name, "port");
+        assert_eq!(report[0].r#type, "number");
+        assert_eq!(report[0].raw, json!("8080"));
+        assert_eq!(report[0].cast, json!(8080));
+        assert_eq!(report[1].raw, report[1].cast);
+    }
+
+    #[tokio::test]
+    async fn test_trace_dumps_one_file_per_iteration() {
+        let trace_dir = tempfile::tempdir().unwrap();
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions {
+            trace_dir: Some(trace_dir.path().to_path_buf()),
+            ..Default::default()
+        });
+
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        engine.run_action_tree(&root, "").await.unwrap();
+
+        let mut traces: Vec<String> = std::fs::read_dir(trace_dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        traces.sort();
+        assert_eq!(traces, vec!["iteration-1.json", "iteration-2.json", "iteration-3.json"]);
+
+        // The last iteration holds the tree with every step executed
+        let last: Value = serde_json::from_str(
+            &std::fs::read_to_string(trace_dir.path().join("iteration-3.json")).unwrap()
+        ).unwrap();
+        assert_eq!(last["steps"]["step3"]["outputs"][0]["value"], json!("hello"));
+    }
+
+    #[test]
+    fn test_parse_manifest_io_rejects_object_inputs() {
+        let inputs = json!({"name": "location", "type": "string"});
+        let error = ExecutionEngine::parse_manifest_io("test/action:0.0.1", "inputs", &inputs).unwrap_err();
+        assert!(error.to_string().contains("`inputs` must be an array"), "unexpected error: {}", error);
+        assert!(error.to_string().contains("test/action:0.0.1"));
+    }
+
+    #[test]
+    fn test_parse_manifest_io_accepts_absent_inputs() {
+        let manifest: ShManifest = serde_json::from_value(manifest_json("action", json!({
+            "kind": "wasm",
+            "outputs": [{"name": "value", "type": "string"}]
+        }))).unwrap();
+
+        let inputs = ExecutionEngine::parse_manifest_io("test/action:0.0.1", "inputs", &manifest.inputs).unwrap();
+        assert!(inputs.is_empty());
+        let outputs = ExecutionEngine::parse_manifest_io("test/action:0.0.1", "outputs", &manifest.outputs).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].name, "value");
+    }
+
+    #[tokio::test]
+    async fn test_execute_action_local_manifest() {
+        let project = tempfile::tempdir().unwrap();
+        let manifest_path = project.path().join("starthub-lock.json");
+        write_manifest(&manifest_path, "echo", json!({
+            "inputs": [{"name": "message", "type": "string"}],
+            "outputs": [{"name": "message", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+
+        let mut engine = ExecutionEngine::new();
+        let result = engine.execute_action(
+            &format!("file://{}", manifest_path.display()),
+            vec![json!("hello")]
+        ).await.unwrap();
+        assert_eq!(result, json!(["hello"]));
+    }
+
+    #[tokio::test]
+    async fn test_execute_action_detailed_names_outputs() {
+        let project = tempfile::tempdir().unwrap();
+        let manifest_path = project.path().join("starthub-lock.json");
+        let action_ref = write_manifest(&manifest_path, "split", json!({
+            "inputs": [
+                {"name": "count", "type": "number"},
+                {"name": "label", "type": "string"}
+            ],
+            "outputs": [
+                {"name": "label", "type": "string", "value": "{{inputs[1]}}"},
+                {"name": "count", "type": "number", "value": "{{inputs[0]}}"}
+            ]
+        }));
+
+        let mut engine = ExecutionEngine::new();
+        let outcome = engine.execute_action_detailed(&action_ref, vec![json!(3), json!("items")]).await.unwrap();
+
+        assert_eq!(outcome.action, action_ref);
+        let outputs: Vec<(&str, &str, &Value)> = outcome.outputs.iter()
+            .map(|output| (output.name.as_str(), output.r#type.as_str(), &output.value))
+            .collect();
+        assert_eq!(outputs, vec![
+            ("label", "string", &json!("items")),
+            ("count", "number", &json!(3)),
+        ]);
+        assert_eq!(outcome.output_values(), vec![json!("items"), json!(3)]);
+    }
+
+    #[test]
+    fn test_output_shapes() {
+        let output = |name: &str, value: Value| OutputEntry { name: name.to_string(), r#type: "string".to_string(), value };
+        let mut outcome = RunOutcome {
+            action: "starthubhq/weather:0.0.1".to_string(),
+            root_id: "root".to_string(),
+            steps: Vec::new(),
+            outputs: vec![output("city", json!("Rome")), output("temperature", json!(21))],
+            input_report: Vec::new(),
+            timings: PhaseTimings::default(),
+        };
+
+        assert_eq!(outcome.result(OutputShape::Array).unwrap(), json!(["Rome", 21]));
+        assert_eq!(outcome.result(OutputShape::Object).unwrap(), json!({ "city": "Rome", "temperature": 21 }));
+        assert_eq!("object".parse::<OutputShape>().unwrap(), OutputShape::Object);
+        assert!("map".parse::<OutputShape>().is_err());
+
+        outcome.outputs.push(output("city", json!("Milan")));
+        let error = outcome.result(OutputShape::Object).unwrap_err().to_string();
+        assert!(error.contains("the name 'city' is declared more than once"), "unexpected error: {}", error);
+        assert_eq!(outcome.result(OutputShape::Array).unwrap(), json!(["Rome", 21, "Milan"]));
+    }
+
+    #[tokio::test]
+    async fn test_execute_action_detailed_reports_phase_timings() {
+        let project = tempfile::tempdir().unwrap();
+        let echo_path = project.path().join("echo.json");
+        write_manifest(&echo_path, "echo", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let root_path = project.path().join("starthub-lock.json");
+        write_manifest(&root_path, "wrapper", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": {
+                "echo": { "uses": format!("file://{}", echo_path.display()), "inputs": ["{{inputs[0]}}"] }
+            }
+        }));
+
+        let mut engine = ExecutionEngine::new();
+        let outcome = engine.execute_action_detailed(&format!("file://{}", root_path.display()), vec![json!("hello")]).await.unwrap();
+
+        assert_eq!(outcome.output_values(), vec![json!("hello")]);
+        assert!(outcome.timings.build_ms >= 0.0, "timings: {:?}", outcome.timings);
+        assert!(outcome.timings.execute_ms >= 0.0, "timings: {:?}", outcome.timings);
+        let reported = serde_json::to_value(&outcome).unwrap();
+        assert!(reported["timings"]["build_ms"].is_number() && reported["timings"]["execute_ms"].is_number());
+    }
+
+    #[tokio::test]
+    async fn test_execute_action_detailed_reports_every_step() {
+        let project = tempfile::tempdir().unwrap();
+        let echo_path = project.path().join("echo.json");
+        let echo_ref = write_manifest(&echo_path, "echo", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let root_path = project.path().join("starthub-lock.json");
+        write_manifest(&root_path, "wrapper", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.lookup.outputs[0]}}"}],
+            "steps": {
+                "echo": { "uses": echo_ref, "inputs": ["{{inputs[0]}}"] },
+                "lookup": { "uses": echo_ref, "inputs": ["{{steps.echo.outputs[0]}}"] }
+            }
+        }));
+
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions {
+            mocks: HashMap::from([("lookup".to_string(), vec![json!("mocked")])]),
+            ..Default::default()
+        });
+        let outcome = engine.execute_action_detailed(&format!("file://{}", root_path.display()), vec![json!("hello")]).await.unwrap();
+
+        assert_eq!(outcome.output_values(), vec![json!("mocked")]);
+        assert!(!outcome.root_id.is_empty());
+        let steps: Vec<(&str, StepStatus, &Vec<Value>)> = outcome.steps.iter()
+            .map(|step| (step.step_path.as_str(), step.status, &step.outputs))
+            .collect();
+        assert_eq!(steps, vec![
+            ("echo", StepStatus::Completed, &vec![json!("hello")]),
+            ("lookup", StepStatus::Mocked, &vec![json!("mocked")]),
+        ]);
+        assert!(outcome.steps.iter().all(|step| step.step_id != outcome.root_id && step.uses == echo_ref));
+
+        // A second run starts from an empty report
+        let outcome = engine.execute_action_detailed(&format!("file://{}", root_path.display()), vec![json!("again")]).await.unwrap();
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(serde_json::to_value(&outcome.steps[0]).unwrap()["status"], json!("completed"));
+    }
+
+    #[tokio::test]
+    async fn test_run_action_tree_logs_are_deterministic() {
+        let mut root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        for name in ["alpha", "bravo", "charlie", "delta", "echo"] {
+            root.steps.insert(name.to_string(), identity_step(name, "{{inputs[0]}}"));
+        }
+
+        let mut engine = ExecutionEngine::new();
+        let mut run_logs = Vec::new();
+        for _ in 0..2 {
+            let mut ws_receiver = engine.get_ws_sender().unwrap().subscribe();
+            engine.run_action_tree(&root, "").await.unwrap();
+
+            let mut logs = Vec::new();
+            while let Ok(msg) = ws_receiver.try_recv() {
+                let mut log: Value = serde_json::from_str(&msg).unwrap();
+                log.as_object_mut().unwrap().remove("timestamp");
+                logs.push(log);
+            }
+            assert!(!logs.is_empty());
+            run_logs.push(logs);
+        }
+
+        assert_eq!(run_logs[0], run_logs[1]);
+    }
+
+    #[tokio::test]
+    async fn test_validate_inputs() {
+        let project = tempfile::tempdir().unwrap();
+        let manifest_path = project.path().join("starthub-lock.json");
+        let action_ref = write_manifest(&manifest_path, "split", json!({
+            "inputs": [
+                {"name": "count", "type": "number", "required": true},
+                {"name": "label", "type": "string", "required": true}
+            ],
+            "outputs": []
+        }));
+        let engine = ExecutionEngine::new();
+
+        let errors = engine.validate_inputs(&action_ref, &vec![json!(3), json!("items")]).await.unwrap();
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+
+        let errors = engine.validate_inputs(&action_ref, &vec![json!("three"), json!("items")]).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 0);
+        assert_eq!(errors[0].name, "count");
+        assert!(errors[0].message.contains("Cannot convert string 'three' to number"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_inputs_required_if() {
+        let project = tempfile::tempdir().unwrap();
+        let manifest_path = project.path().join("starthub-lock.json");
+        let action_ref = write_manifest(&manifest_path, "deploy", json!({
+            "inputs": [
+                {"name": "provider", "type": "string", "required": true},
+                {"name": "region", "type": "string", "required_if": "provider == 'aws'"}
+            ],
+            "outputs": []
+        }));
+        let engine = ExecutionEngine::new();
+
+        // The controlling input asks for a region, which is missing
+        let errors = engine.validate_inputs(&action_ref, &vec![json!("aws")]).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 1);
+        assert_eq!(errors[0].name, "region");
+        assert!(errors[0].message.contains("provider == 'aws'"));
+
+        let errors = engine.validate_inputs(&action_ref, &vec![json!("aws"), json!("eu-west-1")]).await.unwrap();
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+
+        // Any other provider can leave the region out
+        let errors = engine.validate_inputs(&action_ref, &vec![json!("gcp")]).await.unwrap();
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+    }
+
+    #[tokio::test]
+    async fn test_run_enforces_required_if() {
+        let project = tempfile::tempdir().unwrap();
+        let manifest_path = project.path().join("starthub-lock.json");
+        let action_ref = write_manifest(&manifest_path, "deploy", json!({
+            "inputs": [
+                {"name": "provider", "type": "string", "required": true},
+                {"name": "region", "type": "string", "required_if": "provider == 'aws'"}
+            ],
+            "outputs": [
+                {"name": "provider", "type": "string", "value": "{{inputs[0]}}"}
+            ]
+        }));
+        let mut engine = ExecutionEngine::new();
+
+        // The run stops before any step when the controlling input asks for a missing region
+        let error = engine.execute_action_detailed(&action_ref, vec![json!("aws")]).await.unwrap_err().to_string();
+        assert!(error.contains("input 'region' (#1) is missing, required because provider == 'aws'"), "unexpected error: {}", error);
+
+        // Any other provider can leave the region out
+        let outcome = engine.execute_action_detailed(&action_ref, vec![json!("gcp")]).await.unwrap();
+        assert_eq!(outcome.output_values(), vec![json!("gcp")]);
+    }
+
+    #[test]
+    fn test_evaluate_required_if() {
+        let inputs = vec![test_io("provider", "string", Value::Null), test_io("debug", "boolean", Value::Null)];
+
+        let values = vec![json!("aws"), json!(false)];
+        assert!(ExecutionEngine::evaluate_required_if("provider == \"aws\"", &inputs, &values).unwrap());
+        assert!(!ExecutionEngine::evaluate_required_if("provider != 'aws'", &inputs, &values).unwrap());
+        assert!(!ExecutionEngine::evaluate_required_if("debug", &inputs, &values).unwrap());
+        assert!(ExecutionEngine::evaluate_required_if("debug == false", &inputs, &values).unwrap());
+        assert!(ExecutionEngine::evaluate_required_if("missing == 1", &inputs, &values).is_err());
+        assert!(ExecutionEngine::evaluate_required_if("provider == aws", &inputs, &values).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_engine_requests_carry_user_agent() {
+        // Registry capturing the headers of the requests it receives
+        let (requests_tx, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+        let registry = mock_registry(move |request| {
+            let _ = requests_tx.send(request.to_lowercase());
+            ("200 OK", String::new())
+        }).await;
+
+        let mut engine = ExecutionEngine::new();
+        engine.set_user_agent("starthub-cli/9.9.9");
+        engine.http_client.get(format!("{}/manifest", registry)).send().await.unwrap();
+
+        let request = requests_rx.recv().await.unwrap();
+        assert!(request.contains("user-agent: starthub-cli/9.9.9"), "request was: {}", request);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_download_errors_are_concise() {
+        // Registry answering 404 for /missing, an HTML error page for /broken and
+        // a JSON body that isn't a manifest for /invalid
+        let registry = mock_registry(|request| {
+            if request.starts_with("GET /missing ") {
+                ("404 Not Found", String::new())
+            } else if request.starts_with("GET /broken ") {
+                ("502 Bad Gateway", format!("<html><body>{}</body></html>", "Bad gateway. ".repeat(100)))
+            } else {
+                ("200 OK", r#"{"name": "invalid"}"#.to_string())
+            }
+        }).await;
+
+        let engine = ExecutionEngine::new();
+        let error = engine.download_manifest("acme/tpyo:1.0.0", &format!("{}/missing", registry)).await.unwrap_err();
+        assert_eq!(error.to_string(), "action not found: acme/tpyo:1.0.0");
+
+        let error = engine.download_manifest("acme/broken:1.0.0", &format!("{}/broken", registry)).await.unwrap_err().to_string();
+        assert!(error.contains("HTTP 502 Bad Gateway with a text/html response"), "error was: {}", error);
+        assert!(error.contains(&format!("{}/broken", registry)), "error was: {}", error);
+        assert!(error.len() < 400, "error was: {}", error);
+
+        let error = engine.download_manifest("acme/invalid:1.0.0", &format!("{}/invalid", registry)).await.unwrap_err().to_string();
+        assert!(error.starts_with("Invalid starthub-lock.json of acme/invalid:1.0.0"), "error was: {}", error);
+
+        assert_eq!(response_excerpt(&"é".repeat(150)).len(), 200 + "…".len());
+    }
+
+    #[tokio::test]
+    async fn test_engine_requests_go_through_proxy() {
+        // Forward proxy capturing the requests it receives
+        let (requests_tx, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+        let proxy = mock_registry(move |request| {
+            let _ = requests_tx.send(request.to_string());
+            ("200 OK", String::new())
+        }).await;
+
+        let mut engine = ExecutionEngine::new();
+        engine.set_proxy(&proxy).unwrap();
+        // The host doesn't resolve: the request only succeeds through the proxy
+        engine.http_client.get("http://registry.invalid/manifest").send().await.unwrap();
+
+        let request = requests_rx.recv().await.unwrap();
+        assert!(request.starts_with("GET http://registry.invalid/manifest HTTP/1.1"), "request was: {}", request);
+    }
+
+    #[tokio::test]
+    async fn test_check_artifacts_reports_missing_artifacts() {
+        // Mirror serving /present.zip only
+        let mirror = mock_registry(|request| {
+            let status = if request.starts_with("HEAD /present.zip ") { "200 OK" } else { "404 Not Found" };
+            (status, String::new())
+        }).await;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let leaf = |name: &str, kind: &str, file: &str| {
+            write_manifest(&temp_dir.path().join(format!("{}.json", name)), name, json!({
+                "kind": kind,
+                "inputs": [{"name": "value", "type": "string"}],
+                "outputs": [{"name": "value", "type": "string"}],
+                "mirrors": [format!("{}/{}", mirror, file)]
+            }))
+        };
+        let present = leaf("present", "wasm", "present.zip");
+        let missing = leaf("missing", "docker", "nonexistent-image.zip");
+        let root_path = temp_dir.path().join("root.json");
+        let root = write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.second.outputs[0]}}"}],
+            "steps": {
+                "first": { "uses": present, "inputs": ["{{inputs[0]}}"] },
+                "second": { "uses": missing, "inputs": ["{{steps.first.outputs[0]}}"] }
+            }
+        }));
+
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let report = engine.check_artifacts(&root).await.unwrap();
+        assert_eq!(report.len(), 1, "unexpected report: {:?}", report);
+        assert_eq!(report[0].step_path, "second");
+        assert_eq!(report[0].kind, "docker");
+        assert!(
+            report[0].tried.iter().any(|attempt| attempt.contains("nonexistent-image.zip: HTTP 404")),
+            "unexpected attempts: {:?}", report[0].tried
+        );
+
+        // Validating a local manifest with the same steps reports the same artifact, only when asked to
+        let source = std::fs::read_to_string(&root_path).unwrap();
+        assert!(engine.validate_manifest("starthub-lock.json", &source, false).await.unwrap().is_empty());
+        let problems = engine.validate_manifest("starthub-lock.json", &source, true).await.unwrap();
+        assert_eq!(problems.len(), 1, "problems: {:#?}", problems);
+        assert!(
+            problems[0].starts_with(&format!("step 'second' needs the docker artifact of {}, which can't be fetched: ", missing)),
+            "problems: {:#?}", problems
+        );
+    }
+
+    #[tokio::test]
+    async fn test_latency_strategy_tries_fastest_mirror_first() {
+        let mirror = |delay: std::time::Duration| async move {
+            format!("{}/artifact.zip", mock_slow_registry(delay, |_| ("200 OK", String::new())).await)
+        };
+
+        let slow = mirror(std::time::Duration::from_millis(300)).await;
+        let fast = mirror(std::time::Duration::ZERO).await;
+        let mirrors = vec![slow.clone(), fast.clone()];
+        let client = reqwest::Client::new();
+
+        // In-order is the default and doesn't probe anything
+        assert_eq!(http::order_mirrors(&client, http::MirrorStrategy::Order, &mirrors).await, mirrors);
+        assert_eq!(
+            http::order_mirrors(&client, http::MirrorStrategy::Latency, &mirrors).await,
+            vec![fast, slow]
+        );
+        assert_eq!("latency".parse::<http::MirrorStrategy>().unwrap(), http::MirrorStrategy::Latency);
+        assert!("fastest".parse::<http::MirrorStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_requests_carry_auth_header() {
+        // Registry serving a manifest only to requests with the expected bearer token
+        let registry = mock_registry(|request| {
+            if request.to_lowercase().contains("authorization: bearer secret-token\r\n") {
+                ("200 OK", serde_json::to_string(&test_manifest("1.0.0")).unwrap())
+            } else {
+                ("401 Unauthorized", String::new())
+            }
+        }).await;
+        let url = format!("{}/storage/child/1.0.0/starthub-lock.json", registry);
+
+        let anonymous = ExecutionEngine::new();
+        let error = anonymous.download_manifest("starthubhq/child:1.0.0", &url).await.unwrap_err();
+        assert_eq!(error.to_string(), "not authorized to access starthubhq/child:1.0.0; run starthub login");
+
+        let mut authenticated = ExecutionEngine::new();
+        authenticated.set_auth_token("secret-token").unwrap();
+        let manifest = authenticated.download_manifest("starthubhq/child:1.0.0", &url).await.unwrap();
+        assert_eq!(manifest.version, "1.0.0");
+        // The token never shows up in debug output
+        assert!(!format!("{:?}", authenticated.manifest_headers).contains("secret-token"));
+    }
+
+    #[test]
+    fn test_registry_token_precedence() {
+        let env = |token: &'static str| move |name: &str| (name == http::TOKEN_ENV).then(|| token.to_string());
+        let saved = || Some("saved-token".to_string());
+        let configured = || Some("configured-token".to_string());
+
+        // STARTHUB_TOKEN beats the config file, which beats the token saved by `starthub login`
+        assert_eq!(http::registry_token(env("env-token"), configured(), saved()), Some(("env-token".to_string(), http::TOKEN_ENV)));
+        assert_eq!(http::registry_token(env("env-token"), None, saved()), Some(("env-token".to_string(), http::TOKEN_ENV)));
+        assert_eq!(http::registry_token(env(" "), configured(), saved()), Some(("configured-token".to_string(), "the server config file")));
+        assert_eq!(http::registry_token(|_| None, None, saved()), Some(("saved-token".to_string(), "starthub login")));
+        assert_eq!(http::registry_token(|_| None, None, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_fetches_log_cache_status() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Registry counting the requests it serves
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        let registry = mock_registry(move |_| {
+            served.fetch_add(1, Ordering::SeqCst);
+            ("200 OK", serde_json::to_string(&test_manifest("1.0.0")).unwrap())
+        }).await;
+        let url = format!("{}/storage/child/1.0.0/starthub-lock.json", registry);
+
+        let run_logs = RunLogs::default();
+        let mut engine = ExecutionEngine::new();
+        engine.set_run_logs(run_logs.clone(), "run-a");
+        for _ in 0..2 {
+            let manifest = engine.download_manifest("starthubhq/child:1.0.0", &url).await.unwrap();
+            assert_eq!(manifest.version, "1.0.0");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let messages: Vec<String> = run_logs.get("run-a").unwrap().iter()
+            .filter_map(|line| line["message"].as_str().map(|m| m.to_string()))
+            .filter(|message| message.starts_with("Manifest starthubhq/child:1.0.0"))
+            .collect();
+        assert_eq!(messages.len(), 2, "logs were: {:?}", messages);
+        assert!(messages[0].contains(&url) && messages[0].contains("cache miss, HTTP 200"), "logs were: {:?}", messages);
+        assert!(messages[1].contains("cache hit"), "logs were: {:?}", messages);
+    }
+
+    #[tokio::test]
+    async fn test_warmed_cache_serves_offline_runs() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let child = manifest_json("child", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let root = manifest_json("root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": { "echo": { "uses": "starthubhq/child:0.0.1", "inputs": ["{{inputs[0]}}"] } }
+        }));
+
+        // Registry serving both manifests by path, counting the requests
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        let registry = mock_registry(move |request| {
+            served.fetch_add(1, Ordering::SeqCst);
+            let body = if request.contains("/starthubhq/root/") { root.to_string() } else { child.to_string() };
+            ("200 OK", body)
+        }).await;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let refs = vec!["starthubhq/root:0.0.1".to_string()];
+
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir.clone());
+        engine.registry_base_url = registry.clone();
+        let report = engine.warm_cache(&refs).await.unwrap();
+        assert_eq!(report.fetched, vec!["starthubhq/root:0.0.1", "starthubhq/child:0.0.1"]);
+        assert!(report.cached.is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir.clone());
+        engine.registry_base_url = registry.clone();
+        let report = engine.warm_cache(&refs).await.unwrap();
+        assert!(report.fetched.is_empty());
+        assert_eq!(report.cached.len(), 2);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // The registry is unreachable, so the manifests can only come from the cache
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir);
+        engine.registry_base_url = "http://127.0.0.1:9".to_string();
+        engine.set_options(ExecutionOptions { offline: true, ..Default::default() });
+        let result = engine.execute_action("starthubhq/root:0.0.1", vec![json!("hello")]).await.unwrap();
+        assert_eq!(result, json!(["hello"]));
+
+        let error = engine.execute_action("starthubhq/other:0.0.1", vec![]).await.unwrap_err().to_string();
+        assert!(error.contains("isn't cached"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_online_runs_prime_the_cache_per_version() {
+        let echo = |version: &str| manifest_json("echo", json!({
+            "version": version,
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": format!("{}: {{{{inputs[0]}}}}", version)}]
+        }));
+
+        // Registry serving every version of the action
+        let registry = mock_registry(move |request| {
+            let version = if request.contains("/0.0.2/") { "0.0.2" } else { "0.0.1" };
+            ("200 OK", echo(version).to_string())
+        }).await;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir.clone());
+        engine.registry_base_url = registry;
+        for version in ["0.0.1", "0.0.2"] {
+            let result = engine.execute_action(&format!("starthubhq/echo:{}", version), vec![json!("hi")]).await.unwrap();
+            assert_eq!(result, json!([format!("{}: hi", version)]));
+        }
+        assert!(cache_dir.join("starthubhq/echo/0.0.1").join(STARTHUB_MANIFEST_FILENAME).exists());
+        assert!(cache_dir.join("starthubhq/echo/0.0.2").join(STARTHUB_MANIFEST_FILENAME).exists());
+
+        // `latest` may move to another version, so it is never cached
+        engine.execute_action("starthubhq/echo:latest", vec![json!("hi")]).await.unwrap();
+        assert!(!cache_dir.join("starthubhq/echo/latest").exists());
+
+        // Offline, each version is served from its own cache entry
+        let mut engine = ExecutionEngine::with_cache_dir(cache_dir);
+        engine.registry_base_url = "http://127.0.0.1:9".to_string();
+        engine.set_options(ExecutionOptions { offline: true, ..Default::default() });
+        for version in ["0.0.1", "0.0.2"] {
+            let result = engine.execute_action(&format!("starthubhq/echo:{}", version), vec![json!("hi")]).await.unwrap();
+            assert_eq!(result, json!([format!("{}: hi", version)]));
+        }
+        let error = engine.execute_action("starthubhq/echo:0.0.3", vec![json!("hi")]).await.unwrap_err().to_string();
+        assert!(error.contains("isn't cached"), "unexpected error: {}", error);
+        let error = engine.execute_action("starthubhq/echo:latest", vec![json!("hi")]).await.unwrap_err().to_string();
+        assert!(error.contains("isn't cached"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_non_array_step_inputs_are_rejected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        write_manifest(&child_path, "child", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let root_path = temp_dir.path().join("root.json");
+        write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": {
+                "echo": {
+                    "uses": format!("file://{}", child_path.display()),
+                    "inputs": { "value": "{{inputs[0]}}" }
+                }
+            }
+        }));
+
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let error = engine.build_action_tree(&format!("file://{}", root_path.display()), None).await.unwrap_err().to_string();
+        assert!(error.contains("Inputs of step 'echo'") && error.contains("must be an array"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_depth_limited_build_leaves_grandchildren_unexpanded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let composition = |name: &str, uses: &str| manifest_json(name, json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.inner.outputs[0]}}"}],
+            "steps": {
+                "inner": { "uses": uses, "inputs": ["{{inputs[0]}}"] }
+            }
+        }));
+        // The grandchild doesn't exist: fetching it would fail the build
+        let grandchild_ref = format!("file://{}", temp_dir.path().join("missing.json").display());
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&composition("child", &grandchild_ref)).unwrap()).unwrap();
+        let root_path = temp_dir.path().join("root.json");
+        let child_ref = format!("file://{}", child_path.display());
+        std::fs::write(&root_path, serde_json::to_string(&composition("root", &child_ref)).unwrap()).unwrap();
+        let root_ref = format!("file://{}", root_path.display());
+
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let (tree, graph) = engine.plan_action_to_depth(&root_ref, Some(1)).await.unwrap();
+        let child = &tree.steps["inner"];
+        assert_eq!(child.kind, "composition");
+        assert_eq!(child.name, "child");
+        let grandchild = &child.steps["inner"];
+        assert_eq!(grandchild.kind, UNEXPANDED_KIND);
+        assert_eq!(grandchild.uses, grandchild_ref);
+        assert!(grandchild.steps.is_empty());
+        let kinds: Vec<&str> = graph.nodes.iter().map(|node| node.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["composition", "composition", UNEXPANDED_KIND]);
+
+        // Depth 0 only reads the root, and a full build does reach the grandchild
+        let (tree, _) = engine.plan_action_to_depth(&root_ref, Some(0)).await.unwrap();
+        assert_eq!(tree.steps["inner"].kind, UNEXPANDED_KIND);
+        assert!(engine.plan_action(&root_ref).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unwired_required_step_inputs_are_reported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        let child_ref = write_manifest(&child_path, "child", json!({
+            "kind": "wasm",
+            "inputs": [
+                {"name": "url", "type": "string", "required": true},
+                {"name": "token", "type": "string", "required": true},
+                {"name": "timeout", "type": "number"}
+            ],
+            "outputs": [{"name": "body", "type": "string"}]
+        }));
+        let root = |fetch_inputs: Value| manifest_json("root", json!({
+            "inputs": [{"name": "url", "type": "string"}],
+            "outputs": [{"name": "body", "type": "string", "value": "{{steps.fetch.outputs[0]}}"}],
+            "steps": {
+                "fetch": { "uses": child_ref, "inputs": fetch_inputs }
+            }
+        }));
+        let root_path = temp_dir.path().join("root.json");
+        let root_ref = format!("file://{}", root_path.display());
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+
+        // The token is forgotten: the optional timeout may stay unwired
+        std::fs::write(&root_path, serde_json::to_string(&root(json!(["{{inputs[0]}}"]))).unwrap()).unwrap();
+        let error = engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string();
+        assert_eq!(error, format!(
+            "Composition {} leaves required step input(s) unwired: step 'fetch' input 'token' (#1) isn't wired",
+            root_ref
+        ));
+
+        // References to an undeclared input or a missing step can't be resolved either
+        std::fs::write(&root_path, serde_json::to_string(&root(json!(["{{inputs[3]}}", "{{steps.login.outputs[0]}}"]))).unwrap()).unwrap();
+        let error = engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string();
+        assert!(error.ends_with(
+            "step 'fetch' input 'url' (#0) refers to inputs[3], which isn't declared; step 'fetch' input 'token' (#1) refers to step 'login', which doesn't exist"
+        ), "unexpected error: {}", error);
+
+        std::fs::write(&root_path, serde_json::to_string(&root(json!(["{{inputs[0]}}", "literal-token"]))).unwrap()).unwrap();
+        assert!(engine.build_action_tree(&root_ref, None).await.is_ok());
+
+        // Hooks are checked too, and only see the inputs of the composition
+        let with_post = |hook_inputs: Value| {
+            let mut manifest = root(json!(["{{inputs[0]}}", "literal-token"]));
+            manifest["post"] = json!([{ "uses": child_ref, "inputs": hook_inputs }]);
+            manifest
+        };
+        std::fs::write(&root_path, serde_json::to_string(&with_post(json!(["{{steps.fetch.outputs[0]}}"]))).unwrap()).unwrap();
+        let error = engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string();
+        assert!(error.ends_with(
+            "hook 'post[0]' input 'url' (#0) refers to step 'fetch', which hooks can't see; hook 'post[0]' input 'token' (#1) isn't wired"
+        ), "unexpected error: {}", error);
+
+        std::fs::write(&root_path, serde_json::to_string(&with_post(json!("{{inputs[0]}}"))).unwrap()).unwrap();
+        let error = engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string();
+        assert_eq!(error, format!("Hook post[0] of {} has `inputs` that isn't an array", root_ref));
+    }
+
+    #[tokio::test]
+    async fn test_step_timeouts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&test_manifest("0.0.1")).unwrap()).unwrap();
+        let root_path = temp_dir.path().join("root.json");
+        let root = |timeout: Value| manifest_json("root", json!({
+            "steps": {
+                "slow": { "uses": format!("file://{}", child_path.display()), "timeout": timeout }
+            }
+        }));
+        let root_ref = format!("file://{}", root_path.display());
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+
+        // The timeout is read from the step
+        std::fs::write(&root_path, serde_json::to_string(&root(json!(30))).unwrap()).unwrap();
+        let tree = engine.build_action_tree(&root_ref, None).await.unwrap();
+        assert_eq!(tree.steps["slow"].timeout, Some(30));
+        std::fs::write(&root_path, serde_json::to_string(&root(json!("soon"))).unwrap()).unwrap();
+        assert!(engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string().contains("Timeout of step 'slow'"));
+
+        // A composition doesn't run with a timeout, so it can't be given one
+        let mut composition = test_manifest("0.0.1");
+        composition.kind = Some(ShKind::Composition);
+        std::fs::write(&child_path, serde_json::to_string(&composition).unwrap()).unwrap();
+        std::fs::write(&root_path, serde_json::to_string(&root(json!(30))).unwrap()).unwrap();
+        let error = engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string();
+        assert!(error.contains("Timeout of step 'slow' can't apply to"), "unexpected error: {}", error);
+
+        // Without a timeout, the work isn't bounded
+        let mut step = test_action("slow", "docker", "test/slow:0.0.1", vec![], vec![]);
+        engine.set_default_step_timeout(None);
+        assert_eq!(engine.with_step_timeout(&step, "slow", async { Ok(1) }).await.unwrap(), 1);
+
+        // A hanging step is abandoned with a structured error
+        step.timeout = Some(1);
+        let error = engine.with_step_timeout(&step, "deploy.slow", std::future::pending::<Result<()>>()).await.unwrap_err();
+        let timeout = error.downcast_ref::<StepTimeout>().expect("expected a StepTimeout");
+        assert_eq!(timeout.step_path, "deploy.slow");
+        assert_eq!(timeout.step_id, step.id);
+        assert_eq!(timeout.timeout_secs, 1.0);
+        assert!(timeout.elapsed_secs >= 1.0);
+        assert!(error.to_string().starts_with("step 'deploy.slow' timed out after 1."), "unexpected error: {}", error);
+
+        // The engine's default applies to the steps without their own
+        step.timeout = None;
+        engine.set_default_step_timeout(Some(std::time::Duration::from_millis(50)));
+        let error = engine.with_step_timeout(&step, "slow", std::future::pending::<Result<()>>()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<StepTimeout>().unwrap().timeout_secs, 0.05);
+    }
+
+    #[test]
+    fn test_parse_step_timeout() {
+        assert_eq!(parse_step_timeout("30").unwrap(), Some(std::time::Duration::from_secs(30)));
+        assert_eq!(parse_step_timeout(" 5 ").unwrap(), Some(std::time::Duration::from_secs(5)));
+        assert_eq!(parse_step_timeout("0").unwrap(), None);
+
+        // A typo is an error, not a run without any timeout
+        let error = parse_step_timeout("30s").unwrap_err().to_string();
+        assert_eq!(error, "expected a whole number of seconds, got '30s'");
+        assert!(parse_step_timeout("-1").is_err());
+        assert!(parse_step_timeout("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cyclic_and_too_deep_trees_are_rejected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let composition = |name: &str, uses: &str| manifest_json(name, json!({
+            "steps": { "next": { "uses": uses } }
+        }));
+        let a_ref = format!("file://{}", temp_dir.path().join("a.json").display());
+        let b_ref = format!("file://{}", temp_dir.path().join("b.json").display());
+        let c_ref = format!("file://{}", temp_dir.path().join("c.json").display());
+        std::fs::write(temp_dir.path().join("a.json"), serde_json::to_string(&composition("a", &b_ref)).unwrap()).unwrap();
+        std::fs::write(temp_dir.path().join("b.json"), serde_json::to_string(&composition("b", &a_ref)).unwrap()).unwrap();
+        std::fs::write(temp_dir.path().join("c.json"), serde_json::to_string(&composition("c", &a_ref)).unwrap()).unwrap();
+
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let error = engine.build_action_tree(&a_ref, None).await.unwrap_err().to_string();
+        assert_eq!(error, format!("Cyclic action dependency: {} -> {} -> {}", a_ref, b_ref, a_ref));
+
+        // The cycle is reported from where it starts
+        let error = engine.build_action_tree(&c_ref, None).await.unwrap_err().to_string();
+        assert_eq!(error, format!("Cyclic action dependency: {} -> {} -> {}", a_ref, b_ref, a_ref));
+
+        // The depth limit is hit first when it is lower than the cycle
+        engine.set_max_tree_depth(1);
+        let error = engine.build_action_tree(&c_ref, None).await.unwrap_err().to_string();
+        assert_eq!(error, format!("Action tree exceeds the maximum depth of 1: {} -> {} -> {}", c_ref, a_ref, b_ref));
+    }
+
+    #[tokio::test]
+    async fn test_post_hooks_run_whether_the_body_succeeds_or_fails() {
+        let run_logs = RunLogs::default();
+        let mut engine = ExecutionEngine::new();
+        let hook_messages = |run_id: &str| -> Vec<String> {
+            run_logs.get(run_id).unwrap().iter()
+                .filter_map(|line| line["message"].as_str().map(|m| m.to_string()))
+                .filter(|message| message.contains(" hook "))
+                .collect()
+        };
+
+        // The body succeeds
+        engine.set_run_logs(run_logs.clone(), "success");
+        let mut root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        root.post.push(identity_step("cleanup", "{{inputs[0]}}"));
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!("hello")));
+        assert_eq!(hook_messages("success"), vec!["Running post hook 'post[0]': test/cleanup:0.0.1".to_string()]);
+
+        // The body fails: its error is returned once the post hook ran
+        engine.set_run_logs(run_logs.clone(), "failure");
+        let mut root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            failing_step("step3", "wasm", "{{steps.step2.outputs[0]}}"),
+        );
+        root.post.push(identity_step("cleanup", "{{inputs[0]}}"));
+        let error = engine.run_action_tree(&root, "").await.unwrap_err().to_string();
+        assert!(!error.contains("hook"), "unexpected error: {}", error);
+        assert_eq!(hook_messages("failure"), vec!["Running post hook 'post[0]': test/cleanup:0.0.1".to_string()]);
+
+        // A failing pre hook skips the body, not the post hooks
+        engine.set_run_logs(run_logs.clone(), "pre-failure");
+        root.steps.insert("step3".to_string(), identity_step("step3", "{{steps.step2.outputs[0]}}"));
+        root.pre.push(failing_step("setup", "wasm", "{{inputs[0]}}"));
+        let error = engine.run_action_tree(&root, "").await.unwrap_err().to_string();
+        assert!(error.starts_with("pre hook 'pre[0]' failed: "), "unexpected error: {}", error);
+        let messages = hook_messages("pre-failure");
+        assert_eq!(messages.first().map(String::as_str), Some("Running pre hook 'pre[0]': test/missing-wasm-action:0.0.1"));
+        assert_eq!(messages.last().map(String::as_str), Some("Running post hook 'post[0]': test/cleanup:0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_steps_are_retried_per_their_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&test_manifest("0.0.1")).unwrap()).unwrap();
+        let root_path = temp_dir.path().join("root.json");
+        let root = |retry: Value| manifest_json("root", json!({
+            "steps": {
+                "flaky": { "uses": format!("file://{}", child_path.display()), "retry": retry }
+            }
+        }));
+        let root_ref = format!("file://{}", root_path.display());
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+
+        // The policy is read from the step
+        std::fs::write(&root_path, serde_json::to_string(&root(json!({"max_attempts": 3, "backoff_ms": 10, "exponential": true}))).unwrap()).unwrap();
+        let tree = engine.build_action_tree(&root_ref, None).await.unwrap();
+        let retry = tree.steps["flaky"].retry.clone().unwrap();
+        assert_eq!(retry, ShRetry { max_attempts: 3, backoff_ms: 10, exponential: true });
+        assert_eq!(retry.delay_after(1).as_millis(), 10);
+        assert_eq!(retry.delay_after(2).as_millis(), 20);
+        std::fs::write(&root_path, serde_json::to_string(&root(json!({"max_attempts": 0}))).unwrap()).unwrap();
+        assert!(engine.build_action_tree(&root_ref, None).await.unwrap_err().to_string().contains("Retry of step 'flaky'"));
+
+        let run_logs = RunLogs::default();
+        engine.set_run_logs(run_logs.clone(), "retries");
+        let mut step = test_action("flaky", "docker", "test/flaky:0.0.1", vec![], vec![]);
+        step.retry = Some(ShRetry { max_attempts: 3, backoff_ms: 1, exponential: false });
+
+        // A transient failure is retried until the step succeeds
+        let attempts = std::cell::Cell::new(0);
+        let result = engine.with_step_retries(&step, "deploy.flaky", || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move { if attempt < 3 { Err(anyhow::anyhow!("HTTP 503")) } else { Ok(attempt) } }
+        }).await.unwrap();
+        assert_eq!(result, 3);
+        let messages: Vec<String> = run_logs.get("retries").unwrap().iter()
+            .filter_map(|line| line["message"].as_str().map(|m| m.to_string()))
+            .filter(|message| message.starts_with("Step deploy.flaky"))
+            .collect();
+        assert_eq!(messages, vec![
+            "Step deploy.flaky failed on attempt 1 of 3, retrying in 1 ms: HTTP 503".to_string(),
+            "Step deploy.flaky failed on attempt 2 of 3, retrying in 1 ms: HTTP 503".to_string(),
+        ]);
+
+        // Only the last error is surfaced once the attempts are exhausted
+        attempts.set(0);
+        let error = engine.with_step_retries(&step, "deploy.flaky", || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move { Err::<(), _>(anyhow::anyhow!("HTTP 503 on attempt {}", attempt)) }
+        }).await.unwrap_err();
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(error.to_string(), "HTTP 503 on attempt 3");
+
+        // A failing sibling is retried without running the successful steps again
+        engine.set_run_logs(run_logs.clone(), "composition");
+        let mut flaky = failing_step("step2", "wasm", "{{steps.step1.outputs[0]}}");
+        flaky.retry = Some(ShRetry { max_attempts: 2, backoff_ms: 0, exponential: false });
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            flaky,
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        assert!(engine.run_action_tree(&root, "").await.is_err());
+        let messages: Vec<String> = run_logs.get("composition").unwrap().iter()
+            .filter_map(|line| line["message"].as_str().map(|m| m.to_string()))
+            .collect();
+        assert_eq!(messages.iter().filter(|m| m.starts_with("Step step2 failed on attempt 1 of 2")).count(), 1);
+        let step1_runs = messages.iter()
+            .filter_map(|m| m.strip_prefix("Running action: "))
+            .filter_map(|action| serde_json::from_str::<Value>(action).ok())
+            .filter(|action| action["name"] == "step1")
+            .count();
+        assert_eq!(step1_runs, 1);
+    }
+
+    #[test]
+    fn test_file_referenced_inputs_are_rejected() {
+        reject_file_inputs(&[json!("inline"), json!({"$file": "not a reference", "other": 1})]).unwrap();
+
+        let error = reject_file_inputs(&[json!("inline"), json!({"$file": "/etc/passwd"})]).unwrap_err().to_string();
+        assert!(error.starts_with("Input #1: `$file` references such as /etc/passwd are only expanded by the CLI"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_outputs_of_nested_steps_can_be_referenced() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        let mut outer = test_action(
+            "outer",
+            "composition",
+            "test/outer:0.0.1",
+            vec![test_io("value", "string", json!("{{inputs[0]}}"))],
+            vec![test_io("value", "string", json!("{{steps.inner.outputs[0]}}"))],
+        );
+        outer.steps.insert("inner".to_string(), identity_step("inner", "{{inputs[0]}}"));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![
+                test_io("after", "string", json!("{{steps.after.outputs[0]}}")),
+                test_io("inner", "string", json!("{{steps.outer.steps.inner.outputs[0]}}")),
+            ],
+        );
+        root.steps.insert("outer".to_string(), outer);
+        root.steps.insert("after".to_string(), identity_step("after", "{{steps.outer.steps.inner.outputs[0]}}"));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!("hello")));
+        assert_eq!(executed.outputs[1].value, Some(json!("hello")));
+
+        assert_eq!(ExecutionEngine::nested_step(&executed.steps, "outer.steps.inner").map(|step| step.name.as_str()), Some("inner"));
+        assert!(ExecutionEngine::nested_step(&executed.steps, "outer.steps.missing").is_none());
+        assert!(ExecutionEngine::nested_step(&executed.steps, "missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validate_manifest_reports_every_problem() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&test_manifest("0.0.1")).unwrap()).unwrap();
+        let missing_path = temp_dir.path().join("missing.json");
+        let manifest = |kind: &str| manifest_json("root", json!({
+            "kind": kind,
+            "types": { "Coordinates": { "lat": "number", "lon": "number" } },
+            "inputs": [
+                {"name": "place", "type": "Coordinates"},
+                {"name": "units", "type": "Units"}
+            ],
+            "outputs": [
+                {"name": "report", "type": "string", "value": "{{steps.fetch.outputs[0]}}"},
+                {"name": "extra", "type": "string", "value": "{{steps.nowhere.outputs[0]}}"}
+            ],
+            "steps": {
+                "fetch": { "uses": format!("file://{}", child_path.display()), "inputs": ["{{inputs[0]}}"] },
+                "store": { "uses": format!("file://{}", missing_path.display()), "inputs": ["{{steps.ghost.outputs[0]}}"] },
+                "typo": { "use": "starthubhq/echo:0.0.1" }
+            }
+        }));
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+
+        let problems = engine.validate_manifest("starthub-lock.json", &manifest("script").to_string(), false).await.unwrap();
+        assert_eq!(problems.len(), 6, "problems: {:#?}", problems);
+        assert_eq!(problems[0], "unknown kind \"script\", expected composition, wasm or docker");
+        assert!(problems[1].starts_with(&format!("step 'store' uses file://{}, which can't be fetched: ", missing_path.display())), "problems: {:#?}", problems);
+        assert_eq!(&problems[2..], &[
+            "step 'store' input #0 refers to step 'ghost', which doesn't exist".to_string(),
+            "step 'typo' has no `uses`".to_string(),
+            "input 'units' (#1) has type 'Units', which is neither a primitive type nor declared in `types`".to_string(),
+            "output 'extra' (#1) refers to step 'nowhere', which doesn't exist".to_string(),
+        ]);
+
+        // The same manifest with a known kind only loses the kind problem
+        assert_eq!(engine.validate_manifest("starthub-lock.json", &manifest("composition").to_string(), false).await.unwrap().len(), 5);
+
+        let error = engine.validate_manifest("starthub-lock.json", "{ not json", false).await.unwrap_err().to_string();
+        assert!(error.starts_with("starthub-lock.json isn't valid JSON"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_locked_runs_use_the_locked_versions_and_reject_drift() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child = |version: &str| manifest_json("child", json!({
+            "version": version,
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&child("0.0.1")).unwrap()).unwrap();
+        let root_path = temp_dir.path().join("root.json");
+        let root_ref = write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": {
+                "echo": { "uses": "test/child:latest", "inputs": ["{{inputs[0]}}"] }
+            }
+        }));
+        let workspace: HashMap<String, std::path::PathBuf> = [("test/child".to_string(), child_path.clone())].into_iter().collect();
+
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_options(ExecutionOptions { workspace: workspace.clone(), ..Default::default() });
+        let lockfile = engine.lock_action(&root_ref).await.unwrap();
+        assert_eq!(lockfile.root, root_ref);
+        assert_eq!(lockfile.actions.len(), 2);
+        let locked_child = &lockfile.actions["test/child:latest"];
+        assert_eq!(locked_child.resolved, "test/child:0.0.1");
+        assert!(locked_child.digest.starts_with("sha256:") && locked_child.digest.len() == 71);
+        // Locking again gives the same lockfile
+        assert_eq!(engine.lock_action(&root_ref).await.unwrap(), lockfile);
+
+        let locked_engine = |lockfile: Lockfile| {
+            let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+            engine.set_options(ExecutionOptions { workspace: workspace.clone(), lockfile: Some(lockfile), ..Default::default() });
+            engine
+        };
+        let result = locked_engine(lockfile.clone()).execute_action(&root_ref, vec![json!("hi")]).await.unwrap();
+        assert_eq!(result, json!(["hi"]));
+
+        // A reference the lockfile doesn't know can't be satisfied
+        let mut incomplete = lockfile.clone();
+        incomplete.actions.remove("test/child:latest");
+        let error = locked_engine(incomplete).execute_action(&root_ref, vec![json!("hi")]).await.unwrap_err().to_string();
+        assert!(error.contains("test/child:latest isn't in the lockfile"), "unexpected error: {}", error);
+
+        // The child moved on to another version since it was locked
+        std::fs::write(&child_path, serde_json::to_string(&child("0.0.2")).unwrap()).unwrap();
+        let error = locked_engine(lockfile).execute_action(&root_ref, vec![json!("hi")]).await.unwrap_err().to_string();
+        assert!(error.contains("Manifest of test/child:latest (locked as test/child:0.0.1) doesn't match the lockfile"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_locked_runs_reject_a_republished_artifact() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        write_manifest(&child_path, "child", json!({
+            "kind": "wasm",
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string"}]
+        }));
+        let root_path = temp_dir.path().join("root.json");
+        let root_ref = write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.echo.outputs[0]}}"}],
+            "steps": {
+                "echo": {
+                    "uses": "test/child:0.0.1",
+                    "inputs": ["{{inputs[0]}}"],
+                    "retry": {"max_attempts": 3, "backoff_ms": 1}
+                }
+            }
+        }));
+        // The artifact is already cached, so nothing is downloaded
+        let artifact_path = temp_dir.path().join("cache/test/child/0.0.1/artifact.wasm");
+        std::fs::create_dir_all(artifact_path.parent().unwrap()).unwrap();
+        std::fs::write(&artifact_path, b"\0asm locked").unwrap();
+
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let workspace: HashMap<String, std::path::PathBuf> = [("test/child".to_string(), child_path)].into_iter().collect();
+        engine.set_options(ExecutionOptions { workspace: workspace.clone(), ..Default::default() });
+        let lockfile = engine.lock_action(&root_ref).await.unwrap();
+        let locked_child = &lockfile.actions["test/child:0.0.1"];
+        assert_eq!(locked_child.artifact, Some(crate::lockfile::file_digest(&artifact_path).unwrap()));
+        assert_eq!(lockfile.actions[&root_ref].artifact, None);
+
+        // The same version was republished with another module
+        std::fs::write(&artifact_path, b"\0asm republished").unwrap();
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_options(ExecutionOptions { workspace, lockfile: Some(lockfile), ..Default::default() });
+        let run_logs = RunLogs::default();
+        engine.set_run_logs(run_logs.clone(), "republished");
+        let error = engine.execute_action(&root_ref, vec![json!("hi")]).await.unwrap_err().to_string();
+        assert!(error.contains("Artifact of test/child:0.0.1 doesn't match the lockfile"), "unexpected error: {}", error);
+
+        // The artifact is checked once, before the attempts of the step: a mismatch isn't retried
+        assert!(!run_logs.get("republished").unwrap().iter()
+            .any(|line| line["message"].as_str().is_some_and(|message| message.contains("retrying"))));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_lists_ready_and_blocked_steps_without_running_them() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let write = |file: &str, manifest: Value| {
+            let path = temp_dir.path().join(file);
+            std::fs::write(&path, serde_json::to_string(&manifest).unwrap()).unwrap();
+            format!("file://{}", path.display())
+        };
+        // The artifact doesn't exist: running the leaf would fail
+        let leaf_ref = write("leaf.json", manifest_json("leaf", json!({
+            "kind": "wasm",
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string"}]
+        })));
+        let composition = |name: &str, steps: Value| manifest_json(name, json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.shout.outputs[0]}}"}],
+            "steps": steps
+        }));
+        let greet_ref = write("greet.json", composition("greet", json!({
+            "shout": { "uses": leaf_ref, "inputs": ["{{inputs[0]}}"] }
+        })));
+        let root_ref = write("root.json", composition("root", json!({
+            "greet": { "uses": greet_ref, "inputs": ["{{inputs[0]}}"] },
+            "shout": { "uses": leaf_ref, "inputs": ["{{steps.greet.outputs[0]}}"] },
+            "again": { "uses": greet_ref, "inputs": ["{{steps.shout.outputs[0]}}"] }
+        })));
+
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        let plan = engine.dry_run(&root_ref, vec![json!("hi")]).await.unwrap();
+        let paths: Vec<&str> = plan.iter().map(|step| step.step_path.as_str()).collect();
+        // "again" sorts first, but runs after the steps it depends on
+        assert_eq!(paths, vec!["greet", "greet.shout", "shout", "again", "again.shout"]);
+
+        assert!(plan[0].ready && plan[1].ready);
+        assert_eq!(plan[1].inputs[0].value, json!("hi"));
+        assert!(plan[1].inputs[0].resolved);
+        assert!(!plan[2].ready);
+        assert_eq!(plan[2].blocked_by.as_deref(), Some("it waits for the outputs of greet"));
+        assert_eq!(plan[2].inputs[0].value, json!("{{steps.greet.outputs[0]}}"));
+        assert!(!plan[2].inputs[0].resolved);
+        assert_eq!(plan[4].blocked_by.as_deref(), Some("its parent step 'again' is blocked"));
+        assert!(engine.step_results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_runs_stop_before_their_next_step() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        let child_ref = write_manifest(&child_path, "child", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        }));
+        let root_path = temp_dir.path().join("root.json");
+        let root_ref = write_manifest(&root_path, "root", json!({
+            "inputs": [{"name": "value", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{steps.second.outputs[0]}}"}],
+            "steps": {
+                "first": { "uses": child_ref, "inputs": ["{{inputs[0]}}"] },
+                "second": { "uses": child_ref, "inputs": ["{{steps.first.outputs[0]}}"] }
+            }
+        }));
+
+        // The first step is held while it runs, until the run is cancelled
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let cancellation = CancellationToken::new();
+        let run_logs = RunLogs::default();
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.step_gate = Some(gate.clone());
+        engine.set_run_logs(run_logs.clone(), "cancelled-run");
+        engine.set_options(ExecutionOptions {
+            run_id: Some("cancelled-run".to_string()),
+            cancellation: cancellation.clone(),
+            ..Default::default()
+        });
+        let run = tokio::spawn(async move { engine.execute_action_detailed(&root_ref, vec![json!("hi")]).await });
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let started = run_logs.get("cancelled-run").unwrap_or_default().iter()
+                    .any(|line| line["type"] == "step_started" && line["step_path"] == "first");
+                if started {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        // The step in flight completes, the next one never starts
+        cancellation.cancel();
+        gate.add_permits(1);
+
+        let error = run.await.unwrap().unwrap_err();
+        let cancelled = error.downcast_ref::<ExecutionCancelled>().expect("expected an ExecutionCancelled");
+        assert_eq!(cancelled.step_path, "second");
+        assert_eq!(cancelled.steps.len(), 1);
+        assert_eq!(cancelled.steps[0].step_path, "first");
+        assert_eq!(cancelled.steps[0].status, StepStatus::Completed);
+        assert_eq!(cancelled.steps[0].outputs, vec![json!("hi")]);
+    }
+
+    #[test]
+    fn test_manifest_url_follows_path_template() {
+        let mut engine = ExecutionEngine::new();
+        engine.set_manifest_path_template(DEFAULT_MANIFEST_PATH_TEMPLATE).unwrap();
+        assert_eq!(
+            engine.manifest_url("starthubhq/http-get-wasm:0.0.1"),
+            "https://api.starthub.so/storage/v1/object/public/artifacts/starthubhq/http-get-wasm/0.0.1/starthub-lock.json"
+        );
+        assert_eq!(
+            engine.manifest_url("starthubhq/http-get-wasm"),
+            "https://api.starthub.so/storage/v1/object/public/artifacts/starthubhq/http-get-wasm/starthub-lock.json"
+        );
+
+        engine.set_manifest_path_template("/registry/{namespace}/{slug}/manifest.json?version={version}").unwrap();
+        assert_eq!(
+            engine.manifest_url("starthubhq/http-get-wasm:0.0.1"),
+            "https://api.starthub.so/registry/starthubhq/http-get-wasm/manifest.json?version=0.0.1"
+        );
+
+        assert!(engine.set_manifest_path_template("/registry/manifest.json").is_err());
+    }
+
+    #[test]
+    fn test_api_base_is_configurable() {
+        let mut engine = ExecutionEngine::new();
+        engine.set_api_base("http://localhost:54321/").unwrap();
+        assert_eq!(
+            engine.manifest_url("starthubhq/http-get-wasm:0.0.1"),
+            "http://localhost:54321/storage/v1/object/public/artifacts/starthubhq/http-get-wasm/0.0.1/starthub-lock.json"
+        );
+        assert_eq!(
+            http::registry_artifact_url(engine.api_base(), "starthubhq/http-get-wasm:0.0.1").unwrap(),
+            "http://localhost:54321/storage/v1/object/public/artifacts/starthubhq/http-get-wasm/0.0.1/artifact.zip"
+        );
+
+        assert!(engine.set_api_base("ftp://registry.example.com").is_err());
+        assert!(engine.set_api_base("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_aliases_expand_to_their_target() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&test_manifest("0.0.1")).unwrap()).unwrap();
+        let database = Arc::new(Mutex::new(Database::open(&temp_dir.path().join("server.db")).unwrap()));
+        let target = format!("file://{}", child_path.display());
+        database.lock().await.set_alias("child", &target).unwrap();
+
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_database(database);
+        let tree = engine.build_action_tree("child", None).await.unwrap();
+        assert_eq!(tree.uses, "child");
+        assert_eq!(tree.resolved_uses, target);
+        assert_eq!(engine.fetch_manifest("child").await.unwrap().version, "0.0.1");
+
+        // Bare slugs that aren't aliases are left for the registry to resolve
+        assert_eq!(engine.resolve_alias("chirpstack").await.unwrap(), "chirpstack");
+        assert_eq!(engine.resolve_alias("http-get:0.0.1").await.unwrap(), "http-get:0.0.1");
+
+        assert!(check_alias("weather", "starthubhq/get-weather-by-location-name:0.0.1").is_ok());
+        assert!(check_alias("weather", "http-get:0.0.1").is_err());
+        assert!(check_alias("starthubhq/weather", "starthubhq/get-weather-by-location-name:0.0.1").is_err());
+        assert!(check_alias("weather", "other-alias").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_namespaces_are_rejected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_path = temp_dir.path().join("child.json");
+        std::fs::write(&child_path, serde_json::to_string(&test_manifest("0.0.1")).unwrap()).unwrap();
+        let workspace: HashMap<String, std::path::PathBuf> = [
+            ("starthubhq/child".to_string(), child_path.clone()),
+            ("untrusted/child".to_string(), child_path),
+        ].into_iter().collect();
+
+        let mut engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+        engine.set_options(ExecutionOptions { workspace, ..Default::default() });
+        engine.set_allowed_namespaces(Some(vec!["starthubhq".to_string()]));
+
+        let tree = engine.build_action_tree("starthubhq/child:0.0.1", None).await.unwrap();
+        assert_eq!(tree.uses, "starthubhq/child:0.0.1");
+
+        // Rejected before anything is fetched, even though the manifest is available locally
+        let error = engine.build_action_tree("untrusted/child:0.0.1", None).await.unwrap_err().to_string();
+        assert!(error.contains("namespace 'untrusted' is not one of the allowed namespaces (starthubhq)"), "unexpected error: {}", error);
+
+        // Local manifests have no namespace to allow
+        let error = engine.build_action_tree(&format!("file://{}", temp_dir.path().join("child.json").display()), None).await.unwrap_err().to_string();
+        assert!(error.contains("local manifests aren't in any of the allowed namespaces"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_local_manifests_are_confined_to_the_allowed_roots() {
+        let allowed = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let manifest = serde_json::to_string(&test_manifest("0.0.1")).unwrap();
+        std::fs::write(allowed.path().join("child.json"), &manifest).unwrap();
+        std::fs::write(outside.path().join("child.json"), &manifest).unwrap();
+        let roots = vec![allowed.path().to_path_buf()];
+
+        let inside = allowed.path().join("child.json");
+        assert_eq!(confine_path(&inside, Some(&roots)).unwrap(), inside.canonicalize().unwrap());
+        // Not created yet, like a trace directory
+        assert!(confine_path(&allowed.path().join("traces/run"), Some(&roots)).is_ok());
+        assert!(confine_path(&allowed.path().join("../child.json"), Some(&roots)).is_err());
+        assert!(confine_path(std::path::Path::new("child.json"), Some(&roots)).is_err());
+        assert!(confine_path(&outside.path().join("child.json"), None).is_ok());
+
+        let workspace: HashMap<String, std::path::PathBuf> = [
+            ("starthubhq/child".to_string(), outside.path().join("child.json")),
+        ].into_iter().collect();
+        let mut engine = ExecutionEngine::with_cache_dir(allowed.path().join("cache"));
+        engine.set_options(ExecutionOptions { workspace, ..Default::default() });
+        engine.set_allowed_roots(Some(roots));
+        engine.fetch_manifest(&format!("file://{}", inside.display())).await.unwrap();
+
+        let error = engine.fetch_manifest(&format!("file://{}", outside.path().join("child.json").display())).await.unwrap_err().to_string();
+        assert!(error.contains("is outside of the directories the server may access"), "unexpected error: {}", error);
+        let error = engine.fetch_manifest("starthubhq/child:0.0.1").await.unwrap_err().to_string();
+        assert!(error.contains("is outside of the directories the server may access"), "unexpected error: {}", error);
+    }
+
+    #[test]
+    fn test_invalid_proxy_is_rejected() {
+        let mut engine = ExecutionEngine::new();
+        let error = engine.set_proxy("not a url").unwrap_err();
+        assert!(error.to_string().contains("Invalid proxy"));
+    }
+
+    #[test]
+    fn test_default_user_agent() {
+        assert!(http::default_user_agent().starts_with("starthub-cli/"));
+    }
+
+    #[tokio::test]
+    async fn test_blocked_optional_steps_are_skipped() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![
+                test_io("value", "string", json!("{{steps.independent.outputs[0]}}")),
+                test_io("enriched", "object", json!("{{steps.downstream.outputs[0]}}")),
+            ],
+        );
+        // `upstream` waits on a step that doesn't exist, and `downstream` waits on `upstream`
+        let mut upstream = identity_step("upstream", "{{steps.missing.outputs[0]}}");
+        upstream.optional = true;
+        let mut downstream = identity_step("downstream", "{{steps.upstream.outputs[0]}}");
+        downstream.optional = true;
+        root.steps.insert("upstream".to_string(), upstream);
+        root.steps.insert("downstream".to_string(), downstream);
+        root.steps.insert("independent".to_string(), identity_step("independent", "{{inputs[0]}}"));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = tokio::time::timeout(std::time::Duration::from_secs(5), engine.run_action_tree(&root, ""))
+            .await
+            .expect("the run should not hang")
+            .unwrap();
+
+        assert_eq!(executed.outputs[0].value, Some(json!("hello")));
+        assert_eq!(executed.outputs[1].value, Some(Value::Null));
+        assert_eq!(executed.steps["upstream"].outputs[0].value, Some(Value::Null));
+        assert_eq!(executed.steps["downstream"].outputs[0].value, Some(Value::Null));
+    }
+
+    #[tokio::test]
+    async fn test_only_optional_steps_waiting_on_failed_or_skipped_steps_are_skipped() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![test_io("value", "string", json!("{{steps.independent.outputs[0]}}"))],
+        );
+        // `enrich` waits on the broken step, and `report` on `enrich`
+        root.steps.insert("broken".to_string(), failing_step("broken", "wasm", "{{inputs[0]}}"));
+        let mut enrich = identity_step("enrich", "{{steps.broken.outputs[0]}}");
+        enrich.optional = true;
+        let mut report = identity_step("report", "{{steps.enrich.outputs[0].summary}}");
+        report.optional = true;
+        // `notify` is skipped by its condition, and `audit` needs a field of its null output
+        let mut notify = identity_step("notify", "{{inputs[0]}}");
+        notify.when = Some("{{inputs[0]}} == 'goodbye'".to_string());
+        let mut audit = identity_step("audit", "{{steps.notify.outputs[0].id}}");
+        audit.optional = true;
+        // `publish` waits on a required step that never runs, but that didn't fail nor was skipped
+        let gate = identity_step("gate", "{{steps.broken.outputs[0].approved}}");
+        let mut publish = identity_step("publish", "{{steps.gate.outputs[0]}}");
+        publish.optional = true;
+        for step in [enrich, report, notify, audit, gate, publish] {
+            root.steps.insert(step.name.clone(), step);
+        }
+        root.steps.insert("independent".to_string(), identity_step("independent", "{{inputs[0]}}"));
+
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions { on_failure: FailurePolicy::KeepGoing, ..Default::default() });
+        let error = tokio::time::timeout(std::time::Duration::from_secs(5), engine.run_action_tree(&root, ""))
+            .await
+            .expect("the run should not hang")
+            .unwrap_err();
+        assert!(error.downcast_ref::<StepFailures>().is_some(), "unexpected error: {}", error);
+
+        let status = |path: &str| engine.step_results.iter()
+            .find(|result| result.step_path == path)
+            .map(|result| result.status);
+        assert_eq!(status("broken"), Some(StepStatus::Failed));
+        assert_eq!(status("enrich"), Some(StepStatus::Skipped));
+        assert_eq!(status("report"), Some(StepStatus::Skipped));
+        assert_eq!(status("notify"), Some(StepStatus::Skipped));
+        assert_eq!(status("audit"), Some(StepStatus::Skipped));
+        assert_eq!(status("gate"), None);
+        assert_eq!(status("publish"), None);
+    }
+
+    #[tokio::test]
+    async fn test_steps_whose_condition_does_not_hold_are_skipped() {
+        assert_eq!(ExecutionEngine::parse_when("prod == 'prod'"), ("prod ", Some((true, " 'prod'"))));
+        assert_eq!(ExecutionEngine::parse_when("3 != 4"), ("3 ", Some((false, " 4"))));
+        assert_eq!(ExecutionEngine::parse_when("{{steps.a.outputs[0].b}}"), ("{{steps.a.outputs[0].b}}", None));
+        assert_eq!(ExecutionEngine::parse_when("{{inputs[0].x!=1}} == 2"), ("{{inputs[0].x!=1}} ", Some((true, " 2"))));
+        assert!(ExecutionEngine::evaluate_when(&json!("prod"), Some((true, &json!("prod")))));
+        assert!(ExecutionEngine::evaluate_when(&json!(3), Some((false, &json!(4)))));
+        assert!(ExecutionEngine::evaluate_when(&json!(true), None));
+        assert!(!ExecutionEngine::evaluate_when(&json!(false), None));
+        assert!(!ExecutionEngine::evaluate_when(&json!(""), None));
+        assert!(!ExecutionEngine::evaluate_when(&json!(0), None));
+        assert!(!ExecutionEngine::evaluate_when(&Value::Null, None));
+
+        // `step3` waits on the condition of `step2`, which only holds for another input
+        let mut step2 = identity_step("step2", "{{steps.step1.outputs[0]}}");
+        step2.when = Some("{{steps.step1.outputs[0]}} == 'goodbye'".to_string());
+        let mut step3 = identity_step("step3", "{{inputs[0]}}");
+        step3.when = Some("{{steps.step2.outputs[0]}}".to_string());
+        let root = chained_composition(identity_step("step1", "{{inputs[0]}}"), step2, step3);
+
+        let mut engine = ExecutionEngine::new();
+        let executed = tokio::time::timeout(std::time::Duration::from_secs(5), engine.run_action_tree(&root, ""))
+            .await
+            .expect("the run should not hang")
+            .unwrap();
+        assert_eq!(executed.steps["step1"].outputs[0].value, Some(json!("hello")));
+        assert_eq!(executed.steps["step2"].outputs[0].value, Some(Value::Null));
+        assert_eq!(executed.steps["step3"].outputs[0].value, Some(Value::Null));
+
+        let mut root = root;
+        root.steps.get_mut("step2").unwrap().when = Some("{{steps.step1.outputs[0]}} == 'hello'".to_string());
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.steps["step2"].outputs[0].value, Some(json!("hello")));
+        assert_eq!(executed.steps["step3"].outputs[0].value, Some(json!("hello")));
+
+        // An operator within a resolved value is compared as text, not evaluated
+        root.inputs[0].value = Some(json!("x' != 'hello"));
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.steps["step2"].outputs[0].value, Some(Value::Null));
+    }
+
+    #[tokio::test]
+    async fn test_foreach_steps_run_once_per_element() {
+        let mut names = test_io("names", "array", Value::Null);
+        names.value = Some(json!(["ada", "grace", "linus"]));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![names],
+            vec![
+                test_io("greetings", "array", json!("{{steps.greet.outputs[0]}}")),
+                test_io("names", "array", json!("{{steps.echo.outputs[0]}}")),
+            ],
+        );
+        let mut greet = identity_step("greet", "hello {{item}}");
+        greet.foreach = Some("{{inputs[0]}}".to_string());
+        // Waits for `greet`, and runs once per greeting
+        let mut echo = identity_step("echo", "{{item}}");
+        echo.foreach = Some("{{steps.greet.outputs[0]}}".to_string());
+        root.steps.insert("greet".to_string(), greet);
+        root.steps.insert("echo".to_string(), echo);
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!(["hello ada", "hello grace", "hello linus"])));
+        assert_eq!(executed.outputs[1].value, Some(json!(["hello ada", "hello grace", "hello linus"])));
+        let instance_paths: Vec<&str> = engine.step_results.iter()
+            .map(|result| result.step_path.as_str())
+            .filter(|path| path.starts_with("greet["))
+            .collect();
+        assert_eq!(instance_paths, vec!["greet[0]", "greet[1]", "greet[2]"]);
+
+        assert_eq!(executed.steps["greet"].outputs[0].r#type, "array");
+
+        root.inputs[0].value = Some(json!([]));
+        let executed = ExecutionEngine::new().run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!([])));
+
+        // Elements are data: a template in one of them is never interpolated
+        root.inputs[0].value = Some(json!(["{{inputs[0]}}", "\\{\\{inputs\\}\\}"]));
+        let executed = ExecutionEngine::new().run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[1].value, Some(json!(["hello {{inputs[0]}}", "hello \\{\\{inputs\\}\\}"])));
+
+        root.inputs[0].value = Some(json!("ada"));
+        let error = ExecutionEngine::new().run_action_tree(&root, "").await.unwrap_err().to_string();
+        assert!(error.contains("`foreach` of step 'greet' must resolve to an array, got \"ada\""), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_mocked_step_outputs_feed_downstream_steps() {
+        let mut location = test_io("location", "string", Value::Null);
+        location.value = Some(json!("Rome"));
+        let mut root = test_action(
+            "weather",
+            "composition",
+            "test/weather:0.0.1",
+            vec![location],
+            vec![test_io("coordinates", "object", json!("{{steps.get_weather.outputs[0]}}"))],
+        );
+
+        // The geocoding artifact doesn't exist: the run only succeeds if the step is mocked
+        let mut get_coordinates = test_action(
+            "get_coordinates",
+            "wasm",
+            "test/missing-wasm-action:0.0.1",
+            vec![test_io("location", "string", json!("{{inputs[0]}}"))],
+            vec![test_io("coordinates", "Coordinates", Value::Null)],
+        );
+        get_coordinates.types = Some(json!({
+            "Coordinates": {
+                "lat": { "type": "number", "required": true },
+                "lon": { "type": "number", "required": true }
+            }
+        }).as_object().unwrap().clone());
+        let get_weather = test_action(
+            "get_weather",
+            "composition",
+            "test/get-weather:0.0.1",
+            vec![test_io("coordinates", "object", json!("{{steps.get_coordinates.outputs[0]}}"))],
+            vec![test_io("coordinates", "object", json!("{{inputs[0]}}"))],
+        );
+        root.steps.insert("get_coordinates".to_string(), get_coordinates);
+        root.steps.insert("get_weather".to_string(), get_weather);
+
+        let coordinates = json!({ "lat": 41.9, "lon": 12.5 });
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions {
+            mocks: [("get_coordinates".to_string(), vec![coordinates.clone()])].into_iter().collect(),
+            ..Default::default()
+        });
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.steps["get_weather"].inputs[0].value, Some(coordinates.clone()));
+        assert_eq!(executed.outputs[0].value, Some(coordinates));
+
+        // Mocked outputs are validated against the declared types
+        engine.set_options(ExecutionOptions {
+            mocks: [("get_coordinates".to_string(), vec![json!({ "lat": "north" })])].into_iter().collect(),
+            ..Default::default()
+        });
+        let error = engine.run_action_tree(&root, "").await.unwrap_err().to_string();
+        assert!(error.contains("Mocked outputs of step 'get_coordinates'") && error.contains("Coordinates"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_step_outputs_are_posted_to_the_webhook() {
+        // Webhook forwarding the body of every request it receives
+        let (posted_tx, mut posted_rx) = tokio::sync::mpsc::unbounded_channel::<Value>();
+        let webhook = mock_registry(move |request| {
+            let body = request.split_once("\r\n\r\n").map(|(_, body)| body).unwrap_or("");
+            let _ = posted_tx.send(serde_json::from_str(body).unwrap_or(Value::Null));
+            ("204 No Content", String::new())
+        }).await;
+
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions {
+            run_id: Some("webhook-run".to_string()),
+            step_output_webhook: Some(format!("{}/outputs", webhook)),
+            ..Default::default()
+        });
+        engine.run_action_tree(&root, "").await.unwrap();
+
+        let mut posted = Vec::new();
+        for _ in 0..3 {
+            let body = tokio::time::timeout(std::time::Duration::from_secs(5), posted_rx.recv())
+                .await
+                .expect("expected one POST per executed step")
+                .unwrap();
+            posted.push(body);
+        }
+        posted.sort_by_key(|body| body["step"].as_str().unwrap_or("").to_string());
+        for (body, step) in posted.iter().zip(["step1", "step2", "step3"]) {
+            assert_eq!(body, &json!({ "run_id": "webhook-run", "step": step, "output": ["hello"] }));
+        }
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(200), posted_rx.recv()).await.is_err());
+
+        // An unreachable webhook doesn't fail the run
+        engine.set_options(ExecutionOptions {
+            step_output_webhook: Some("http://127.0.0.1:9/outputs".to_string()),
+            ..Default::default()
+        });
+        engine.run_action_tree(&root, "").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_resolved_from_the_environment_and_masked() {
+        let name = format!("TEST_TOKEN_{}", uuid::Uuid::new_v4().simple());
+        let secret = "s3cr3t-value";
+        std::env::set_var(format!("{}{}", secrets::SECRET_ENV_PREFIX, name), secret);
+
+        let root = chained_composition(
+            identity_step("step1", &format!("{{{{inputs[0]}}}} {{{{secret.{}}}}}", name)),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let run_logs = RunLogs::default();
+        let mut engine = ExecutionEngine::new();
+        engine.set_secrets_provider(Box::new(secrets::EnvSecretsProvider));
+        engine.set_run_logs(run_logs.clone(), "secret-run");
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        // The steps get the real value...
+        assert_eq!(executed.outputs[0].value, Some(json!(format!("hello {}", secret))));
+
+        // ...but the logs never show it
+        let lines = run_logs.get("secret-run").unwrap();
+        let messages: Vec<String> = lines.iter().filter_map(|line| line["message"].as_str().map(String::from)).collect();
+        assert!(messages.iter().all(|message| !message.contains(secret)), "secret leaked in: {:?}", messages);
+        assert!(messages.iter().any(|message| message.contains("hello ***")), "secret not masked in: {:?}", messages);
+
+        // An unknown secret is a clear error
+        let missing = chained_composition(
+            identity_step("step1", "{{secret.STARTHUB_TEST_MISSING_SECRET}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let error = engine.run_action_tree(&missing, "").await.unwrap_err().to_string();
+        assert!(error.contains("Secret 'STARTHUB_TEST_MISSING_SECRET' is not set"), "unexpected error: {}", error);
+
+        // A secret template arriving as data is passed on as it is, never resolved
+        let mut smuggled = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let template = format!("{{{{secret.{}}}}}", name);
+        smuggled.inputs[0].value = Some(json!(template));
+        let executed = engine.run_action_tree(&smuggled, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!(template)));
+    }
+
+    #[tokio::test]
+    async fn test_secret_inputs_never_appear_in_the_logs() {
+        let secret = "p4ssw0rd-for-the-db";
+        let run_logs = RunLogs::default();
+        let mut engine = ExecutionEngine::new();
+        engine.set_run_logs(run_logs.clone(), "secret-input-run");
+
+        let mut root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let declared = vec![test_io("password", "secret", Value::Null)];
+        root.inputs = engine.cast_input_values_to_typed_array("test/root:0.0.1", &declared, &vec![json!(secret)], &None).unwrap();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        engine.logger.log_error(&format!("Failed to connect with {}", secret), None);
+
+        // The steps get the real value...
+        assert_eq!(executed.outputs[0].value, Some(json!(secret)));
+        // ...but no captured message shows it
+        let lines = run_logs.get("secret-input-run").unwrap();
+        assert!(lines.iter().all(|line| !line.to_string().contains(secret)), "secret leaked in: {:?}", lines);
+        assert!(lines.iter().any(|line| line["message"] == "Failed to connect with ***"));
+        assert_eq!(engine.redact(&json!([secret])), json!(["***"]));
+    }
+
+    #[tokio::test]
+    async fn test_secret_inputs_are_redacted_before_the_run_starts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manifest_path = temp_dir.path().join("deploy.json");
+        write_manifest(&manifest_path, "deploy", json!({
+            "inputs": [{"name": "host", "type": "string"}, {"name": "password", "type": "secret"}],
+            "outputs": []
+        }));
+        let engine = ExecutionEngine::with_cache_dir(temp_dir.path().join("cache"));
+
+        let inputs = [json!("db.internal"), json!("p4ssw0rd")];
+        let redacted = engine.redact_secret_inputs(&format!("file://{}", manifest_path.display()), &inputs).await;
+        assert_eq!(redacted, vec![json!("db.internal"), json!("***")]);
+
+        // Without the manifest, any input could be a secret
+        let missing = format!("file://{}", temp_dir.path().join("missing.json").display());
+        assert_eq!(engine.redact_secret_inputs(&missing, &inputs).await, vec![json!("***"), json!("***")]);
+    }
+
+    #[tokio::test]
+    async fn test_outputs_resolved_from_inputs_skip_the_steps() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        // The step would fail if it ran: its artifact doesn't exist
+        let mut root = test_action(
+            "pass-through",
+            "composition",
+            "test/pass-through:0.0.1",
+            vec![input],
+            vec![test_io("value", "string", json!("{{inputs[0]}}"))],
+        );
+        root.steps.insert("broken".to_string(), failing_step("broken", "wasm", "{{inputs[0]}}"));
+
+        let mut engine = ExecutionEngine::new();
+        let executed = engine.run_action_tree(&root, "").await.unwrap();
+        assert_eq!(executed.outputs[0].value, Some(json!("hello")));
+        assert_eq!(executed.steps["broken"].outputs[0].value, None);
+
+        // An output depending on the step still needs it to run
+        root.outputs.push(test_io("broken", "string", json!("{{steps.broken.outputs[0]}}")));
+        assert!(engine.run_action_tree(&root, "").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failure_policies_on_a_failing_branch() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![test_io("value", "string", json!("{{steps.step2.outputs[0]}}"))],
+        );
+        root.steps.insert("broken".to_string(), failing_step("broken", "wasm", "{{inputs[0]}}"));
+        root.steps.insert("step1".to_string(), identity_step("step1", "{{inputs[0]}}"));
+        root.steps.insert("step2".to_string(), identity_step("step2", "{{steps.step1.outputs[0]}}"));
+        root.steps = ExecutionEngine::new().produce_steps_with_priorities(&root.steps, &HashMap::new());
+
+        // Fail fast is the default: the failure of the first step is returned as is
+        let mut engine = ExecutionEngine::new();
+        let error = engine.run_action_tree(&root, "").await.unwrap_err();
+        assert!(error.downcast_ref::<StepFailures>().is_none(), "unexpected error: {}", error);
+
+        // Keeping going, the independent branch completes and only the broken step is reported
+        engine.set_options(ExecutionOptions { on_failure: FailurePolicy::KeepGoing, ..Default::default() });
+        let error = engine.run_action_tree(&root, "").await.unwrap_err();
+        let summary = error.downcast_ref::<StepFailures>().expect("expected a StepFailures error");
+        let failed: Vec<&str> = summary.failures.iter().map(|f| f.step_path.as_str()).collect();
+        assert_eq!(failed, vec!["broken"]);
+
+        assert_eq!("keep-going".parse::<FailurePolicy>().unwrap(), FailurePolicy::KeepGoing);
+        assert_eq!("fail-fast".parse::<FailurePolicy>().unwrap(), FailurePolicy::FailFast);
+        assert!("sometimes".parse::<FailurePolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keep_going_reports_every_failed_step() {
+        let mut input = test_io("value", "string", Value::Null);
+        input.value = Some(json!("hello"));
+        let mut root = test_action(
+            "root",
+            "composition",
+            "test/root:0.0.1",
+            vec![input],
+            vec![test_io("value", "string", json!("{{steps.step1.outputs[0]}}"))],
+        );
+        root.steps.insert("branch_a".to_string(), failing_step("branch_a", "wasm", "{{inputs[0]}}"));
+        root.steps.insert("branch_b".to_string(), failing_step("branch_b", "docker", "{{inputs[0]}}"));
+        root.steps.insert("step1".to_string(), identity_step("step1", "{{inputs[0]}}"));
+
+        let mut engine = ExecutionEngine::new();
+        engine.set_options(ExecutionOptions {
+            on_failure: FailurePolicy::KeepGoing,
+            ..Default::default()
+        });
+        let error = engine.run_action_tree(&root, "").await.unwrap_err();
+
+        let summary = error.downcast_ref::<StepFailures>().expect("expected a StepFailures error");
+        let failed: Vec<&str> = summary.failures.iter().map(|f| f.step_path.as_str()).collect();
+        assert_eq!(failed, vec!["branch_a", "branch_b"]);
+        let message = error.to_string();
+        assert!(message.starts_with("2 step(s) failed"), "unexpected summary: {}", message);
+        assert!(message.contains("branch_a") && message.contains("branch_b"));
+    }
+
+    #[tokio::test]
+    async fn test_with_cache_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("nested").join("cache");
+
+        let engine = ExecutionEngine::with_cache_dir(cache_dir.clone());
+        assert_eq!(engine.cache_dir(), cache_dir.as_path());
+        assert!(cache_dir.is_dir());
+
+        // Artifacts are cached under the engine's cache directory
+        let _ = wasm::download_wasm(&engine.http_client, engine.api_base(), "test/missing-action:0.0.1", &[], &engine.cache_dir).await;
+        assert!(cache_dir.join("test/missing-action/0.0.1").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_step_events_carry_step_name() {
+        let root = chained_composition(
+            identity_step("step1", "{{inputs[0]}}"),
+            identity_step("step2", "{{steps.step1.outputs[0]}}"),
+            identity_step("step3", "{{steps.step2.outputs[0]}}"),
+        );
+        let step_ids: HashMap<String, String> = root.steps.iter()
+            .map(|(name, step)| (step.id.clone(), name.clone()))
+            .collect();
+
+        let mut engine = ExecutionEngine::new();
+        let mut ws_receiver = engine.get_ws_sender().unwrap().subscribe();
+        engine.run_action_tree(&root, "").await.unwrap();
+
+        let mut named_steps = Vec::new();
+        while let Ok(msg) = ws_receiver.try_recv() {
+            let log: Value = serde_json::from_str(&msg).unwrap();
+            if let Some(step_name) = log["action_id"].as_str().and_then(|id| step_ids.get(id)) {
+                assert_eq!(log["step_name"], json!(step_name));
+                assert_eq!(log["step_path"], json!(step_name));
+                named_steps.push(step_name.clone());
+            }
+        }
+        named_steps.dedup();
+        assert_eq!(named_steps, vec!["step1", "step2", "step3"]);
+    }
+}
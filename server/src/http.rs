@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Environment variable overriding the User-Agent of outbound requests
+pub const USER_AGENT_ENV: &str = "STARTHUB_USER_AGENT";
+/// Environment variable holding a proxy for all outbound requests, taking precedence over
+/// HTTP_PROXY and HTTPS_PROXY
+pub const PROXY_ENV: &str = "STARTHUB_PROXY";
+/// Environment variable selecting the order in which artifact mirrors are tried
+pub const MIRROR_STRATEGY_ENV: &str = "STARTHUB_MIRROR_STRATEGY";
+/// Environment variable holding a registry token, taking precedence over the one saved by `starthub login`
+pub const TOKEN_ENV: &str = "STARTHUB_TOKEN";
+
+// How long a mirror gets to answer the latency probe
+const MIRROR_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Latency measured for every probed mirror, for the lifetime of the process.
+// Unreachable mirrors are recorded as `None`.
+static MIRROR_LATENCIES: OnceLock<Mutex<HashMap<String, Option<Duration>>>> = OnceLock::new();
+
+/// Order in which the mirrors of an artifact are tried
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MirrorStrategy {
+    /// In the order the manifest lists them
+    #[default]
+    Order,
+    /// Fastest first, as measured by a HEAD request to each mirror
+    Latency,
+}
+
+impl std::str::FromStr for MirrorStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value.trim() {
+            "order" => Ok(Self::Order),
+            "latency" => Ok(Self::Latency),
+            other => Err(anyhow::anyhow!("Unknown mirror strategy '{}', expected 'order' or 'latency'", other)),
+        }
+    }
+}
+
+/// Default User-Agent of outbound requests
+pub fn default_user_agent() -> String {
+    format!("starthub-cli/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// User-Agent of outbound requests, taken from the environment when set
+pub fn user_agent() -> String {
+    std::env::var(USER_AGENT_ENV)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(default_user_agent)
+}
+
+/// Explicit proxy for outbound requests, taken from the environment when set
+pub fn proxy() -> Option<String> {
+    std::env::var(PROXY_ENV)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Mirror strategy taken from the environment, in-order when unset or invalid
+pub fn mirror_strategy() -> MirrorStrategy {
+    std::env::var(MIRROR_STRATEGY_ENV)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Orders mirrors according to the strategy. With the latency strategy every mirror not seen
+/// before is probed once, and mirrors are sorted fastest first with unreachable ones last.
+pub async fn order_mirrors(client: &reqwest::Client, strategy: MirrorStrategy, mirrors: &[String]) -> Vec<String> {
+    if strategy == MirrorStrategy::Order || mirrors.len() < 2 {
+        return mirrors.to_vec();
+    }
+
+    let latencies = MIRROR_LATENCIES.get_or_init(|| Mutex::new(HashMap::new()));
+    let unprobed: Vec<&String> = {
+        let known = latencies.lock().unwrap_or_else(|e| e.into_inner());
+        mirrors.iter().filter(|mirror| !known.contains_key(*mirror)).collect()
+    };
+    let probes = futures_util::future::join_all(unprobed.into_iter().map(|mirror| async move {
+        let started = Instant::now();
+        let response = client.head(mirror).timeout(MIRROR_PROBE_TIMEOUT).send().await;
+        (mirror.clone(), response.ok().map(|_| started.elapsed()))
+    })).await;
+
+    let mut known = latencies.lock().unwrap_or_else(|e| e.into_inner());
+    known.extend(probes);
+    let mut ordered = mirrors.to_vec();
+    // The sort is stable, so mirrors with the same latency keep their listed order
+    ordered.sort_by_key(|mirror| known.get(mirror).copied().flatten().unwrap_or(Duration::MAX));
+    ordered
+}
+
+/// Registry URL of the artifact of a wasm or docker action, from its `namespace/slug:version` reference
+pub fn registry_artifact_url(api_base: &str, action_ref: &str) -> anyhow::Result<String> {
+    let parts: Vec<&str> = action_ref.split(':').collect();
+    if parts.len() != 2 {
+        return Err(anyhow::anyhow!("Invalid action reference format: {}", action_ref));
+    }
+    
+    let (namespace_slug, version) = (parts[0], parts[1]);
+    let namespace_parts: Vec<&str> = namespace_slug.split('/').collect();
+    if namespace_parts.len() != 2 {
+        return Err(anyhow::anyhow!("Invalid namespace format: {}", namespace_slug));
+    }
+    
+    let (namespace, slug) = (namespace_parts[0], namespace_parts[1]);
+    Ok(format!("{}/storage/v1/object/public/artifacts/{}/{}/{}/artifact.zip", api_base, namespace, slug, version))
+}
+
+/// Token saved by `starthub login`, if any
+pub fn saved_token() -> Option<String> {
+    let token_file = dirs::config_dir()?.join("starthub").join("token");
+    std::fs::read_to_string(token_file)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Token registry requests are authenticated with, and where it comes from: STARTHUB_TOKEN, e.g.
+/// in CI, or else the `auth_token` of the server config file, or else `saved`, the one saved by
+/// `starthub login`. `env` looks up environment variables.
+pub fn registry_token(
+    env: impl Fn(&str) -> Option<String>,
+    configured: Option<String>,
+    saved: Option<String>,
+) -> Option<(String, &'static str)> {
+    let non_empty = |token: Option<String>| token.map(|token| token.trim().to_string()).filter(|token| !token.is_empty());
+    non_empty(env(TOKEN_ENV)).map(|token| (token, TOKEN_ENV))
+        .or_else(|| non_empty(configured).map(|token| (token, "the server config file")))
+        .or_else(|| non_empty(saved).map(|token| (token, "starthub login")))
+}
+
+/// Parses a `Name: value` header
+pub fn parse_header(value: &str) -> anyhow::Result<(String, String)> {
+    let (name, header_value) = value.split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Invalid header '{}', expected 'Name: value'", value))?;
+    let name = name.trim();
+    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Invalid header name '{}'", name))?;
+    Ok((name.to_string(), header_value.trim().to_string()))
+}
+
+/// Builds the HTTP client used for outbound requests (manifests and artifacts).
+/// Without an explicit proxy, HTTP_PROXY, HTTPS_PROXY and NO_PROXY are honored.
+pub fn build_client(user_agent: &str, proxy: Option<&str>) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().user_agent(user_agent);
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy '{}': {}", proxy, e))?;
+        builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env()));
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,322 @@
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+/// Number of runs executed at the same time by default
+pub const DEFAULT_WORKERS: usize = 4;
+/// How long a cancelled job gets to stop on its own and report what it completed, before it is dropped
+pub const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// Number of settled runs whose state is kept in memory
+pub const SETTLED_RUNS_KEPT: usize = 1000;
+/// How long the state of a settled run is kept in memory
+pub const SETTLED_RUN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Runs submitted to the server, executed in the background by at most `workers` tasks at a time.
+/// The state of every run is kept in memory, keyed by run id, until it has settled for longer
+/// than the TTL or too many runs settled after it. The database still has evicted runs.
+#[derive(Clone)]
+pub struct RunQueue {
+    workers: Arc<Semaphore>,
+    runs: Arc<Mutex<HashMap<String, Value>>>,
+    // Cancellation signal of every queued or running run
+    cancels: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    // Settled runs, oldest first, with when they settled
+    settled: Arc<Mutex<VecDeque<(String, Instant)>>>,
+    max_settled: usize,
+    settled_ttl: Duration,
+}
+
+impl RunQueue {
+    pub fn new(workers: usize) -> Self {
+        Self::with_retention(workers, SETTLED_RUNS_KEPT, SETTLED_RUN_TTL)
+    }
+
+    /// A queue keeping at most `max_settled` settled runs in memory, for at most `settled_ttl`
+    pub fn with_retention(workers: usize, max_settled: usize, settled_ttl: Duration) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            runs: Arc::new(Mutex::new(HashMap::new())),
+            cancels: Arc::new(Mutex::new(HashMap::new())),
+            settled: Arc::new(Mutex::new(VecDeque::new())),
+            max_settled,
+            settled_ttl,
+        }
+    }
+
+    /// Records the final state of a run, and evicts the settled runs past the TTL or the cap
+    async fn settle(&self, run_id: String, result: Value) {
+        let mut settled = self.settled.lock().await;
+        let mut runs = self.runs.lock().await;
+        runs.insert(run_id.clone(), result);
+        settled.push_back((run_id, Instant::now()));
+        while let Some((run_id, settled_at)) = settled.front() {
+            if settled.len() <= self.max_settled && settled_at.elapsed() < self.settled_ttl {
+                break;
+            }
+            // A run id submitted again is live, and settles again later
+            if runs.get(run_id).is_some_and(|run| run["status"] != "queued" && run["status"] != "running") {
+                runs.remove(run_id);
+            }
+            settled.pop_front();
+        }
+    }
+
+    /// Queues a run. Its state is `queued` until a worker picks it up, `running` while
+    /// the job executes, and then the value returned by the job, or `cancelled`.
+    pub async fn submit<F>(&self, run_id: &str, action: &str, job: F)
+    where
+        F: Future<Output = Value> + Send + 'static,
+    {
+        self.submit_cancellable(run_id, action, CancellationToken::new(), job).await
+    }
+
+    /// Like `submit`, for a job that watches `cancel` itself: once cancelled, a running job gets
+    /// `CANCEL_GRACE_PERIOD` to settle, and its own value becomes the state of the run
+    pub async fn submit_cancellable<F>(&self, run_id: &str, action: &str, cancel: CancellationToken, job: F)
+    where
+        F: Future<Output = Value> + Send + 'static,
+    {
+        let run_state = |status: &str| json!({ "status": status, "run_id": run_id, "action": action });
+        let mut running = run_state("running");
+        let panicked = json!({
+            "status": "error",
+            "message": "Execution failed",
+            "run_id": run_id,
+            "action": action,
+        });
+        let cancelled = run_state("cancelled");
+        self.runs.lock().await.insert(run_id.to_string(), run_state("queued"));
+        self.cancels.lock().await.insert(run_id.to_string(), cancel.clone());
+
+        let queue = self.clone();
+        let run_id = run_id.to_string();
+        tokio::spawn(async move {
+            // The semaphore is never closed, so acquiring only waits for a free worker
+            let permit = tokio::select! {
+                permit = queue.workers.clone().acquire_owned() => permit,
+                _ = cancel.cancelled() => {
+                    queue.settle(run_id, cancelled).await;
+                    return;
+                }
+            };
+            let Ok(_permit) = permit else {
+                return;
+            };
+            running["started_at"] = json!(chrono::Utc::now().to_rfc3339());
+            queue.runs.lock().await.insert(run_id.clone(), running);
+
+            // Run the job in its own task so that a panic still settles the run
+            let mut job = tokio::spawn(job);
+            let joined = tokio::select! {
+                joined = &mut job => Some(joined),
+                _ = cancel.cancelled() => match tokio::time::timeout(CANCEL_GRACE_PERIOD, &mut job).await {
+                    Ok(joined) => Some(joined),
+                    Err(_) => {
+                        job.abort();
+                        None
+                    }
+                },
+            };
+            let result = match joined {
+                Some(Ok(result)) => result,
+                Some(Err(e)) => {
+                    let mut result = panicked;
+                    result["error"] = json!(e.to_string());
+                    result
+                }
+                None => cancelled,
+            };
+            queue.cancels.lock().await.remove(&run_id);
+            queue.settle(run_id, result).await;
+        });
+    }
+
+    /// Cancels a queued or running run. False when there is no such run, or it already settled.
+    pub async fn cancel(&self, run_id: &str) -> bool {
+        match self.cancels.lock().await.remove(run_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels every queued or running run and returns their ids, sorted.
+    /// A job that doesn't settle within the grace period is dropped at its next await point.
+    pub async fn cancel_all(&self) -> Vec<String> {
+        let mut cancelled: Vec<String> = Vec::new();
+        for (run_id, cancel) in self.cancels.lock().await.drain() {
+            cancel.cancel();
+            cancelled.push(run_id);
+        }
+        cancelled.sort();
+        cancelled
+    }
+
+    /// State of every queued or running run, the running ones first, then by run id
+    pub async fn active(&self) -> Vec<Value> {
+        let run_ids: Vec<String> = self.cancels.lock().await.keys().cloned().collect();
+        let runs = self.runs.lock().await;
+        let mut active: Vec<Value> = run_ids.iter()
+            .filter_map(|run_id| runs.get(run_id).cloned())
+            .filter(|run| run["status"] == "queued" || run["status"] == "running")
+            .collect();
+        active.sort_by(|a, b| {
+            (a["status"] != "running", a["run_id"].as_str()).cmp(&(b["status"] != "running", b["run_id"].as_str()))
+        });
+        active
+    }
+
+    /// Current state of a run submitted since the server started, unless it was evicted
+    pub async fn get(&self, run_id: &str) -> Option<Value> {
+        self.runs.lock().await.get(run_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn wait_for_status(queue: &RunQueue, run_id: &str, status: &str) -> Value {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(run) = queue.get(run_id).await {
+                    if run["status"] == status {
+                        return run;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("run {} never reached status {}", run_id, status))
+    }
+
+    #[tokio::test]
+    async fn test_runs_execute_concurrently() {
+        let queue = RunQueue::new(2);
+        // Neither job can finish unless both are running at the same time
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        for run_id in ["run-a", "run-b"] {
+            let barrier = barrier.clone();
+            queue.submit(run_id, "test/action:0.0.1", async move {
+                barrier.wait().await;
+                json!({ "status": "success", "run_id": run_id })
+            }).await;
+        }
+
+        assert_eq!(wait_for_status(&queue, "run-a", "success").await["run_id"], "run-a");
+        assert_eq!(wait_for_status(&queue, "run-b", "success").await["run_id"], "run-b");
+    }
+
+    #[tokio::test]
+    async fn test_runs_wait_for_a_free_worker() {
+        let queue = RunQueue::new(1);
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        queue.submit("run-a", "test/action:0.0.1", async move {
+            let _ = released.await;
+            json!({ "status": "success" })
+        }).await;
+        queue.submit("run-b", "test/action:0.0.1", async { json!({ "status": "success" }) }).await;
+
+        wait_for_status(&queue, "run-a", "running").await;
+        assert_eq!(queue.get("run-b").await.unwrap()["status"], "queued");
+
+        release.send(()).unwrap();
+        wait_for_status(&queue, "run-a", "success").await;
+        wait_for_status(&queue, "run-b", "success").await;
+    }
+
+    #[tokio::test]
+    async fn test_active_runs_are_listed_until_they_settle() {
+        let queue = RunQueue::new(1);
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        queue.submit("run-b", "test/action:0.0.1", async move {
+            let _ = released.await;
+            json!({ "status": "success" })
+        }).await;
+        wait_for_status(&queue, "run-b", "running").await;
+        queue.submit("run-a", "test/action:0.0.1", async { json!({ "status": "success" }) }).await;
+
+        let active = queue.active().await;
+        let statuses: Vec<(&str, &str)> = active.iter()
+            .map(|run| (run["run_id"].as_str().unwrap(), run["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(statuses, vec![("run-b", "running"), ("run-a", "queued")]);
+        assert!(active[0]["started_at"].is_string());
+
+        release.send(()).unwrap();
+        wait_for_status(&queue, "run-a", "success").await;
+        assert!(queue.active().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all_stops_running_and_queued_runs() {
+        let queue = RunQueue::new(1);
+        queue.submit("run-a", "test/action:0.0.1", std::future::pending()).await;
+        queue.submit("run-b", "test/action:0.0.1", std::future::pending()).await;
+        wait_for_status(&queue, "run-a", "running").await;
+        assert_eq!(queue.get("run-b").await.unwrap()["status"], "queued");
+
+        assert_eq!(queue.cancel_all().await, vec!["run-a", "run-b"]);
+        wait_for_status(&queue, "run-a", "cancelled").await;
+        wait_for_status(&queue, "run-b", "cancelled").await;
+        assert!(queue.cancel_all().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_jobs_settle_with_their_own_result() {
+        let queue = RunQueue::new(1);
+        let cancel = CancellationToken::new();
+        let watched = cancel.clone();
+        queue.submit_cancellable("run-a", "test/action:0.0.1", cancel, async move {
+            watched.cancelled().await;
+            json!({ "status": "cancelled", "steps": ["first"] })
+        }).await;
+        wait_for_status(&queue, "run-a", "running").await;
+
+        assert!(queue.cancel("run-a").await);
+        let run = wait_for_status(&queue, "run-a", "cancelled").await;
+        assert_eq!(run["steps"], json!(["first"]));
+        assert!(!queue.cancel("run-a").await);
+        assert!(!queue.cancel("unknown").await);
+    }
+
+    #[tokio::test]
+    async fn test_settled_runs_are_evicted() {
+        let queue = RunQueue::with_retention(1, 2, Duration::from_secs(60));
+        for run_id in ["run-a", "run-b", "run-c"] {
+            queue.submit(run_id, "test/action:0.0.1", async { json!({ "status": "success" }) }).await;
+            wait_for_status(&queue, run_id, "success").await;
+        }
+        assert!(queue.get("run-a").await.is_none());
+        assert!(queue.get("run-b").await.is_some());
+        assert!(queue.get("run-c").await.is_some());
+
+        let queue = RunQueue::with_retention(1, 10, Duration::ZERO);
+        queue.submit("run-a", "test/action:0.0.1", async { json!({ "status": "success" }) }).await;
+        queue.submit("run-b", "test/action:0.0.1", std::future::pending()).await;
+        wait_for_status(&queue, "run-b", "running").await;
+        // Only settled runs expire
+        assert!(queue.get("run-a").await.is_none());
+        assert_eq!(queue.get("run-b").await.unwrap()["status"], "running");
+    }
+
+    #[tokio::test]
+    async fn test_panicking_run_is_reported_as_failed() {
+        fn boom() -> Value {
+            panic!("boom")
+        }
+
+        let queue = RunQueue::new(1);
+        queue.submit("run-a", "test/action:0.0.1", async { boom() }).await;
+
+        let run = wait_for_status(&queue, "run-a", "error").await;
+        assert_eq!(run["action"], "test/action:0.0.1");
+    }
+}
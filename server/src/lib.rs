@@ -3,4 +3,13 @@ pub mod execution;
 pub mod wasm;
 pub mod logger;
 pub mod docker;
-pub mod database;
\ No newline at end of file
+pub mod database;
+pub mod http;
+pub mod prune;
+pub mod jobs;
+pub mod secrets;
+pub mod config;
+pub mod lockfile;
+pub mod step;
+#[cfg(test)]
+pub(crate) mod test_support;
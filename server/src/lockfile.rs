@@ -0,0 +1,125 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::models::ShManifest;
+
+/// Default name of a lockfile, next to the manifest of the project
+pub const LOCKFILE_FILENAME: &str = "starthub.lock";
+/// Version of the lockfile format written by this server
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// Version and digest an action reference resolved to when the lockfile was written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedAction {
+    /// Concrete reference the manifest was fetched as, e.g. `namespace/slug:1.2.0` for `namespace/slug:latest`
+    pub resolved: String,
+    /// `sha256:<hex>` digest of the manifest
+    pub digest: String,
+    /// `sha256:<hex>` digest of the downloaded wasm module or docker image, for wasm and docker actions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<String>,
+}
+
+/// Every action of a tree, transitive children and hooks included, pinned to a concrete
+/// version and the digest of its manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub lockfile_version: u32,
+    /// Reference of the root action
+    pub root: String,
+    /// Locked actions, keyed by the reference as written in the root or in a `uses`
+    pub actions: BTreeMap<String, LockedAction>,
+}
+
+impl Lockfile {
+    pub fn new(root: &str) -> Self {
+        Self {
+            lockfile_version: LOCKFILE_VERSION,
+            root: root.to_string(),
+            actions: BTreeMap::new(),
+        }
+    }
+
+    /// Parses the content of a lockfile. `name` only identifies the lockfile in the messages.
+    pub fn parse(content: &str, name: &str) -> Result<Self> {
+        let lockfile: Lockfile = serde_json::from_str(content)
+            .map_err(|e| anyhow::anyhow!("Invalid lockfile {}: {}", name, e))?;
+        if lockfile.lockfile_version != LOCKFILE_VERSION {
+            bail!(
+                "Lockfile {} has version {}, this server reads version {}",
+                name,
+                lockfile.lockfile_version,
+                LOCKFILE_VERSION
+            );
+        }
+        Ok(lockfile)
+    }
+
+    /// What a reference is locked to, failing when the lockfile doesn't know it
+    pub fn locked(&self, action_ref: &str) -> Result<&LockedAction> {
+        self.actions.get(action_ref).ok_or_else(|| anyhow::anyhow!(
+            "{} isn't in the lockfile of {}: run 'starthub lock' again to update it",
+            action_ref,
+            self.root
+        ))
+    }
+
+    /// Checks that a manifest fetched for a locked reference is the one that was locked
+    pub fn verify(&self, action_ref: &str, manifest: &ShManifest) -> Result<()> {
+        let locked = self.locked(action_ref)?;
+        let digest = manifest_digest(manifest)?;
+        if digest != locked.digest {
+            bail!(
+                "Manifest of {} (locked as {}) doesn't match the lockfile: its digest is {}, the lockfile expects {}",
+                action_ref,
+                locked.resolved,
+                digest,
+                locked.digest
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that the artifact downloaded for a concrete reference is the one that was locked.
+    /// Artifacts of actions locked without an artifact digest aren't checked.
+    pub fn verify_artifact(&self, resolved: &str, artifact: &Path) -> Result<()> {
+        let Some(expected) = self.actions.values()
+            .filter(|locked| locked.resolved == resolved)
+            .find_map(|locked| locked.artifact.as_ref()) else {
+            return Ok(());
+        };
+        let digest = file_digest(artifact)?;
+        if &digest != expected {
+            bail!(
+                "Artifact of {} doesn't match the lockfile: its digest is {}, the lockfile expects {}",
+                resolved,
+                digest,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `sha256:<hex>` digest of a manifest. Its JSON keys are sorted first, so that the digest
+/// doesn't depend on the order the fields were written or deserialized in.
+pub fn manifest_digest(manifest: &ShManifest) -> Result<String> {
+    // serde_json::Value keeps object keys sorted
+    let canonical = serde_json::to_vec(&serde_json::to_value(manifest)?)?;
+    Ok(sha256_digest(&canonical))
+}
+
+/// `sha256:<hex>` digest of a downloaded artifact
+pub fn file_digest(path: &Path) -> Result<String> {
+    let content = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read artifact {}: {}", path.display(), e))?;
+    Ok(sha256_digest(&content))
+}
+
+fn sha256_digest(bytes: &[u8]) -> String {
+    let digest = sodiumoxide::crypto::hash::sha256::hash(bytes);
+    let hex: String = digest.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
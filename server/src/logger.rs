@@ -1,10 +1,144 @@
-use serde_json::json;
-use tokio::sync::broadcast;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
 use chrono;
 
+/// Log lines kept in memory for each run by default
+pub const DEFAULT_RUN_LOG_LINES: usize = 500;
+/// Runs whose logs are kept in memory by default
+pub const DEFAULT_MAX_RUN_LOGS: usize = 100;
+
+/// The most recent log lines of the most recent runs, keyed by run id.
+/// Each run keeps at most `lines_per_run` lines; once more than `max_runs` runs are
+/// buffered, the buffer of the run that started logging first is dropped.
+#[derive(Clone)]
+pub struct RunLogs {
+    inner: Arc<Mutex<RunLogsInner>>,
+}
+
+struct RunLogsInner {
+    lines_per_run: usize,
+    max_runs: usize,
+    // Run ids in the order they started logging
+    order: VecDeque<String>,
+    runs: HashMap<String, VecDeque<Value>>,
+    // Receivers of the lines of a run as they are pushed
+    subscribers: HashMap<String, Vec<mpsc::UnboundedSender<Value>>>,
+}
+
+impl RunLogs {
+    pub fn new(lines_per_run: usize, max_runs: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(RunLogsInner {
+                lines_per_run: lines_per_run.max(1),
+                max_runs: max_runs.max(1),
+                order: VecDeque::new(),
+                runs: HashMap::new(),
+                subscribers: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Appends a log line to the buffer of a run, dropping its oldest line when full
+    pub fn push(&self, run_id: &str, line: Value) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if !inner.runs.contains_key(run_id) {
+            inner.order.push_back(run_id.to_string());
+            inner.runs.insert(run_id.to_string(), VecDeque::new());
+            while inner.order.len() > inner.max_runs {
+                if let Some(evicted) = inner.order.pop_front() {
+                    inner.runs.remove(&evicted);
+                    inner.subscribers.remove(&evicted);
+                }
+            }
+        }
+        let lines_per_run = inner.lines_per_run;
+        if let Some(lines) = inner.runs.get_mut(run_id) {
+            if lines.len() >= lines_per_run {
+                lines.pop_front();
+            }
+            lines.push_back(line.clone());
+        }
+        if let Some(subscribers) = inner.subscribers.get_mut(run_id) {
+            subscribers.retain(|subscriber| subscriber.send(line.clone()).is_ok());
+            if subscribers.is_empty() {
+                inner.subscribers.remove(run_id);
+            }
+        }
+    }
+
+    /// The lines buffered so far for a run, and a receiver of the lines pushed from now on.
+    /// Nothing is missed or repeated between the two.
+    pub fn subscribe(&self, run_id: &str) -> (Vec<Value>, mpsc::UnboundedReceiver<Value>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let Ok(mut inner) = self.inner.lock() else {
+            return (Vec::new(), receiver);
+        };
+        // Forget the receivers of streams that closed since, including those of runs that no longer log
+        inner.subscribers.retain(|_, subscribers| {
+            subscribers.retain(|subscriber| !subscriber.is_closed());
+            !subscribers.is_empty()
+        });
+        let buffered = inner.runs.get(run_id).map(|lines| lines.iter().cloned().collect()).unwrap_or_default();
+        inner.subscribers.entry(run_id.to_string()).or_default().push(sender);
+        (buffered, receiver)
+    }
+
+    /// Drops the receivers of the lines of a run once it settled: they get the lines pushed so far,
+    /// then the end of the stream
+    pub fn close(&self, run_id: &str) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.subscribers.remove(run_id);
+        }
+    }
+
+    /// Number of runs with receivers of their lines
+    pub fn subscribed_runs(&self) -> usize {
+        self.inner.lock().map(|inner| inner.subscribers.len()).unwrap_or_default()
+    }
+
+    /// Path of the innermost step of a run that started and hasn't completed or failed yet,
+    /// from the step events still buffered
+    pub fn current_step(&self, run_id: &str) -> Option<String> {
+        let mut in_flight: Vec<String> = Vec::new();
+        for line in self.get(run_id)? {
+            let Some(step_path) = line["step_path"].as_str() else {
+                continue;
+            };
+            match line["type"].as_str() {
+                Some("step_started") => in_flight.push(step_path.to_string()),
+                Some("step_completed") | Some("step_failed") => in_flight.retain(|path| path != step_path),
+                _ => {}
+            }
+        }
+        in_flight.pop()
+    }
+
+    /// Buffered log lines of a run, oldest first
+    pub fn get(&self, run_id: &str) -> Option<Vec<Value>> {
+        let inner = self.inner.lock().ok()?;
+        inner.runs.get(run_id).map(|lines| lines.iter().cloned().collect())
+    }
+}
+
+impl Default for RunLogs {
+    fn default() -> Self {
+        Self::new(DEFAULT_RUN_LOG_LINES, DEFAULT_MAX_RUN_LOGS)
+    }
+}
+
 /// Logger struct that handles all logging functionality
 pub struct Logger {
     ws_sender: Option<broadcast::Sender<String>>,
+    /// Stable step name and step path of the actions being run, keyed by action id
+    steps: Mutex<HashMap<String, (String, String)>>,
+    /// In-memory buffer the messages are also captured to, with the id of the run they belong to
+    run_logs: Option<(RunLogs, String)>,
+    /// Secret values replaced by `***` in every message
+    masked: Arc<Mutex<Vec<String>>>,
 }
 
 impl Logger {
@@ -12,6 +146,9 @@ impl Logger {
     pub fn new() -> Self {
         Self {
             ws_sender: None,
+            steps: Mutex::new(HashMap::new()),
+            run_logs: None,
+            masked: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -19,6 +156,9 @@ impl Logger {
     pub fn new_with_ws_sender(ws_sender: Option<broadcast::Sender<String>>) -> Self {
         Self {
             ws_sender,
+            steps: Mutex::new(HashMap::new()),
+            run_logs: None,
+            masked: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -27,26 +167,139 @@ impl Logger {
         self.ws_sender = Some(sender);
     }
 
+    /// Also capture the messages to the buffer of the given run
+    pub fn set_run_logs(&mut self, run_logs: RunLogs, run_id: &str) {
+        self.run_logs = Some((run_logs, run_id.to_string()));
+    }
+
+    /// A logger reporting to the same WebSocket and run buffer, for background tasks outliving a borrow of this one.
+    /// Step names aren't carried over.
+    pub fn detached(&self) -> Self {
+        Self {
+            ws_sender: self.ws_sender.clone(),
+            steps: Mutex::new(HashMap::new()),
+            run_logs: self.run_logs.clone(),
+            masked: self.masked.clone(),
+        }
+    }
+
+    /// Never print the given value again: it's replaced by `***` in the messages logged from now on
+    pub fn mask(&self, secret: &str) {
+        if secret.is_empty() {
+            return;
+        }
+        if let Ok(mut masked) = self.masked.lock() {
+            if !masked.iter().any(|value| value == secret) {
+                masked.push(secret.to_string());
+                // Longest first, so that a secret containing another one is masked whole
+                masked.sort_by_key(|value| std::cmp::Reverse(value.len()));
+            }
+        }
+    }
+
+    /// The message with every masked value replaced by `***`
+    pub fn redact(&self, message: &str) -> String {
+        let Ok(masked) = self.masked.lock() else {
+            return message.to_string();
+        };
+        masked.iter().fold(message.to_string(), |message, secret| message.replace(secret.as_str(), "***"))
+    }
+
+    /// The value with every masked value replaced by `***` in its strings, keys included
+    pub fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact(s)),
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.redact_value(item)).collect()),
+            Value::Object(fields) => Value::Object(fields.iter()
+                .map(|(name, field)| (self.redact(name), self.redact_value(field)))
+                .collect()),
+            _ => value.clone(),
+        }
+    }
+
     /// Get the WebSocket sender
     pub fn get_ws_sender(&self) -> Option<broadcast::Sender<String>> {
         self.ws_sender.clone()
     }
 
-    /// Core logging function that sends messages via WebSocket
+    /// Register the step name and path of an action, so that its log messages can be
+    /// mapped to the step even though the action id changes on every build
+    pub fn register_step(&self, action_id: &str, step_name: &str, step_path: &str) {
+        if let Ok(mut steps) = self.steps.lock() {
+            steps.insert(action_id.to_string(), (step_name.to_string(), step_path.to_string()));
+        }
+    }
+
+    /// Announces a lifecycle event of a step: `step_started`, `step_completed` or `step_failed`
+    pub fn log_step_event(&self, event: &str, step_path: &str, action_id: &str) {
+        let step_name = self.steps.lock().ok().and_then(|steps| steps.get(action_id).map(|(name, _)| name.clone()));
+        self.emit(json!({
+            "type": event,
+            "step_name": step_name,
+            "step_path": step_path,
+            "action_id": action_id,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }));
+    }
+
+    /// Sends a line a step wrote to its stdout or stderr as a `step_log` message.
+    /// Broadcasting never waits for clients: without one connected the line is only captured to
+    /// the run's buffer, and a client that falls behind misses lines rather than slowing the step.
+    pub fn log_step_output(&self, stream: &str, line: &str, action_id: Option<&str>) {
+        if self.ws_sender.is_none() && self.run_logs.is_none() {
+            return;
+        }
+
+        let (step_name, step_path) = action_id
+            .and_then(|id| self.steps.lock().ok().and_then(|steps| steps.get(id).cloned()))
+            .unzip();
+        self.emit(json!({
+            "type": "step_log",
+            "action_id": action_id,
+            "step_name": step_name,
+            "step_path": step_path,
+            "stream": stream,
+            "line": line,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }));
+    }
+
+    /// Core logging function that sends messages via WebSocket and captures them to the run's buffer
     pub fn log(&self, level: &str, message: &str, action_id: Option<&str>) {
+        if self.ws_sender.is_none() && self.run_logs.is_none() {
+            return;
+        }
+        
+        let (step_name, step_path) = action_id
+            .and_then(|id| self.steps.lock().ok().and_then(|steps| steps.get(id).cloned()))
+            .unzip();
+        let log_msg = json!({
+            "type": "log",
+            "level": level,
+            "message": message,
+            "action_id": action_id,
+            "step_name": step_name,
+            "step_path": step_path,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        });
+        self.emit(log_msg);
+    }
+
+    /// Sends a message via WebSocket and captures it to the run's buffer, with the masked values redacted.
+    /// Messages of a run carry its id, so that WebSocket clients can tell the runs apart.
+    fn emit(&self, message: Value) {
+        let mut message = self.redact_value(&message);
+        if let (Some((_, run_id)), Some(fields)) = (&self.run_logs, message.as_object_mut()) {
+            fields.insert("run_id".to_string(), json!(run_id));
+        }
         if let Some(sender) = &self.ws_sender {
-            let log_msg = json!({
-                "type": "log",
-                "level": level,
-                "message": message,
-                "action_id": action_id,
-                "timestamp": chrono::Utc::now().to_rfc3339()
-            });
-            
-            if let Ok(msg_str) = serde_json::to_string(&log_msg) {
+            if let Ok(msg_str) = serde_json::to_string(&message) {
                 let _ = sender.send(msg_str);
             }
         }
+        if let Some((run_logs, run_id)) = &self.run_logs {
+            run_logs.push(run_id, message);
+        }
     }
 
     /// Log an info message
@@ -75,6 +328,27 @@ impl Logger {
     }
 }
 
+/// Waits for `done` while passing the lines a step writes to `log_output` as they arrive, then
+/// passes the lines still in flight until every sender of `lines` is dropped
+pub async fn forward_step_output<T>(
+    done: impl std::future::Future<Output = T>,
+    lines: &mut mpsc::UnboundedReceiver<(&'static str, String)>,
+    step_id: &str,
+    log_output: &(dyn Fn(&str, &str, Option<&str>) + Send + Sync),
+) -> T {
+    tokio::pin!(done);
+    let result = loop {
+        tokio::select! {
+            result = &mut done => break result,
+            Some((stream, line)) = lines.recv() => log_output(stream, &line, Some(step_id)),
+        }
+    };
+    while let Some((stream, line)) = lines.recv().await {
+        log_output(stream, &line, Some(step_id));
+    }
+    result
+}
+
 /// Trait for objects that can log messages
 pub trait Loggable {
     fn log_info(&self, message: &str, action_id: Option<&str>);
@@ -105,3 +379,153 @@ impl Loggable for Logger {
         self.log_debug(message, action_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_logs_are_bounded() {
+        let run_logs = RunLogs::new(2, 2);
+        for line in 0..3 {
+            run_logs.push("run-a", json!(line));
+        }
+        // Only the most recent lines of a run are kept
+        assert_eq!(run_logs.get("run-a").unwrap(), vec![json!(1), json!(2)]);
+
+        run_logs.push("run-b", json!("b"));
+        run_logs.push("run-c", json!("c"));
+        // The run that started logging first is evicted
+        assert!(run_logs.get("run-a").is_none());
+        assert_eq!(run_logs.get("run-b").unwrap(), vec![json!("b")]);
+        assert_eq!(run_logs.get("run-c").unwrap(), vec![json!("c")]);
+    }
+
+    #[test]
+    fn test_subscribers_receive_the_lines_pushed_after_the_buffered_ones() {
+        let run_logs = RunLogs::default();
+        run_logs.push("run-a", json!("before"));
+        let (buffered, mut receiver) = run_logs.subscribe("run-a");
+        run_logs.push("run-a", json!("after"));
+        run_logs.push("run-b", json!("other run"));
+
+        assert_eq!(buffered, vec![json!("before")]);
+        assert_eq!(receiver.try_recv().unwrap(), json!("after"));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_subscribers_are_pruned_once_closed_or_settled() {
+        let run_logs = RunLogs::default();
+        let (_, closed) = run_logs.subscribe("run-a");
+        drop(closed);
+        let (_, mut settled) = run_logs.subscribe("run-b");
+        // The closed stream of run-a is forgotten without run-a pushing anything
+        assert_eq!(run_logs.subscribed_runs(), 1);
+
+        run_logs.push("run-b", json!("last"));
+        run_logs.close("run-b");
+        assert_eq!(run_logs.subscribed_runs(), 0);
+        assert_eq!(settled.try_recv().unwrap(), json!("last"));
+        assert!(matches!(settled.try_recv(), Err(mpsc::error::TryRecvError::Disconnected)));
+    }
+
+    #[test]
+    fn test_current_step_is_the_innermost_unsettled_one() {
+        let run_logs = RunLogs::default();
+        assert_eq!(run_logs.current_step("run-a"), None);
+        let event = |event: &str, step_path: &str| json!({ "type": event, "step_path": step_path });
+        run_logs.push("run-a", event("step_started", "fetch"));
+        run_logs.push("run-a", event("step_completed", "fetch"));
+        run_logs.push("run-a", event("step_started", "deploy"));
+        run_logs.push("run-a", event("step_started", "deploy.create_vm"));
+        run_logs.push("run-a", json!({ "type": "log", "message": "creating", "step_path": "deploy.create_vm" }));
+        assert_eq!(run_logs.current_step("run-a").as_deref(), Some("deploy.create_vm"));
+
+        run_logs.push("run-a", event("step_failed", "deploy.create_vm"));
+        assert_eq!(run_logs.current_step("run-a").as_deref(), Some("deploy"));
+        run_logs.push("run-a", event("step_completed", "deploy"));
+        assert_eq!(run_logs.current_step("run-a"), None);
+    }
+
+    #[test]
+    fn test_logger_captures_to_run_logs() {
+        let run_logs = RunLogs::default();
+        let mut logger = Logger::new();
+        logger.set_run_logs(run_logs.clone(), "run-a");
+        logger.log_warning("careful", None);
+
+        let lines = run_logs.get("run-a").unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "warning");
+        assert_eq!(lines[0]["message"], "careful");
+    }
+
+    #[test]
+    fn test_step_output_is_captured_without_connected_clients() {
+        let run_logs = RunLogs::default();
+        // No receiver is subscribed to the broadcast channel
+        let (ws_sender, _) = broadcast::channel::<String>(1);
+        let mut logger = Logger::new_with_ws_sender(Some(ws_sender));
+        logger.set_run_logs(run_logs.clone(), "run-a");
+        logger.register_step("build", "build", "deploy.build");
+        for line in 0..3 {
+            logger.log_step_output("stderr", &format!("line {}", line), Some("build"));
+        }
+
+        let lines = run_logs.get("run-a").unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2]["type"], "step_log");
+        assert_eq!(lines[2]["stream"], "stderr");
+        assert_eq!(lines[2]["line"], "line 2");
+        assert_eq!(lines[2]["action_id"], "build");
+        assert_eq!(lines[2]["step_path"], "deploy.build");
+    }
+
+    #[test]
+    fn test_masked_values_are_redacted_from_every_message() {
+        let run_logs = RunLogs::default();
+        let mut logger = Logger::new();
+        logger.set_run_logs(run_logs.clone(), "run-a");
+        logger.mask("hunter2");
+        logger.log_info("connecting with hunter2", None);
+        logger.log_step_output("stdout", "password=hunter2", None);
+        logger.log_step_event("step_started", "login.hunter2", "id");
+
+        let lines = run_logs.get("run-a").unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| !line.to_string().contains("hunter2")), "secret leaked in: {:?}", lines);
+        assert_eq!(lines[1]["line"], "password=***");
+        assert_eq!(logger.redact_value(&json!({ "hunter2": ["hunter2", 1] })), json!({ "***": ["***", 1] }));
+    }
+
+    #[tokio::test]
+    async fn test_step_output_is_forwarded_before_the_step_completes() {
+        let (lines_tx, mut lines_rx) = mpsc::unbounded_channel();
+        let (first_line_tx, first_line_rx) = tokio::sync::oneshot::channel::<()>();
+        let first_line_tx = Mutex::new(Some(first_line_tx));
+        let forwarded = Mutex::new(Vec::new());
+        let log_output = |stream: &str, line: &str, step_id: Option<&str>| {
+            forwarded.lock().unwrap().push(format!("{} {} {}", step_id.unwrap(), stream, line));
+            if let Some(tx) = first_line_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+        };
+
+        lines_tx.send(("stdout", "started".to_string())).unwrap();
+        // The step only completes once its first line was forwarded
+        let done = async {
+            first_line_rx.await.unwrap();
+            lines_tx.send(("stderr", "finishing".to_string())).unwrap();
+            drop(lines_tx);
+            "done"
+        };
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            forward_step_output(done, &mut lines_rx, "build", &log_output),
+        ).await.expect("output wasn't forwarded while the step ran");
+
+        assert_eq!(result, "done");
+        assert_eq!(*forwarded.lock().unwrap(), vec!["build stdout started", "build stderr finishing"]);
+    }
+}
@@ -4,7 +4,7 @@ use serde::{Serialize, Deserialize};
 use serde_json::Value;
 
 // ---- Action roles ----
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ShRole {
     #[serde(rename = "flow_control")]
     FlowControl,
@@ -27,7 +27,9 @@ pub struct ShManifest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<String>,
     pub license: String,
+    #[serde(default)]
     pub inputs: serde_json::Value,
+    #[serde(default)]
     pub outputs: serde_json::Value,
     // Custom type definitions
     #[serde(default)]
@@ -37,6 +39,13 @@ pub struct ShManifest {
     #[serde(default)]
     #[serde(skip_serializing_if = "std::collections::HashMap::is_empty")]
     pub steps: std::collections::HashMap<String, serde_json::Value>,
+    // Steps run in order before the steps of a composition, and after them even when they fail
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<serde_json::Value>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<serde_json::Value>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub wires: Vec<ShWire>,
@@ -53,7 +62,32 @@ pub struct ShManifest {
     pub permissions: Option<ShPermissions>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Whether a step input is written in the older `{"value": ...}` form: an object holding only
+/// `value`. An object with other fields is an input value of its own.
+pub fn is_value_wrapper(input: &Value) -> bool {
+    input.as_object().is_some_and(|fields| fields.len() == 1 && fields.contains_key("value"))
+}
+
+/// Retry configuration of a wasm or docker step, from the step in the parent manifest
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShRetry {
+    pub max_attempts: u32,
+    #[serde(default)]
+    pub backoff_ms: u64,
+    /// Doubles the backoff after every failed attempt
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exponential: bool,
+}
+
+impl ShRetry {
+    /// Delay before the attempt following the given failed one (1-based)
+    pub fn delay_after(&self, attempt: u32) -> std::time::Duration {
+        let factor = if self.exponential { 2u64.saturating_pow(attempt.saturating_sub(1)) } else { 1 };
+        std::time::Duration::from_millis(self.backoff_ms.saturating_mul(factor))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ShPermissions {
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -63,7 +97,7 @@ pub struct ShPermissions {
     pub net: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ShIO {
     pub name: String,
     #[serde(rename = "type")]
@@ -71,22 +105,53 @@ pub struct ShIO {
     pub template: Value,
     pub value: Option<Value>,
     pub required: bool,
+    /// Makes the input required when the expression holds against the other inputs,
+    /// e.g. `provider == "aws"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_if: Option<String>,
+    /// Reshapes an output after interpolation: a JSON shape whose strings starting with `.`
+    /// are paths into the interpolated value, e.g. `{"host": ".[0]", "port": ".[1]"}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Value>,
+    /// Value an input takes when the caller leaves it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
 }
 
 // Data flow edge representing a variable dependency between steps
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, PartialEq, Eq)]
 pub struct ShAction {
     pub id: String,
     pub name: String,                    // "get_coordinates" or "get_weather_response"
     pub kind: String,                    // "composition", "wasm", "docker"
     pub uses: String,                    // Reference to the action
+    #[serde(default)]
+    pub resolved_uses: String,           // Concrete reference the `uses` resolved to (e.g. `latest` → `1.2.0`)
     pub inputs: Vec<ShIO>,              // Array format: [{"name": "...", "type": "...", "value": ...}]
     pub outputs: Vec<ShIO>,             // Array format: [{"name": "...", "type": "...", "value": ...}]
     pub parent_action: Option<String>,   // UUID of parent action (None for root)
     pub steps: HashMap<String, ShAction>, // Nested actions keyed by UUID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<ShAction>,              // Setup steps run in order before the steps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<ShAction>,             // Teardown steps run in order after the steps, even when they fail
     pub role: Option<ShRole>,            // Role: FlowControl, TypingControl, etc.
     #[serde(default = "default_priority")]
     pub priority: i32,                   // Execution priority (lower = higher priority)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,                  // Skipped with null outputs when its inputs can never be resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,            // Condition template the step only runs if, from the step in the parent manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_holds: Option<bool>,        // Value of the condition once its templates resolve
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreach: Option<String>,         // Template of the array the step runs once per element of, from the step in the parent manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreach_instances: Option<Vec<ShAction>>, // One instance of the step per element, once the array and their inputs resolve
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,            // Seconds the step may run for, from the step in the parent manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<ShRetry>,          // Attempts of a failing wasm or docker step, from the step in the parent manifest
     
     // Manifest structure fields
     pub types: Option<serde_json::Map<String, Value>>,   // From manifest.types
@@ -104,7 +169,7 @@ fn default_priority() -> i32 {
     0
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ShKind { 
     Wasm, 
     Docker,
@@ -145,6 +210,8 @@ impl<'de> serde::Deserialize<'de> for ShKind {
 pub enum ShType {
     String,
     Number,
+    Integer,
+    Secret,
     Boolean,
     Object,
     Array,
@@ -162,6 +229,8 @@ impl serde::Serialize for ShType {
             ShType::String => "string",
             ShType::Id => "id",
             ShType::Number => "number",
+            ShType::Integer => "integer",
+            ShType::Secret => "secret",
             ShType::Boolean => "boolean",
             ShType::Object => "object",
             ShType::Array => "array",
@@ -180,6 +249,8 @@ impl<'de> serde::Deserialize<'de> for ShType {
         match s.as_str() {
             "string" => Ok(ShType::String),
             "number" => Ok(ShType::Number),
+            "integer" => Ok(ShType::Integer),
+            "secret" => Ok(ShType::Secret),
             "boolean" => Ok(ShType::Boolean),
             "object" => Ok(ShType::Object),
             "array" => Ok(ShType::Array),
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What a prune removed, or would remove in a dry run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PruneReport {
+    /// Number of runs removed from the history
+    pub runs: usize,
+    /// Number of cached artifact directories removed
+    pub artifacts: usize,
+    /// Disk space freed by the removed artifacts
+    pub bytes: u64,
+    pub dry_run: bool,
+}
+
+/// Removes the cached artifacts that haven't been written since `cutoff`.
+/// Returns the number of artifact directories and the bytes removed.
+pub fn prune_artifacts(cache_dir: &Path, cutoff: SystemTime, dry_run: bool) -> Result<(usize, u64)> {
+    let mut removed = 0;
+    let mut bytes = 0;
+
+    for artifact_dir in artifact_dirs(cache_dir)? {
+        let (size, last_modified) = dir_usage(&artifact_dir)?;
+        if last_modified >= cutoff {
+            continue;
+        }
+        if !dry_run {
+            std::fs::remove_dir_all(&artifact_dir)?;
+        }
+        removed += 1;
+        bytes += size;
+    }
+
+    Ok((removed, bytes))
+}
+
+/// Lists the artifact directories of the cache, laid out as <namespace>/<slug>/<version>
+fn artifact_dirs(cache_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !cache_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut dirs = vec![cache_dir.to_path_buf()];
+    for _ in 0..3 {
+        let mut children = Vec::new();
+        for dir in &dirs {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    children.push(entry.path());
+                }
+            }
+        }
+        dirs = children;
+    }
+
+    Ok(dirs)
+}
+
+/// Total size and most recent modification time of the files in a directory tree.
+/// Empty directories fall back to the modification time of the directory itself.
+fn dir_usage(dir: &Path) -> Result<(u64, SystemTime)> {
+    let mut size = 0;
+    let mut last_modified: Option<SystemTime> = None;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += metadata.len();
+                let modified = metadata.modified()?;
+                last_modified = Some(last_modified.map_or(modified, |last| last.max(modified)));
+            }
+        }
+    }
+
+    let last_modified = match last_modified {
+        Some(modified) => modified,
+        None => std::fs::metadata(dir)?.modified()?,
+    };
+    Ok((size, last_modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn write_artifact(cache_dir: &Path, version_path: &str, age: Duration) -> PathBuf {
+        let artifact_dir = cache_dir.join(version_path);
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        let artifact = artifact_dir.join("artifact.wasm");
+        std::fs::write(&artifact, vec![0u8; 128]).unwrap();
+        std::fs::File::options().write(true).open(&artifact).unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        artifact_dir
+    }
+
+    #[test]
+    fn test_prune_artifacts_removes_only_old_artifacts() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let old = write_artifact(cache_dir.path(), "tgirotto/http-get/0.0.1", Duration::from_secs(10 * 24 * 3600));
+        let recent = write_artifact(cache_dir.path(), "tgirotto/http-get/0.0.2", Duration::from_secs(60));
+        let cutoff = SystemTime::now() - Duration::from_secs(7 * 24 * 3600);
+
+        // Dry runs only report
+        assert_eq!(prune_artifacts(cache_dir.path(), cutoff, true).unwrap(), (1, 128));
+        assert!(old.exists());
+
+        assert_eq!(prune_artifacts(cache_dir.path(), cutoff, false).unwrap(), (1, 128));
+        assert!(!old.exists());
+        assert!(recent.exists());
+    }
+
+    #[test]
+    fn test_prune_artifacts_without_cache_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing");
+        assert_eq!(prune_artifacts(&missing, SystemTime::now(), false).unwrap(), (0, 0));
+    }
+}
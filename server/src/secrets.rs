@@ -0,0 +1,99 @@
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Environment variable selecting where `{{secret.NAME}}` templates are resolved from:
+/// `env` (default) or `file:<path>`
+pub const SECRETS_PROVIDER_ENV: &str = "STARTHUB_SECRETS";
+/// Prefix of the environment variables read by the env-backed provider
+pub const SECRET_ENV_PREFIX: &str = "STARTHUB_SECRET_";
+
+/// Source of the secrets referenced by `{{secret.NAME}}` templates
+pub trait SecretsProvider: Send + Sync {
+    /// Short description of the provider, used in error messages
+    fn describe(&self) -> String;
+    /// Value of the secret, None when the provider doesn't know it
+    fn get(&self, name: &str) -> Result<Option<String>>;
+}
+
+/// Reads the secret `NAME` from the environment variable `STARTHUB_SECRET_NAME`
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecretsProvider;
+
+impl SecretsProvider for EnvSecretsProvider {
+    fn describe(&self) -> String {
+        format!("the environment (as {}<NAME>)", SECRET_ENV_PREFIX)
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(std::env::var(format!("{}{}", SECRET_ENV_PREFIX, name)).ok())
+    }
+}
+
+/// Reads the secrets from a JSON file mapping their names to their values
+#[derive(Debug, Clone)]
+pub struct FileSecretsProvider {
+    path: PathBuf,
+}
+
+impl FileSecretsProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SecretsProvider for FileSecretsProvider {
+    fn describe(&self) -> String {
+        format!("the secrets file {}", self.path.display())
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        // Read on every lookup, so that a rotated secret is picked up by the next run
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to read the secrets file {}: {}", self.path.display(), e))?;
+        let secrets: Value = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("The secrets file {} isn't a JSON object: {}", self.path.display(), e))?;
+        let Some(secrets) = secrets.as_object() else {
+            bail!("The secrets file {} isn't a JSON object", self.path.display());
+        };
+        Ok(secrets.get(name).map(|value| match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }))
+    }
+}
+
+/// Parses a provider specification: `env` or `file:<path>`
+pub fn provider_from_spec(spec: &str) -> Result<Box<dyn SecretsProvider>> {
+    match spec.trim() {
+        "" | "env" => Ok(Box::new(EnvSecretsProvider)),
+        spec => match spec.strip_prefix("file:") {
+            Some(path) if !path.is_empty() => Ok(Box::new(FileSecretsProvider::new(path))),
+            _ => bail!("Unknown secrets provider '{}', expected 'env' or 'file:<path>'", spec),
+        },
+    }
+}
+
+/// Provider selected by the environment, the env-backed one when unset. An invalid specification
+/// is an error rather than a silent fallback to another source.
+pub fn provider() -> Result<Box<dyn SecretsProvider>> {
+    match std::env::var(SECRETS_PROVIDER_ENV) {
+        Ok(spec) => provider_from_spec(&spec).map_err(|e| anyhow::anyhow!("Invalid {}: {}", SECRETS_PROVIDER_ENV, e)),
+        Err(_) => Ok(Box::new(EnvSecretsProvider)),
+    }
+}
+
+/// Matches the `{{secret.NAME}}` templates of a string
+pub fn template_regex() -> &'static regex::Regex {
+    static SECRET_RE: OnceLock<regex::Regex> = OnceLock::new();
+    SECRET_RE.get_or_init(|| regex::Regex::new(r"\{\{secret\.([A-Za-z0-9_.\-]+)\}\}").expect("valid secret template regex"))
+}
+
+/// Looks up a secret, failing clearly when the provider doesn't know it
+pub fn resolve(provider: &dyn SecretsProvider, name: &str) -> Result<String> {
+    match provider.get(name)? {
+        Some(value) => Ok(value),
+        None => bail!("Secret '{}' is not set: it wasn't found in {}", name, provider.describe()),
+    }
+}
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+/// What a wasm or docker step runs with: where its artifact is downloaded from and cached, and
+/// where its logs go. Log callbacks take the message and the id of the step.
+pub struct StepContext<'a> {
+    pub cache_dir: &'a PathBuf,
+    pub client: &'a reqwest::Client,
+    /// API base URL of the registry the artifact is downloaded from, before the mirrors
+    pub api_base: &'a str,
+    pub log_info: &'a (dyn Fn(&str, Option<&str>) + Send + Sync),
+    pub log_success: &'a (dyn Fn(&str, Option<&str>) + Send + Sync),
+    pub log_error: &'a (dyn Fn(&str, Option<&str>) + Send + Sync),
+    /// Receives every line the step writes, with its stream: `stdout` or `stderr`
+    pub log_output: &'a (dyn Fn(&str, &str, Option<&str>) + Send + Sync),
+}
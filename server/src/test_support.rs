@@ -0,0 +1,27 @@
+//! Fixtures shared by the tests of the library and of the server binary, which includes this file
+//! as its own module
+
+use serde_json::{json, Value};
+
+/// Builds a manifest from the fields a test cares about, filling in the rest: by default a
+/// composition at version 0.0.1. `fields` can override any of the defaults.
+pub(crate) fn manifest_json(name: &str, fields: Value) -> Value {
+    let mut manifest = json!({
+        "name": name,
+        "version": "0.0.1",
+        "kind": "composition",
+        "manifest_version": 1,
+        "repository": "",
+        "license": "MIT"
+    });
+    if let (Some(manifest), Value::Object(fields)) = (manifest.as_object_mut(), fields) {
+        manifest.extend(fields);
+    }
+    manifest
+}
+
+/// Writes [`manifest_json`] to `path` and returns the `file://` reference to it.
+pub(crate) fn write_manifest(path: &std::path::Path, name: &str, fields: Value) -> String {
+    std::fs::write(path, serde_json::to_string(&manifest_json(name, fields)).unwrap()).unwrap();
+    format!("file://{}", path.display())
+}
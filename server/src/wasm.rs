@@ -1,4 +1,6 @@
+use crate::logger::forward_step_output;
 use crate::models::ShAction;
+use crate::step::StepContext;
 use anyhow::{bail, Result};
 use serde_json::Value;
 use std::path::PathBuf;
@@ -6,18 +8,159 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command as TokioCommand;
 use tokio::sync::mpsc;
 use zip::ZipArchive;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Environment variable selecting the optimization level modules are compiled with
+pub const WASM_OPT_LEVEL_ENV: &str = "STARTHUB_WASM_OPT_LEVEL";
+
+// Number of times each module was compiled, keyed by the path of the module and the level
+static COMPILATIONS: Mutex<Option<HashMap<(PathBuf, WasmOptLevel), usize>>> = Mutex::new(None);
+
+// Version of the installed wasmtime, which compiled modules are only valid for
+static WASMTIME_VERSION: OnceLock<String> = OnceLock::new();
+
+/// Optimization level modules are compiled with, mapping to wasmtime's `OptLevel`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WasmOptLevel {
+    /// No optimizations, for the fastest cold start
+    None,
+    /// Optimized for speed, wasmtime's default
+    #[default]
+    Speed,
+    /// Optimized for speed and code size
+    SpeedAndSize,
+}
+
+impl WasmOptLevel {
+    /// Value of wasmtime's `-O opt-level=` option
+    fn wasmtime_value(&self) -> &'static str {
+        match self {
+            Self::None => "0",
+            Self::Speed => "2",
+            Self::SpeedAndSize => "s",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Speed => "speed",
+            Self::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+impl std::str::FromStr for WasmOptLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim() {
+            "none" => Ok(Self::None),
+            "speed" => Ok(Self::Speed),
+            "speed_and_size" => Ok(Self::SpeedAndSize),
+            other => Err(anyhow::anyhow!("Unknown wasm optimization level '{}', expected 'none', 'speed' or 'speed_and_size'", other)),
+        }
+    }
+}
+
+/// Optimization level taken from the environment, wasmtime's default when unset. An invalid value
+/// is an error rather than a silent fallback to the default.
+pub fn opt_level() -> Result<WasmOptLevel> {
+    match std::env::var(WASM_OPT_LEVEL_ENV) {
+        Ok(value) => value.parse().map_err(|e| anyhow::anyhow!("Invalid {}: {}", WASM_OPT_LEVEL_ENV, e)),
+        Err(_) => Ok(WasmOptLevel::default()),
+    }
+}
+
+/// Number of times the module at `module_path` was compiled at the given level by this process
+pub fn compilations(module_path: &Path, opt_level: WasmOptLevel) -> usize {
+    COMPILATIONS.lock()
+        .ok()
+        .and_then(|counts| counts.as_ref().and_then(|counts| counts.get(&(module_path.to_path_buf(), opt_level)).copied()))
+        .unwrap_or(0)
+}
+
+/// Version of the installed wasmtime, e.g. `25.0.1`, asked once per process
+async fn wasmtime_version() -> Result<String> {
+    if let Some(version) = WASMTIME_VERSION.get() {
+        return Ok(version.clone());
+    }
+    let output = TokioCommand::new("wasmtime")
+        .kill_on_drop(true)
+        .arg("--version")
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("Failed to get the wasmtime version: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    // `wasmtime 25.0.1 (<commit> <date>)`
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.split_whitespace().nth(1).unwrap_or("unknown")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-'))
+        .collect::<String>();
+    Ok(WASMTIME_VERSION.get_or_init(|| version).clone())
+}
+
+/// Path of the precompiled module cached next to the module. It is keyed on the content of the
+/// module and the wasmtime version, so a replaced module or an upgraded wasmtime never loads a
+/// stale compilation.
+async fn compiled_module_path(module_path: &Path, opt_level: WasmOptLevel) -> Result<PathBuf> {
+    let digest = crate::lockfile::file_digest(module_path)?;
+    let hash = digest.trim_start_matches("sha256:");
+    let version = wasmtime_version().await?;
+    Ok(module_path.with_extension(format!("{}.{}.wasmtime-{}.cwasm", opt_level.name(), &hash[..16], version)))
+}
+
+/// Compiles a module ahead of time, unless it was already compiled at this level,
+/// and returns the path of the compiled module
+async fn ensure_compiled(module_path: &Path, opt_level: WasmOptLevel) -> Result<PathBuf> {
+    let compiled_path = compiled_module_path(module_path, opt_level).await?;
+    if compiled_path.exists() {
+        return Ok(compiled_path);
+    }
+
+    // Compiled to a temporary file renamed into place, so that a concurrent run never loads a
+    // partially written module
+    let temp_path = compiled_path.with_extension(format!("cwasm.{}.tmp", uuid::Uuid::new_v4()));
+    let output = TokioCommand::new("wasmtime")
+        .kill_on_drop(true)
+        .arg("compile")
+        .arg("-O")
+        .arg(format!("opt-level={}", opt_level.wasmtime_value()))
+        .arg(module_path)
+        .arg("-o")
+        .arg(&temp_path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        bail!("Failed to compile {:?}: {}", module_path, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    if let Err(e) = tokio::fs::rename(&temp_path, &compiled_path).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        bail!("Failed to cache the compiled module {:?}: {}", compiled_path, e);
+    }
+
+    if let Ok(mut counts) = COMPILATIONS.lock() {
+        *counts.get_or_insert_with(HashMap::new).entry((module_path.to_path_buf(), opt_level)).or_insert(0) += 1;
+    }
+    Ok(compiled_path)
+}
 
 /// Executes a WASM step by downloading and running the WASM module
 pub async fn run_wasm_step(
     action: &ShAction,
     inputs: &Value,
-    cache_dir: &PathBuf,
-    log_info: &(dyn Fn(&str, Option<&str>) + Send + Sync),
-    log_success: &(dyn Fn(&str, Option<&str>) + Send + Sync),
-    log_error: &(dyn Fn(&str, Option<&str>) + Send + Sync),
+    opt_level: WasmOptLevel,
+    context: &StepContext<'_>,
 ) -> Result<String> {
+    let StepContext { cache_dir, client, api_base, log_info, log_success, log_error, log_output } = *context;
+
     if which::which("wasmtime").is_err() {
         log_error("wasmtime not found in PATH", Some(&action.id));
         bail!("wasmtime not found in PATH");
@@ -26,7 +169,7 @@ pub async fn run_wasm_step(
     
     // For now, we'll create a simple implementation that downloads the WASM file
     // In a real implementation, this would download from the registry
-    let module_path = download_wasm(&action.uses, &action.mirrors, cache_dir).await?;
+    let module_path = download_wasm(client, api_base, &action.uses, &action.mirrors, cache_dir).await?;
     log_success(&format!("WASM module downloaded: {:?}", module_path), Some(&action.id));
     
     // Verify the WASM file exists and is readable
@@ -39,10 +182,12 @@ pub async fn run_wasm_step(
         return Err(anyhow::anyhow!("WASM file not accessible at {:?}: {}", module_path, e));
     }
 
+    // Repeated runs of the module skip compilation
+    let compiled_path = ensure_compiled(&module_path, opt_level).await?;
+
     // build stdin payload - use the pre-built parameters
     let input_json = serde_json::to_string(inputs)?;
 
-    println!("Input: {}", input_json);
     println!("Module path: {:?}", module_path);
     println!("Action ID: {}", action.id);
     println!("--------------------------------");
@@ -146,10 +291,11 @@ pub async fn run_wasm_step(
         }
     }
     
-    cmd.arg(&module_path);
+    cmd.arg("--allow-precompiled").arg(&compiled_path);
 
-    // spawn with piped stdio
+    // spawn with piped stdio. A step that times out or is cancelled drops the child, which kills it
     let mut child = cmd
+        .kill_on_drop(true)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
@@ -171,11 +317,17 @@ pub async fn run_wasm_step(
     let mut err_reader = BufReader::new(stderr);
 
     let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    // Lines are passed to log_output as the module writes them
+    let (lines_tx, mut lines_rx) = mpsc::unbounded_channel::<(&'static str, String)>();
+    let err_lines_tx = lines_tx.clone();
 
     let pump_out = tokio::spawn(async move {
         let mut output = String::new();
         let mut line = String::new();
         while out_reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+            if !line.trim().is_empty() {
+                let _ = lines_tx.send(("stdout", line.trim().to_string()));
+            }
             output.push_str(&line);
             line.clear();
         }
@@ -187,12 +339,14 @@ pub async fn run_wasm_step(
     let pump_err = tokio::spawn(async move {
         let mut line = String::new();
         while err_reader.read_line(&mut line).await.unwrap_or(0) > 0 {
-            // Just consume stderr for now
+            if !line.trim().is_empty() {
+                let _ = err_lines_tx.send(("stderr", line.trim().to_string()));
+            }
             line.clear();
         }
     });
 
-    let status = child.wait().await?;
+    let status = forward_step_output(child.wait(), &mut lines_rx, &action.id, log_output).await?;
     let _ = pump_out.await;
     let _ = pump_err.await;
 
@@ -224,6 +378,8 @@ pub async fn run_wasm_step(
 
 /// Downloads a WASM module from the registry or mirrors
 pub async fn download_wasm(
+    client: &reqwest::Client,
+    api_base: &str,
     action_ref: &str, 
     mirrors: &[String], 
     cache_dir: &PathBuf
@@ -241,25 +397,11 @@ pub async fn download_wasm(
         return Ok(wasm_path);
     }
     
-    let client = reqwest::Client::new();
-    
     // First try the default registry
-    let parts: Vec<&str> = action_ref.split(':').collect();
-    if parts.len() != 2 {
-        return Err(anyhow::anyhow!("Invalid action reference format: {}", action_ref));
-    }
-    
-    let (namespace_slug, version) = (parts[0], parts[1]);
-    let namespace_parts: Vec<&str> = namespace_slug.split('/').collect();
-    if namespace_parts.len() != 2 {
-        return Err(anyhow::anyhow!("Invalid namespace format: {}", namespace_slug));
-    }
-    
-    let (namespace, slug) = (namespace_parts[0], namespace_parts[1]);
-    let default_url = format!("https://api.starthub.so/storage/v1/object/public/artifacts/{}/{}/{}/artifact.zip", namespace, slug, version);
+    let default_url = crate::http::registry_artifact_url(api_base, action_ref)?;
     println!("Trying to download from default registry: {}", default_url);
     
-    match try_download_from_url(&client, &default_url, &wasm_dir, &wasm_path).await {
+    match try_download_from_url(client, &default_url, &wasm_dir, &wasm_path).await {
         Ok(path) => {
             println!("Successfully downloaded from default registry");
             return Ok(path);
@@ -275,7 +417,7 @@ pub async fn download_wasm(
         let url = mirror.clone();
         println!("Trying to download from mirror: {}", url);
         
-        match try_download_from_url(&client, &url, &wasm_dir, &wasm_path).await {
+        match try_download_from_url(client, &url, &wasm_dir, &wasm_path).await {
             Ok(path) => {
                 println!("Successfully downloaded from mirror: {}", url);
                 return Ok(path);
@@ -8,14 +8,84 @@ use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
 use webbrowser;
 use reqwest;
 use dirs;
+use starthub_server::models::is_value_wrapper;
 
 use crate::models::{ShManifest, ShKind, ShPort, ShType};
 use crate::templates;
 use crate::config::SUPABASE_ANON_KEY;
 
-// Global constants for local development server
-const LOCAL_SERVER_URL: &str = "http://127.0.0.1:3000";
+// Address the local server listens on unless STARTHUB_BIND or its config file say otherwise
 const LOCAL_SERVER_HOST: &str = "127.0.0.1:3000";
+/// Environment variable holding the address the server listens on, shared with the server
+pub const BIND_ENV: &str = "STARTHUB_BIND";
+/// Environment variable holding the path of the server config file, shared with the server
+pub const SERVER_CONFIG_ENV: &str = "STARTHUB_SERVER_CONFIG";
+const LOCAL_MANIFEST_FILENAME: &str = "starthub-lock.json";
+const USER_AGENT_ENV: &str = "STARTHUB_USER_AGENT";
+pub const CACHE_DIR_ENV: &str = "STARTHUB_CACHE_DIR";
+pub const PROXY_ENV: &str = "STARTHUB_PROXY";
+/// Environment variable holding the API base URL of the registry, read by the server
+pub const API_BASE_ENV: &str = "STARTHUB_API_BASE";
+pub const MIRROR_STRATEGY_ENV: &str = "STARTHUB_MIRROR_STRATEGY";
+/// Environment variable holding how many seconds the CLI waits for a queued run to finish
+pub const RUN_WAIT_TIMEOUT_ENV: &str = "STARTHUB_RUN_WAIT_TIMEOUT";
+// How long the CLI waits for a queued run to finish unless STARTHUB_RUN_WAIT_TIMEOUT says otherwise
+const DEFAULT_RUN_WAIT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+// How long commands wait for the server they start to become healthy, like `starthub start` by default
+const SERVER_START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Directory where the server caches downloaded artifacts, overridable with STARTHUB_CACHE_DIR
+pub fn cache_dir() -> std::path::PathBuf {
+    std::env::var_os(CACHE_DIR_ENV)
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("starthub/oci"))
+}
+
+/// Where the server config file is read from: STARTHUB_SERVER_CONFIG, or else `server.json` in the
+/// starthub config directory. The server reads the same file.
+fn server_config_path(env: &impl Fn(&str) -> Option<String>) -> Option<std::path::PathBuf> {
+    env(SERVER_CONFIG_ENV)
+        .map(std::path::PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("starthub").join("server.json")))
+}
+
+/// Settings of the server config file, empty when there is none. The server reports an unreadable
+/// config when it starts.
+fn server_config_file(env: &impl Fn(&str) -> Option<String>) -> serde_json::Value {
+    server_config_path(env)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .filter(|config| config.is_object())
+        .unwrap_or_else(|| serde_json::json!({}))
+}
+
+/// Address the server listens on, and where it came from: STARTHUB_BIND, or else the `bind` of the
+/// server config file, or else the default. `env` looks up environment variables.
+pub fn server_bind(env: impl Fn(&str) -> Option<String>) -> (String, ConfigSource) {
+    if let Some(bind) = env(BIND_ENV).filter(|bind| !bind.trim().is_empty()) {
+        return (bind, ConfigSource::Env);
+    }
+    let file_bind = server_config_file(&env)["bind"].as_str().map(|bind| bind.to_string());
+    match file_bind {
+        Some(bind) => (bind, ConfigSource::File),
+        None => (LOCAL_SERVER_HOST.to_string(), ConfigSource::Default),
+    }
+}
+
+/// Base URL of the local server, at the address it is configured to listen on
+fn server_url() -> String {
+    base_url(&server_bind(|name| std::env::var(name).ok()).0)
+}
+
+/// User-Agent of outbound requests, overridable with STARTHUB_USER_AGENT
+pub fn user_agent() -> String {
+    std::env::var(USER_AGENT_ENV)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| format!("starthub-cli/{}", env!("CARGO_PKG_VERSION")))
+}
 
 /// Check if required dependencies (wasmtime and docker) are installed
 fn check_dependencies() -> Result<()> {
@@ -62,7 +132,29 @@ fn check_dependencies() -> Result<()> {
 }
 
 
-pub async fn cmd_publish_docker_inner(m: &ShManifest, no_build: bool) -> anyhow::Result<()> {
+/// Validates a `--build-arg` value, which must have the `KEY=VALUE` form
+pub fn parse_build_arg(value: &str) -> Result<String> {
+    match value.split_once('=') {
+        Some((key, _)) if !key.is_empty() && !key.contains(char::is_whitespace) => Ok(value.to_string()),
+        _ => Err(anyhow::anyhow!("Invalid build arg '{}', expected KEY=VALUE", value)),
+    }
+}
+
+/// Arguments of the `docker build` invocation used to publish an image
+fn docker_build_args(image: &str, no_cache: bool, build_args: &[String]) -> Vec<String> {
+    let mut args = vec!["build".to_string(), "-t".to_string(), image.to_string()];
+    if no_cache {
+        args.push("--no-cache".to_string());
+    }
+    for build_arg in build_args {
+        args.push("--build-arg".to_string());
+        args.push(build_arg.clone());
+    }
+    args.push(".".to_string());
+    args
+}
+
+pub async fn cmd_publish_docker_inner(m: &ShManifest, no_build: bool, no_cache: bool, build_args: &[String]) -> anyhow::Result<()> {
     // Implementation for Docker publishing
     println!("🐳 Publishing Docker image for {}", m.name);
 
@@ -74,7 +166,7 @@ pub async fn cmd_publish_docker_inner(m: &ShManifest, no_build: bool) -> anyhow:
         }
         
         let build_cmd = PCommand::new("docker")
-            .args(&["build", "-t", &format!("{}:{}", m.name, m.version), "."])
+            .args(docker_build_args(&format!("{}:{}", m.name, m.version), no_cache, build_args))
             .output()?;
             
         if !build_cmd.status.success() {
@@ -478,9 +570,7 @@ pub async fn cmd_reset() -> anyhow::Result<()> {
     println!("🧹 Clearing cache...");
     
     // Get cache directory (same as used in execution.rs)
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(|| std::env::temp_dir())
-        .join("starthub/oci");
+    let cache_dir = cache_dir();
     
     if cache_dir.exists() {
         // Remove the entire cache directory
@@ -493,17 +583,20 @@ pub async fn cmd_reset() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn cmd_start(bind: String) -> Result<()> {
+pub async fn cmd_start(bind: Option<String>, api_base: Option<String>, wait_timeout: Duration) -> Result<()> {
     // Check for required dependencies
     check_dependencies()?;
     
     println!("🚀 Starting StartHub server in detached mode...");
     
-    // Start the server as a detached process
-    let server_process = start_server_process_detached(&bind).await?;
+    // Start the server as a detached process. Without --bind, it listens where STARTHUB_BIND
+    // or its config file say, like the other commands expect. Without --api-base, it uses
+    // the registry STARTHUB_API_BASE names, or else the default one.
+    let server_process = start_server_process_detached(bind.as_deref(), api_base.as_deref()).await?;
+    let bind = bind.unwrap_or_else(|| server_bind(|name| std::env::var(name).ok()).0);
     
-    // Wait a moment for server to start
-    sleep(Duration::from_millis(2000)).await;
+    // Only report success once the server actually answers
+    wait_for_server(&health_url(&bind), wait_timeout).await?;
     
     println!("✅ Server started successfully!");
     println!("🌐 Server running at: http://{}", bind);
@@ -609,32 +702,152 @@ fn get_server_log_file() -> Result<std::path::PathBuf> {
     Ok(starthub_dir.join("server.log"))
 }
 
-pub async fn cmd_run(action: String) -> Result<()> {
+/// Flags of `starthub run`
+#[derive(Debug)]
+pub struct RunOptions {
+    pub resume: Option<String>,
+    pub trace: Option<String>,
+    /// "fail-fast" or "keep-going"
+    pub on_failure: String,
+    pub strict_inputs: bool,
+    pub explain_inputs: bool,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    /// `step=file.json` pairs substituting the outputs of steps
+    pub mocks: Vec<String>,
+    /// File the JSON result is written to, `-` for stdout
+    pub output_file: Option<String>,
+    /// Webhook the outputs of every completed step are posted to
+    pub on_step_output: Option<String>,
+    /// "array" or "object"
+    pub output_shape: String,
+    /// Print the JSON result on a single line
+    pub json_compact: bool,
+    /// File mapping action references to local manifests
+    pub workspace: Option<String>,
+    /// JSON file listing input combinations, each run separately
+    pub matrix: Option<String>,
+    /// Number of matrix combinations run at the same time
+    pub concurrency: usize,
+    /// Use the cached manifests only
+    pub offline: bool,
+    /// Lockfile pinning the versions and digests of the actions
+    pub locked: Option<String>,
+    /// Only print the steps in the order they would run in
+    pub dry_run: bool,
+}
+
+pub async fn cmd_run(action: Option<String>, options: RunOptions) -> Result<()> {
     // Check for required dependencies
     check_dependencies()?;
     
+    // Without an explicit action, run the manifest of the project in the current directory
+    let action = resolve_run_action(action, &std::env::current_dir()?)?;
+    
+    ensure_server_running().await?;
+    
+    // Aliases are saved on the server: expand them before parsing the action
+    let action = if is_alias(&action) { resolve_alias(&action).await? } else { action };
+    
     // Parse the action argument to extract namespace, slug, and version
     let (namespace, slug, version) = parse_action_arg(&action);
     
-    // Check if server is already running
-    let server_running = check_server_running().await?;
+    // The server writes the traces and recordings, so hand it absolute paths
+    let current_dir = std::env::current_dir()?;
+    let absolute = |dir: &Option<String>| dir.as_ref().map(|dir| current_dir.join(dir).to_string_lossy().to_string());
+    let trace_dir = absolute(&options.trace);
+    let record_dir = absolute(&options.record);
+    let replay_dir = absolute(&options.replay);
+    let workspace = absolute(&options.workspace);
+    let lockfile = match &options.locked {
+        Some(path) => Some(fs::read_to_string(current_dir.join(path))
+            .map_err(|e| anyhow::anyhow!("Failed to read lockfile {}: {}", path, e))?),
+        None => None,
+    };
     
-    if !server_running {
-        println!("🚀 Starting server...");
-        // Start the server as a separate process
-        let server_process = start_server_process().await?;
-        
-        // Wait a moment for server to start
-        sleep(Duration::from_millis(2000)).await;
-        
-        println!("✅ Server started at {}", LOCAL_SERVER_URL);
-    } else {
-        println!("✅ Server already running at {}", LOCAL_SERVER_URL);
+    let mocks = read_mocks(&options.mocks, &current_dir)?;
+    let payload = |resume: Option<&String>| serde_json::json!({
+        "action": action,
+        "inputs": [],
+        "resume": resume,
+        "trace_dir": trace_dir,
+        "on_failure": options.on_failure,
+        "strict_inputs": options.strict_inputs,
+        "explain_inputs": options.explain_inputs,
+        "record_dir": record_dir,
+        "replay_dir": replay_dir,
+        "mocks": mocks,
+        "workspace": workspace,
+        "offline": options.offline,
+        "locked": options.locked,
+        "lockfile": lockfile,
+        "output_shape": options.output_shape,
+        "on_step_output": options.on_step_output,
+    });
+    
+    if options.dry_run {
+        let response: serde_json::Value = reqwest::Client::new()
+            .post(format!("{}/api/run", server_url()))
+            .query(&[("dry_run", "true")])
+            .json(&payload(None))
+            .send()
+            .await?
+            .json()
+            .await?;
+        println!("🧪 Dry run of {}", action);
+        for line in dry_run_lines(&response)? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    
+    if let Some(matrix) = &options.matrix {
+        let combinations = read_input_file(&current_dir.join(matrix))?;
+        eprintln!("▶️  Running {} with {} input combination(s)", action, combinations.len());
+        let payloads: Vec<serde_json::Value> = combinations.into_iter()
+            .map(|inputs| {
+                let mut combination = payload(None);
+                combination["inputs"] = inputs;
+                combination
+            })
+            .collect();
+        let results = run_matrix(payloads, options.concurrency, post_run).await;
+        return print_matrix_results(&results, options.output_file.as_deref(), options.json_compact);
+    }
+    
+    if let Some(run_id) = &options.resume {
+        eprintln!("🔁 Resuming run {} of {}", run_id, action);
+        let response = post_run(payload(Some(run_id))).await?;
+        return print_run_response(&response, options.output_file.as_deref(), options.json_compact);
+    }
+    
+    // Local manifests aren't published, so there is no UI route for them: run them directly.
+    // Mocks and lockfiles can't be handed to the UI either, nor can the UI write the result to a file.
+    if action.starts_with("file://") || !mocks.is_empty() || lockfile.is_some() || options.output_file.is_some() {
+        eprintln!("▶️  Running {}", action);
+        let response = post_run(payload(None)).await?;
+        return print_run_response(&response, options.output_file.as_deref(), options.json_compact);
     }
     
     // Open browser to the server with a proper route for the Vue app
-    let url = format!("{}/{}/{}/{}", LOCAL_SERVER_URL, namespace, slug, version);
-    match webbrowser::open(&url) {
+    let mut url = reqwest::Url::parse(&format!("{}/{}/{}/{}", server_url(), namespace, slug, version))?;
+    for (key, dir) in [("trace", &trace_dir), ("record", &record_dir), ("replay", &replay_dir), ("workspace", &workspace), ("on_step_output", &options.on_step_output)] {
+        if let Some(dir) = dir {
+            url.query_pairs_mut().append_pair(key, dir);
+        }
+    }
+    url.query_pairs_mut().append_pair("on_failure", &options.on_failure);
+    url.query_pairs_mut().append_pair("output_shape", &options.output_shape);
+    for (key, enabled) in [
+        ("strict_inputs", options.strict_inputs),
+        ("explain_inputs", options.explain_inputs),
+        ("offline", options.offline),
+    ] {
+        if enabled {
+            url.query_pairs_mut().append_pair(key, "true");
+        }
+    }
+    match webbrowser::open(url.as_str()) {
         Ok(_) => println!("↗ Opened browser to: {url}"),
         Err(e) => println!("→ Browser: {url} (couldn't auto-open: {e})"),
     }
@@ -644,181 +857,1435 @@ pub async fn cmd_run(action: String) -> Result<()> {
     Ok(())
 }
 
-async fn check_server_running() -> Result<bool> {
-    // Try to make a request to the server to see if it's running
+pub async fn cmd_cache_warm(refs: Vec<String>) -> Result<()> {
+    ensure_server_running().await?;
+    
     let client = reqwest::Client::new();
-    let response = client
-        .get(LOCAL_SERVER_URL)
-        .timeout(Duration::from_millis(1000))
+    let response: serde_json::Value = client
+        .post(format!("{}/api/cache/warm", server_url()))
+        .json(&serde_json::json!({ "refs": refs }))
         .send()
-        .await;
+        .await?
+        .json()
+        .await?;
     
-    match response {
-        Ok(resp) => Ok(resp.status().is_success()),
-        Err(_) => Ok(false),
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Failed to warm the cache: {}", error));
     }
+    
+    let count = |key: &str| response["report"][key].as_array().map(|refs| refs.len()).unwrap_or(0);
+    let (fetched, cached) = (count("fetched"), count("cached"));
+    println!("🔥 Warmed {} manifest(s): {} fetched, {} already cached", fetched + cached, fetched, cached);
+    Ok(())
 }
 
-async fn start_server_process_detached(bind: &str) -> Result<std::process::Child> {
-    // Try to find the starthub-server binary
-    let server_binary = if cfg!(target_os = "windows") {
-        "starthub-server.exe"
-    } else {
-        "starthub-server"
-    };
-    
-    // First try to find it in the current directory or PATH
-    let server_path = which::which(server_binary)
-        .or_else(|_| {
-            // Try relative to the current binary
-            let current_exe = std::env::current_exe()?;
-            let current_dir = current_exe.parent().unwrap();
-            Ok::<std::path::PathBuf, anyhow::Error>(current_dir.join(server_binary))
-        })
-        .or_else(|_| {
-            // Try in the target/release directory
-            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("release").join(server_binary))
-        })
-        .or_else(|_| {
-            // Try in the target/debug directory for development
-            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("debug").join(server_binary))
-        })?;
+/// Lines describing the plan of a dry run: every step in the order it would run in, indented by
+/// depth, whether it could start right away or what it waits for, and the inputs it would get
+fn dry_run_lines(response: &serde_json::Value) -> Result<Vec<String>> {
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Dry run failed: {}", error));
+    }
     
-    if !server_path.exists() {
-        return Err(anyhow::anyhow!(
-            "Server binary not found. Please build the server first with: cargo build --bin starthub-server"
+    let empty = Vec::new();
+    let mut lines = Vec::new();
+    for (index, step) in response["plan"].as_array().unwrap_or(&empty).iter().enumerate() {
+        let path = step["step_path"].as_str().unwrap_or("");
+        let indent = "  ".repeat(path.matches('.').count() + 1);
+        let state = match step["blocked_by"].as_str() {
+            Some(reason) => format!("⏸ blocked: {}", reason),
+            None => "▶ ready".to_string(),
+        };
+        lines.push(format!(
+            "{}{}. {} [{}] {} {}",
+            indent,
+            index + 1,
+            path,
+            step["kind"].as_str().unwrap_or("?"),
+            step["uses"].as_str().unwrap_or(""),
+            state
         ));
+        for input in step["inputs"].as_array().unwrap_or(&empty) {
+            let name = input["name"].as_str().filter(|name| !name.is_empty()).unwrap_or("(unnamed)");
+            let unresolved = if input["resolved"].as_bool().unwrap_or(false) { "" } else { " (unresolved)" };
+            lines.push(format!("{}     {} = {}{}", indent, name, input["value"], unresolved));
+        }
+    }
+    Ok(lines)
+}
+
+/// An alias is a bare name, with neither a namespace, a version nor a scheme.
+fn is_alias(action: &str) -> bool {
+    !action.contains('/') && !action.contains(':') && !action.contains('@')
+}
+
+/// Looks up the target of an alias saved on the server. A name that isn't an alias is returned
+/// as is, to be parsed as a slug in the default namespace.
+async fn resolve_alias(alias: &str) -> Result<String> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/aliases/{}", server_url(), alias))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(alias.to_string());
+    }
+    let response: serde_json::Value = response.json().await?;
+    match response.get("target").and_then(|t| t.as_str()) {
+        Some(target) => {
+            eprintln!("🔗 {} is an alias of {}", alias, target);
+            Ok(target.to_string())
+        }
+        None => {
+            let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+            Err(anyhow::anyhow!("Failed to look up alias '{}': {}", alias, error))
+        }
     }
+}
+
+pub async fn cmd_plan(action: Option<String>, depth: Option<usize>) -> Result<()> {
+    let action = resolve_run_action(action, &std::env::current_dir()?)?;
     
-    println!("🚀 Starting server process: {:?}", server_path);
+    ensure_server_running().await?;
     
-    // Get log file path
-    let log_file = get_server_log_file()?;
-    let log_file_path = log_file.as_path();
+    let client = reqwest::Client::new();
+    let response: serde_json::Value = client
+        .get(format!("{}/api/graph", server_url()))
+        .query(&[("action", action.as_str())])
+        .query(&depth.map(|depth| vec![("depth", depth.to_string())]).unwrap_or_default())
+        .send()
+        .await?
+        .json()
+        .await?;
     
-    // Open log file for writing (append mode)
-    let log_file_handle = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_file_path)?;
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Failed to plan {}: {}", action, error));
+    }
     
-    // Start the server process in detached mode with stdout/stderr redirected to log file
-    let child = std::process::Command::new(&server_path)
-        .arg("--bind")
-        .arg(bind)
-        .stdout(Stdio::from(log_file_handle.try_clone()?))
-        .stderr(Stdio::from(log_file_handle))
-        .spawn()?;
+    println!("📋 Plan for {}", action);
+    let empty = Vec::new();
+    for node in response["graph"]["nodes"].as_array().unwrap_or(&empty) {
+        let path = node["path"].as_str().filter(|p| !p.is_empty()).unwrap_or("(root)");
+        let uses = node["uses"].as_str().unwrap_or("");
+        let resolved = node["resolved_uses"].as_str().unwrap_or(uses);
+        if resolved == uses {
+            println!("  {} [{}] {}", path, node["kind"].as_str().unwrap_or("?"), uses);
+        } else {
+            println!("  {} [{}] {} → {}", path, node["kind"].as_str().unwrap_or("?"), uses, resolved);
+        }
+    }
+    for edge in response["graph"]["edges"].as_array().unwrap_or(&empty) {
+        println!("  {} → {}", edge["from"].as_str().unwrap_or(""), edge["to"].as_str().unwrap_or(""));
+    }
     
-    println!("📝 Server logs will be written to: {:?}", log_file_path);
-    println!("💡 Use 'starthub logs' to view logs");
+    Ok(())
+}
+
+/// Starts the local server unless it is already running
+async fn ensure_server_running() -> Result<()> {
+    // Progress goes to stderr: stdout is kept for the output of the command
+    if check_server_running().await? {
+        eprintln!("✅ Server already running at {}", server_url());
+        return Ok(());
+    }
     
-    Ok(child)
+    eprintln!("🚀 Starting server...");
+    // Start the server as a separate process
+    start_server_process().await?;
+    
+    // Only go on once the server actually answers
+    wait_for_server(&health_url(&server_bind(|name| std::env::var(name).ok()).0), SERVER_START_TIMEOUT).await?;
+    
+    eprintln!("✅ Server started at {}", server_url());
+    Ok(())
 }
 
-async fn start_server_process() -> Result<Option<tokio::process::Child>> {
-    // Try to find the starthub-server binary
-    let server_binary = if cfg!(target_os = "windows") {
-        "starthub-server.exe"
-    } else {
-        "starthub-server"
+pub async fn cmd_test_action(action: String, input_file: Option<String>) -> Result<()> {
+    check_dependencies()?;
+    
+    let inputs = match input_file {
+        Some(path) => read_input_file(Path::new(&path))?,
+        None => Vec::new(),
     };
     
-    // First try to find it in the current directory or PATH
-    let server_path = which::which(server_binary)
-        .or_else(|_| {
-            // Try relative to the current binary
-            let current_exe = std::env::current_exe()?;
-            let current_dir = current_exe.parent().unwrap();
-            Ok::<std::path::PathBuf, anyhow::Error>(current_dir.join(server_binary))
-        })
-        .or_else(|_| {
-            // Try in the target/release directory
-            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("release").join(server_binary))
-        })
-        .or_else(|_| {
-            // Try in the target/debug directory for development
-            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("debug").join(server_binary))
-        })?;
+    ensure_server_running().await?;
     
-    if !server_path.exists() {
-        return Err(anyhow::anyhow!(
-            "Server binary not found. Please build the server first with: cargo build --bin starthub-server"
-        ));
-    }
+    println!("🧪 Running {} in isolation", action);
+    let client = reqwest::Client::new();
+    let response: serde_json::Value = client
+        .post(format!("{}/api/test-action", server_url()))
+        .json(&serde_json::json!({
+            "action": action,
+            "inputs": inputs,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
     
-    println!("🚀 Starting server process: {:?}", server_path);
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Action failed: {}", error));
+    }
     
-    // Start the server process
-    let child = tokio::process::Command::new(&server_path)
-        .arg("--bind")
-        .arg(LOCAL_SERVER_HOST)
-        .spawn()?;
+    println!("📤 Raw output:\n{}", response["raw_output"].as_str().unwrap_or(""));
+    println!("🧩 Parsed output:\n{}", serde_json::to_string_pretty(&response["parsed"])?);
+    println!("✅ Outputs:\n{}", serde_json::to_string_pretty(&response["outputs"])?);
     
-    Ok(Some(child))
+    Ok(())
 }
 
-async fn kill_starthub_server_processes() -> Result<usize> {
-    let mut killed_count = 0;
-    
-    #[cfg(unix)]
-    {
-        // Unix/Linux/macOS: Use ps and kill commands
-        let output = std::process::Command::new("ps")
-            .args(&["-ax", "-o", "pid,comm"])
-            .output()?;
-        
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("Failed to list processes"));
+/// Reads the inputs of an action from a JSON file holding an array of values.
+/// `{"$file": "<path>"}` inputs are replaced by the content of their file, with relative paths
+/// resolved against the directory of the input file; the server never reads them itself.
+fn read_input_file(path: &Path) -> Result<Vec<serde_json::Value>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read input file {}: {}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    match serde_json::from_str(&content)? {
+        serde_json::Value::Array(values) => values.into_iter().map(|value| resolve_file_inputs(value, base_dir)).collect(),
+        _ => Err(anyhow::anyhow!("Input file {} must contain a JSON array of inputs", path.display())),
+    }
+}
+
+/// Replaces `{"$file": "<path>"}` inputs by the content of their file, including within the
+/// combinations of a matrix: a string when it is UTF-8 text, its base64 encoding otherwise
+fn resolve_file_inputs(value: serde_json::Value, base_dir: &Path) -> Result<serde_json::Value> {
+    use base64::Engine as _;
+    match value {
+        serde_json::Value::Array(values) => {
+            Ok(serde_json::Value::Array(values.into_iter().map(|value| resolve_file_inputs(value, base_dir)).collect::<Result<_>>()?))
         }
-        
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        
-        for line in output_str.lines() {
-            if line.contains("starthub-server") {
-                let parts: Vec<&str> = line.trim().split_whitespace().collect();
-                if let Some(pid_str) = parts.first() {
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        println!("🔍 Found starthub-server process: PID {}", pid);
-                        
-                        // Try to kill the process gracefully first
-                        let kill_result = std::process::Command::new("kill")
-                            .arg("-TERM")
-                            .arg(pid.to_string())
-                            .output();
-                        
-                        match kill_result {
-                            Ok(output) => {
-                                if output.status.success() {
-                                    println!("✅ Killed process {}", pid);
-                                    killed_count += 1;
-                                } else {
-                                    println!("⚠️  Failed to kill process {}: {}", pid, String::from_utf8_lossy(&output.stderr));
-                                }
-                            }
-                            Err(e) => {
-                                println!("⚠️  Failed to kill process {}: {}", pid, e);
-                            }
-                        }
-                    }
-                }
+        serde_json::Value::Object(object) if object.len() == 1 && object.get("$file").is_some_and(|file| file.is_string()) => {
+            let file = base_dir.join(object["$file"].as_str().unwrap_or_default());
+            let bytes = fs::read(&file)
+                .map_err(|e| anyhow::anyhow!("Failed to read the input file {}: {}", file.display(), e))?;
+            Ok(serde_json::Value::String(match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(e) => base64::engine::general_purpose::STANDARD.encode(e.into_bytes()),
+            }))
+        }
+        other => Ok(other),
+    }
+}
+
+/// Latency of one run of a benchmark, in milliseconds
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BenchSample {
+    /// Time the server spent on the run, building and executing it
+    pub server_ms: f64,
+    /// Wall-clock time of the run, as seen by the CLI
+    pub total_ms: f64,
+    /// Time the server spent fetching manifests and building the action tree
+    pub build_ms: f64,
+    /// Time the server spent executing the steps
+    pub execute_ms: f64,
+}
+
+/// Aggregate server latency of several runs, in milliseconds
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct LatencyStats {
+    pub runs: usize,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    fn of(samples: &[BenchSample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let totals: Vec<f64> = samples.iter().map(|sample| sample.server_ms).collect();
+        Some(Self {
+            runs: totals.len(),
+            mean_ms: totals.iter().sum::<f64>() / totals.len() as f64,
+            min_ms: totals.iter().cloned().fold(f64::INFINITY, f64::min),
+            max_ms: totals.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// Latencies of a benchmark: the first run is cold, as it populates the cache, the others are warm
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BenchReport {
+    pub samples: Vec<BenchSample>,
+    pub cold: Option<BenchSample>,
+    pub warm: Option<LatencyStats>,
+    pub overall: Option<LatencyStats>,
+}
+
+impl BenchReport {
+    pub fn new(samples: Vec<BenchSample>) -> Self {
+        Self {
+            cold: samples.first().cloned(),
+            warm: samples.get(1..).and_then(LatencyStats::of),
+            overall: LatencyStats::of(&samples),
+            samples,
+        }
+    }
+}
+
+/// Reads the latency of a finished run from its response: the wall-clock time measured by the
+/// caller, and the phase timings reported by the server
+fn bench_sample(response: &serde_json::Value, total: Duration) -> Result<BenchSample> {
+    if response.get("status").and_then(|v| v.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        anyhow::bail!("Run {} failed: {}", response["run_id"].as_str().unwrap_or("?"), error);
+    }
+    let timing = |phase: &str| response["timings"][phase].as_f64().unwrap_or_default();
+    Ok(BenchSample {
+        server_ms: timing("build_ms") + timing("execute_ms"),
+        total_ms: total.as_secs_f64() * 1000.0,
+        build_ms: timing("build_ms"),
+        execute_ms: timing("execute_ms"),
+    })
+}
+
+/// Runs an action once for a benchmark. The run is followed on the NDJSON stream, so its wall-clock
+/// time ends as soon as the server reports its result rather than at the next poll.
+async fn bench_run(client: &reqwest::Client, server_url: &str, payload: &serde_json::Value) -> Result<BenchSample> {
+    let started = std::time::Instant::now();
+    let response = stream_run(client, server_url, payload).await?;
+    bench_sample(&response, started.elapsed())
+}
+
+/// Submits a run to the NDJSON stream endpoint of the server and returns its `result` line
+async fn stream_run(client: &reqwest::Client, server_url: &str, payload: &serde_json::Value) -> Result<serde_json::Value> {
+    let mut response = client
+        .post(format!("{}/api/run/stream", server_url))
+        .json(payload)
+        .send()
+        .await?;
+    let status = response.status();
+    let mut buffer = Vec::new();
+    loop {
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let event: serde_json::Value = match serde_json::from_slice(&line) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            if event.get("type").and_then(|t| t.as_str()) == Some("result") {
+                return Ok(event);
             }
         }
+        match response.chunk().await? {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            None if buffer.is_empty() => break,
+            // The last line may not end with a newline
+            None => buffer.push(b'\n'),
+        }
     }
+    Err(anyhow::anyhow!("Server returned {} for run request without a result", status))
+}
+
+pub async fn cmd_bench(action: String, runs: usize, input_file: Option<String>, json: bool) -> Result<()> {
+    check_dependencies()?;
     
-    #[cfg(windows)]
-    {
-        // Windows: Use tasklist and taskkill commands
-        let output = std::process::Command::new("tasklist")
-            .args(&["/FI", "IMAGENAME eq starthub-server.exe", "/FO", "CSV"])
-            .output()?;
-        
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("Failed to list processes"));
-        }
-        
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        
+    let inputs = match input_file {
+        Some(path) => read_input_file(Path::new(&path))?,
+        None => Vec::new(),
+    };
+    
+    ensure_server_running().await?;
+    
+    let runs = runs.max(1);
+    eprintln!("⏱️  Running {} {} time(s)", action, runs);
+    let client = reqwest::Client::new();
+    let payload = serde_json::json!({ "action": action, "inputs": inputs });
+    let mut samples = Vec::with_capacity(runs);
+    for run in 0..runs {
+        let sample = bench_run(&client, &server_url(), &payload).await?;
+        eprintln!(
+            "  [{}] {} {:.1}ms (build {:.1}ms, execute {:.1}ms, wall-clock {:.1}ms)",
+            run, if run == 0 { "cold" } else { "warm" }, sample.server_ms, sample.build_ms, sample.execute_ms, sample.total_ms
+        );
+        samples.push(sample);
+    }
+    
+    let report = BenchReport::new(samples);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    if let Some(cold) = &report.cold {
+        println!(
+            "❄️  Cold: {:.1}ms (build {:.1}ms, execute {:.1}ms, wall-clock {:.1}ms)",
+            cold.server_ms, cold.build_ms, cold.execute_ms, cold.total_ms
+        );
+    }
+    if let Some(warm) = &report.warm {
+        println!("🔥 Warm: mean {:.1}ms, min {:.1}ms, max {:.1}ms over {} run(s)", warm.mean_ms, warm.min_ms, warm.max_ms, warm.runs);
+    }
+    if let Some(overall) = &report.overall {
+        println!("📊 Overall: mean {:.1}ms over {} run(s)", overall.mean_ms, overall.runs);
+    }
+    Ok(())
+}
+
+pub async fn cmd_list(pattern: Option<String>, json: bool) -> Result<()> {
+    ensure_server_running().await?;
+    
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/api/actions", server_url()))
+        .query(&[("limit", "1000")])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Failed to list actions: {}", response.status()));
+    }
+    let actions: Vec<serde_json::Value> = response.json().await?;
+    let actions = filter_actions(actions, pattern.as_deref());
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&actions)?);
+        return Ok(());
+    }
+    
+    if actions.is_empty() {
+        println!("ℹ️  No actions found");
+        return Ok(());
+    }
+    
+    let rows: Vec<[String; 3]> = actions.iter().map(|action| [
+        action_ref(action),
+        action["latest_version"]["version_number"].as_str().unwrap_or("-").to_string(),
+        action["kind"].as_str().unwrap_or("-").to_string(),
+    ]).collect();
+    let name_width = rows.iter().map(|row| row[0].len()).max().unwrap_or(0).max("ACTION".len());
+    let version_width = rows.iter().map(|row| row[1].len()).max().unwrap_or(0).max("VERSION".len());
+    println!("{:<name_width$}  {:<version_width$}  KIND", "ACTION", "VERSION");
+    for [name, version, kind] in rows {
+        println!("{:<name_width$}  {:<version_width$}  {}", name, version, kind);
+    }
+    
+    Ok(())
+}
+
+pub async fn cmd_info(action: String, json: bool) -> Result<()> {
+    ensure_server_running().await?;
+    
+    let client = reqwest::Client::new();
+    let response: serde_json::Value = client
+        .get(format!("{}/api/manifest", server_url()))
+        .query(&[("action", action.as_str())])
+        .send()
+        .await?
+        .json()
+        .await?;
+    
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Failed to fetch {}: {}", action, error));
+    }
+    
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response["manifest"])?);
+        return Ok(());
+    }
+    for line in format_action_info(&response["manifest"]) {
+        println!("{}", line);
+    }
+    
+    Ok(())
+}
+
+/// Readable description of a manifest, one line per entry
+fn format_action_info(manifest: &serde_json::Value) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{}@{} ({})",
+        manifest["name"].as_str().unwrap_or("?"),
+        manifest["version"].as_str().unwrap_or("?"),
+        manifest["kind"].as_str().unwrap_or("unknown kind")
+    )];
+    if let Some(description) = manifest["description"].as_str().filter(|d| !d.is_empty()) {
+        lines.push(description.to_string());
+    }
+    
+    for (title, ports) in [("Inputs", &manifest["inputs"]), ("Outputs", &manifest["outputs"])] {
+        let ports = ports.as_array().cloned().unwrap_or_default();
+        if ports.is_empty() {
+            continue;
+        }
+        lines.push(format!("{}:", title));
+        for port in ports {
+            let mut line = format!(
+                "  - {}: {}",
+                port["name"].as_str().unwrap_or("?"),
+                port["type"].as_str().unwrap_or("?")
+            );
+            if port["required"].as_bool() == Some(true) {
+                line.push_str(" (required)");
+            }
+            if let Some(description) = port["description"].as_str().filter(|d| !d.is_empty()) {
+                line.push_str(&format!(" - {}", description));
+            }
+            lines.push(line);
+        }
+    }
+    
+    if let Some(types) = manifest["types"].as_object().filter(|t| !t.is_empty()) {
+        lines.push(format!("Types: {}", types.keys().cloned().collect::<Vec<_>>().join(", ")));
+    }
+    if let Some(permissions) = manifest["permissions"].as_object() {
+        for (kind, entries) in permissions {
+            let entries: Vec<&str> = entries.as_array().into_iter().flatten().filter_map(|e| e.as_str()).collect();
+            if !entries.is_empty() {
+                lines.push(format!("Permissions ({}): {}", kind, entries.join(", ")));
+            }
+        }
+    }
+    if let Some(mirrors) = manifest["mirrors"].as_array().filter(|m| !m.is_empty()) {
+        lines.push("Mirrors:".to_string());
+        for mirror in mirrors {
+            lines.push(format!("  - {}", mirror.as_str().unwrap_or("?")));
+        }
+    }
+    
+    lines
+}
+
+/// `namespace/slug` of an action returned by the server
+fn action_ref(action: &serde_json::Value) -> String {
+    format!(
+        "{}/{}",
+        action["namespace"].as_str().unwrap_or(""),
+        action["slug"].as_str().unwrap_or("")
+    )
+}
+
+/// Keeps the actions whose `namespace/slug` matches the glob. A pattern without a
+/// slash is matched against the slug alone.
+fn filter_actions(actions: Vec<serde_json::Value>, pattern: Option<&str>) -> Vec<serde_json::Value> {
+    let Some(pattern) = pattern else {
+        return actions;
+    };
+    actions.into_iter().filter(|action| {
+        if pattern.contains('/') {
+            glob_match(pattern, &action_ref(action))
+        } else {
+            glob_match(pattern, action["slug"].as_str().unwrap_or(""))
+        }
+    }).collect()
+}
+
+/// Matches `text` against a glob where `*` is any run of characters and `?` is one character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at, for backtracking
+    let mut backtrack: Option<(usize, usize)> = None;
+    
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub async fn cmd_fmt(manifest: Option<String>, check: bool, stdout: bool) -> Result<()> {
+    let path = manifest
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::PathBuf::from(LOCAL_MANIFEST_FILENAME));
+    let original = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let formatted = format_manifest_source(&original)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+    
+    if check {
+        if formatted != original {
+            anyhow::bail!("{} is not formatted, run `starthub fmt` to fix it", path.display());
+        }
+        return Ok(());
+    }
+    if stdout {
+        print!("{}", formatted);
+    } else if formatted != original {
+        fs::write(&path, formatted)?;
+        println!("✅ Formatted {}", path.display());
+    }
+    
+    Ok(())
+}
+
+/// Top-level manifest fields that are left out when empty
+const OPTIONAL_MANIFEST_FIELDS: [&str; 8] = ["description", "image", "types", "steps", "wires", "export", "mirrors", "permissions"];
+
+/// Normalized text of a manifest: keys sorted, empty optional fields dropped and
+/// step inputs in their direct form, pretty-printed with a trailing newline
+fn format_manifest_source(source: &str) -> Result<String> {
+    let mut manifest: serde_json::Value = serde_json::from_str(source)?;
+    normalize_manifest(&mut manifest);
+    // Objects keep their keys sorted, so serializing orders them
+    Ok(format!("{}\n", serde_json::to_string_pretty(&manifest)?))
+}
+
+fn normalize_manifest(manifest: &mut serde_json::Value) {
+    let Some(fields) = manifest.as_object_mut() else {
+        return;
+    };
+    fields.retain(|key, value| !(OPTIONAL_MANIFEST_FIELDS.contains(&key.as_str()) && is_empty_value(value)));
+    
+    // Steps are keyed by name, or listed by older manifests
+    let steps: Vec<&mut serde_json::Value> = match fields.get_mut("steps") {
+        Some(serde_json::Value::Object(steps)) => steps.values_mut().collect(),
+        Some(serde_json::Value::Array(steps)) => steps.iter_mut().collect(),
+        _ => Vec::new(),
+    };
+    for step in steps {
+        let Some(inputs) = step.get_mut("inputs").and_then(|inputs| inputs.as_array_mut()) else {
+            continue;
+        };
+        // Old form: { "value": ... } around the input
+        for input in inputs.iter_mut() {
+            if is_value_wrapper(input) {
+                *input = input["value"].take();
+            }
+        }
+    }
+}
+
+fn is_empty_value(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::String(s) => s.is_empty(),
+        serde_json::Value::Array(items) => items.is_empty(),
+        serde_json::Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// How serious a problem found in a manifest is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Worth fixing, but the manifest still works
+    Warning,
+    /// The manifest can't be run or published as is
+    Error,
+}
+
+/// A problem found in a manifest by `lint` or `validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn warning(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, message: message.into() }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, message: message.into() }
+    }
+}
+
+/// Prints the findings about a manifest as a numbered list. Fails, and so exits with code 1, when
+/// there is an error, or any finding at all with `fail_on_warning`.
+fn report_findings(path: &Path, findings: &[Finding], fail_on_warning: bool) -> Result<()> {
+    if findings.is_empty() {
+        println!("✅ {} has no problems", path.display());
+        return Ok(());
+    }
+    for (index, finding) in findings.iter().enumerate() {
+        let label = match finding.severity {
+            Severity::Warning => "⚠️  warning",
+            Severity::Error => "❌ error",
+        };
+        println!("{}. {}: {}", index + 1, label, finding.message);
+    }
+    let errors = findings.iter().filter(|finding| finding.severity == Severity::Error).count();
+    let warnings = findings.len() - errors;
+    if errors > 0 {
+        anyhow::bail!("{} has {} error(s) and {} warning(s)", path.display(), errors, warnings);
+    }
+    if fail_on_warning {
+        anyhow::bail!("{} has {} warning(s), failing because of --fail-on-warning", path.display(), warnings);
+    }
+    Ok(())
+}
+
+/// Checks a manifest without fetching anything: missing or unknown fields are errors, while unused
+/// inputs, the old value-wrapped step input form and unformatted sources are warnings
+fn lint_manifest_source(source: &str) -> Vec<Finding> {
+    let manifest: serde_json::Value = match serde_json::from_str(source) {
+        Ok(manifest) => manifest,
+        Err(e) => return vec![Finding::error(format!("the manifest isn't valid JSON: {}", e))],
+    };
+    let Some(fields) = manifest.as_object() else {
+        return vec![Finding::error("the manifest must be a JSON object")];
+    };
+    
+    let mut findings = Vec::new();
+    for field in ["name", "version"] {
+        if fields.get(field).and_then(|value| value.as_str()).is_none_or(|value| value.is_empty()) {
+            findings.push(Finding::error(format!("`{}` is missing", field)));
+        }
+    }
+    match fields.get("kind").and_then(|kind| kind.as_str()) {
+        Some("composition") | Some("wasm") | Some("docker") => {}
+        Some(kind) => findings.push(Finding::error(format!("unknown kind '{}', expected composition, wasm or docker", kind))),
+        None => findings.push(Finding::error("`kind` is missing")),
+    }
+    
+    let steps: Vec<(String, &serde_json::Value)> = match fields.get("steps") {
+        Some(serde_json::Value::Object(steps)) => steps.iter().map(|(name, step)| (name.clone(), step)).collect(),
+        Some(serde_json::Value::Array(steps)) => steps.iter().enumerate().map(|(index, step)| (format!("#{}", index), step)).collect(),
+        _ => Vec::new(),
+    };
+    for (name, step) in &steps {
+        if step.get("uses").and_then(|uses| uses.as_str()).is_none_or(|uses| uses.is_empty()) {
+            findings.push(Finding::error(format!("step '{}' has no `uses`", name)));
+        }
+        let wrapped = step.get("inputs").and_then(|inputs| inputs.as_array())
+            .is_some_and(|inputs| inputs.iter().any(is_value_wrapper));
+        if wrapped {
+            findings.push(Finding::warning(format!(
+                "step '{}' wraps its inputs in {{\"value\": ...}}, pass the values directly (`starthub fmt` converts them)", name
+            )));
+        }
+    }
+    
+    // Inputs of a composition are consumed by its steps and outputs
+    if fields.get("kind").and_then(|kind| kind.as_str()) == Some("composition") {
+        let consumers = serde_json::to_string(&(fields.get("steps"), fields.get("outputs"))).unwrap_or_default();
+        let inputs = fields.get("inputs").and_then(|inputs| inputs.as_array()).cloned().unwrap_or_default();
+        for (index, input) in inputs.iter().enumerate() {
+            if !consumers.contains(&format!("{{{{inputs[{}]", index)) && !consumers.contains("{{inputs}}") {
+                let name = input.get("name").and_then(|name| name.as_str()).unwrap_or("?");
+                findings.push(Finding::warning(format!("input '{}' (#{}) isn't used by any step or output", name, index)));
+            }
+        }
+    }
+    
+    if format_manifest_source(source).is_ok_and(|formatted| formatted != source) {
+        findings.push(Finding::warning("the manifest isn't formatted, run `starthub fmt` to fix it"));
+    }
+    findings
+}
+
+pub async fn cmd_lint(manifest: Option<String>, fail_on_warning: bool) -> Result<()> {
+    let path = manifest
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::PathBuf::from(LOCAL_MANIFEST_FILENAME));
+    let source = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    report_findings(&path, &lint_manifest_source(&source), fail_on_warning)
+}
+
+/// Findings of the server's validation of a manifest, all of them errors
+fn validation_findings(response: &serde_json::Value) -> Result<Vec<Finding>> {
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        anyhow::bail!("Failed to validate the manifest: {}", error);
+    }
+    Ok(response["problems"].as_array()
+        .map(|problems| problems.iter().filter_map(|problem| problem.as_str()).map(Finding::error).collect())
+        .unwrap_or_default())
+}
+
+pub async fn cmd_validate(manifest: Option<String>, check_artifacts: bool, fail_on_warning: bool) -> Result<()> {
+    let path = manifest
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::PathBuf::from(LOCAL_MANIFEST_FILENAME));
+    let source = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    
+    ensure_server_running().await?;
+    
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/validate", server_url()))
+        .json(&serde_json::json!({
+            "manifest": path.display().to_string(),
+            "source": source,
+            "check_artifacts": check_artifacts,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    report_findings(&path, &validation_findings(&response)?, fail_on_warning)
+}
+
+pub async fn cmd_schema(action: String) -> Result<()> {
+    ensure_server_running().await?;
+    
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/schema", server_url()))
+        .json(&serde_json::json!({ "action": action }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Failed to export the input schema of {}: {}", action, error));
+    }
+    
+    println!("{}", serde_json::to_string_pretty(&response["schema"])?);
+    Ok(())
+}
+
+pub async fn cmd_lock(action: Option<String>, out: String) -> Result<()> {
+    let action = resolve_run_action(action, &std::env::current_dir()?)?;
+    
+    ensure_server_running().await?;
+    
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/lock", server_url()))
+        .json(&serde_json::json!({ "action": action }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Failed to lock {}: {}", action, error));
+    }
+    
+    let lockfile = &response["lockfile"];
+    fs::write(&out, format!("{}\n", serde_json::to_string_pretty(lockfile)?))?;
+    let count = lockfile["actions"].as_object().map(|actions| actions.len()).unwrap_or(0);
+    println!("🔒 Locked {} action(s) of {} in {}", count, action, out);
+    Ok(())
+}
+
+/// Global flags given on the command line
+#[derive(Debug, Default)]
+pub struct GlobalFlags {
+    pub verbose: bool,
+    pub cache_dir: Option<String>,
+    pub proxy: Option<String>,
+    pub mirror_strategy: Option<String>,
+}
+
+/// Where an effective setting comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    /// The server config file
+    File,
+    Env,
+    Flag,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File => write!(f, "file"),
+            Self::Env => write!(f, "env"),
+            Self::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+/// A setting that took effect, and where it came from
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub key: &'static str,
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+/// Resolves the settings of the CLI and of the server it starts: a flag beats its environment
+/// variable, which beats the default. `env` looks up environment variables.
+pub fn effective_config(flags: &GlobalFlags, env: impl Fn(&str) -> Option<String>) -> Vec<ConfigEntry> {
+    // Settings the server also reads from its config file are looked up there under their key
+    let file = server_config_file(&env);
+    let setting = |key: &'static str, flag: Option<&String>, env_name: &str, default: String| {
+        let file_value = match &file[key] {
+            serde_json::Value::String(value) => Some(value.clone()),
+            serde_json::Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        };
+        let (value, source) = match (flag, env(env_name).filter(|value| !value.trim().is_empty()), file_value) {
+            (Some(flag), _, _) => (flag.clone(), ConfigSource::Flag),
+            (None, Some(value), _) => (value, ConfigSource::Env),
+            (None, None, Some(value)) => (value, ConfigSource::File),
+            (None, None, None) => (default, ConfigSource::Default),
+        };
+        ConfigEntry { key, value, source }
+    };
+    let default_cache_dir = dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("starthub/oci")
+        .to_string_lossy()
+        .to_string();
+    // STARTHUB_LOG takes precedence over --verbose
+    let log_filter = match (env("STARTHUB_LOG"), flags.verbose) {
+        (Some(filter), _) => ConfigEntry { key: "log_filter", value: filter, source: ConfigSource::Env },
+        (None, true) => ConfigEntry { key: "log_filter", value: "info".to_string(), source: ConfigSource::Flag },
+        (None, false) => ConfigEntry { key: "log_filter", value: "warn".to_string(), source: ConfigSource::Default },
+    };
+
+    let (bind, bind_source) = server_bind(&env);
+
+    vec![
+        setting("api_base", None, API_BASE_ENV, crate::config::STARTHUB_API_BASE.to_string()),
+        ConfigEntry { key: "server_url", value: base_url(&bind), source: bind_source },
+        setting("cache_dir", flags.cache_dir.as_ref(), CACHE_DIR_ENV, default_cache_dir),
+        setting("workers", None, "STARTHUB_WORKERS", "4".to_string()),
+        setting("proxy", flags.proxy.as_ref(), PROXY_ENV, "none (HTTP_PROXY/HTTPS_PROXY honored)".to_string()),
+        setting("mirror_strategy", flags.mirror_strategy.as_ref(), MIRROR_STRATEGY_ENV, "order".to_string()),
+        setting("user_agent", None, USER_AGENT_ENV, format!("starthub-cli/{}", env!("CARGO_PKG_VERSION"))),
+        log_filter,
+        setting("allowed_namespaces", None, "STARTHUB_ALLOWED_NAMESPACES", "any".to_string()),
+        setting("manifest_path_template", None, "STARTHUB_MANIFEST_PATH_TEMPLATE", "registry default".to_string()),
+        setting("wasm_opt_level", None, "STARTHUB_WASM_OPT_LEVEL", "speed".to_string()),
+    ]
+}
+
+pub async fn cmd_config_show(flags: &GlobalFlags, json: bool) -> Result<()> {
+    let entries = effective_config(flags, |name| std::env::var(name).ok());
+    if json {
+        let entries: Vec<serde_json::Value> = entries.iter()
+            .map(|entry| serde_json::json!({
+                "key": entry.key,
+                "value": entry.value,
+                "source": entry.source.to_string(),
+            }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    
+    let width = entries.iter().map(|entry| entry.key.len()).max().unwrap_or(0);
+    for entry in &entries {
+        println!("{:width$}  {}  ({})", entry.key, entry.value, entry.source, width = width);
+    }
+    Ok(())
+}
+
+pub async fn cmd_prune(older_than: String, runs: bool, artifacts: bool, yes: bool) -> Result<()> {
+    let older_than = parse_duration(&older_than)?;
+    
+    ensure_server_running().await?;
+    
+    // Without --runs or --artifacts, prune both
+    let prune_all = !runs && !artifacts;
+    let client = reqwest::Client::new();
+    let response: serde_json::Value = client
+        .post(format!("{}/api/prune", server_url()))
+        .json(&serde_json::json!({
+            "older_than_secs": older_than.as_secs(),
+            "runs": runs || prune_all,
+            "artifacts": artifacts || prune_all,
+            "dry_run": !yes,
+        }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    
+    if response.get("status").and_then(|s| s.as_str()) != Some("success") {
+        let error = response.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(anyhow::anyhow!("Prune failed: {}", error));
+    }
+    
+    let report = &response["report"];
+    let removed_runs = report["runs"].as_u64().unwrap_or(0);
+    let removed_artifacts = report["artifacts"].as_u64().unwrap_or(0);
+    let freed = report["bytes"].as_u64().unwrap_or(0);
+    if yes {
+        println!("🧹 Removed {} run(s) and {} artifact(s), freeing {} bytes", removed_runs, removed_artifacts, freed);
+    } else {
+        println!("🔍 Would remove {} run(s) and {} artifact(s), freeing {} bytes", removed_runs, removed_artifacts, freed);
+        println!("💡 Re-run with --yes to delete them");
+    }
+    
+    Ok(())
+}
+
+/// Parses a duration such as 30d, 12h, 45m or 90s
+fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration '{}', expected e.g. 30d, 12h, 45m or 90s", value))?;
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => anyhow::bail!("Invalid duration unit in '{}', expected one of s, m, h, d, w", value),
+    };
+    let seconds = amount.checked_mul(unit_secs)
+        .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too long", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Resolves the action to run: the given one, or else the local manifest of the project in `dir`
+fn resolve_run_action(action: Option<String>, dir: &Path) -> Result<String> {
+    if let Some(action) = action {
+        return Ok(action);
+    }
+    
+    let manifest_path = dir.join(LOCAL_MANIFEST_FILENAME);
+    if !manifest_path.exists() {
+        anyhow::bail!(
+            "No action given and no {} found in {}. Usage: starthub run <action>",
+            LOCAL_MANIFEST_FILENAME,
+            dir.display()
+        );
+    }
+    
+    Ok(format!("file://{}", manifest_path.canonicalize()?.display()))
+}
+
+/// Reads the outputs of the steps mocked with `--mock step=file.json`. Each file holds
+/// the array of output values of its step.
+fn read_mocks(mocks: &[String], dir: &Path) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut outputs = serde_json::Map::new();
+    for mock in mocks {
+        let (step, file) = mock.split_once('=')
+            .filter(|(step, file)| !step.is_empty() && !file.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid mock '{}', expected STEP=FILE", mock))?;
+        let path = dir.join(file);
+        let values = read_input_file(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read the mocked outputs of step '{}': {}", step, e))?;
+        outputs.insert(step.to_string(), serde_json::Value::Array(values));
+    }
+    Ok(outputs)
+}
+
+/// Submits a run to the local server and returns its JSON response
+async fn post_run(payload: serde_json::Value) -> Result<serde_json::Value> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/api/run", server_url()))
+        .json(&payload)
+        .send()
+        .await?;
+    
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Server returned {} for run request", response.status()));
+    }
+    
+    let response: serde_json::Value = response.json().await?;
+    if response.get("status").and_then(|v| v.as_str()) != Some("accepted") {
+        return Ok(response);
+    }
+    
+    // The run is queued on the server: wait for it to finish
+    let run_id = response.get("run_id").and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Server accepted the run without a run id"))?;
+    eprintln!("⏳ Run {} queued", run_id);
+    wait_for_run(&client, &server_url(), run_id, run_wait_timeout()).await
+}
+
+/// How long to wait for a queued run: STARTHUB_RUN_WAIT_TIMEOUT seconds, or else an hour
+fn run_wait_timeout() -> Duration {
+    std::env::var(RUN_WAIT_TIMEOUT_ENV)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RUN_WAIT_TIMEOUT)
+}
+
+/// Polls a queued run until it settles, and fails if the server can't report it or it is still
+/// queued or running after `timeout`
+async fn wait_for_run(client: &reqwest::Client, server_url: &str, run_id: &str, timeout: Duration) -> Result<serde_json::Value> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        sleep(Duration::from_millis(500)).await;
+        let response = client
+            .get(format!("{}/api/runs/{}", server_url, run_id))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("Run {} is unknown to the server", run_id));
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Server returned {} for run {}", response.status(), run_id));
+        }
+        let run: serde_json::Value = response.json().await?;
+        match run.get("status").and_then(|v| v.as_str()) {
+            Some("queued") | Some("running") if tokio::time::Instant::now() >= deadline => {
+                return Err(anyhow::anyhow!(
+                    "Run {} did not finish within {}s, it is still running on the server (see {})",
+                    run_id, timeout.as_secs(), RUN_WAIT_TIMEOUT_ENV
+                ));
+            }
+            Some("queued") | Some("running") => continue,
+            _ => return Ok(run),
+        }
+    }
+}
+
+/// Prints the response of a run, or writes it to `output_file`, and fails if the run did not succeed.
+/// Everything but the response goes to stderr.
+fn print_run_response(response: &serde_json::Value, output_file: Option<&str>, compact: bool) -> Result<()> {
+    let report = format_input_report(response);
+    if !report.is_empty() {
+        eprintln!("🔎 Inputs:");
+        for line in report {
+            eprintln!("  {}", line);
+        }
+    }
+    match output_file {
+        Some(path) if path != "-" => {
+            write_run_result(response, Path::new(path), compact)?;
+            eprintln!("📄 Result written to {}", path);
+        }
+        _ => println!("{}", format_json(response, compact)?),
+    }
+    
+    if response.get("status").and_then(|v| v.as_str()) != Some("success") {
+        let run_id = response.get("run_id").and_then(|v| v.as_str()).unwrap_or("unknown");
+        return Err(anyhow::anyhow!(
+            "Run {} failed. Resume it with: starthub run <action> --resume {}",
+            run_id, run_id
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Submits one run per payload, at most `concurrency` at a time, and returns an entry per
+/// combination, in order: its inputs, run id, status and result or error
+async fn run_matrix<F, Fut>(payloads: Vec<serde_json::Value>, concurrency: usize, run: F) -> Vec<serde_json::Value>
+where
+    F: Fn(serde_json::Value) -> Fut,
+    Fut: std::future::Future<Output = Result<serde_json::Value>> + Send + 'static,
+{
+    let mut entries = vec![serde_json::Value::Null; payloads.len()];
+    let mut running = tokio::task::JoinSet::new();
+    let mut pending = payloads.into_iter().enumerate();
+    loop {
+        // Keep `concurrency` runs in flight
+        while running.len() < concurrency.max(1) {
+            let Some((index, payload)) = pending.next() else {
+                break;
+            };
+            let inputs = payload["inputs"].clone();
+            let submitted = run(payload);
+            running.spawn(async move { (index, inputs, submitted.await) });
+        }
+        let Some(finished) = running.join_next().await else {
+            break;
+        };
+        // A panicked submission leaves its entry null, which is reported as a failure
+        let Ok((index, inputs, response)) = finished else {
+            continue;
+        };
+        entries[index] = match response {
+            Ok(response) => serde_json::json!({
+                "inputs": inputs,
+                "run_id": response["run_id"],
+                "status": response["status"],
+                "result": response["result"],
+                "error": response["error"],
+            }),
+            Err(e) => serde_json::json!({
+                "inputs": inputs,
+                "status": "error",
+                "error": e.to_string(),
+            }),
+        };
+    }
+    entries
+}
+
+/// Reports every combination of a matrix run on stderr, then prints or writes the aggregated results.
+/// Fails if any combination did not succeed.
+fn print_matrix_results(results: &[serde_json::Value], output_file: Option<&str>, compact: bool) -> Result<()> {
+    for (index, entry) in results.iter().enumerate() {
+        if entry["status"] == "success" {
+            eprintln!("✅ [{}] {} succeeded (run {})", index, entry["inputs"], entry["run_id"].as_str().unwrap_or("?"));
+        } else {
+            eprintln!("❌ [{}] {} failed: {}", index, entry["inputs"], entry["error"].as_str().unwrap_or("unknown error"));
+        }
+    }
+    let aggregated = serde_json::Value::Array(results.to_vec());
+    match output_file {
+        Some(path) if path != "-" => {
+            write_run_result(&aggregated, Path::new(path), compact)?;
+            eprintln!("📄 Results written to {}", path);
+        }
+        _ => println!("{}", format_json(&aggregated, compact)?),
+    }
+    
+    let failed = results.iter().filter(|entry| entry["status"] != "success").count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} combination(s) failed", failed, results.len());
+    }
+    Ok(())
+}
+
+/// Writes the response of a run to `path`, creating its parent directories
+fn write_run_result(response: &serde_json::Value, path: &Path, compact: bool) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{}\n", format_json(response, compact)?))
+        .map_err(|e| anyhow::anyhow!("Failed to write the result to {}: {}", path.display(), e))
+}
+
+/// Serializes a JSON result, on a single line when `compact`, pretty-printed otherwise
+fn format_json(value: &serde_json::Value, compact: bool) -> Result<String> {
+    Ok(if compact { serde_json::to_string(value)? } else { serde_json::to_string_pretty(value)? })
+}
+
+/// One line per root input of a run response: its name, declared type, raw value and cast value
+fn format_input_report(response: &serde_json::Value) -> Vec<String> {
+    let empty = Vec::new();
+    response.get("input_report")
+        .and_then(|r| r.as_array())
+        .unwrap_or(&empty)
+        .iter()
+        .map(|input| format!(
+            "{} ({}): {} → {}",
+            input["name"].as_str().unwrap_or(""),
+            input["type"].as_str().unwrap_or("?"),
+            input["raw"],
+            input["cast"]
+        ))
+        .collect()
+}
+
+/// Health endpoint of a server bound to the given address
+fn health_url(bind: &str) -> String {
+    format!("{}/healthz", base_url(bind))
+}
+
+/// URL the server listening on an address is reached at
+fn base_url(bind: &str) -> String {
+    // A wildcard bind is reachable on loopback
+    let host = match bind.strip_prefix("0.0.0.0:") {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => bind.to_string(),
+    };
+    format!("http://{}", host)
+}
+
+/// Polls the health endpoint until it responds successfully or the timeout elapses
+async fn wait_for_server(url: &str, timeout: Duration) -> Result<()> {
+    let client = reqwest::Client::new();
+    let deadline = tokio::time::Instant::now() + timeout;
+    
+    loop {
+        let response = client
+            .get(url)
+            .timeout(Duration::from_millis(500))
+            .send()
+            .await;
+        if matches!(response, Ok(ref resp) if resp.status().is_success()) {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "Server did not become healthy at {} within {}s; check 'starthub logs'",
+                url,
+                timeout.as_secs()
+            ));
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+async fn check_server_running() -> Result<bool> {
+    // Try to make a request to the server to see if it's running
+    let client = reqwest::Client::new();
+    let response = client
+        .get(server_url())
+        .timeout(Duration::from_millis(1000))
+        .send()
+        .await;
+    
+    match response {
+        Ok(resp) => Ok(resp.status().is_success()),
+        Err(_) => Ok(false),
+    }
+}
+
+async fn start_server_process_detached(bind: Option<&str>, api_base: Option<&str>) -> Result<std::process::Child> {
+    // Try to find the starthub-server binary
+    let server_binary = if cfg!(target_os = "windows") {
+        "starthub-server.exe"
+    } else {
+        "starthub-server"
+    };
+    
+    // First try to find it in the current directory or PATH
+    let server_path = which::which(server_binary)
+        .or_else(|_| {
+            // Try relative to the current binary
+            let current_exe = std::env::current_exe()?;
+            let current_dir = current_exe.parent().unwrap();
+            Ok::<std::path::PathBuf, anyhow::Error>(current_dir.join(server_binary))
+        })
+        .or_else(|_| {
+            // Try in the target/release directory
+            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("release").join(server_binary))
+        })
+        .or_else(|_| {
+            // Try in the target/debug directory for development
+            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("debug").join(server_binary))
+        })?;
+    
+    if !server_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Server binary not found. Please build the server first with: cargo build --bin starthub-server"
+        ));
+    }
+    
+    println!("🚀 Starting server process: {:?}", server_path);
+    
+    // Get log file path
+    let log_file = get_server_log_file()?;
+    let log_file_path = log_file.as_path();
+    
+    // Open log file for writing (append mode)
+    let log_file_handle = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file_path)?;
+    
+    // Start the server process in detached mode with stdout/stderr redirected to log file
+    let mut command = std::process::Command::new(&server_path);
+    command.env(USER_AGENT_ENV, user_agent());
+    if let Some(bind) = bind {
+        command.arg("--bind").arg(bind);
+    }
+    if let Some(api_base) = api_base {
+        command.arg("--api-base").arg(api_base);
+    }
+    let child = command
+        .stdout(Stdio::from(log_file_handle.try_clone()?))
+        .stderr(Stdio::from(log_file_handle))
+        .spawn()?;
+    
+    println!("📝 Server logs will be written to: {:?}", log_file_path);
+    println!("💡 Use 'starthub logs' to view logs");
+    
+    Ok(child)
+}
+
+async fn start_server_process() -> Result<Option<tokio::process::Child>> {
+    // Try to find the starthub-server binary
+    let server_binary = if cfg!(target_os = "windows") {
+        "starthub-server.exe"
+    } else {
+        "starthub-server"
+    };
+    
+    // First try to find it in the current directory or PATH
+    let server_path = which::which(server_binary)
+        .or_else(|_| {
+            // Try relative to the current binary
+            let current_exe = std::env::current_exe()?;
+            let current_dir = current_exe.parent().unwrap();
+            Ok::<std::path::PathBuf, anyhow::Error>(current_dir.join(server_binary))
+        })
+        .or_else(|_| {
+            // Try in the target/release directory
+            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("release").join(server_binary))
+        })
+        .or_else(|_| {
+            // Try in the target/debug directory for development
+            Ok::<std::path::PathBuf, anyhow::Error>(std::env::current_dir()?.join("target").join("debug").join(server_binary))
+        })?;
+    
+    if !server_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Server binary not found. Please build the server first with: cargo build --bin starthub-server"
+        ));
+    }
+    
+    eprintln!("🚀 Starting server process: {:?}", server_path);
+    
+    // Start the server process, which reads its address from STARTHUB_BIND or its config file too.
+    // Its logs go to stderr as well.
+    let child = tokio::process::Command::new(&server_path)
+        .env(USER_AGENT_ENV, user_agent())
+        .stdout(std::io::stderr())
+        .spawn()?;
+    
+    Ok(Some(child))
+}
+
+async fn kill_starthub_server_processes() -> Result<usize> {
+    let mut killed_count = 0;
+    
+    #[cfg(unix)]
+    {
+        // Unix/Linux/macOS: Use ps and kill commands
+        let output = std::process::Command::new("ps")
+            .args(&["-ax", "-o", "pid,comm"])
+            .output()?;
+        
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("Failed to list processes"));
+        }
+        
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        
+        for line in output_str.lines() {
+            if line.contains("starthub-server") {
+                let parts: Vec<&str> = line.trim().split_whitespace().collect();
+                if let Some(pid_str) = parts.first() {
+                    if let Ok(pid) = pid_str.parse::<u32>() {
+                        println!("🔍 Found starthub-server process: PID {}", pid);
+                        
+                        // Try to kill the process gracefully first
+                        let kill_result = std::process::Command::new("kill")
+                            .arg("-TERM")
+                            .arg(pid.to_string())
+                            .output();
+                        
+                        match kill_result {
+                            Ok(output) => {
+                                if output.status.success() {
+                                    println!("✅ Killed process {}", pid);
+                                    killed_count += 1;
+                                } else {
+                                    println!("⚠️  Failed to kill process {}: {}", pid, String::from_utf8_lossy(&output.stderr));
+                                }
+                            }
+                            Err(e) => {
+                                println!("⚠️  Failed to kill process {}: {}", pid, e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    #[cfg(windows)]
+    {
+        // Windows: Use tasklist and taskkill commands
+        let output = std::process::Command::new("tasklist")
+            .args(&["/FI", "IMAGENAME eq starthub-server.exe", "/FO", "CSV"])
+            .output()?;
+        
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("Failed to list processes"));
+        }
+        
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        
         for line in output_str.lines() {
             if line.contains("starthub-server.exe") {
                 let parts: Vec<&str> = line.split(',').collect();
@@ -907,7 +2374,7 @@ pub async fn cmd_status() -> Result<()> {
     // Check if server is responding to HTTP requests
     let server_running = check_server_running().await?;
     if server_running {
-        println!("🌐 Server is responding at {}", LOCAL_SERVER_URL);
+        println!("🌐 Server is responding at {}", server_url());
     } else {
         println!("⚠️  Server process is running but not responding to HTTP requests");
         println!("💡 The server may still be starting up, or there may be an issue");
@@ -1024,3 +2491,577 @@ async fn get_action_id(api_base: &str, action_name: &str, namespace: &str, acces
     
     Err(anyhow::anyhow!("Action not found"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves 200 on every connection to the given port, once `delay` has passed
+    fn spawn_delayed_health_server(port: u16, delay: std::time::Duration) {
+        std::thread::spawn(move || {
+            use std::io::Read;
+            std::thread::sleep(delay);
+            let listener = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer);
+                let body = r#"{"status":"ok"}"#;
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            }
+        });
+    }
+
+    /// Answers every request with the given status and JSON body, and returns the server URL
+    fn spawn_json_server(status: &'static str, body: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            use std::io::Read;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+            }
+        });
+        url
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_server_returns_once_reachable() {
+        let port = free_port();
+        let delay = Duration::from_millis(300);
+        spawn_delayed_health_server(port, delay);
+
+        let started = std::time::Instant::now();
+        wait_for_server(&health_url(&format!("127.0.0.1:{}", port)), Duration::from_secs(5)).await.unwrap();
+        assert!(started.elapsed() >= delay);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_server_times_out() {
+        let port = free_port();
+        let result = wait_for_server(&health_url(&format!("127.0.0.1:{}", port)), Duration::from_millis(300)).await;
+        assert!(result.unwrap_err().to_string().contains("did not become healthy"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_run_settles_times_out_and_checks_the_status() {
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(5);
+
+        let url = spawn_json_server("200 OK", r#"{"status":"success","run_id":"run-a"}"#);
+        assert_eq!(wait_for_run(&client, &url, "run-a", timeout).await.unwrap()["status"], "success");
+
+        let url = spawn_json_server("200 OK", r#"{"status":"running","run_id":"run-a"}"#);
+        let error = wait_for_run(&client, &url, "run-a", Duration::ZERO).await.unwrap_err();
+        assert!(error.to_string().contains("did not finish within 0s"), "error was: {}", error);
+
+        let url = spawn_json_server("404 Not Found", r#"{"status":"error"}"#);
+        let error = wait_for_run(&client, &url, "run-a", timeout).await.unwrap_err();
+        assert!(error.to_string().contains("unknown to the server"), "error was: {}", error);
+
+        let url = spawn_json_server("500 Internal Server Error", r#"{"status":"error"}"#);
+        let error = wait_for_run(&client, &url, "run-a", timeout).await.unwrap_err();
+        assert!(error.to_string().contains("500"), "error was: {}", error);
+    }
+
+    #[test]
+    fn test_health_url() {
+        assert_eq!(health_url("127.0.0.1:3000"), "http://127.0.0.1:3000/healthz");
+        assert_eq!(health_url("0.0.0.0:8080"), "http://127.0.0.1:8080/healthz");
+    }
+
+    #[test]
+    fn test_read_input_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("in.json");
+
+        fs::write(&path, r#"[1, "two", {"three": 3}]"#).unwrap();
+        assert_eq!(read_input_file(&path).unwrap(), vec![serde_json::json!(1), serde_json::json!("two"), serde_json::json!({"three": 3})]);
+
+        fs::write(&path, r#"{"not": "an array"}"#).unwrap();
+        assert!(read_input_file(&path).unwrap_err().to_string().contains("JSON array"));
+
+        // File references are replaced by their content, relative ones resolved against the directory of the input file
+        fs::write(dir.path().join("payload.bin"), [0xff, 0x00, 0xfe]).unwrap();
+        let text = dir.path().join("big.txt");
+        fs::write(&text, "a large payload").unwrap();
+        fs::write(&path, format!(r#"[{{"$file": "payload.bin"}}, [{{"$file": {:?}}}], {{"$file": "x", "other": 1}}]"#, text.display().to_string())).unwrap();
+        assert_eq!(read_input_file(&path).unwrap(), vec![
+            serde_json::json!("/wD+"),
+            serde_json::json!(["a large payload"]),
+            serde_json::json!({"$file": "x", "other": 1}),
+        ]);
+
+        fs::write(&path, r#"[{"$file": "missing.txt"}]"#).unwrap();
+        assert!(read_input_file(&path).unwrap_err().to_string().starts_with("Failed to read the input file"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("starthubhq/*", "starthubhq/http-get"));
+        assert!(glob_match("*-get", "http-get"));
+        assert!(glob_match("http-???", "http-get"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("starthubhq/*", "acme/http-get"));
+        assert!(!glob_match("http-?", "http-get"));
+    }
+
+    #[test]
+    fn test_filter_actions_by_pattern() {
+        let actions = vec![
+            serde_json::json!({"namespace": "starthubhq", "slug": "http-get"}),
+            serde_json::json!({"namespace": "starthubhq", "slug": "do-droplet"}),
+            serde_json::json!({"namespace": "acme", "slug": "http-get"}),
+        ];
+
+        let refs = |pattern: Option<&str>| -> Vec<String> {
+            filter_actions(actions.clone(), pattern).iter().map(action_ref).collect()
+        };
+        assert_eq!(refs(None).len(), 3);
+        assert_eq!(refs(Some("starthubhq/*")), vec!["starthubhq/http-get", "starthubhq/do-droplet"]);
+        assert_eq!(refs(Some("http-*")), vec!["starthubhq/http-get", "acme/http-get"]);
+        assert_eq!(refs(Some("acme/do-*")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_format_input_report() {
+        let response = serde_json::json!({
+            "status": "success",
+            "input_report": [
+                { "name": "port", "type": "number", "raw": "8080", "cast": 8080.0 }
+            ]
+        });
+        assert_eq!(format_input_report(&response), vec!["port (number): \"8080\" → 8080.0"]);
+        assert!(format_input_report(&serde_json::json!({ "status": "success" })).is_empty());
+    }
+
+    #[test]
+    fn test_format_action_info() {
+        let manifest = serde_json::json!({
+            "name": "get-weather-by-location-name",
+            "version": "0.0.1",
+            "kind": "composition",
+            "manifest_version": 1,
+            "repository": "github.com/tgirotto/get-weather-by-location-name",
+            "license": "MIT",
+            "inputs": [
+                { "name": "weather_config", "type": "WeatherConfig", "required": true, "description": "Location and API key" }
+            ],
+            "outputs": [
+                { "name": "response", "type": "WeatherResponse" }
+            ],
+            "types": { "WeatherConfig": {}, "WeatherResponse": {} },
+            "permissions": { "net": ["api.openweathermap.org"] }
+        });
+        
+        let lines = format_action_info(&manifest);
+        assert_eq!(lines[0], "get-weather-by-location-name@0.0.1 (composition)");
+        assert!(lines.contains(&"Inputs:".to_string()));
+        assert!(lines.contains(&"  - weather_config: WeatherConfig (required) - Location and API key".to_string()));
+        assert!(lines.contains(&"  - response: WeatherResponse".to_string()));
+        assert!(lines.contains(&"Types: WeatherConfig, WeatherResponse".to_string()));
+        assert!(lines.contains(&"Permissions (net): api.openweathermap.org".to_string()));
+    }
+
+    #[test]
+    fn test_format_manifest_converts_value_wrapped_inputs() {
+        let source = r#"{
+            "name": "weather",
+            "version": "0.0.1",
+            "description": "",
+            "types": {},
+            "steps": {
+                "get_weather": {
+                    "uses": "starthubhq/http-get-wasm:0.0.1",
+                    "inputs": [{ "value": "{{inputs[0].url}}" }, { "headers": {} }, "direct", { "value": 1, "unit": "s" }]
+                }
+            }
+        }"#;
+
+        let wrapping = |source: &str| lint_manifest_source(source).iter().any(|finding| finding.message.contains("wraps its inputs"));
+        assert!(wrapping(source));
+        let formatted_source = format_manifest_source(source).unwrap();
+        // Lint agrees with fmt on what is left
+        assert!(!wrapping(&formatted_source));
+        let formatted: serde_json::Value = serde_json::from_str(&formatted_source).unwrap();
+        assert_eq!(formatted["steps"]["get_weather"]["inputs"], serde_json::json!(["{{inputs[0].url}}", { "headers": {} }, "direct", { "value": 1, "unit": "s" }]));
+        assert!(formatted.get("description").is_none());
+        assert!(formatted.get("types").is_none());
+        assert_eq!(formatted["name"], "weather");
+    }
+
+    #[tokio::test]
+    async fn test_fmt_check_fails_on_unformatted_manifest() {
+        let project = tempfile::tempdir().unwrap();
+        let path = project.path().join(LOCAL_MANIFEST_FILENAME);
+        let unformatted = r#"{"version":"0.0.1","name":"weather"}"#;
+        fs::write(&path, unformatted).unwrap();
+        let manifest = Some(path.display().to_string());
+
+        let error = cmd_fmt(manifest.clone(), true, false).await.unwrap_err();
+        assert!(error.to_string().contains("is not formatted"), "unexpected error: {}", error);
+        assert_eq!(fs::read_to_string(&path).unwrap(), unformatted);
+
+        // Formatting in place makes the check pass
+        cmd_fmt(manifest.clone(), false, false).await.unwrap();
+        assert_ne!(fs::read_to_string(&path).unwrap(), unformatted);
+        cmd_fmt(manifest, true, false).await.unwrap();
+    }
+
+    #[test]
+    fn test_read_mocks() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("coordinates.json"), r#"[{"lat": 41.9, "lon": 12.5}]"#).unwrap();
+
+        let mocks = read_mocks(&["get_coordinates=coordinates.json".to_string()], project.path()).unwrap();
+        assert_eq!(mocks["get_coordinates"], serde_json::json!([{ "lat": 41.9, "lon": 12.5 }]));
+
+        assert!(read_mocks(&["get_coordinates".to_string()], project.path()).is_err());
+        assert!(read_mocks(&["get_coordinates=missing.json".to_string()], project.path()).is_err());
+    }
+
+    #[test]
+    fn test_write_run_result_creates_parent_directories() {
+        let project = tempfile::tempdir().unwrap();
+        let path = project.path().join("artifacts/runs/result.json");
+        let response = serde_json::json!({ "status": "success", "run_id": "run-1", "result": ["hello"] });
+
+        write_run_result(&response, &path, false).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, response);
+
+        // A failed run is still written before the command fails
+        let failed = serde_json::json!({ "status": "error", "run_id": "run-2" });
+        assert!(print_run_response(&failed, Some(path.to_str().unwrap()), false).is_err());
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, failed);
+    }
+
+    #[tokio::test]
+    async fn test_run_matrix_runs_every_combination() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = std::sync::Arc::new(AtomicUsize::new(0));
+        let payloads = vec![
+            serde_json::json!({ "action": "tgirotto/echo:0.0.1", "inputs": ["first"] }),
+            serde_json::json!({ "action": "tgirotto/echo:0.0.1", "inputs": ["second"] }),
+        ];
+        let counter = runs.clone();
+        let results = run_matrix(payloads, 2, move |payload| {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(serde_json::json!({
+                    "status": "success",
+                    "run_id": format!("run-{}", run),
+                    "result": payload["inputs"],
+                }))
+            }
+        }).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["inputs"], serde_json::json!(["first"]));
+        assert_eq!(results[0]["result"], serde_json::json!(["first"]));
+        assert_eq!(results[1]["inputs"], serde_json::json!(["second"]));
+        assert_eq!(results[1]["result"], serde_json::json!(["second"]));
+        assert!(results.iter().all(|entry| entry["status"] == "success"));
+
+        // A single failed combination fails the whole matrix
+        let results = run_matrix(vec![serde_json::json!({ "inputs": ["third"] })], 1, |_| async {
+            Err(anyhow::anyhow!("server unreachable"))
+        }).await;
+        assert_eq!(results[0]["error"], "server unreachable");
+        let project = tempfile::tempdir().unwrap();
+        let output = project.path().join("matrix.json");
+        assert!(print_matrix_results(&results, Some(output.to_str().unwrap()), false).is_err());
+    }
+
+    #[test]
+    fn test_effective_config_reports_sources() {
+        let env: std::collections::HashMap<&str, &str> = [
+            (PROXY_ENV, "http://env-proxy:8080"),
+            (CACHE_DIR_ENV, "/tmp/env-cache"),
+            (API_BASE_ENV, "https://staging.example.com"),
+        ].into_iter().collect();
+        let flags = GlobalFlags {
+            proxy: Some("http://flag-proxy:8080".to_string()),
+            ..Default::default()
+        };
+        let entries = effective_config(&flags, |name| env.get(name).map(|value| value.to_string()));
+        let entry = |key: &str| entries.iter().find(|entry| entry.key == key).unwrap().clone();
+
+        assert_eq!(entry("proxy").value, "http://flag-proxy:8080");
+        assert_eq!(entry("proxy").source, ConfigSource::Flag);
+        assert_eq!(entry("cache_dir").value, "/tmp/env-cache");
+        assert_eq!(entry("cache_dir").source, ConfigSource::Env);
+        assert_eq!(entry("mirror_strategy").value, "order");
+        assert_eq!(entry("mirror_strategy").source, ConfigSource::Default);
+        assert_eq!(entry("api_base").value, "https://staging.example.com");
+        assert_eq!(entry("api_base").source, ConfigSource::Env);
+    }
+
+    #[test]
+    fn test_server_bind_is_shared_with_the_server() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("server.json");
+        let config = config_path.display().to_string();
+        let env = |bind: Option<&'static str>| {
+            let config = config.clone();
+            move |name: &str| match name {
+                SERVER_CONFIG_ENV => Some(config.clone()),
+                BIND_ENV => bind.map(|bind| bind.to_string()),
+                _ => None,
+            }
+        };
+
+        assert_eq!(server_bind(env(None)), (LOCAL_SERVER_HOST.to_string(), ConfigSource::Default));
+        fs::write(&config_path, r#"{"bind": "0.0.0.0:4000"}"#).unwrap();
+        assert_eq!(server_bind(env(None)), ("0.0.0.0:4000".to_string(), ConfigSource::File));
+        assert_eq!(server_bind(env(Some("127.0.0.1:5000"))), ("127.0.0.1:5000".to_string(), ConfigSource::Env));
+
+        fs::write(&config_path, r#"{"bind": "0.0.0.0:4000", "workers": 8, "cache_dir": "/tmp/file-cache"}"#).unwrap();
+        let entries = effective_config(&GlobalFlags::default(), env(None));
+        let entry = |key: &str| entries.iter().find(|entry| entry.key == key).unwrap().clone();
+        assert_eq!(entry("server_url").value, "http://127.0.0.1:4000");
+        assert_eq!(entry("server_url").source, ConfigSource::File);
+        assert_eq!(entry("workers").value, "8");
+        assert_eq!(entry("workers").source, ConfigSource::File);
+        assert_eq!(entry("cache_dir").value, "/tmp/file-cache");
+        assert_eq!(entry("cache_dir").source, ConfigSource::File);
+        assert_eq!(entry("api_base").value, crate::config::STARTHUB_API_BASE);
+        assert_eq!(entry("api_base").source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_is_alias() {
+        assert!(is_alias("weather"));
+        assert!(!is_alias("starthubhq/get-weather-by-location-name:0.0.1"));
+        assert!(!is_alias("file:///tmp/starthub-lock.json"));
+        // Slugs with a version are never aliases
+        assert!(!is_alias("http-get:0.0.1"));
+        assert!(!is_alias("http-get@0.0.1"));
+    }
+
+    #[test]
+    fn test_format_json_compact_and_pretty() {
+        let result = serde_json::json!({ "status": "success", "result": [{ "name": "value", "value": "hello" }] });
+
+        let compact = format_json(&result, true).unwrap();
+        assert!(!compact.contains('\n'), "compact output was: {}", compact);
+        let pretty = format_json(&result, false).unwrap();
+        assert!(pretty.lines().count() > 1, "pretty output was: {}", pretty);
+
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&compact).unwrap(), result);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&pretty).unwrap(), result);
+    }
+
+    #[test]
+    fn test_bench_report_separates_the_cold_run() {
+        let responses = [
+            serde_json::json!({ "status": "success", "run_id": "run-0", "timings": { "build_ms": 80.0, "execute_ms": 20.0 } }),
+            serde_json::json!({ "status": "success", "run_id": "run-1", "timings": { "build_ms": 2.0, "execute_ms": 18.0 } }),
+            serde_json::json!({ "status": "success", "run_id": "run-2", "timings": { "build_ms": 1.0, "execute_ms": 29.0 } }),
+        ];
+        let samples: Vec<BenchSample> = responses.iter()
+            .zip([120, 20, 30])
+            .map(|(response, ms)| bench_sample(response, Duration::from_millis(ms)).unwrap())
+            .collect();
+
+        let report = BenchReport::new(samples);
+        assert_eq!(report.samples.len(), 3);
+        let cold = report.cold.unwrap();
+        assert_eq!(cold.server_ms, 100.0);
+        assert_eq!(cold.total_ms, 120.0);
+        assert_eq!(cold.build_ms, 80.0);
+        let warm = report.warm.unwrap();
+        assert_eq!(warm.runs, 2);
+        assert_eq!(warm.mean_ms, 25.0);
+        assert_eq!((warm.min_ms, warm.max_ms), (20.0, 30.0));
+        assert_eq!(report.overall.unwrap().runs, 3);
+
+        // A single run is only cold
+        let report = BenchReport::new(vec![BenchSample::default()]);
+        assert!(report.cold.is_some() && report.warm.is_none());
+
+        // A failed run stops the benchmark
+        let failed = serde_json::json!({ "status": "error", "run_id": "run-3", "error": "boom" });
+        assert!(bench_sample(&failed, Duration::from_millis(5)).unwrap_err().to_string().contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_bench_run_follows_the_run_stream() {
+        let client = reqwest::Client::new();
+        let payload = serde_json::json!({ "action": "starthubhq/echo:0.0.1", "inputs": [] });
+
+        let url = spawn_json_server("200 OK", concat!(
+            r#"{"type":"status","run_id":"run-a","status":"running"}"#, "\n",
+            r#"{"type":"step_completed","step_path":"echo"}"#, "\n",
+            r#"{"type":"result","status":"success","run_id":"run-a","timings":{"build_ms":3.0,"execute_ms":4.5}}"#, "\n",
+        ));
+        let started = std::time::Instant::now();
+        let sample = bench_run(&client, &url, &payload).await.unwrap();
+        assert_eq!((sample.build_ms, sample.execute_ms, sample.server_ms), (3.0, 4.5, 7.5));
+        // Completion is seen as soon as the result arrives, without waiting for a poll
+        assert!(sample.total_ms <= started.elapsed().as_secs_f64() * 1000.0);
+        assert!(sample.total_ms < 500.0, "wall-clock was {}ms", sample.total_ms);
+
+        // A run rejected before being queued answers with its result line alone
+        let url = spawn_json_server("400 Bad Request", r#"{"type":"result","status":"error","run_id":"run-b","error":"bad options"}"#);
+        let error = bench_run(&client, &url, &payload).await.unwrap_err();
+        assert!(error.to_string().contains("bad options"), "error was: {}", error);
+
+        let url = spawn_json_server("404 Not Found", "");
+        let error = bench_run(&client, &url, &payload).await.unwrap_err();
+        assert!(error.to_string().contains("404"), "error was: {}", error);
+    }
+
+    #[test]
+    fn test_validation_problems_are_errors() {
+        let response = serde_json::json!({
+            "status": "success",
+            "problems": ["step 'fetch' has no `uses`", "unknown kind \"script\", expected composition, wasm or docker"]
+        });
+        let findings = validation_findings(&response).unwrap();
+        assert_eq!(findings, vec![
+            Finding::error("step 'fetch' has no `uses`"),
+            Finding::error("unknown kind \"script\", expected composition, wasm or docker"),
+        ]);
+        assert!(report_findings(Path::new("starthub-lock.json"), &findings, false).is_err());
+
+        let valid = serde_json::json!({ "status": "success", "problems": [] });
+        assert!(report_findings(Path::new("starthub-lock.json"), &validation_findings(&valid).unwrap(), false).is_ok());
+
+        let failed = serde_json::json!({ "status": "error", "error": "starthub-lock.json isn't valid JSON" });
+        assert!(validation_findings(&failed).unwrap_err().to_string().contains("isn't valid JSON"));
+    }
+
+    #[test]
+    fn test_dry_run_lines_show_why_steps_are_blocked() {
+        let response = serde_json::json!({
+            "status": "success",
+            "plan": [
+                {"step_path": "fetch", "kind": "wasm", "uses": "starthubhq/http-get:0.0.1", "ready": true,
+                 "inputs": [{"name": "url", "resolved": true, "value": "https://example.com"}]},
+                {"step_path": "parse", "kind": "composition", "uses": "starthubhq/parse:0.0.1", "ready": false,
+                 "blocked_by": "it waits for the outputs of fetch",
+                 "inputs": [{"name": "body", "resolved": false, "value": "{{steps.fetch.outputs[0]}}"}]},
+                {"step_path": "parse.decode", "kind": "wasm", "uses": "starthubhq/decode:0.0.1", "ready": false,
+                 "blocked_by": "its parent step 'parse' is blocked", "inputs": []}
+            ]
+        });
+        assert_eq!(dry_run_lines(&response).unwrap(), vec![
+            "  1. fetch [wasm] starthubhq/http-get:0.0.1 ▶ ready",
+            "       url = \"https://example.com\"",
+            "  2. parse [composition] starthubhq/parse:0.0.1 ⏸ blocked: it waits for the outputs of fetch",
+            "       body = \"{{steps.fetch.outputs[0]}}\" (unresolved)",
+            "    3. parse.decode [wasm] starthubhq/decode:0.0.1 ⏸ blocked: its parent step 'parse' is blocked",
+        ]);
+
+        let failed = serde_json::json!({ "status": "error", "error": "Unknown manifest kind" });
+        assert!(dry_run_lines(&failed).unwrap_err().to_string().contains("Unknown manifest kind"));
+    }
+
+    #[test]
+    fn test_lint_fails_on_warnings_only_when_asked() {
+        // Valid and formatted, but the second input is never used
+        let manifest = serde_json::json!({
+            "name": "echo",
+            "version": "0.0.1",
+            "kind": "composition",
+            "inputs": [{"name": "value", "type": "string"}, {"name": "unused", "type": "string"}],
+            "outputs": [{"name": "value", "type": "string", "value": "{{inputs[0]}}"}]
+        });
+        let source = format!("{}\n", serde_json::to_string_pretty(&manifest).unwrap());
+        let findings = lint_manifest_source(&source);
+        assert_eq!(findings, vec![Finding::warning("input 'unused' (#1) isn't used by any step or output")]);
+
+        let path = Path::new("starthub-lock.json");
+        assert!(report_findings(path, &findings, false).is_ok());
+        let error = report_findings(path, &findings, true).unwrap_err().to_string();
+        assert!(error.contains("1 warning(s)") && error.contains("--fail-on-warning"), "unexpected error: {}", error);
+
+        // Errors fail either way
+        let findings = lint_manifest_source(r#"{"name": "echo", "kind": "script"}"#);
+        assert!(findings.contains(&Finding::error("`version` is missing")));
+        assert!(findings.contains(&Finding::error("unknown kind 'script', expected composition, wasm or docker")));
+        assert!(report_findings(path, &findings, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("45m").unwrap(), Duration::from_secs(45 * 60));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_duration("30d").unwrap(), Duration::from_secs(30 * 24 * 3600));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999w").unwrap_err().to_string().contains("too long"));
+    }
+
+    #[test]
+    fn test_resolve_run_action_prefers_explicit_action() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(LOCAL_MANIFEST_FILENAME), "{}").unwrap();
+
+        let action = resolve_run_action(Some("tgirotto/http-get:0.0.1".to_string()), project.path()).unwrap();
+        assert_eq!(action, "tgirotto/http-get:0.0.1");
+    }
+
+    #[test]
+    fn test_resolve_run_action_defaults_to_local_manifest() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(LOCAL_MANIFEST_FILENAME), "{}").unwrap();
+
+        let action = resolve_run_action(None, project.path()).unwrap();
+        let expected = project.path().join(LOCAL_MANIFEST_FILENAME).canonicalize().unwrap();
+        assert_eq!(action, format!("file://{}", expected.display()));
+    }
+
+    #[test]
+    fn test_resolve_run_action_requires_action_outside_a_project() {
+        let project = tempfile::tempdir().unwrap();
+        let error = resolve_run_action(None, project.path()).unwrap_err();
+        assert!(error.to_string().contains("No action given"));
+    }
+
+    #[test]
+    fn test_docker_build_args_use_cache_by_default() {
+        let args = docker_build_args("my-action:0.0.1", false, &[]);
+        assert_eq!(args, vec!["build", "-t", "my-action:0.0.1", "."]);
+    }
+
+    #[test]
+    fn test_docker_build_args_with_no_cache() {
+        let args = docker_build_args("my-action:0.0.1", true, &[]);
+        assert_eq!(args, vec!["build", "-t", "my-action:0.0.1", "--no-cache", "."]);
+    }
+
+    #[test]
+    fn test_docker_build_args_with_build_args() {
+        let build_args = vec!["BASE_IMAGE=alpine:3.20".to_string(), "DEBUG=".to_string()];
+        let args = docker_build_args("my-action:0.0.1", false, &build_args);
+        assert_eq!(args, vec![
+            "build", "-t", "my-action:0.0.1",
+            "--build-arg", "BASE_IMAGE=alpine:3.20",
+            "--build-arg", "DEBUG=",
+            ".",
+        ]);
+    }
+
+    #[test]
+    fn test_parse_build_arg() {
+        assert_eq!(parse_build_arg("VERSION=1.2.3").unwrap(), "VERSION=1.2.3");
+        assert_eq!(parse_build_arg("URL=http://a?b=c").unwrap(), "URL=http://a?b=c");
+        assert!(parse_build_arg("VERSION").is_err());
+        assert!(parse_build_arg("=1.2.3").is_err());
+        assert!(parse_build_arg("MY VAR=1").is_err());
+    }
+}
@@ -19,6 +19,15 @@ struct Cli {
     /// Verbose logs
     #[arg(short, long, global = true)]
     verbose: bool,
+    /// Directory where downloaded artifacts are cached (also read from STARTHUB_CACHE_DIR)
+    #[arg(long, global = true, value_name = "DIR")]
+    cache_dir: Option<String>,
+    /// Proxy for requests to the registry (also read from STARTHUB_PROXY; HTTP_PROXY/HTTPS_PROXY are honored otherwise)
+    #[arg(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
+    /// Order in which artifact mirrors are tried: as listed, or fastest first (also read from STARTHUB_MIRROR_STRATEGY)
+    #[arg(long, global = true, value_name = "STRATEGY", value_parser = ["order", "latency"])]
+    mirror_strategy: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -32,17 +41,177 @@ enum Commands {
         /// Do not build, only push/tag (assumes image exists locally)
         #[arg(long)]
         no_build: bool,
+        /// Rebuild the docker image from scratch instead of reusing cached layers
+        #[arg(long)]
+        no_cache: bool,
+        /// Build-time variable passed to docker build (repeatable)
+        #[arg(long = "build-arg", value_name = "KEY=VALUE", value_parser = commands::parse_build_arg)]
+        build_args: Vec<String>,
     },
     /// Deploy with the given config
     Run {
-        /// Package slug/name, e.g. "chirpstack"
-        action: String,       
+        /// Package slug/name, e.g. "chirpstack". Defaults to the starthub-lock.json of the current directory
+        action: Option<String>,
+        /// Resume a previously failed run, skipping the steps it already completed
+        #[arg(long, value_name = "RUN_ID")]
+        resume: Option<String>,
+        /// Dump the action tree to <DIR>/iteration-<n>.json after every executed step
+        #[arg(long, value_name = "DIR")]
+        trace: Option<String>,
+        /// Shorthand for --on-failure keep-going
+        #[arg(long)]
+        keep_going: bool,
+        /// What to do when a step fails: abort the run, or keep running the independent steps
+        #[arg(long, value_name = "POLICY", value_parser = ["fail-fast", "keep-going"], default_value = "fail-fast", conflicts_with = "keep_going")]
+        on_failure: String,
+        /// Fail when a provided input isn't referenced by any step or output
+        #[arg(long)]
+        strict_inputs: bool,
+        /// Print how every input was cast to its declared type
+        #[arg(long)]
+        explain_inputs: bool,
+        /// Record every fetched manifest to <DIR>/manifests.json
+        #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+        record: Option<String>,
+        /// Serve manifests from a previous --record instead of fetching them
+        #[arg(long, value_name = "DIR")]
+        replay: Option<String>,
+        /// Substitute the outputs of a step with the array of values in FILE instead of running it (repeatable)
+        #[arg(long = "mock", value_name = "STEP=FILE")]
+        mocks: Vec<String>,
+        /// JSON file mapping action references to the local directories they are developed in,
+        /// used instead of the registry
+        #[arg(long, value_name = "FILE")]
+        workspace: Option<String>,
+        /// JSON file holding an array of input arrays: the action runs once per entry and the results are aggregated
+        #[arg(long, value_name = "FILE", conflicts_with = "resume")]
+        matrix: Option<String>,
+        /// Number of matrix entries run at the same time
+        #[arg(long, default_value_t = 1, requires = "matrix")]
+        concurrency: usize,
+        /// Write the JSON result of the run to PATH instead of stdout ("-" for stdout)
+        #[arg(long, value_name = "PATH")]
+        output_file: Option<String>,
+        /// POST the outputs of every completed step to URL, as {run_id, step, output}
+        #[arg(long, value_name = "URL")]
+        on_step_output: Option<String>,
+        /// Return the outputs as an array, or as an object keyed by output name
+        #[arg(long, value_name = "SHAPE", value_parser = ["array", "object"], default_value = "array")]
+        output_shape: String,
+        /// Print or write the JSON result on a single line instead of pretty-printed
+        #[arg(long)]
+        json_compact: bool,
+        /// Print the steps of the action with the concrete versions they resolve to, without running it
+        #[arg(long)]
+        plan: bool,
+        /// With --plan, only expand N levels of steps, leaving deeper steps unexpanded
+        #[arg(long, value_name = "N", requires = "plan")]
+        depth: Option<usize>,
+        /// Use the cached manifests only, without network access (see `starthub cache warm`)
+        #[arg(long)]
+        offline: bool,
+        /// Run every action at the version and digest pinned by a lockfile (see `starthub lock`)
+        #[arg(long, value_name = "FILE")]
+        locked: Option<String>,
+        /// Print the order the steps would run in, with their inputs and whether they could start
+        /// right away, without running any of them
+        #[arg(long, conflicts_with_all = ["plan", "matrix", "resume"])]
+        dry_run: bool,
+    },
+    /// Run a single wasm or docker action in isolation and print its raw and parsed outputs
+    TestAction {
+        /// Action reference, e.g. "starthubhq/http-get-wasm:0.0.1"
+        action: String,
+        /// JSON file holding the array of inputs
+        #[arg(long, value_name = "FILE")]
+        input_file: Option<String>,
+    },
+    /// Run an action several times and report the latency of the first (cold) and following (warm) runs
+    Bench {
+        /// Action reference, e.g. "starthubhq/http-get-wasm:0.0.1"
+        action: String,
+        /// Number of runs
+        #[arg(long, default_value_t = 5)]
+        runs: usize,
+        /// JSON file holding the array of inputs
+        #[arg(long, value_name = "FILE")]
+        input_file: Option<String>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the actions known to the local server
+    List {
+        /// Glob on namespace/slug, e.g. "starthubhq/*"
+        pattern: Option<String>,
+        /// Print the actions as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the metadata of an action: kind, inputs, outputs, types, permissions and mirrors
+    Info {
+        /// Action reference, e.g. "starthubhq/http-get-wasm:0.0.1"
+        action: String,
+        /// Print the manifest as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Normalize a manifest: sorted keys, no empty optional fields, direct step inputs
+    Fmt {
+        /// Manifest to format. Defaults to the starthub-lock.json of the current directory
+        manifest: Option<String>,
+        /// Only check the formatting, and fail if the manifest needs changes
+        #[arg(long, conflicts_with = "stdout")]
+        check: bool,
+        /// Print the formatted manifest instead of writing it back
+        #[arg(long)]
+        stdout: bool,
+    },
+    /// Check a manifest for problems without fetching anything, e.g. before publishing
+    Lint {
+        /// Manifest to check. Defaults to the starthub-lock.json of the current directory
+        manifest: Option<String>,
+        /// Exit with code 1 on warnings too, not only on errors
+        #[arg(long)]
+        fail_on_warning: bool,
+    },
+    /// Check a manifest against the registry before publishing: unknown kinds, steps whose `uses` can't
+    /// be fetched, undeclared types and templates referring to missing steps
+    Validate {
+        /// Manifest to check. Defaults to the starthub-lock.json of the current directory
+        manifest: Option<String>,
+        /// Also check that the wasm and docker artifacts of every step can be fetched (needs the network)
+        #[arg(long)]
+        check_artifacts: bool,
+        /// Exit with code 1 on warnings too, not only on errors
+        #[arg(long)]
+        fail_on_warning: bool,
+    },
+    /// Print the JSON Schema of the inputs of an action, to build forms or validate payloads before running it
+    Schema {
+        /// Action reference, e.g. "starthubhq/http-get-wasm:0.0.1"
+        action: String,
+    },
+    /// Resolve an action and all its transitive children to concrete versions, and pin them with their digests
+    Lock {
+        /// Action reference. Defaults to the starthub-lock.json of the current directory
+        action: Option<String>,
+        /// Lockfile to write
+        #[arg(long, value_name = "FILE", default_value = "starthub.lock")]
+        out: String,
     },
     /// Start the server in detached mode
     Start {
-        /// Host to bind to
-        #[arg(long, default_value = "127.0.0.1:3000")]
-        bind: String,
+        /// Host and port to bind to (defaults to STARTHUB_BIND, then the server config file, then 127.0.0.1:3000)
+        #[arg(long)]
+        bind: Option<String>,
+        /// API base URL of the registry the server downloads manifests and artifacts from, e.g. a staging
+        /// one (defaults to STARTHUB_API_BASE, then https://api.starthub.so)
+        #[arg(long, value_name = "URL")]
+        api_base: Option<String>,
+        /// Seconds to wait for the server to become healthy
+        #[arg(long, default_value_t = 10)]
+        wait_timeout: u64,
     },
     /// Stop the running server
     Stop,
@@ -68,6 +237,51 @@ enum Commands {
     Auth,
     /// Clear the cache
     Reset,
+    /// Delete old runs and cached artifacts (dry run unless --yes)
+    Prune {
+        /// Age above which entries are deleted, e.g. 30d, 12h, 45m
+        #[arg(long, value_name = "DURATION")]
+        older_than: String,
+        /// Only prune the run history
+        #[arg(long)]
+        runs: bool,
+        /// Only prune cached artifacts
+        #[arg(long)]
+        artifacts: bool,
+        /// Actually delete, instead of only reporting what would be deleted
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Manage the local cache of manifests
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print every effective setting and whether it comes from a default, an environment variable or a flag
+    Show {
+        /// Print the settings as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Fetch and cache the manifests of actions and of all their children, without running them
+    Warm {
+        /// Action references, e.g. "starthubhq/http-get-wasm:0.0.1"
+        #[arg(required = true)]
+        refs: Vec<String>,
+    },
 }
 
 #[tokio::main]
@@ -82,11 +296,63 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    // Exported so that the server processes started by the CLI use the same cache
+    if let Some(cache_dir) = &cli.cache_dir {
+        std::env::set_var(commands::CACHE_DIR_ENV, std::env::current_dir()?.join(cache_dir));
+    }
+    if let Some(proxy) = &cli.proxy {
+        std::env::set_var(commands::PROXY_ENV, proxy);
+    }
+    if let Some(strategy) = &cli.mirror_strategy {
+        std::env::set_var(commands::MIRROR_STRATEGY_ENV, strategy);
+    }
+
+    let flags = commands::GlobalFlags {
+        verbose: cli.verbose,
+        cache_dir: cli.cache_dir.clone(),
+        proxy: cli.proxy.clone(),
+        mirror_strategy: cli.mirror_strategy.clone(),
+    };
+
     match cli.command {
         Commands::Init { path } => commands::cmd_init(path).await?,
-        Commands::Publish { no_build } => publish::cmd_publish(no_build).await?,
-        Commands::Run { action } => commands::cmd_run(action).await?,
-        Commands::Start { bind } => commands::cmd_start(bind).await?,
+        Commands::Publish { no_build, no_cache, build_args } => publish::cmd_publish(no_build, no_cache, build_args).await?,
+        Commands::Run { action, resume, trace, keep_going, on_failure, strict_inputs, explain_inputs, record, replay, mocks, output_file, on_step_output, output_shape, json_compact, workspace, matrix, concurrency, plan, depth, offline, locked, dry_run } => {
+            if plan {
+                commands::cmd_plan(action, depth).await?
+            } else {
+                commands::cmd_run(action, commands::RunOptions {
+                    resume,
+                    trace,
+                    on_failure: if keep_going { "keep-going".to_string() } else { on_failure },
+                    strict_inputs,
+                    explain_inputs,
+                    record,
+                    replay,
+                    mocks,
+                    output_file,
+                    on_step_output,
+                    output_shape,
+                    json_compact,
+                    workspace,
+                    matrix,
+                    concurrency,
+                    offline,
+                    locked,
+                    dry_run,
+                }).await?
+            }
+        }
+        Commands::TestAction { action, input_file } => commands::cmd_test_action(action, input_file).await?,
+        Commands::Bench { action, runs, input_file, json } => commands::cmd_bench(action, runs, input_file, json).await?,
+        Commands::List { pattern, json } => commands::cmd_list(pattern, json).await?,
+        Commands::Info { action, json } => commands::cmd_info(action, json).await?,
+        Commands::Fmt { manifest, check, stdout } => commands::cmd_fmt(manifest, check, stdout).await?,
+        Commands::Lint { manifest, fail_on_warning } => commands::cmd_lint(manifest, fail_on_warning).await?,
+        Commands::Validate { manifest, check_artifacts, fail_on_warning } => commands::cmd_validate(manifest, check_artifacts, fail_on_warning).await?,
+        Commands::Schema { action } => commands::cmd_schema(action).await?,
+        Commands::Lock { action, out } => commands::cmd_lock(action, out).await?,
+        Commands::Start { bind, api_base, wait_timeout } => commands::cmd_start(bind, api_base, std::time::Duration::from_secs(wait_timeout)).await?,
         Commands::Stop => commands::cmd_stop().await?,
         Commands::Logs { follow, lines } => commands::cmd_logs(follow, lines).await?,
         Commands::Status => commands::cmd_status().await?,
@@ -94,6 +360,9 @@ async fn main() -> Result<()> {
         Commands::Logout => commands::cmd_logout_starthub().await?,
         Commands::Auth => commands::cmd_auth_status().await?,
         Commands::Reset => commands::cmd_reset().await?,
+        Commands::Prune { older_than, runs, artifacts, yes } => commands::cmd_prune(older_than, runs, artifacts, yes).await?,
+        Commands::Cache { command: CacheCommand::Warm { refs } } => commands::cmd_cache_warm(refs).await?,
+        Commands::Config { command: ConfigCommand::Show { json } } => commands::cmd_config_show(&flags, json).await?,
     }
     Ok(())
 }
\ No newline at end of file
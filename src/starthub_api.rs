@@ -48,7 +48,10 @@ impl Client {
         Self {
             base: base.into(),
             token,
-            http: reqwest::Client::new(),
+            http: reqwest::Client::builder()
+                .user_agent(crate::commands::user_agent())
+                .build()
+                .unwrap_or_else(|_| reqwest::Client::new()),
         }
     }
 